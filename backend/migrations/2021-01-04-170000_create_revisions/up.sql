@@ -0,0 +1,9 @@
+CREATE TABLE revisions (
+  id SERIAL PRIMARY KEY,
+  sheet_id INT NOT NULL,
+  row INT NOT NULL,
+  col INT NOT NULL,
+  raw VARCHAR NOT NULL
+);
+
+CREATE INDEX revisions_sheet_id_idx ON revisions (sheet_id);
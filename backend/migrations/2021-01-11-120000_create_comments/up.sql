@@ -0,0 +1,12 @@
+CREATE TABLE comments (
+  id SERIAL PRIMARY KEY,
+  sheet_id INT NOT NULL,
+  row INT NOT NULL,
+  col INT NOT NULL,
+  parent_id INT REFERENCES comments (id) ON DELETE CASCADE,
+  author VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  resolved BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX comments_sheet_id_idx ON comments (sheet_id);
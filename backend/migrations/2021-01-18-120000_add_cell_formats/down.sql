@@ -0,0 +1 @@
+ALTER TABLE cells DROP COLUMN format;
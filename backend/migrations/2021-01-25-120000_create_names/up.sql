@@ -0,0 +1,9 @@
+CREATE TABLE names (
+  id SERIAL PRIMARY KEY,
+  sheet_id INT NOT NULL,
+  name VARCHAR NOT NULL,
+  definition VARCHAR NOT NULL
+);
+
+-- Names ignore case, `Rate` and `RATE` are the same name.
+CREATE UNIQUE INDEX names_sheet_id_name ON names (sheet_id, lower(name));
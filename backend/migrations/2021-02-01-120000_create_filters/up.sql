@@ -0,0 +1,5 @@
+-- One filter view per sheet, stored as the JSON of `models::Filter`.
+CREATE TABLE filters (
+  sheet_id INT PRIMARY KEY,
+  filter VARCHAR NOT NULL
+);
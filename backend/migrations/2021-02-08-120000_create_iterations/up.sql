@@ -0,0 +1,6 @@
+-- Iterative calculation settings, for the sheets that turned it on.
+CREATE TABLE iterations (
+  sheet_id INT PRIMARY KEY,
+  max_iterations INT NOT NULL,
+  tolerance DOUBLE PRECISION NOT NULL
+);
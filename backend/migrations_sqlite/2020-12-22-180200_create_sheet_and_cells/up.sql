@@ -0,0 +1,12 @@
+CREATE TABLE sheets (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL
+);
+
+CREATE TABLE cells (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  sheet_id INTEGER NOT NULL,
+  row INTEGER NOT NULL,
+  col INTEGER NOT NULL,
+  raw VARCHAR NOT NULL,
+  UNIQUE(sheet_id, row, col)
+);
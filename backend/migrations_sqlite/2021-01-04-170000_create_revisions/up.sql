@@ -0,0 +1,9 @@
+CREATE TABLE revisions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  sheet_id INTEGER NOT NULL,
+  row INTEGER NOT NULL,
+  col INTEGER NOT NULL,
+  raw VARCHAR NOT NULL
+);
+
+CREATE INDEX revisions_sheet_id_idx ON revisions (sheet_id);
@@ -0,0 +1,12 @@
+CREATE TABLE comments (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  sheet_id INTEGER NOT NULL,
+  row INTEGER NOT NULL,
+  col INTEGER NOT NULL,
+  parent_id INTEGER REFERENCES comments (id) ON DELETE CASCADE,
+  author VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  resolved BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX comments_sheet_id_idx ON comments (sheet_id);
@@ -0,0 +1 @@
+ALTER TABLE cells ADD COLUMN format VARCHAR NOT NULL DEFAULT '';
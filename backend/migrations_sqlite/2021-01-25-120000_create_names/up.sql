@@ -0,0 +1,9 @@
+CREATE TABLE names (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  sheet_id INTEGER NOT NULL,
+  name VARCHAR NOT NULL,
+  definition VARCHAR NOT NULL
+);
+
+-- Names ignore case, `Rate` and `RATE` are the same name.
+CREATE UNIQUE INDEX names_sheet_id_name ON names (sheet_id, lower(name));
@@ -0,0 +1,6 @@
+-- Iterative calculation settings, for the sheets that turned it on.
+CREATE TABLE iterations (
+  sheet_id INTEGER PRIMARY KEY NOT NULL,
+  max_iterations INTEGER NOT NULL,
+  tolerance DOUBLE NOT NULL
+);
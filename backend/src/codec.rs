@@ -0,0 +1,103 @@
+//! Wire encodings for `Request` and `Response`. Every session starts out
+//! speaking JSON over text frames; clients advertising the `msgpack`
+//! capability in `Hello` switch to MessagePack over binary frames, which
+//! keeps big `Connected` snapshots small and cheap to parse.
+
+use super::server::{Request, Response};
+use actix_web::web::Bytes;
+
+/// Capability clients advertise to receive MessagePack encoded responses.
+pub const MSGPACK: &str = "msgpack";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+  Json,
+  MessagePack,
+}
+
+/// An encoded response, ready to be written as a websocket frame.
+pub enum Frame {
+  Text(String),
+  Binary(Bytes),
+}
+
+impl Encoding {
+  /// Picks the encoding for a session from its negotiated capabilities.
+  pub fn negotiate(capabilities: &[String]) -> Encoding {
+    if capabilities.iter().any(|c| c == MSGPACK) {
+      Encoding::MessagePack
+    } else {
+      Encoding::Json
+    }
+  }
+
+  pub fn encode(self, resp: &Response) -> Frame {
+    match self {
+      Encoding::Json => Frame::Text(serde_json::to_string(resp).unwrap()),
+      // Encode structs as maps so that field names survive, matching the JSON shape.
+      Encoding::MessagePack => Frame::Binary(rmp_serde::to_vec_named(resp).unwrap().into()),
+    }
+  }
+}
+
+/// Requests are accepted in either encoding regardless of what was negotiated.
+pub fn decode_text(text: &str) -> Result<Request, String> {
+  serde_json::from_str(text).map_err(|e| e.to_string())
+}
+
+pub fn decode_binary(bin: &[u8]) -> Result<Request, String> {
+  rmp_serde::from_slice(bin).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::models::Cell;
+
+  #[test]
+  fn msgpack_round_trips_requests() {
+    let req = Request::UpdateCell {
+      user_id: 1,
+      sheet_id: 1,
+      row: 2,
+      col: 3,
+      raw: "=A1".to_string(),
+    };
+    let bin = rmp_serde::to_vec_named(&req).unwrap();
+    match decode_binary(&bin).unwrap() {
+      Request::UpdateCell { row, col, raw, .. } => assert_eq!((row, col, raw.as_str()), (2, 3, "=A1")),
+      other => panic!("unexpected request {:?}", other),
+    }
+  }
+
+  #[test]
+  fn msgpack_keeps_json_shape() {
+    let resp = Response::CellUpdated {
+      user_id: 7,
+      cell: Cell {
+        id: 1,
+        sheet_id: 1,
+        row: 0,
+        col: 0,
+        raw: "hi".to_string(),
+        format: "0.00".to_string(),
+      },
+    };
+    let bin = match Encoding::MessagePack.encode(&resp) {
+      Frame::Binary(bin) => bin,
+      Frame::Text(_) => panic!("expected a binary frame"),
+    };
+    let decoded: serde_json::Value = rmp_serde::from_slice(&bin).unwrap();
+    let json: serde_json::Value = serde_json::to_value(&resp).unwrap();
+    assert_eq!(decoded, json);
+  }
+
+  #[test]
+  fn negotiate_defaults_to_json() {
+    assert_eq!(Encoding::negotiate(&[]), Encoding::Json);
+    assert_eq!(
+      Encoding::negotiate(&[MSGPACK.to_string()]),
+      Encoding::MessagePack
+    );
+  }
+}
@@ -0,0 +1,18 @@
+// Diesel 1.x's `table!` and derive macros expand to impls nested inside
+// functions, which newer compilers flag on every invocation.
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+
+pub mod codec;
+pub mod models;
+pub mod names;
+pub mod schema;
+pub mod search;
+pub mod server;
+pub mod session;
+pub mod store;
+pub mod viewport;
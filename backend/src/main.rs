@@ -1,129 +1,11 @@
-#[macro_use]
-extern crate diesel;
 extern crate dotenv;
 
-use std::time::{Duration, Instant};
-
 use actix::prelude::*;
-use actix_web::{middleware, web, App, Error, HttpRequest, HttpResponse, HttpServer};
-use actix_web_actors::ws;
+use actix_web::{middleware, App, HttpServer};
+use backend::{server, session, store};
 use dotenv::dotenv;
 use std::env;
 
-pub mod models;
-pub mod schema;
-mod server;
-
-/// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-/// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
-
-async fn ws_index(
-    r: HttpRequest,
-    stream: web::Payload,
-    srv: web::Data<Addr<server::WsServer>>,
-) -> Result<HttpResponse, Error> {
-    println!("{:?}", r);
-    let res = ws::start(
-        WsSession {
-            id: 0,
-            hb: Instant::now(),
-            addr: srv.get_ref().clone(),
-        },
-        &r,
-        stream,
-    );
-    println!("{:?}", res);
-    res
-}
-
-struct WsSession {
-    id: i32,
-    /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
-    /// otherwise we drop connection.
-    hb: Instant,
-    // Spreadsheet server
-    addr: Addr<server::WsServer>,
-}
-
-impl Actor for WsSession {
-    type Context = ws::WebsocketContext<Self>;
-
-    fn started(&mut self, ctx: &mut Self::Context) {
-        self.hb(ctx);
-
-        let sheet_id = 1;
-        let addr = ctx.address().recipient();
-        self.addr
-            .send(server::Connect { sheet_id, addr })
-            .into_actor(self)
-            .then(|res, act, ctx| {
-                match res {
-                    Ok(res) => act.id = res,
-                    _ => ctx.stop(),
-                }
-                fut::ready(())
-            })
-            .wait(ctx);
-    }
-
-    fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        self.addr.do_send(server::Disconnect { user_id: self.id });
-        Running::Stop
-    }
-}
-
-impl Handler<server::Response> for WsSession {
-    type Result = ();
-
-    fn handle(&mut self, msg: server::Response, ctx: &mut Self::Context) {
-        let json = serde_json::to_string(&msg).unwrap();
-        ctx.text(json);
-    }
-}
-
-impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
-    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        match msg {
-            Ok(ws::Message::Ping(msg)) => {
-                self.hb = Instant::now();
-                ctx.pong(&msg);
-            }
-            Ok(ws::Message::Pong(_)) => {
-                self.hb = Instant::now();
-            }
-            Ok(ws::Message::Text(text)) => {
-                self.addr.do_send(server::Text {
-                    user_id: self.id,
-                    data: text,
-                });
-            }
-            Ok(ws::Message::Binary(bin)) => ctx.binary(bin),
-            Ok(ws::Message::Close(reason)) => {
-                ctx.close(reason);
-                ctx.stop();
-            }
-            _ => ctx.stop(),
-        }
-    }
-}
-
-impl WsSession {
-    // sends ping to client every second to check if alive.
-    fn hb(&self, ctx: &mut <Self as Actor>::Context) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
-                println!("Websocket Client heartbeat failed, disconnecting!");
-                act.addr.do_send(server::Disconnect { user_id: act.id });
-                ctx.stop();
-                return;
-            }
-            ctx.ping(b"");
-        });
-    }
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_server=info,actix_web=info");
@@ -131,7 +13,9 @@ async fn main() -> std::io::Result<()> {
 
     dotenv().ok();
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let server = server::WsServer::new(&db_url).start();
+    let store = store::connect(&db_url)
+        .unwrap_or_else(|e| panic!("Error connecting to {}: {}", db_url, e));
+    let server = server::WsServer::new(store).start();
 
     HttpServer::new(move || {
         App::new()
@@ -139,7 +23,7 @@ async fn main() -> std::io::Result<()> {
             // enable logger
             .wrap(middleware::Logger::default())
             // websocket route
-            .service(web::resource("/ws/").route(web::get().to(ws_index)))
+            .configure(session::configure)
     })
     .bind("127.0.0.1:8888")?
     .run()
@@ -1,13 +1,16 @@
-use super::schema::cells;
+use super::schema::{cells, comments, names, revisions};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize, Queryable)]
+#[derive(Clone, Debug, Deserialize, Serialize, Queryable, JsonSchema)]
 pub struct Cell {
   pub id: i32,
   pub sheet_id: i32,
   pub row: i32,
   pub col: i32,
   pub raw: String,
+  /// Number format code, e.g. `#,##0.00`. Empty for the default.
+  pub format: String,
 }
 
 #[derive(AsChangeset, Debug, Insertable)]
@@ -17,6 +20,149 @@ pub struct NewCell {
   pub row: i32,
   pub col: i32,
   pub raw: String,
+  /// `None` keeps the format of the cell.
+  pub format: Option<String>,
+}
+
+/// What to type in a cell, and maybe its format, for requests setting
+/// several cells at once.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct CellEdit {
+  pub row: i32,
+  pub col: i32,
+  pub raw: String,
+  /// The number format to give the cell, when it changes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub format: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Queryable)]
+pub struct Revision {
+  pub id: i32,
+  pub sheet_id: i32,
+  pub row: i32,
+  pub col: i32,
+  pub raw: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "revisions"]
+pub struct NewRevision<'a> {
+  pub sheet_id: i32,
+  pub row: i32,
+  pub col: i32,
+  pub raw: &'a str,
+}
+
+impl<'a> From<&'a NewCell> for NewRevision<'a> {
+  fn from(cell: &'a NewCell) -> Self {
+    NewRevision {
+      sheet_id: cell.sheet_id,
+      row: cell.row,
+      col: cell.col,
+      raw: &cell.raw,
+    }
+  }
+}
+
+/// A note attached to a cell. Replies point to the first comment of their
+/// thread through `parent_id` and live on the same cell.
+#[derive(Clone, Debug, Deserialize, Serialize, Queryable, JsonSchema)]
+pub struct Comment {
+  pub id: i32,
+  pub sheet_id: i32,
+  pub row: i32,
+  pub col: i32,
+  pub parent_id: Option<i32>,
+  pub author: String,
+  pub body: String,
+  pub resolved: bool,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "comments"]
+pub struct NewComment {
+  pub sheet_id: i32,
+  pub row: i32,
+  pub col: i32,
+  pub parent_id: Option<i32>,
+  pub author: String,
+  pub body: String,
+}
+
+/// A name formulas can use instead of a cell, a range or a constant, such
+/// as `TaxRate` for `B1`. Names are unique per sheet, ignoring case.
+#[derive(Clone, Debug, Deserialize, Serialize, Queryable, JsonSchema)]
+pub struct Name {
+  pub id: i32,
+  pub sheet_id: i32,
+  pub name: String,
+  /// What the name stands for as typed, e.g. `B1`, `A1:A10` or `0.2`.
+  pub definition: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "names"]
+pub struct NewName {
+  pub sheet_id: i32,
+  pub name: String,
+  pub definition: String,
+}
+
+/// Iterative calculation settings of a sheet. While set, reference cycles
+/// are evaluated up to `max_iterations` times, stopping once no number in
+/// them changes by more than `tolerance`. Clients evaluate, the server only
+/// stores them.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct Iteration {
+  pub max_iterations: i32,
+  pub tolerance: f64,
+}
+
+/// A filter view, shared by everyone on the sheet. Rows of `range` whose
+/// values don't meet every condition are hidden, the first row aside since
+/// it holds the headers. Clients evaluate it, the server only stores it.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct Filter {
+  pub range: Rect,
+  pub conditions: Vec<FilterCondition>,
+}
+
+/// Rows are shown when the value in column `col` meets `criterion`, written
+/// like the criteria of `COUNTIF`, e.g. `>10` or `East`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct FilterCondition {
+  pub col: i32,
+  pub criterion: String,
+}
+
+/// Inclusive rectangle of cells, `top..=bottom` rows by `left..=right` columns.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub struct Rect {
+  pub top: i32,
+  pub left: i32,
+  pub bottom: i32,
+  pub right: i32,
+}
+
+impl Rect {
+  pub fn cell(row: i32, col: i32) -> Rect {
+    Rect {
+      top: row,
+      left: col,
+      bottom: row,
+      right: col,
+    }
+  }
+
+  pub fn contains(&self, row: i32, col: i32) -> bool {
+    self.top <= row && row <= self.bottom && self.left <= col && col <= self.right
+  }
+
+  pub fn coords(&self) -> impl Iterator<Item = (i32, i32)> {
+    let (left, right) = (self.left, self.right);
+    (self.top..=self.bottom).flat_map(move |row| (left..=right).map(move |col| (row, col)))
+  }
 }
 
 #[derive(Queryable)]
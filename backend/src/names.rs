@@ -0,0 +1,363 @@
+//! Names let formulas say `=Price*TaxRate` instead of `=B2*$F$1`. The
+//! engine evaluates them, the server only needs to validate them and to
+//! rewrite formulas when a name changes, so like `viewport::references` this
+//! scans formulas for identifiers rather than running the real parser.
+
+/// Longest name that can be defined, in bytes.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Whether `name` can be defined: an identifier that can't be mistaken for a
+/// coordinate (`A1`) or a boolean. Same rules as the engine.
+pub fn is_valid_name(name: &str) -> bool {
+  let mut chars = name.chars();
+  let starts_ok = chars
+    .next()
+    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+  let is_boolean = name.eq_ignore_ascii_case("TRUE") || name.eq_ignore_ascii_case("FALSE");
+  starts_ok
+    && chars.all(is_ident_char)
+    && !is_coordinate(name)
+    && !is_boolean
+    && name.len() <= MAX_NAME_LEN
+}
+
+/// Whether `definition` is a `LAMBDA`, which formulas call like a function,
+/// e.g. `=Double(2)`.
+pub fn is_lambda(definition: &str) -> bool {
+  let definition = definition.trim();
+  let definition = definition.strip_prefix('=').unwrap_or(definition);
+  let definition = definition.trim_start().as_bytes();
+  definition.len() > 7 && definition[..7].eq_ignore_ascii_case(b"LAMBDA(")
+}
+
+/// Whether the formula `raw` refers to `name`, ignoring case. Calls count,
+/// in case `name` is a `LAMBDA`.
+pub fn uses_name(raw: &str, name: &str) -> bool {
+  identifiers(raw)
+    .iter()
+    .any(|(start, end, _)| raw[*start..*end].eq_ignore_ascii_case(name))
+}
+
+/// Rewrites the formula `raw` to use `new` wherever it uses `old`, returning
+/// `None` when there is nothing to rewrite. Strings with the same text are
+/// left alone, and so are function calls unless `calls` is set, for names
+/// defined as a `LAMBDA`.
+pub fn rename_references(raw: &str, old: &str, new: &str, calls: bool) -> Option<String> {
+  let mut renamed = String::with_capacity(raw.len());
+  let mut last = 0;
+  for (start, end, is_call) in identifiers(raw) {
+    if raw[start..end].eq_ignore_ascii_case(old) && (calls || !is_call) {
+      renamed.push_str(&raw[last..start]);
+      renamed.push_str(new);
+      last = end;
+    }
+  }
+  if last == 0 {
+    return None;
+  }
+  renamed.push_str(&raw[last..]);
+  Some(renamed)
+}
+
+/// Like `rename_references`, for the definition of another name, which
+/// doesn't need a leading `=`.
+pub fn rename_in_definition(definition: &str, old: &str, new: &str, calls: bool) -> Option<String> {
+  let raw = format!("={}", definition.trim().trim_start_matches('='));
+  rename_references(&raw, old, new, calls)
+}
+
+// Returns the byte ranges of the identifiers of a formula that could be
+// names, and whether they're called like functions. Coordinates, strings
+// and the parameters and variables of the `LAMBDA`s and `LET`s around an
+// identifier aren't names.
+fn identifiers(raw: &str) -> Vec<(usize, usize, bool)> {
+  let mut found = vec![];
+  // The calls and parentheses the scan is inside of, innermost last.
+  let mut frames: Vec<Frame> = vec![];
+  let mut called = None;
+  for token in tokens(raw) {
+    match token {
+      Token::Ident(start, end, is_call) => {
+        let name = raw[start..end].to_ascii_uppercase();
+        let holds = frames.last().is_some_and(|f| f.may_bind());
+        if holds {
+          // `LAMBDA(x, ...)`, which binds `x` if an argument follows.
+          let frame = frames.last_mut().unwrap();
+          frame.lone = Some((start, end, is_call));
+          frame.tokens += 1;
+        } else {
+          release(&mut frames, raw, &mut found);
+          push_free(&frames, raw, (start, end, is_call), &mut found);
+          if let Some(frame) = frames.last_mut() {
+            frame.tokens += 1;
+          }
+        }
+        called = if is_call { Some(name) } else { None };
+        continue;
+      }
+      Token::Open => {
+        release(&mut frames, raw, &mut found);
+        if let Some(frame) = frames.last_mut() {
+          frame.tokens += 1;
+        }
+        let binder = match called.as_deref() {
+          Some("LAMBDA") => Some(Binder::Lambda),
+          Some("LET") => Some(Binder::Let),
+          _ => None,
+        };
+        frames.push(Frame::new(binder));
+      }
+      Token::Comma => {
+        if let Some(frame) = frames.last_mut() {
+          match (frame.binder, frame.lone.take()) {
+            (Some(Binder::Lambda), Some((start, end, _))) if frame.tokens == 1 => {
+              frame.bound.push(raw[start..end].to_ascii_uppercase());
+            }
+            (Some(Binder::Let), Some((start, end, _))) if frame.tokens == 1 => {
+              // The value of a variable doesn't see it, what follows does.
+              frame.pending = Some(raw[start..end].to_ascii_uppercase());
+            }
+            (_, lone) => {
+              if let Some(pending) = frame.pending.take() {
+                frame.bound.push(pending);
+              }
+              if let Some(lone) = lone {
+                push_free(&frames, raw, lone, &mut found);
+              }
+            }
+          }
+          let frame = frames.last_mut().unwrap();
+          frame.arg += 1;
+          frame.tokens = 0;
+        }
+      }
+      Token::Close => {
+        release(&mut frames, raw, &mut found);
+        frames.pop();
+      }
+      Token::Other => {
+        release(&mut frames, raw, &mut found);
+        if let Some(frame) = frames.last_mut() {
+          frame.tokens += 1;
+        }
+      }
+    }
+    called = None;
+  }
+  release(&mut frames, raw, &mut found);
+  found
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Binder {
+  Lambda,
+  Let,
+}
+
+struct Frame {
+  binder: Option<Binder>,
+  // Which argument the scan is in, and how many tokens of it it has seen.
+  arg: usize,
+  tokens: usize,
+  // An identifier alone at the start of an argument, held back until it's
+  // known whether it's a parameter or a variable.
+  lone: Option<(usize, usize, bool)>,
+  // Uppercased names bound for the rest of the call.
+  bound: Vec<String>,
+  pending: Option<String>,
+}
+
+impl Frame {
+  fn new(binder: Option<Binder>) -> Frame {
+    Frame {
+      binder,
+      arg: 0,
+      tokens: 0,
+      lone: None,
+      bound: vec![],
+      pending: None,
+    }
+  }
+
+  // Whether an identifier starting the current argument could bind a name.
+  fn may_bind(&self) -> bool {
+    self.tokens == 0
+      && match self.binder {
+        Some(Binder::Lambda) => true,
+        Some(Binder::Let) => self.arg.is_multiple_of(2),
+        None => false,
+      }
+  }
+}
+
+// An identifier held back that turned out not to be a binding, like the
+// body of `LAMBDA(x, x)` or the `x` of `LAMBDA(x+1)`.
+fn release(frames: &mut [Frame], raw: &str, found: &mut Vec<(usize, usize, bool)>) {
+  if let Some(lone) = frames.last_mut().and_then(|f| f.lone.take()) {
+    push_free(frames, raw, lone, found);
+  }
+}
+
+fn push_free(
+  frames: &[Frame],
+  raw: &str,
+  ident: (usize, usize, bool),
+  found: &mut Vec<(usize, usize, bool)>,
+) {
+  let name = raw[ident.0..ident.1].to_ascii_uppercase();
+  if !frames.iter().any(|f| f.bound.contains(&name)) {
+    found.push(ident);
+  }
+}
+
+enum Token {
+  // Byte range, and whether it's called like a function.
+  Ident(usize, usize, bool),
+  Open,
+  Comma,
+  Close,
+  // Anything else: operators, numbers, strings, coordinates.
+  Other,
+}
+
+fn tokens(raw: &str) -> Vec<Token> {
+  if !raw.starts_with('=') {
+    return vec![];
+  }
+  let bytes = raw.as_bytes();
+  let mut tokens = vec![];
+  let mut i = 1;
+  while i < bytes.len() {
+    let c = bytes[i] as char;
+    if c == '"' {
+      // `""` inside a string is an escaped quote, skipping it as an empty
+      // string followed by another one works out the same.
+      i += 1;
+      while i < bytes.len() && bytes[i] != b'"' {
+        i += 1;
+      }
+      i += 1;
+      tokens.push(Token::Other);
+    } else if (c.is_ascii_alphabetic() || c == '_') && !is_ident_char(bytes[i - 1] as char) {
+      let start = i;
+      while i < bytes.len() && is_ident_char(bytes[i] as char) {
+        i += 1;
+      }
+      if is_coordinate(&raw[start..i]) {
+        tokens.push(Token::Other);
+      } else {
+        let is_call = raw[i..].trim_start().starts_with('(');
+        tokens.push(Token::Ident(start, i, is_call));
+      }
+    } else {
+      match c {
+        '(' => tokens.push(Token::Open),
+        ',' => tokens.push(Token::Comma),
+        ')' => tokens.push(Token::Close),
+        c if c.is_whitespace() => (),
+        _ => tokens.push(Token::Other),
+      }
+      i += 1;
+    }
+  }
+  tokens
+}
+
+fn is_ident_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// `Letters Digits`, e.g. `A1` or `xfd1048576`.
+fn is_coordinate(s: &str) -> bool {
+  let digits = s.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+  digits.len() < s.len() && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn valid_names() {
+    assert!(is_valid_name("TaxRate"));
+    assert!(is_valid_name("_total.2024"));
+    assert!(is_valid_name("Q1x"));
+    assert!(!is_valid_name(""));
+    assert!(!is_valid_name("Q1"));
+    assert!(!is_valid_name("true"));
+    assert!(!is_valid_name("1st"));
+    assert!(!is_valid_name("Tax Rate"));
+    assert!(!is_valid_name(&"a".repeat(MAX_NAME_LEN + 1)));
+  }
+
+  #[test]
+  fn renames_references() {
+    assert_eq!(
+      rename_references("=price*taxrate+SUM(Price)", "Price", "Cost", false),
+      Some("=Cost*taxrate+SUM(Cost)".to_string())
+    );
+    assert_eq!(rename_references("Price", "Price", "Cost", false), None);
+    assert_eq!(
+      rename_references("=Prices+PriceA1", "Price", "Cost", false),
+      None
+    );
+  }
+
+  #[test]
+  fn renames_lambda_calls() {
+    assert!(is_lambda("=lambda(x, x*2)"));
+    assert!(!is_lambda("LAMBDA (x, x*2)"));
+    assert!(!is_lambda("A1"));
+    assert_eq!(
+      rename_references("=Double(2)+Double", "double", "Twice", true),
+      Some("=Twice(2)+Twice".to_string())
+    );
+    assert_eq!(
+      rename_in_definition("LAMBDA(x, x*Rate)", "Rate", "Tax", false),
+      Some("=LAMBDA(x, x*Tax)".to_string())
+    );
+    assert!(uses_name("=Double(2)", "Double"));
+  }
+
+  #[test]
+  fn renaming_skips_functions_strings_and_coordinates() {
+    assert_eq!(rename_references("=SUM(A1)", "SUM", "Total", false), None);
+    assert_eq!(
+      rename_references("=\"Price\"&Price", "Price", "Cost", false),
+      Some("=\"Price\"&Cost".to_string())
+    );
+    assert!(!uses_name("=A1+B2", "A1"));
+    assert!(uses_name("=1+rate", "Rate"));
+  }
+
+  #[test]
+  fn renaming_skips_parameters_and_variables() {
+    assert_eq!(
+      rename_in_definition("LAMBDA(Price, Price*2)", "Price", "Cost", false),
+      None
+    );
+    assert_eq!(
+      rename_references("=LAMBDA(x, x*Price)(Price)", "Price", "Cost", false),
+      Some("=LAMBDA(x, x*Cost)(Cost)".to_string())
+    );
+    assert_eq!(
+      rename_references("=LET(price, 2, price*3)+Price", "Price", "Cost", false),
+      Some("=LET(price, 2, price*3)+Cost".to_string())
+    );
+    // A variable's own value doesn't see it, and the body of a lambda isn't
+    // a parameter.
+    assert_eq!(
+      rename_references("=LET(Price, Price*2, Price)", "Price", "Cost", false),
+      Some("=LET(Price, Cost*2, Price)".to_string())
+    );
+    assert_eq!(
+      rename_in_definition("LAMBDA(Price)", "Price", "Cost", false),
+      Some("=LAMBDA(Cost)".to_string())
+    );
+    assert_eq!(
+      rename_references("=LET(f, LAMBDA(x, x*2), f(3))+F(1)", "f", "g", true),
+      Some("=LET(f, LAMBDA(x, x*2), f(3))+g(1)".to_string())
+    );
+    assert!(!uses_name("=LAMBDA(Rate, Rate)(1)", "Rate"));
+  }
+}
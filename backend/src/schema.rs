@@ -5,6 +5,54 @@ table! {
         row -> Int4,
         col -> Int4,
         raw -> Varchar,
+        format -> Varchar,
+    }
+}
+
+table! {
+    comments (id) {
+        id -> Int4,
+        sheet_id -> Int4,
+        row -> Int4,
+        col -> Int4,
+        parent_id -> Nullable<Int4>,
+        author -> Varchar,
+        body -> Text,
+        resolved -> Bool,
+    }
+}
+
+table! {
+    filters (sheet_id) {
+        sheet_id -> Int4,
+        filter -> Varchar,
+    }
+}
+
+table! {
+    iterations (sheet_id) {
+        sheet_id -> Int4,
+        max_iterations -> Int4,
+        tolerance -> Float8,
+    }
+}
+
+table! {
+    names (id) {
+        id -> Int4,
+        sheet_id -> Int4,
+        name -> Varchar,
+        definition -> Varchar,
+    }
+}
+
+table! {
+    revisions (id) {
+        id -> Int4,
+        sheet_id -> Int4,
+        row -> Int4,
+        col -> Int4,
+        raw -> Varchar,
     }
 }
 
@@ -16,5 +64,10 @@ table! {
 
 allow_tables_to_appear_in_same_query!(
     cells,
+    comments,
+    filters,
+    iterations,
+    names,
+    revisions,
     sheets,
 );
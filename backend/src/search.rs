@@ -0,0 +1,132 @@
+//! Find and replace over what was typed in cells, with the same rules as the
+//! engine's `search::Matcher`. Only clients evaluate formulas, so they search
+//! what cells show themselves. Empty cells never match, not even `.*`.
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Longest text that can be searched for, in characters.
+pub const MAX_QUERY_LEN: usize = 1_000;
+// Patterns come from clients, keep what they compile to small.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// What to look for. `text` is matched literally unless `regex` is set.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(default)]
+pub struct Query {
+  pub text: String,
+  pub match_case: bool,
+  /// Only match cells whose whole text matches.
+  pub whole_cell: bool,
+  /// `text` is a regular expression, replacements can refer to its groups
+  /// as `$1`.
+  pub regex: bool,
+}
+
+/// A `Query` ready to run.
+pub struct Matcher {
+  regex: Regex,
+  // Whether replacements expand `$1` and the like.
+  expand: bool,
+}
+
+impl Matcher {
+  pub fn new(query: &Query) -> Result<Matcher, String> {
+    if query.text.is_empty() {
+      return Err("nothing to search for".to_string());
+    }
+    if query.text.chars().count() > MAX_QUERY_LEN {
+      return Err(format!(
+        "searches can't be longer than {} characters",
+        MAX_QUERY_LEN
+      ));
+    }
+    let pattern = if query.regex {
+      query.text.clone()
+    } else {
+      regex::escape(&query.text)
+    };
+    let pattern = if query.whole_cell {
+      format!("^(?:{})$", pattern)
+    } else {
+      pattern
+    };
+    let regex = RegexBuilder::new(&pattern)
+      .case_insensitive(!query.match_case)
+      .size_limit(MAX_REGEX_SIZE)
+      .build()
+      .map_err(|e| format!("invalid regular expression: {}", e))?;
+    Ok(Matcher {
+      regex,
+      expand: query.regex,
+    })
+  }
+
+  pub fn is_match(&self, raw: &str) -> bool {
+    !raw.is_empty() && self.regex.is_match(raw)
+  }
+
+  /// `raw` with every match replaced, `None` when nothing matched.
+  pub fn replace(&self, raw: &str, replacement: &str) -> Option<String> {
+    if !self.is_match(raw) {
+      return None;
+    }
+    let replaced = if self.expand {
+      self.regex.replace_all(raw, replacement)
+    } else {
+      self.regex.replace_all(raw, NoExpand(replacement))
+    };
+    Some(replaced.into_owned())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn matcher(text: &str, f: impl FnOnce(&mut Query)) -> Matcher {
+    let mut query = Query {
+      text: text.to_string(),
+      ..Query::default()
+    };
+    f(&mut query);
+    Matcher::new(&query).unwrap()
+  }
+
+  #[test]
+  fn replace_follows_the_query() {
+    let plain = matcher("sku-1", |_| ());
+    assert_eq!(plain.replace("SKU-1, sku-1", "P-$1").unwrap(), "P-$1, P-$1");
+    assert_eq!(plain.replace("SKU-2", "P-"), None);
+    assert!(!plain.is_match(""));
+
+    let match_case = matcher("sku", |q| q.match_case = true);
+    assert_eq!(match_case.replace("SKU-1", "P"), None);
+
+    let whole_cell = matcher("sku", |q| q.whole_cell = true);
+    assert_eq!(whole_cell.replace("SKU-1", "P"), None);
+    assert_eq!(whole_cell.replace("Sku", "P").unwrap(), "P");
+
+    let regex = matcher(r"sku-(\d+)", |q| q.regex = true);
+    assert_eq!(regex.replace("=SKU-12&sku-3", "P$1").unwrap(), "=P12&P3");
+    // Empty cells aren't matched even by patterns matching nothing.
+    assert!(!matcher(".*", |q| q.regex = true).is_match(""));
+  }
+
+  #[test]
+  fn invalid_queries() {
+    assert!(Matcher::new(&Query::default()).is_err());
+    let unbalanced = Query {
+      text: "(".to_string(),
+      regex: true,
+      ..Query::default()
+    };
+    assert!(Matcher::new(&unbalanced).is_err());
+    let long = Query {
+      text: "x".repeat(MAX_QUERY_LEN + 1),
+      ..Query::default()
+    };
+    assert!(Matcher::new(&long).is_err());
+  }
+}
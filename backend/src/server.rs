@@ -2,18 +2,82 @@
 //! And manages available rooms. Peers send messages to other peers in same
 //! room through `ChatServer`.
 
+use super::codec;
 use super::models::*;
-use super::schema::cells;
+use super::names;
+use super::search::{self, Query};
+use super::store::{CellMove, SheetStore};
+use super::viewport::{self, Subscription};
 use actix::prelude::*;
-use diesel::pg::PgConnection;
-use diesel::prelude::*;
 use rand::{self, rngs::ThreadRng, Rng};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Version of the websocket protocol spoken by this server. Bump it whenever
+/// a change to `Request` or `Response` would break existing clients.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol version clients may still use.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// Optional protocol features this server knows about. Clients advertise the
+/// ones they support in `Hello` and get back the ones both sides understand.
+pub const CAPABILITIES: &[&str] = &[codec::MSGPACK, viewport::VIEWPORT];
+
+/// Minimum time between two `SelectionChanged` broadcasts for the same user.
+/// Selections sent in between are coalesced, only the latest one goes out.
+const SELECTION_THROTTLE: Duration = Duration::from_millis(100);
+/// Colors handed out to participants, in order.
+const COLORS: &[&str] = &[
+  "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+];
+const MAX_NAME_LEN: usize = 32;
+const MAX_COMMENT_LEN: usize = 10_000;
+const MAX_FORMAT_LEN: usize = 255;
+const MAX_DEFINITION_LEN: usize = 1_000;
+const MAX_CRITERION_LEN: usize = 255;
+/// Most passes over a cycle a sheet may ask every client for.
+const MAX_ITERATIONS: i32 = 10_000;
+/// Most cells a single request may set at once.
+const MAX_BATCH_LEN: usize = 10_000;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Participant {
+  pub id: i32,
+  pub name: String,
+  pub color: String,
+}
+
+/// A participant's active cell, plus the range they selected if any.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Selection {
+  pub row: i32,
+  pub col: i32,
+  pub range: Option<Rect>,
+}
+
+/// What volatile functions evaluate with. Clients draw `RAND()` from `seed`
+/// and take `NOW()` from `now`, read as UTC wherever they are, so that
+/// everyone on a sheet agrees on them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Recalculation {
+  pub seed: u32,
+  /// Unix time in milliseconds.
+  pub now: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum Request {
+  /// Must be the first request sent on a connection.
+  Hello {
+    protocol_version: u32,
+    #[serde(default)]
+    capabilities: Vec<String>,
+    /// Shown to other participants, a guest name is picked if missing.
+    #[serde(default)]
+    name: Option<String>,
+  },
   UpdateCell {
     user_id: i32,
     sheet_id: i32,
@@ -21,17 +85,219 @@ pub enum Request {
     col: i32,
     raw: String,
   },
+  /// Sets a number format code such as `#,##0.00` on a cell. The server
+  /// checks that it parses, the client renders it. `sheet_id` is ignored, the
+  /// format lands on the sheet the session joined.
+  SetFormat {
+    #[serde(default)]
+    sheet_id: i32,
+    row: i32,
+    col: i32,
+    format: String,
+  },
+  /// Replaces the area of the sheet the client is showing, at most 10,000
+  /// cells. Requires the `viewport` capability.
+  Subscribe {
+    viewport: Rect,
+  },
+  SelectionChanged {
+    selection: Selection,
+  },
+  /// Starts a thread on a cell, or replies to one when `parent_id` is set.
+  /// Replies always land on the cell of their thread.
+  AddComment {
+    row: i32,
+    col: i32,
+    body: String,
+    #[serde(default)]
+    parent_id: Option<i32>,
+  },
+  /// Replaces the body of a comment. Anyone on the sheet may, authors are
+  /// display names that anyone can pick.
+  EditComment {
+    comment_id: i32,
+    body: String,
+  },
+  /// Resolves or reopens the thread started by `comment_id`.
+  ResolveComment {
+    comment_id: i32,
+    resolved: bool,
+  },
+  /// Defines a name, or redefines it if the sheet already has it (ignoring
+  /// case). The client checks the definition, the server stores it as is.
+  DefineName {
+    name: String,
+    definition: String,
+  },
+  /// Renames a name and rewrites the formulas using it.
+  RenameName {
+    name_id: i32,
+    name: String,
+  },
+  /// Formulas still using the name evaluate to `#NAME?`.
+  DeleteName {
+    name_id: i32,
+  },
+  /// Asks everyone on the sheet to re-evaluate volatile functions such as
+  /// `NOW()` and `RAND()`.
+  Recalculate,
+  /// Turns iterative calculation on for everyone on the sheet, or off with
+  /// `None`.
+  SetIteration {
+    iteration: Option<Iteration>,
+  },
+  /// Rearranges the rows of `range`, `order` listing them as they should
+  /// end up, as sorted by the client. Formats and comments follow their
+  /// cells. References to the cells that moved are rewritten by the client,
+  /// which parses formulas: `cells` holds every cell whose raw input changed,
+  /// moved or not, and `names` the new definitions of the names that
+  /// changed. Cells go out as a single `CellsUpdated`.
+  SortRange {
+    range: Rect,
+    order: Vec<i32>,
+    #[serde(default)]
+    cells: Vec<CellEdit>,
+    #[serde(default)]
+    names: BTreeMap<String, String>,
+  },
+  /// Replaces the filter view of the sheet, or removes it with `None`.
+  SetFilter {
+    filter: Option<Filter>,
+  },
+  /// Sets several cells at once, either all of them or none, like the
+  /// cells filled by the client's autofill. They go out as a single
+  /// `CellsUpdated`.
+  UpdateCells {
+    cells: Vec<CellEdit>,
+  },
+  /// Lists the cells whose raw input matches `query` as `Found`.
+  Find {
+    query: Query,
+  },
+  /// Replaces what `query` matches in the raw input of every cell, like
+  /// `UpdateCells`. The sender gets `Replaced` back to undo it.
+  ReplaceAll {
+    query: Query,
+    replacement: String,
+  },
 }
 
-#[derive(Clone, Debug, Message, Serialize, Deserialize)]
+#[derive(Clone, Debug, Message, Serialize, Deserialize, JsonSchema)]
 #[rtype(result = "()")]
 #[serde(tag = "type")]
 pub enum Response {
-  Connected { user_id: i32, cells: Vec<Cell> },
-  Participants { ids: HashSet<i32> },
-  CellLocked { user_id: i32, cell: Cell },
-  CellUpdated { user_id: i32, cell: Cell },
-  Error { message: String },
+  /// Handshake accepted. Followed by `Connected`.
+  Welcome {
+    protocol_version: u32,
+    capabilities: Vec<String>,
+  },
+  /// Handshake refused. The server closes the connection right after.
+  Rejected {
+    reason: String,
+    min_protocol_version: u32,
+    max_protocol_version: u32,
+  },
+  /// `cells` is empty when the `viewport` capability was negotiated, they
+  /// are sent as `CellsLoaded` once the client subscribes.
+  Connected {
+    user_id: i32,
+    cells: Vec<Cell>,
+    /// What the other participants evaluated volatile functions with.
+    recalculation: Recalculation,
+    /// Iterative calculation settings of the sheet, `None` while it's off.
+    iteration: Option<Iteration>,
+  },
+  /// Cells the client didn't have yet, after subscribing or because a
+  /// visible formula started referencing them.
+  CellsLoaded {
+    cells: Vec<Cell>,
+  },
+  Participants {
+    ids: HashSet<i32>,
+    participants: Vec<Participant>,
+  },
+  /// Another participant moved their cursor or selection.
+  SelectionChanged {
+    user_id: i32,
+    selection: Selection,
+  },
+  /// Another participant left, forget their selection.
+  SelectionCleared {
+    user_id: i32,
+  },
+  /// Every comment of the sheet, sent right after `Connected`.
+  Comments {
+    comments: Vec<Comment>,
+  },
+  CommentAdded {
+    user_id: i32,
+    comment: Comment,
+  },
+  /// A comment was edited, resolved or reopened.
+  CommentUpdated {
+    user_id: i32,
+    comment: Comment,
+  },
+  /// Every name of the sheet, sent right after `Comments`.
+  Names {
+    names: Vec<Name>,
+  },
+  /// A name was defined, redefined or renamed. Cells rewritten by a rename
+  /// follow as `CellUpdated`.
+  NameUpdated {
+    user_id: i32,
+    name: Name,
+  },
+  NameDeleted {
+    user_id: i32,
+    name_id: i32,
+  },
+  /// The filter view of the sheet, sent right after `Names` when it has
+  /// one.
+  Filter {
+    filter: Filter,
+  },
+  /// The filter view was replaced, or removed when `filter` is `None`.
+  FilterChanged {
+    user_id: i32,
+    filter: Option<Filter>,
+  },
+  /// Volatile functions must be re-evaluated with `recalculation`.
+  Recalculated {
+    user_id: i32,
+    recalculation: Recalculation,
+  },
+  /// Iterative calculation was turned on or changed, or off when
+  /// `iteration` is `None`.
+  IterationChanged {
+    user_id: i32,
+    iteration: Option<Iteration>,
+  },
+  CellLocked {
+    user_id: i32,
+    cell: Cell,
+  },
+  CellUpdated {
+    user_id: i32,
+    cell: Cell,
+  },
+  /// Cells set together by `UpdateCells` or `ReplaceAll`.
+  CellsUpdated {
+    user_id: i32,
+    cells: Vec<Cell>,
+  },
+  /// The cells matching a `Find`, row by row.
+  Found {
+    cells: Vec<Cell>,
+  },
+  /// What the cells changed by `ReplaceAll` held before, sending them back
+  /// as `UpdateCells` undoes it.
+  Replaced {
+    previous: Vec<CellEdit>,
+  },
+  Error {
+    message: String,
+  },
 }
 
 #[derive(Message)]
@@ -39,6 +305,9 @@ pub enum Response {
 pub struct Connect {
   pub sheet_id: i32,
   pub addr: Recipient<Response>,
+  /// Negotiated during the handshake.
+  pub capabilities: Vec<String>,
+  pub name: Option<String>,
 }
 
 #[derive(Message)]
@@ -49,38 +318,98 @@ pub struct Disconnect {
 
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Text {
+pub struct ClientRequest {
   pub user_id: i32,
-  pub data: String,
+  pub req: Request,
+}
+
+/// Checks the client's `Hello` against what this server speaks, returning
+/// the `Welcome` or `Rejected` response to send back.
+pub fn handshake(protocol_version: u32, capabilities: &[String]) -> Response {
+  if protocol_version < MIN_PROTOCOL_VERSION || protocol_version > PROTOCOL_VERSION {
+    return Response::Rejected {
+      reason: format!("unsupported protocol version {}", protocol_version),
+      min_protocol_version: MIN_PROTOCOL_VERSION,
+      max_protocol_version: PROTOCOL_VERSION,
+    };
+  }
+  // Unknown capabilities are ignored so newer clients can still connect.
+  let capabilities = capabilities
+    .iter()
+    .filter(|c| CAPABILITIES.contains(&c.as_str()))
+    .cloned()
+    .collect();
+  Response::Welcome {
+    protocol_version,
+    capabilities,
+  }
+}
+
+/// JSON Schema describing every message of the protocol. A copy is checked
+/// in at `protocol/schema.json` for clients to consume.
+pub fn protocol_schema() -> serde_json::Value {
+  serde_json::json!({
+    "protocol_version": PROTOCOL_VERSION,
+    "min_protocol_version": MIN_PROTOCOL_VERSION,
+    "capabilities": CAPABILITIES,
+    "request": schemars::schema_for!(Request),
+    "response": schemars::schema_for!(Response),
+  })
 }
 
 // Create an individual message for cell update...
 
+struct SelectionState {
+  selection: Selection,
+  last_sent: Option<Instant>,
+  // A throttled broadcast is scheduled
+  pending: bool,
+}
+
 pub struct WsServer {
-  db: PgConnection,
+  store: Box<dyn SheetStore>,
   // User ID -> WebSocket Actor
   user_to_addr: HashMap<i32, Recipient<Response>>,
   // Spreadsheet ID -> User IDs
   sheet_to_users: HashMap<i32, HashSet<i32>>,
   // User ID -> Spreadsheet ID
   user_to_sheet: HashMap<i32, i32>,
+  // User ID -> Viewport, only for users that negotiated the capability
+  user_to_subscription: HashMap<i32, Subscription>,
+  // User ID -> Name and color
+  user_to_participant: HashMap<i32, Participant>,
+  // User ID -> Last known selection
+  user_to_selection: HashMap<i32, SelectionState>,
+  // Spreadsheet ID -> Inputs of volatile functions, while someone is on it
+  sheet_to_recalculation: HashMap<i32, Recalculation>,
+  next_guest: u32,
   rng: ThreadRng,
 }
 
 impl WsServer {
-  pub fn new(db_url: &str) -> WsServer {
+  pub fn new(store: Box<dyn SheetStore>) -> WsServer {
     WsServer {
-      db: PgConnection::establish(db_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", db_url)),
+      store,
       user_to_addr: HashMap::new(),
       sheet_to_users: HashMap::new(),
       user_to_sheet: HashMap::new(),
+      user_to_subscription: HashMap::new(),
+      user_to_participant: HashMap::new(),
+      user_to_selection: HashMap::new(),
+      sheet_to_recalculation: HashMap::new(),
+      next_guest: 1,
       rng: rand::thread_rng(),
     }
   }
 
-  fn handle_req(&self, req: Request) {
+  fn handle_req(&mut self, user_id: i32, req: Request, ctx: &mut Context<Self>) {
     match req {
+      Request::Hello { .. } => {
+        let resp = Response::Error {
+          message: "handshake already completed".to_string(),
+        };
+        self.send(user_id, resp);
+      }
       Request::UpdateCell {
         user_id,
         sheet_id,
@@ -90,30 +419,696 @@ impl WsServer {
       } => {
         self.update_cell(user_id, sheet_id, row, col, raw);
       }
+      Request::SetFormat {
+        row,
+        col,
+        format,
+        ..
+      } => self.set_format(user_id, row, col, format),
+      Request::Subscribe { viewport } => self.subscribe(user_id, viewport),
+      Request::SelectionChanged { selection } => self.change_selection(user_id, selection, ctx),
+      Request::AddComment {
+        row,
+        col,
+        body,
+        parent_id,
+      } => self.add_comment(user_id, row, col, body, parent_id),
+      Request::EditComment { comment_id, body } => self.edit_comment(user_id, comment_id, body),
+      Request::ResolveComment {
+        comment_id,
+        resolved,
+      } => self.resolve_comment(user_id, comment_id, resolved),
+      Request::DefineName { name, definition } => self.define_name(user_id, name, definition),
+      Request::RenameName { name_id, name } => self.rename_name(user_id, name_id, name),
+      Request::DeleteName { name_id } => self.delete_name(user_id, name_id),
+      Request::Recalculate => self.recalculate(user_id),
+      Request::SetIteration { iteration } => self.set_iteration(user_id, iteration),
+      Request::SortRange {
+        range,
+        order,
+        cells,
+        names,
+      } => self.sort_range(user_id, range, order, cells, names),
+      Request::SetFilter { filter } => self.set_filter(user_id, filter),
+      Request::UpdateCells { cells } => self.update_cells(user_id, cells),
+      Request::Find { query } => self.find(user_id, query),
+      Request::ReplaceAll { query, replacement } => self.replace_all(user_id, query, replacement),
+    };
+  }
+
+  fn add_comment(
+    &mut self,
+    user_id: i32,
+    row: i32,
+    col: i32,
+    body: String,
+    parent_id: Option<i32>,
+  ) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = self.check_comment_body(&body).and_then(|_| {
+      let (row, col) = match parent_id {
+        Some(parent_id) => {
+          let parent = self.find_comment(sheet_id, parent_id)?;
+          if parent.parent_id.is_some() {
+            return Err("can't reply to a reply".to_string());
+          }
+          (parent.row, parent.col)
+        }
+        None => (row, col),
+      };
+      let new_comment = NewComment {
+        sheet_id,
+        row,
+        col,
+        parent_id,
+        author: self.author(user_id),
+        body,
+      };
+      self
+        .store
+        .add_comment(new_comment)
+        .map_err(|e| format!("failed to add comment: {}", e))
+    });
+    match res {
+      Ok(comment) => self.broadcast(sheet_id, Response::CommentAdded { user_id, comment }),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
+  }
+
+  fn edit_comment(&mut self, user_id: i32, comment_id: i32, body: String) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = self.check_comment_body(&body).and_then(|_| {
+      self.find_comment(sheet_id, comment_id)?;
+      self
+        .store
+        .edit_comment(comment_id, &body)
+        .map_err(|e| format!("failed to edit comment {}: {}", comment_id, e))
+    });
+    match res {
+      Ok(comment) => self.broadcast(sheet_id, Response::CommentUpdated { user_id, comment }),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
+  }
+
+  fn resolve_comment(&mut self, user_id: i32, comment_id: i32, resolved: bool) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = self.find_comment(sheet_id, comment_id).and_then(|comment| {
+      if comment.parent_id.is_some() {
+        return Err("only the first comment of a thread can be resolved".to_string());
+      }
+      self
+        .store
+        .resolve_comment(comment_id, resolved)
+        .map_err(|e| format!("failed to resolve comment {}: {}", comment_id, e))
+    });
+    match res {
+      Ok(comment) => self.broadcast(sheet_id, Response::CommentUpdated { user_id, comment }),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
+  }
+
+  // Looks a comment up, making sure it belongs to the user's sheet.
+  fn find_comment(&mut self, sheet_id: i32, comment_id: i32) -> Result<Comment, String> {
+    match self.store.get_comment(comment_id) {
+      Ok(Some(comment)) if comment.sheet_id == sheet_id => Ok(comment),
+      Ok(_) => Err(format!("comment {} not found", comment_id)),
+      Err(e) => Err(format!("failed to load comment {}: {}", comment_id, e)),
+    }
+  }
+
+  fn check_comment_body(&self, body: &str) -> Result<(), String> {
+    if body.trim().is_empty() {
+      return Err("comments can't be empty".to_string());
+    }
+    if body.chars().count() > MAX_COMMENT_LEN {
+      return Err(format!(
+        "comments can't be longer than {} characters",
+        MAX_COMMENT_LEN
+      ));
+    }
+    Ok(())
+  }
+
+  fn define_name(&mut self, user_id: i32, name: String, definition: String) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
     };
+    let res = check_definition(&name, &definition).and_then(|_| {
+      let definition = definition.trim();
+      let existing = self.find_name(sheet_id, |n| n.name.eq_ignore_ascii_case(&name))?;
+      let res = match existing {
+        // Redefining keeps the spelling the name was created with.
+        Some(existing) => self
+          .store
+          .update_name(existing.id, &existing.name, definition),
+        None => self.store.add_name(NewName {
+          sheet_id,
+          name: name.clone(),
+          definition: definition.to_string(),
+        }),
+      };
+      res.map_err(|e| format!("failed to define name {}: {}", name, e))
+    });
+    match res {
+      Ok(name) => self.name_updated(sheet_id, user_id, name),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
   }
 
-  fn update_cell(&self, user_id: i32, sheet_id: i32, row: i32, col: i32, raw: String) {
+  fn rename_name(&mut self, user_id: i32, name_id: i32, new_name: String) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = check_name(&new_name).and_then(|_| {
+      let old = self
+        .find_name(sheet_id, |n| n.id == name_id)?
+        .ok_or_else(|| format!("name {} not found", name_id))?;
+      let taken = self.find_name(sheet_id, |n| {
+        n.id != name_id && n.name.eq_ignore_ascii_case(&new_name)
+      })?;
+      if taken.is_some() {
+        return Err(format!("{} is already defined", new_name));
+      }
+      let name = self
+        .store
+        .update_name(name_id, &new_name, &old.definition)
+        .map_err(|e| format!("failed to rename {}: {}", old.name, e))?;
+      let cells = self
+        .store
+        .load_sheet(sheet_id)
+        .map_err(|e| format!("failed to load cells using {}: {}", old.name, e))?;
+      Ok((old, name, cells))
+    });
+    let (old, name, cells) = match res {
+      Ok(res) => res,
+      Err(message) => return self.send(user_id, Response::Error { message }),
+    };
+    self.name_updated(sheet_id, user_id, name);
+    // Formulas call names defined as a `LAMBDA`.
+    let calls = names::is_lambda(&old.definition);
+    for cell in cells {
+      let raw = match names::rename_references(&cell.raw, &old.name, &new_name, calls) {
+        Some(raw) => raw,
+        None => continue,
+      };
+      self.update_cell(user_id, sheet_id, cell.row, cell.col, raw);
+    }
+    // So do the definitions of other lambdas.
+    let others = match self.store.list_names(sheet_id) {
+      Ok(others) => others,
+      Err(e) => {
+        println!("failed to load names using {}: {}", old.name, e);
+        return;
+      }
+    };
+    for other in others
+      .into_iter()
+      .filter(|n| names::is_lambda(&n.definition))
+    {
+      let definition =
+        match names::rename_in_definition(&other.definition, &old.name, &new_name, calls) {
+          Some(definition) => definition,
+          None => continue,
+        };
+      match self.store.update_name(other.id, &other.name, &definition) {
+        Ok(other) => self.name_updated(sheet_id, user_id, other),
+        Err(e) => println!("failed to rename {} in {}: {}", old.name, other.name, e),
+      }
+    }
+  }
+
+  fn delete_name(&mut self, user_id: i32, name_id: i32) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = self
+      .find_name(sheet_id, |n| n.id == name_id)
+      .and_then(|name| name.ok_or_else(|| format!("name {} not found", name_id)))
+      .and_then(|_| {
+        self
+          .store
+          .delete_name(name_id)
+          .map_err(|e| format!("failed to delete name {}: {}", name_id, e))
+      });
+    match res {
+      Ok(()) => self.broadcast(sheet_id, Response::NameDeleted { user_id, name_id }),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
+  }
+
+  fn find_name(
+    &mut self,
+    sheet_id: i32,
+    predicate: impl Fn(&Name) -> bool,
+  ) -> Result<Option<Name>, String> {
+    match self.store.list_names(sheet_id) {
+      Ok(names) => Ok(names.into_iter().find(predicate)),
+      Err(e) => Err(format!("failed to load names: {}", e)),
+    }
+  }
+
+  // Broadcasts the name, first loading what it now refers to for the
+  // sessions showing a viewport.
+  fn name_updated(&mut self, sheet_id: i32, user_id: i32, name: Name) {
+    let user_ids = match self.sheet_to_users.get(&sheet_id) {
+      Some(ids) => ids.clone(),
+      None => return,
+    };
+    for id in user_ids {
+      if let Some(subscription) = self.user_to_subscription.get_mut(&id) {
+        match subscription.refresh(self.store.as_mut(), sheet_id) {
+          Ok(cells) if cells.is_empty() => (),
+          Ok(cells) => self.send(id, Response::CellsLoaded { cells }),
+          Err(e) => println!("failed to load cells of {:?}: {}", name, e),
+        }
+      }
+      let resp = Response::NameUpdated {
+        user_id,
+        name: name.clone(),
+      };
+      self.send(id, resp);
+    }
+  }
+
+  fn recalculate(&mut self, user_id: i32) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let recalculation = self.new_recalculation();
+    self.sheet_to_recalculation.insert(sheet_id, recalculation);
+    let resp = Response::Recalculated {
+      user_id,
+      recalculation,
+    };
+    self.broadcast(sheet_id, resp);
+  }
+
+  fn sort_range(
+    &mut self,
+    user_id: i32,
+    range: Rect,
+    order: Vec<i32>,
+    cells: Vec<CellEdit>,
+    names: BTreeMap<String, String>,
+  ) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    // Everything is checked before anything is written, so that a bad name
+    // doesn't leave the sheet half sorted.
+    let res = row_moves(range, &order).and_then(|moves| {
+      for (name, definition) in &names {
+        check_definition(name, definition)?;
+      }
+      let stored = self
+        .store
+        .load_sheet(sheet_id)
+        .map_err(|e| format!("failed to load cells to sort: {}", e))?;
+      Ok((moves, stored))
+    });
+    let (moves, stored) = match res {
+      Ok(res) => res,
+      Err(message) => return self.send(user_id, Response::Error { message }),
+    };
+    let stored: HashMap<(i32, i32), Cell> = stored
+      .into_iter()
+      .map(|cell| ((cell.row, cell.col), cell))
+      .collect();
+    let format = |at: &(i32, i32)| stored.get(at).map_or("", |cell| cell.format.as_str());
+
+    // The client's edits, plus the cells whose format moved.
+    let mut edits: BTreeMap<(i32, i32), CellEdit> = cells
+      .into_iter()
+      .map(|edit| {
+        (
+          (edit.row, edit.col),
+          CellEdit {
+            format: None,
+            ..edit
+          },
+        )
+      })
+      .collect();
+    for (from, to) in &moves {
+      if format(from) == format(to) {
+        continue;
+      }
+      let edit = edits.entry(*to).or_insert_with(|| CellEdit {
+        row: to.0,
+        col: to.1,
+        raw: stored
+          .get(to)
+          .map_or(String::new(), |cell| cell.raw.clone()),
+        format: None,
+      });
+      edit.format = Some(format(from).to_string());
+    }
+    let edits = edits.into_values().collect();
+    if let Err(message) = self.store_edits(sheet_id, user_id, edits) {
+      return self.send(user_id, Response::Error { message });
+    }
+
+    let res = self.store.list_comments(sheet_id).and_then(|before| {
+      self.store.move_comments(sheet_id, &moves)?;
+      let after = self.store.list_comments(sheet_id)?;
+      Ok((before, after))
+    });
+    match res {
+      Ok((before, after)) => {
+        for (old, comment) in before.iter().zip(after) {
+          if (old.row, old.col) != (comment.row, comment.col) {
+            self.broadcast(sheet_id, Response::CommentUpdated { user_id, comment });
+          }
+        }
+      }
+      Err(e) => println!("failed to move comments of sheet {}: {}", sheet_id, e),
+    }
+
+    for (name, definition) in names {
+      self.define_name(user_id, name, definition);
+    }
+  }
+
+  fn set_filter(&mut self, user_id: i32, filter: Option<Filter>) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = filter.as_ref().map_or(Ok(()), check_filter).and_then(|_| {
+      self
+        .store
+        .set_filter(sheet_id, filter.as_ref())
+        .map_err(|e| format!("failed to set filter: {}", e))
+    });
+    match res {
+      Ok(()) => self.broadcast(sheet_id, Response::FilterChanged { user_id, filter }),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
+  }
+
+  fn set_iteration(&mut self, user_id: i32, iteration: Option<Iteration>) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = iteration.as_ref().map_or(Ok(()), check_iteration).and_then(|_| {
+      self
+        .store
+        .set_iteration(sheet_id, iteration)
+        .map_err(|e| format!("failed to set iterative calculation: {}", e))
+    });
+    match res {
+      Ok(()) => self.broadcast(sheet_id, Response::IterationChanged { user_id, iteration }),
+      Err(message) => self.send(user_id, Response::Error { message }),
+    }
+  }
+
+  fn update_cells(&mut self, user_id: i32, edits: Vec<CellEdit>) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    if let Err(message) = self.store_edits(sheet_id, user_id, edits) {
+      self.send(user_id, Response::Error { message });
+    }
+  }
+
+  fn find(&mut self, user_id: i32, query: Query) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = search::Matcher::new(&query).and_then(|matcher| {
+      let mut cells = self
+        .store
+        .load_sheet(sheet_id)
+        .map_err(|e| format!("failed to load cells to search: {}", e))?;
+      cells.retain(|cell| matcher.is_match(&cell.raw));
+      cells.sort_by_key(|cell| (cell.row, cell.col));
+      Ok(cells)
+    });
+    let resp = match res {
+      Ok(cells) => Response::Found { cells },
+      Err(message) => Response::Error { message },
+    };
+    self.send(user_id, resp);
+  }
+
+  fn replace_all(&mut self, user_id: i32, query: Query, replacement: String) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = search::Matcher::new(&query).and_then(|matcher| {
+      let mut cells = self
+        .store
+        .load_sheet(sheet_id)
+        .map_err(|e| format!("failed to load cells to replace: {}", e))?;
+      cells.sort_by_key(|cell| (cell.row, cell.col));
+      let mut previous = vec![];
+      let mut edits = vec![];
+      for cell in cells {
+        let raw = match matcher.replace(&cell.raw, &replacement) {
+          Some(raw) if raw != cell.raw => raw,
+          _ => continue,
+        };
+        edits.push(CellEdit {
+          row: cell.row,
+          col: cell.col,
+          raw,
+          format: None,
+        });
+        previous.push(CellEdit {
+          row: cell.row,
+          col: cell.col,
+          raw: cell.raw,
+          format: None,
+        });
+      }
+      self.store_edits(sheet_id, user_id, edits)?;
+      Ok(previous)
+    });
+    let resp = match res {
+      Ok(previous) => Response::Replaced { previous },
+      Err(message) => Response::Error { message },
+    };
+    self.send(user_id, resp);
+  }
+
+  // Stores `edits` in one go and broadcasts them.
+  fn store_edits(
+    &mut self,
+    sheet_id: i32,
+    user_id: i32,
+    edits: Vec<CellEdit>,
+  ) -> Result<(), String> {
+    if edits.len() > MAX_BATCH_LEN {
+      return Err(format!(
+        "can't set more than {} cells at once",
+        MAX_BATCH_LEN
+      ));
+    }
+    if let Some(edit) = edits.iter().find(|e| e.row < 0 || e.col < 0) {
+      return Err(format!("invalid cell ({}, {})", edit.row, edit.col));
+    }
+    for format in edits.iter().filter_map(|e| e.format.as_ref()) {
+      check_format(format)?;
+    }
+    if edits.is_empty() {
+      return Ok(());
+    }
+    let new_cells = edits
+      .into_iter()
+      .map(|edit| NewCell {
+        sheet_id,
+        row: edit.row,
+        col: edit.col,
+        raw: edit.raw,
+        format: edit.format,
+      })
+      .collect();
+    let cells = self
+      .store
+      .upsert_cells(new_cells)
+      .map_err(|e| format!("failed to update cells: {}", e))?;
+    self.broadcast_cells(sheet_id, user_id, cells);
+    Ok(())
+  }
+
+  fn new_recalculation(&mut self) -> Recalculation {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |d| d.as_millis() as i64);
+    Recalculation {
+      seed: self.rng.gen(),
+      now,
+    }
+  }
+
+  fn author(&self, user_id: i32) -> String {
+    self
+      .user_to_participant
+      .get(&user_id)
+      .map(|p| p.name.clone())
+      .unwrap_or_default()
+  }
+
+  fn change_selection(&mut self, user_id: i32, selection: Selection, ctx: &mut Context<Self>) {
+    if !self.user_to_sheet.contains_key(&user_id) {
+      return;
+    }
+    let state = self
+      .user_to_selection
+      .entry(user_id)
+      .or_insert(SelectionState {
+        selection,
+        last_sent: None,
+        pending: false,
+      });
+    state.selection = selection;
+    if state.pending {
+      // Already scheduled, it will pick up the latest selection.
+      return;
+    }
+    let wait = state.last_sent.map_or(Duration::from_secs(0), |t| {
+      SELECTION_THROTTLE.saturating_sub(t.elapsed())
+    });
+    if wait == Duration::from_secs(0) {
+      self.flush_selection(user_id);
+    } else {
+      state.pending = true;
+      ctx.run_later(wait, move |act, _| act.flush_selection(user_id));
+    }
+  }
+
+  fn flush_selection(&mut self, user_id: i32) {
+    let (sheet_id, state) = match (
+      self.user_to_sheet.get(&user_id),
+      self.user_to_selection.get_mut(&user_id),
+    ) {
+      (Some(sheet_id), Some(state)) => (*sheet_id, state),
+      // The user left before the throttled selection went out.
+      _ => return,
+    };
+    state.pending = false;
+    state.last_sent = Some(Instant::now());
+    let resp = Response::SelectionChanged {
+      user_id,
+      selection: state.selection,
+    };
+    self.broadcast_others(sheet_id, user_id, resp);
+  }
+
+  fn new_participant(&mut self, user_id: i32, sheet_id: i32, name: Option<String>) -> Participant {
+    let name = name
+      .map(|n| n.trim().chars().take(MAX_NAME_LEN).collect::<String>())
+      .filter(|n| !n.is_empty())
+      .unwrap_or_else(|| {
+        self.next_guest += 1;
+        format!("Guest {}", self.next_guest - 1)
+      });
+
+    // Prefer a color nobody else in the sheet is using.
+    let taken: HashSet<&str> = self
+      .sheet_to_users
+      .get(&sheet_id)
+      .into_iter()
+      .flatten()
+      .filter_map(|id| self.user_to_participant.get(id))
+      .map(|p| p.color.as_str())
+      .collect();
+    let color = COLORS
+      .iter()
+      .find(|c| !taken.contains(*c))
+      .unwrap_or(&COLORS[user_id.rem_euclid(COLORS.len() as i32) as usize]);
+
+    Participant {
+      id: user_id,
+      name,
+      color: color.to_string(),
+    }
+  }
+
+  fn subscribe(&mut self, user_id: i32, viewport: Rect) {
+    let (subscription, sheet_id) = match (
+      self.user_to_subscription.get_mut(&user_id),
+      self.user_to_sheet.get(&user_id),
+    ) {
+      (Some(subscription), Some(sheet_id)) => (subscription, *sheet_id),
+      _ => {
+        let resp = Response::Error {
+          message: "Subscribe requires the viewport capability".to_string(),
+        };
+        self.send(user_id, resp);
+        return;
+      }
+    };
+    if let Err(message) = check_viewport(viewport) {
+      self.send(user_id, Response::Error { message });
+      return;
+    }
+    let resp = match subscription.subscribe(self.store.as_mut(), sheet_id, viewport) {
+      Ok(cells) => Response::CellsLoaded { cells },
+      Err(e) => Response::Error {
+        message: format!("failed to load viewport {:?}: {}", viewport, e),
+      },
+    };
+    self.send(user_id, resp);
+  }
+
+  fn update_cell(&mut self, user_id: i32, sheet_id: i32, row: i32, col: i32, raw: String) {
     let new_cell = NewCell {
       sheet_id,
       row,
       col,
       raw,
+      format: None,
     };
-    match diesel::insert_into(cells::table)
-      .values(&new_cell)
-      .on_conflict((cells::sheet_id, cells::row, cells::col))
-      .do_update()
-      .set(&new_cell)
-      .get_result(&self.db)
-    {
-      Ok(cell) => {
-        let resp = Response::CellUpdated { user_id, cell };
-        self.broadcast(sheet_id, resp);
+    let description = format!("{:?}", new_cell);
+    match self.store.upsert_cell(new_cell) {
+      Ok(cell) => self.broadcast_cell(sheet_id, user_id, cell),
+      Err(e) => {
+        let resp = Response::Error {
+          message: format!("failed to update cell {}: {}", description, e),
+        };
+        self.send(user_id, resp);
       }
-      Err(_) => {
+    };
+  }
+
+  fn set_format(&mut self, user_id: i32, row: i32, col: i32, format: String) {
+    let sheet_id = match self.user_to_sheet.get(&user_id) {
+      Some(sheet_id) => *sheet_id,
+      None => return,
+    };
+    let res = if row < 0 || col < 0 {
+      Err(format!("invalid cell ({}, {})", row, col))
+    } else {
+      check_format(&format)
+    };
+    if let Err(message) = res {
+      self.send(user_id, Response::Error { message });
+      return;
+    }
+    match self.store.set_format(sheet_id, row, col, &format) {
+      Ok(cell) => self.broadcast_cell(sheet_id, user_id, cell),
+      Err(e) => {
         let resp = Response::Error {
-          message: format!("failed to update cell {:?}", new_cell),
+          message: format!("failed to set format of ({}, {}): {}", row, col, e),
         };
         self.send(user_id, resp);
       }
@@ -122,13 +1117,78 @@ impl WsServer {
 
   fn broadcast_participants(&self, sheet_id: i32) {
     if let Some(user_ids) = self.sheet_to_users.get(&sheet_id) {
+      let participants = user_ids
+        .iter()
+        .filter_map(|id| self.user_to_participant.get(id))
+        .cloned()
+        .collect();
       let resp = Response::Participants {
         ids: user_ids.clone(),
+        participants,
       };
       self.broadcast(sheet_id, resp);
     }
   }
 
+  // Like `broadcast`, but skips users whose viewport doesn't cover the cell.
+  fn broadcast_cell(&mut self, sheet_id: i32, user_id: i32, cell: Cell) {
+    let user_ids = match self.sheet_to_users.get(&sheet_id) {
+      Some(ids) => ids.clone(),
+      None => return,
+    };
+    for id in user_ids {
+      if let Some(subscription) = self.user_to_subscription.get_mut(&id) {
+        if !subscription.contains(cell.row, cell.col) {
+          continue;
+        }
+        // Make sure the client can evaluate the cell before it gets it.
+        match subscription.follow(self.store.as_mut(), sheet_id, std::slice::from_ref(&cell)) {
+          Ok(cells) if cells.is_empty() => (),
+          Ok(cells) => self.send(id, Response::CellsLoaded { cells }),
+          Err(e) => println!("failed to load precedents of {:?}: {}", cell, e),
+        }
+      }
+      let resp = Response::CellUpdated {
+        user_id,
+        cell: cell.clone(),
+      };
+      self.send(id, resp);
+    }
+  }
+
+  // Like `broadcast_cell` for cells updated together.
+  fn broadcast_cells(&mut self, sheet_id: i32, user_id: i32, cells: Vec<Cell>) {
+    let user_ids = match self.sheet_to_users.get(&sheet_id) {
+      Some(ids) => ids.clone(),
+      None => return,
+    };
+    for id in user_ids {
+      let visible = match self.user_to_subscription.get_mut(&id) {
+        Some(subscription) => {
+          let visible: Vec<Cell> = cells
+            .iter()
+            .filter(|cell| subscription.contains(cell.row, cell.col))
+            .cloned()
+            .collect();
+          match subscription.follow(self.store.as_mut(), sheet_id, &visible) {
+            Ok(loaded) if loaded.is_empty() => (),
+            Ok(loaded) => self.send(id, Response::CellsLoaded { cells: loaded }),
+            Err(e) => println!("failed to load precedents of {:?}: {}", visible, e),
+          }
+          visible
+        }
+        None => cells.clone(),
+      };
+      if !visible.is_empty() {
+        let resp = Response::CellsUpdated {
+          user_id,
+          cells: visible,
+        };
+        self.send(id, resp);
+      }
+    }
+  }
+
   fn send(&self, user_id: i32, response: Response) {
     println!("sending response {:?} to user {}", response, user_id);
     match self.user_to_addr.get(&user_id) {
@@ -139,6 +1199,14 @@ impl WsServer {
     };
   }
 
+  fn broadcast_others(&self, sheet_id: i32, user_id: i32, response: Response) {
+    if let Some(user_ids) = self.sheet_to_users.get(&sheet_id) {
+      for id in user_ids.iter().filter(|id| **id != user_id) {
+        self.send(*id, response.clone());
+      }
+    }
+  }
+
   fn broadcast(&self, sheet_id: i32, response: Response) {
     println!("broadcasting response {:?} to sheet {}", response, sheet_id);
     let user_ids = match self.sheet_to_users.get(&sheet_id) {
@@ -162,6 +1230,149 @@ impl WsServer {
   }
 }
 
+fn check_name(name: &str) -> Result<(), String> {
+  if names::is_valid_name(name) {
+    Ok(())
+  } else {
+    Err(format!(
+      "{:?} isn't a valid name, names start with a letter or '_' and can't look like a cell",
+      name
+    ))
+  }
+}
+
+// Formats are rendered by the clients, a code they can't parse would break
+// every one of them. Same rules as the engine's `Format::parse`.
+fn check_format(format: &str) -> Result<(), String> {
+  if format.chars().count() > MAX_FORMAT_LEN {
+    return Err(format!(
+      "formats can't be longer than {} characters",
+      MAX_FORMAT_LEN
+    ));
+  }
+  let mut sections = 1;
+  let mut chars = format.chars();
+  while let Some(c) = chars.next() {
+    let closed = match c {
+      ';' => {
+        sections += 1;
+        true
+      }
+      '"' => chars.any(|c| c == '"'),
+      '[' => chars.any(|c| c == ']'),
+      '\\' => chars.next().is_some(),
+      _ => true,
+    };
+    if !closed {
+      return Err(format!("{:?} isn't a valid format", format));
+    }
+  }
+  if sections > 4 {
+    return Err(format!("{:?} has more than 4 sections", format));
+  }
+  Ok(())
+}
+
+fn check_definition(name: &str, definition: &str) -> Result<(), String> {
+  check_name(name)?;
+  let definition = definition.trim();
+  if definition.is_empty() || definition.chars().count() > MAX_DEFINITION_LEN {
+    return Err(format!(
+      "definitions must have between 1 and {} characters",
+      MAX_DEFINITION_LEN
+    ));
+  }
+  Ok(())
+}
+
+// Where each cell of `range` goes when its rows are put in `order`, the row
+// that ends up first coming first. Cells that stay are left out.
+fn row_moves(range: Rect, order: &[i32]) -> Result<Vec<CellMove>, String> {
+  let mut rows = order.to_vec();
+  rows.sort_unstable();
+  if range.top < 0
+    || range.left < 0
+    || range.left > range.right
+    || !rows.into_iter().eq(range.top..=range.bottom)
+  {
+    return Err(format!(
+      "{:?} does not order the rows of {:?}",
+      order, range
+    ));
+  }
+  let rows = range.bottom as i64 - range.top as i64 + 1;
+  let cols = range.right as i64 - range.left as i64 + 1;
+  if rows * cols > MAX_BATCH_LEN as i64 {
+    return Err(format!(
+      "can't sort more than {} cells at once",
+      MAX_BATCH_LEN
+    ));
+  }
+  let mut moves = vec![];
+  for (to, from) in (range.top..).zip(order) {
+    if *from != to {
+      for col in range.left..=range.right {
+        moves.push(((*from, col), (to, col)));
+      }
+    }
+  }
+  Ok(moves)
+}
+
+fn check_viewport(viewport: Rect) -> Result<(), String> {
+  if viewport.top < 0
+    || viewport.left < 0
+    || viewport.top > viewport.bottom
+    || viewport.left > viewport.right
+  {
+    return Err(format!("invalid viewport {:?}", viewport));
+  }
+  let rows = viewport.bottom as i64 - viewport.top as i64 + 1;
+  let cols = viewport.right as i64 - viewport.left as i64 + 1;
+  if rows * cols > viewport::MAX_VIEWPORT_CELLS {
+    return Err(format!(
+      "a viewport can't cover more than {} cells",
+      viewport::MAX_VIEWPORT_CELLS
+    ));
+  }
+  Ok(())
+}
+
+fn check_iteration(iteration: &Iteration) -> Result<(), String> {
+  if iteration.max_iterations < 1 || iteration.max_iterations > MAX_ITERATIONS {
+    return Err(format!(
+      "max iterations must be between 1 and {}",
+      MAX_ITERATIONS
+    ));
+  }
+  if !iteration.tolerance.is_finite() || iteration.tolerance < 0. {
+    return Err(format!("invalid tolerance {}", iteration.tolerance));
+  }
+  Ok(())
+}
+
+fn check_filter(filter: &Filter) -> Result<(), String> {
+  let range = filter.range;
+  if range.top < 0 || range.left < 0 || range.top > range.bottom || range.left > range.right {
+    return Err(format!("invalid filter range {:?}", range));
+  }
+  for condition in &filter.conditions {
+    if condition.col < range.left || condition.col > range.right {
+      return Err(format!(
+        "column {} is not part of the filter",
+        condition.col
+      ));
+    }
+    if condition.criterion.chars().count() > MAX_CRITERION_LEN {
+      return Err(format!(
+        "criteria can't be longer than {} characters",
+        MAX_CRITERION_LEN
+      ));
+    }
+  }
+  Ok(())
+}
+
 impl Actor for WsServer {
   type Context = Context<Self>;
 }
@@ -174,6 +1385,8 @@ impl Handler<Connect> for WsServer {
 
     // register session with random id
     let new_user_id = self.rng.gen::<i32>();
+    let participant = self.new_participant(new_user_id, msg.sheet_id, msg.name);
+    self.user_to_participant.insert(new_user_id, participant);
     self.user_to_addr.insert(new_user_id, msg.addr);
     self.user_to_sheet.insert(new_user_id, msg.sheet_id);
 
@@ -181,20 +1394,42 @@ impl Handler<Connect> for WsServer {
     self
       .sheet_to_users
       .entry(msg.sheet_id)
-      .or_insert_with(HashSet::new)
+      .or_default()
       .insert(new_user_id);
 
+    // Clients showing a viewport load their cells once they subscribe
+    let cells = if msg.capabilities.iter().any(|c| c == viewport::VIEWPORT) {
+      self
+        .user_to_subscription
+        .insert(new_user_id, Subscription::new());
+      Ok(vec![])
+    } else {
+      self.store.load_sheet(msg.sheet_id)
+    };
+
+    // Newcomers evaluate volatile functions like everyone already there
+    let recalculation = match self.sheet_to_recalculation.get(&msg.sheet_id) {
+      Some(recalculation) => *recalculation,
+      None => {
+        let recalculation = self.new_recalculation();
+        self
+          .sheet_to_recalculation
+          .insert(msg.sheet_id, recalculation);
+        recalculation
+      }
+    };
+
     // Get cells from spreadsheet in DB
-    match cells::table
-      .filter(cells::sheet_id.eq(msg.sheet_id as i32))
-      .load::<Cell>(&self.db)
-    {
-      Ok(cells) => {
+    let iteration = self.store.get_iteration(msg.sheet_id);
+    match cells.and_then(|cells| Ok((cells, iteration?))) {
+      Ok((cells, iteration)) => {
         self.send(
           new_user_id,
           Response::Connected {
             user_id: new_user_id,
             cells,
+            recalculation,
+            iteration,
           },
         );
       }
@@ -210,9 +1445,51 @@ impl Handler<Connect> for WsServer {
       }
     };
 
+    match self.store.list_comments(msg.sheet_id) {
+      Ok(comments) => self.send(new_user_id, Response::Comments { comments }),
+      Err(e) => self.send(
+        new_user_id,
+        Response::Error {
+          message: format!("failed to load comments: {}", e),
+        },
+      ),
+    };
+
+    match self.store.list_names(msg.sheet_id) {
+      Ok(names) => self.send(new_user_id, Response::Names { names }),
+      Err(e) => self.send(
+        new_user_id,
+        Response::Error {
+          message: format!("failed to load names: {}", e),
+        },
+      ),
+    };
+
+    match self.store.get_filter(msg.sheet_id) {
+      Ok(Some(filter)) => self.send(new_user_id, Response::Filter { filter }),
+      Ok(None) => (),
+      Err(e) => self.send(
+        new_user_id,
+        Response::Error {
+          message: format!("failed to load filter: {}", e),
+        },
+      ),
+    };
+
     // Announce to other users that are connected to this spreadsheet someone else joined
     self.broadcast_participants(msg.sheet_id);
 
+    // Let the newcomer know where everyone else is
+    for (id, state) in &self.user_to_selection {
+      if self.user_to_sheet.get(id) == Some(&msg.sheet_id) {
+        let resp = Response::SelectionChanged {
+          user_id: *id,
+          selection: state.selection,
+        };
+        self.send(new_user_id, resp);
+      }
+    }
+
     new_user_id
   }
 }
@@ -224,6 +1501,9 @@ impl Handler<Disconnect> for WsServer {
     println!("{} disconnected", msg.user_id);
 
     self.user_to_addr.remove(&msg.user_id);
+    self.user_to_subscription.remove(&msg.user_id);
+    self.user_to_participant.remove(&msg.user_id);
+    let had_selection = self.user_to_selection.remove(&msg.user_id).is_some();
 
     let sheet_id = match self.user_to_sheet.remove(&msg.user_id) {
       Some(sheet_id) => sheet_id,
@@ -244,26 +1524,23 @@ impl Handler<Disconnect> for WsServer {
     if sheet_users.is_empty() {
       // Prevent memory leak, remove entry once all sessions are closed
       self.sheet_to_users.remove(&sheet_id);
+      self.sheet_to_recalculation.remove(&sheet_id);
     }
 
+    if had_selection {
+      let resp = Response::SelectionCleared {
+        user_id: msg.user_id,
+      };
+      self.broadcast(sheet_id, resp);
+    }
     self.broadcast_participants(sheet_id);
   }
 }
 
-impl Handler<Text> for WsServer {
+impl Handler<ClientRequest> for WsServer {
   type Result = ();
 
-  fn handle(&mut self, msg: Text, _: &mut Context<Self>) {
-    let req: Request = match serde_json::from_str(&msg.data) {
-      Ok(req) => req,
-      Err(_) => {
-        let resp = Response::Error {
-          message: format!("unable to parse request {:?}", msg.data),
-        };
-        self.send(msg.user_id, resp);
-        return;
-      }
-    };
-    self.handle_req(req);
+  fn handle(&mut self, msg: ClientRequest, ctx: &mut Context<Self>) {
+    self.handle_req(msg.user_id, msg.req, ctx);
   }
 }
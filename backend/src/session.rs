@@ -0,0 +1,233 @@
+//! `WsSession` is the actor handling a single websocket connection. It
+//! forwards client requests to `WsServer` and writes its responses back.
+
+use std::time::{Duration, Instant};
+
+use super::codec::{self, Encoding};
+use super::server;
+use actix::prelude::*;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+
+/// How often heartbeat pings are sent
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long before lack of client response causes a timeout
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long clients have to send `Hello` after opening the connection
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn ws_index(
+    r: HttpRequest,
+    stream: web::Payload,
+    srv: web::Data<Addr<server::WsServer>>,
+) -> Result<HttpResponse, Error> {
+    println!("{:?}", r);
+    let res = ws::start(
+        WsSession {
+            id: 0,
+            joined: false,
+            encoding: Encoding::Json,
+            hb: Instant::now(),
+            addr: srv.get_ref().clone(),
+        },
+        &r,
+        stream,
+    );
+    println!("{:?}", res);
+    res
+}
+
+struct WsSession {
+    id: i32,
+    /// Whether the handshake completed and the server assigned us an `id`.
+    joined: bool,
+    /// How responses are written, negotiated during the handshake.
+    encoding: Encoding,
+    /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
+    /// otherwise we drop connection.
+    hb: Instant,
+    // Spreadsheet server
+    addr: Addr<server::WsServer>,
+}
+
+impl Actor for WsSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.hb(ctx);
+
+        ctx.run_later(HANDSHAKE_TIMEOUT, |act, ctx| {
+            if !act.joined {
+                act.reject("handshake timed out, expected Hello".to_string(), ctx);
+            }
+        });
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if self.joined {
+            self.addr.do_send(server::Disconnect { user_id: self.id });
+        }
+        Running::Stop
+    }
+}
+
+impl Handler<server::Response> for WsSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: server::Response, ctx: &mut Self::Context) {
+        self.respond(&msg, ctx);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.hb = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.hb = Instant::now();
+            }
+            Ok(ws::Message::Text(text)) if !self.joined => self.handshake(&text, ctx),
+            Ok(ws::Message::Text(text)) => {
+                let req = codec::decode_text(&text);
+                self.forward(req, &text, ctx);
+            }
+            Ok(ws::Message::Binary(_)) if !self.joined => {
+                self.reject("expected Hello, got a binary message".to_string(), ctx);
+            }
+            Ok(ws::Message::Binary(bin)) => {
+                let req = codec::decode_binary(&bin);
+                self.forward(req, &bin, ctx);
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => ctx.stop(),
+        }
+    }
+}
+
+impl WsSession {
+    fn respond(&self, resp: &server::Response, ctx: &mut <Self as Actor>::Context) {
+        match self.encoding.encode(resp) {
+            codec::Frame::Text(text) => ctx.text(text),
+            codec::Frame::Binary(bin) => ctx.binary(bin),
+        }
+    }
+
+    fn forward<T: std::fmt::Debug + ?Sized>(
+        &self,
+        req: Result<server::Request, String>,
+        raw: &T,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        match req {
+            Ok(req) => self.addr.do_send(server::ClientRequest {
+                user_id: self.id,
+                req,
+            }),
+            Err(e) => {
+                let resp = server::Response::Error {
+                    message: format!("unable to parse request {:?}: {}", raw, e),
+                };
+                self.respond(&resp, ctx);
+            }
+        }
+    }
+
+    fn handshake(&mut self, text: &str, ctx: &mut <Self as Actor>::Context) {
+        let (protocol_version, capabilities, name) = match codec::decode_text(text) {
+            Ok(server::Request::Hello {
+                protocol_version,
+                capabilities,
+                name,
+            }) => (protocol_version, capabilities, name),
+            _ => {
+                self.reject(format!("expected Hello, got {:?}", text), ctx);
+                return;
+            }
+        };
+
+        let resp = server::handshake(protocol_version, &capabilities);
+        // The handshake reply always goes out as JSON, the negotiated
+        // encoding only applies from then on.
+        self.respond(&resp, ctx);
+        match resp {
+            server::Response::Rejected { reason, .. } => self.close(reason, ctx),
+            server::Response::Welcome { capabilities, .. } => {
+                self.encoding = Encoding::negotiate(&capabilities);
+                self.join(capabilities, name, ctx);
+            }
+            _ => unreachable!("handshake only returns Welcome or Rejected"),
+        }
+    }
+
+    fn join(
+        &mut self,
+        capabilities: Vec<String>,
+        name: Option<String>,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        let sheet_id = 1;
+        let addr = ctx.address().recipient();
+        self.addr
+            .send(server::Connect {
+                sheet_id,
+                addr,
+                capabilities,
+                name,
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(res) => {
+                        act.id = res;
+                        act.joined = true;
+                    }
+                    _ => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn reject(&mut self, reason: String, ctx: &mut <Self as Actor>::Context) {
+        let resp = server::Response::Rejected {
+            reason: reason.clone(),
+            min_protocol_version: server::MIN_PROTOCOL_VERSION,
+            max_protocol_version: server::PROTOCOL_VERSION,
+        };
+        self.respond(&resp, ctx);
+        self.close(reason, ctx);
+    }
+
+    fn close(&mut self, reason: String, ctx: &mut <Self as Actor>::Context) {
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Protocol,
+            description: Some(reason),
+        }));
+        ctx.stop();
+    }
+
+    // sends ping to client every second to check if alive.
+    fn hb(&self, ctx: &mut <Self as Actor>::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+                println!("Websocket Client heartbeat failed, disconnecting!");
+                act.addr.do_send(server::Disconnect { user_id: act.id });
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+}
+
+/// Registers the websocket route. Expects `web::Data<Addr<server::WsServer>>`
+/// to be set on the app.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/ws/").route(web::get().to(ws_index)));
+}
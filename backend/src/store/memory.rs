@@ -0,0 +1,250 @@
+use super::{moved_comments, CellMove, SheetStore, StoreError, StoreResult};
+use crate::models::{
+  Cell, Comment, Filter, Iteration, Name, NewCell, NewComment, NewName, Rect, Revision,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+/// Keeps everything in process memory. Data is lost on restart.
+#[derive(Default)]
+pub struct MemoryStore {
+  // (Sheet ID, row, col) -> Cell
+  cells: BTreeMap<(i32, i32, i32), Cell>,
+  revisions: Vec<Revision>,
+  // Comment ID - 1 -> Comment
+  comments: Vec<Comment>,
+  names: Vec<Name>,
+  // Sheet ID -> Filter
+  filters: HashMap<i32, Filter>,
+  // Sheet ID -> Iteration
+  iterations: HashMap<i32, Iteration>,
+  next_cell_id: i32,
+  next_name_id: i32,
+}
+
+impl MemoryStore {
+  pub fn new() -> MemoryStore {
+    Default::default()
+  }
+
+  fn comment_mut(&mut self, comment_id: i32) -> StoreResult<&mut Comment> {
+    // Ids come from clients, they may be anything.
+    let comments = &mut self.comments;
+    usize::try_from(comment_id)
+      .ok()
+      .and_then(|id| id.checked_sub(1))
+      .and_then(move |index| comments.get_mut(index))
+      .ok_or_else(|| StoreError(format!("comment {} not found", comment_id)))
+  }
+
+  // Same as the unique index of the databases, names ignore case.
+  fn check_name_free(&self, sheet_id: i32, name_id: Option<i32>, name: &str) -> StoreResult<()> {
+    let taken = self.names.iter().any(|n| {
+      n.sheet_id == sheet_id && Some(n.id) != name_id && n.name.eq_ignore_ascii_case(name)
+    });
+    if taken {
+      return Err(StoreError(format!("{} is already defined", name)));
+    }
+    Ok(())
+  }
+}
+
+impl SheetStore for MemoryStore {
+  fn load_sheet(&mut self, sheet_id: i32) -> StoreResult<Vec<Cell>> {
+    let cells = self
+      .cells
+      .range((sheet_id, i32::MIN, i32::MIN)..=(sheet_id, i32::MAX, i32::MAX))
+      .map(|(_, cell)| cell.clone())
+      .collect();
+    Ok(cells)
+  }
+
+  fn load_range(&mut self, sheet_id: i32, rect: Rect) -> StoreResult<Vec<Cell>> {
+    let cells = self
+      .cells
+      .range((sheet_id, rect.top, i32::MIN)..=(sheet_id, rect.bottom, i32::MAX))
+      .filter(|(_, cell)| rect.contains(cell.row, cell.col))
+      .map(|(_, cell)| cell.clone())
+      .collect();
+    Ok(cells)
+  }
+
+  fn upsert_cell(&mut self, cell: NewCell) -> StoreResult<Cell> {
+    self.revisions.push(Revision {
+      id: self.revisions.len() as i32 + 1,
+      sheet_id: cell.sheet_id,
+      row: cell.row,
+      col: cell.col,
+      raw: cell.raw.clone(),
+    });
+
+    let next_cell_id = &mut self.next_cell_id;
+    let stored = self
+      .cells
+      .entry((cell.sheet_id, cell.row, cell.col))
+      .or_insert_with(|| {
+        *next_cell_id += 1;
+        Cell {
+          id: *next_cell_id,
+          sheet_id: cell.sheet_id,
+          row: cell.row,
+          col: cell.col,
+          raw: String::new(),
+          format: String::new(),
+        }
+      });
+    stored.raw = cell.raw;
+    if let Some(format) = cell.format {
+      stored.format = format;
+    }
+    Ok(stored.clone())
+  }
+
+  fn upsert_cells(&mut self, cells: Vec<NewCell>) -> StoreResult<Vec<Cell>> {
+    cells.into_iter().map(|cell| self.upsert_cell(cell)).collect()
+  }
+
+  fn set_format(&mut self, sheet_id: i32, row: i32, col: i32, format: &str) -> StoreResult<Cell> {
+    let next_cell_id = &mut self.next_cell_id;
+    let stored = self.cells.entry((sheet_id, row, col)).or_insert_with(|| {
+      *next_cell_id += 1;
+      Cell {
+        id: *next_cell_id,
+        sheet_id,
+        row,
+        col,
+        raw: String::new(),
+        format: String::new(),
+      }
+    });
+    stored.format = format.to_string();
+    Ok(stored.clone())
+  }
+
+  fn list_revisions(&mut self, sheet_id: i32) -> StoreResult<Vec<Revision>> {
+    let revisions = self
+      .revisions
+      .iter()
+      .filter(|r| r.sheet_id == sheet_id)
+      .cloned()
+      .collect();
+    Ok(revisions)
+  }
+
+  fn list_comments(&mut self, sheet_id: i32) -> StoreResult<Vec<Comment>> {
+    let comments = self
+      .comments
+      .iter()
+      .filter(|c| c.sheet_id == sheet_id)
+      .cloned()
+      .collect();
+    Ok(comments)
+  }
+
+  fn get_comment(&mut self, comment_id: i32) -> StoreResult<Option<Comment>> {
+    Ok(self.comment_mut(comment_id).ok().cloned())
+  }
+
+  fn add_comment(&mut self, comment: NewComment) -> StoreResult<Comment> {
+    let comment = Comment {
+      id: self.comments.len() as i32 + 1,
+      sheet_id: comment.sheet_id,
+      row: comment.row,
+      col: comment.col,
+      parent_id: comment.parent_id,
+      author: comment.author,
+      body: comment.body,
+      resolved: false,
+    };
+    self.comments.push(comment.clone());
+    Ok(comment)
+  }
+
+  fn edit_comment(&mut self, comment_id: i32, body: &str) -> StoreResult<Comment> {
+    let comment = self.comment_mut(comment_id)?;
+    comment.body = body.to_string();
+    Ok(comment.clone())
+  }
+
+  fn resolve_comment(&mut self, comment_id: i32, resolved: bool) -> StoreResult<Comment> {
+    let comment = self.comment_mut(comment_id)?;
+    comment.resolved = resolved;
+    Ok(comment.clone())
+  }
+
+  fn move_comments(&mut self, sheet_id: i32, moves: &[CellMove]) -> StoreResult<()> {
+    let comments = self.list_comments(sheet_id)?;
+    for (id, row, col) in moved_comments(&comments, moves) {
+      let comment = self.comment_mut(id)?;
+      comment.row = row;
+      comment.col = col;
+    }
+    Ok(())
+  }
+
+  fn list_names(&mut self, sheet_id: i32) -> StoreResult<Vec<Name>> {
+    let names = self
+      .names
+      .iter()
+      .filter(|n| n.sheet_id == sheet_id)
+      .cloned()
+      .collect();
+    Ok(names)
+  }
+
+  fn add_name(&mut self, name: NewName) -> StoreResult<Name> {
+    self.check_name_free(name.sheet_id, None, &name.name)?;
+    self.next_name_id += 1;
+    let name = Name {
+      id: self.next_name_id,
+      sheet_id: name.sheet_id,
+      name: name.name,
+      definition: name.definition,
+    };
+    self.names.push(name.clone());
+    Ok(name)
+  }
+
+  fn update_name(&mut self, name_id: i32, name: &str, definition: &str) -> StoreResult<Name> {
+    if let Some(sheet_id) = self.names.iter().find(|n| n.id == name_id).map(|n| n.sheet_id) {
+      self.check_name_free(sheet_id, Some(name_id), name)?;
+    }
+    let stored = self
+      .names
+      .iter_mut()
+      .find(|n| n.id == name_id)
+      .ok_or_else(|| StoreError(format!("name {} not found", name_id)))?;
+    stored.name = name.to_string();
+    stored.definition = definition.to_string();
+    Ok(stored.clone())
+  }
+
+  fn delete_name(&mut self, name_id: i32) -> StoreResult<()> {
+    self.names.retain(|n| n.id != name_id);
+    Ok(())
+  }
+
+  fn get_filter(&mut self, sheet_id: i32) -> StoreResult<Option<Filter>> {
+    Ok(self.filters.get(&sheet_id).cloned())
+  }
+
+  fn set_filter(&mut self, sheet_id: i32, filter: Option<&Filter>) -> StoreResult<()> {
+    match filter {
+      Some(filter) => self.filters.insert(sheet_id, filter.clone()),
+      None => self.filters.remove(&sheet_id),
+    };
+    Ok(())
+  }
+
+  fn get_iteration(&mut self, sheet_id: i32) -> StoreResult<Option<Iteration>> {
+    Ok(self.iterations.get(&sheet_id).copied())
+  }
+
+  fn set_iteration(&mut self, sheet_id: i32, iteration: Option<Iteration>) -> StoreResult<()> {
+    match iteration {
+      Some(iteration) => self.iterations.insert(sheet_id, iteration),
+      None => self.iterations.remove(&sheet_id),
+    };
+    Ok(())
+  }
+}
@@ -0,0 +1,363 @@
+//! Persistence layer for spreadsheets. `WsServer` only talks to a
+//! `SheetStore`, so the same server can run on top of Postgres, an embedded
+//! SQLite file, or plain memory (handy for tests and local hacking).
+
+mod memory;
+mod pg;
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use pg::PgStore;
+pub use sqlite::SqliteStore;
+
+use super::models::{
+  Cell, Comment, Filter, Iteration, Name, NewCell, NewComment, NewName, Rect, Revision,
+};
+use std::fmt;
+
+#[derive(Debug)]
+pub struct StoreError(String);
+
+impl fmt::Display for StoreError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl From<diesel::result::Error> for StoreError {
+  fn from(e: diesel::result::Error) -> Self {
+    StoreError(e.to_string())
+  }
+}
+
+impl From<diesel::ConnectionError> for StoreError {
+  fn from(e: diesel::ConnectionError) -> Self {
+    StoreError(e.to_string())
+  }
+}
+
+impl From<diesel_migrations::RunMigrationsError> for StoreError {
+  fn from(e: diesel_migrations::RunMigrationsError) -> Self {
+    StoreError(e.to_string())
+  }
+}
+
+impl From<serde_json::Error> for StoreError {
+  fn from(e: serde_json::Error) -> Self {
+    StoreError(e.to_string())
+  }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+pub trait SheetStore {
+  /// Returns every non-empty cell of the sheet.
+  fn load_sheet(&mut self, sheet_id: i32) -> StoreResult<Vec<Cell>>;
+
+  /// Returns the non-empty cells of the sheet that fall within `rect`.
+  fn load_range(&mut self, sheet_id: i32, rect: Rect) -> StoreResult<Vec<Cell>>;
+
+  /// Inserts or overwrites the cell at `(sheet_id, row, col)` and records
+  /// the change as a new revision.
+  fn upsert_cell(&mut self, cell: NewCell) -> StoreResult<Cell>;
+
+  /// Like `upsert_cell` for several cells at once, either all of them are
+  /// stored or none. Returns them in the same order.
+  fn upsert_cells(&mut self, cells: Vec<NewCell>) -> StoreResult<Vec<Cell>>;
+
+  /// Sets the number format of the cell at `(sheet_id, row, col)`, creating
+  /// an empty cell if there is none.
+  fn set_format(&mut self, sheet_id: i32, row: i32, col: i32, format: &str) -> StoreResult<Cell>;
+
+  /// Returns every change made to the sheet, oldest first.
+  fn list_revisions(&mut self, sheet_id: i32) -> StoreResult<Vec<Revision>>;
+
+  /// Returns every comment of the sheet, oldest first.
+  fn list_comments(&mut self, sheet_id: i32) -> StoreResult<Vec<Comment>>;
+
+  fn get_comment(&mut self, comment_id: i32) -> StoreResult<Option<Comment>>;
+
+  fn add_comment(&mut self, comment: NewComment) -> StoreResult<Comment>;
+
+  fn edit_comment(&mut self, comment_id: i32, body: &str) -> StoreResult<Comment>;
+
+  fn resolve_comment(&mut self, comment_id: i32, resolved: bool) -> StoreResult<Comment>;
+
+  /// Makes comments follow their cells when rows or columns move. Each
+  /// `(from, to)` pair moves the comments at `from` to `to`, all at once, so
+  /// swapping two cells swaps their comments.
+  fn move_comments(&mut self, sheet_id: i32, moves: &[CellMove]) -> StoreResult<()>;
+
+  /// Returns every name defined in the sheet, oldest first.
+  fn list_names(&mut self, sheet_id: i32) -> StoreResult<Vec<Name>>;
+
+  /// Fails if the sheet has a name spelled the same, ignoring case.
+  fn add_name(&mut self, name: NewName) -> StoreResult<Name>;
+
+  /// Renames and/or redefines a name. Like `add_name`, names are unique
+  /// within a sheet ignoring case.
+  fn update_name(&mut self, name_id: i32, name: &str, definition: &str) -> StoreResult<Name>;
+
+  fn delete_name(&mut self, name_id: i32) -> StoreResult<()>;
+
+  fn get_filter(&mut self, sheet_id: i32) -> StoreResult<Option<Filter>>;
+
+  /// Replaces the filter view of the sheet, or removes it with `None`.
+  fn set_filter(&mut self, sheet_id: i32, filter: Option<&Filter>) -> StoreResult<()>;
+
+  /// `None` while iterative calculation is off, the default.
+  fn get_iteration(&mut self, sheet_id: i32) -> StoreResult<Option<Iteration>>;
+
+  /// Replaces the iterative calculation settings of the sheet, or turns it
+  /// off with `None`.
+  fn set_iteration(&mut self, sheet_id: i32, iteration: Option<Iteration>) -> StoreResult<()>;
+}
+
+/// `(row, col)` a cell moves from, and where it ends up.
+pub type CellMove = ((i32, i32), (i32, i32));
+
+// Shared by the stores to work out where each comment lands, returns
+// `(comment id, row, col)` for the comments that move.
+fn moved_comments(comments: &[Comment], moves: &[CellMove]) -> Vec<(i32, i32, i32)> {
+  let moves: std::collections::HashMap<_, _> = moves.iter().cloned().collect();
+  comments
+    .iter()
+    .filter_map(|c| {
+      let (row, col) = moves.get(&(c.row, c.col))?;
+      Some((c.id, *row, *col))
+    })
+    .collect()
+}
+
+/// Picks a store based on the scheme of `url`:
+///
+/// - `postgres://...` or `postgresql://...` connects to Postgres.
+/// - `sqlite://<path>` opens (or creates) a SQLite database at `<path>`.
+///   `sqlite://:memory:` gives a throwaway database.
+/// - `memory://` keeps everything in process memory.
+pub fn connect(url: &str) -> StoreResult<Box<dyn SheetStore>> {
+  if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+    Ok(Box::new(PgStore::connect(url)?))
+  } else if let Some(path) = url.strip_prefix("sqlite://") {
+    Ok(Box::new(SqliteStore::open(path)?))
+  } else if url.starts_with("memory://") {
+    Ok(Box::new(MemoryStore::new()))
+  } else {
+    Err(StoreError(format!("unsupported database url {}", url)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::models::FilterCondition;
+
+  fn new_cell(sheet_id: i32, row: i32, col: i32, raw: &str) -> NewCell {
+    NewCell {
+      sheet_id,
+      row,
+      col,
+      raw: raw.to_string(),
+      format: None,
+    }
+  }
+
+  // Runs the same scenario against any store so that backends can't drift apart.
+  fn check_store(store: &mut dyn SheetStore) {
+    assert!(store.load_sheet(1).unwrap().is_empty());
+
+    let first = store.upsert_cell(new_cell(1, 0, 0, "1")).unwrap();
+    store.upsert_cell(new_cell(1, 0, 1, "=A1")).unwrap();
+    store.upsert_cell(new_cell(2, 0, 0, "other sheet")).unwrap();
+    let updated = store.upsert_cell(new_cell(1, 0, 0, "2")).unwrap();
+    assert_eq!(first.id, updated.id);
+    assert_eq!(updated.raw, "2");
+
+    let mut cells = store.load_sheet(1).unwrap();
+    cells.sort_by_key(|c| (c.row, c.col));
+    let raws: Vec<&str> = cells.iter().map(|c| c.raw.as_str()).collect();
+    assert_eq!(raws, vec!["2", "=A1"]);
+
+    store.upsert_cell(new_cell(1, 5, 5, "far away")).unwrap();
+    let rect = Rect {
+      top: 0,
+      left: 1,
+      bottom: 5,
+      right: 4,
+    };
+    let cells = store.load_range(1, rect).unwrap();
+    let raws: Vec<&str> = cells.iter().map(|c| c.raw.as_str()).collect();
+    assert_eq!(raws, vec!["=A1"]);
+
+    let formatted = store.set_format(1, 0, 0, "0.00").unwrap();
+    assert_eq!((formatted.id, formatted.raw.as_str()), (first.id, "2"));
+    let created = store.set_format(1, 7, 7, "0%").unwrap();
+    assert_eq!((created.raw.as_str(), created.format.as_str()), ("", "0%"));
+    // Editing the contents keeps the format.
+    assert_eq!(
+      store.upsert_cell(new_cell(1, 0, 0, "3")).unwrap().format,
+      "0.00"
+    );
+    let cells = store.load_range(1, Rect::cell(7, 7)).unwrap();
+    assert_eq!(cells[0].format, "0%");
+
+    let revisions = store.list_revisions(1).unwrap();
+    let raws: Vec<&str> = revisions.iter().map(|r| r.raw.as_str()).collect();
+    assert_eq!(raws, vec!["1", "=A1", "2", "far away", "3"]);
+
+    let formatted = NewCell {
+      format: Some("0.0".to_string()),
+      ..new_cell(1, 0, 0, "4")
+    };
+    let batch = vec![
+      new_cell(1, 0, 1, "=A1*2"),
+      new_cell(1, 9, 0, "new"),
+      formatted,
+    ];
+    let cells = store.upsert_cells(batch).unwrap();
+    let stored: Vec<_> = cells
+      .iter()
+      .map(|c| (c.row, c.col, c.raw.as_str(), c.format.as_str()))
+      .collect();
+    assert_eq!(
+      stored,
+      vec![(0, 1, "=A1*2", ""), (9, 0, "new", ""), (0, 0, "4", "0.0")]
+    );
+    assert_eq!(store.list_revisions(1).unwrap().len(), 8);
+
+    check_comments(store);
+    check_names(store);
+    check_filters(store);
+    check_iterations(store);
+  }
+
+  fn new_comment(row: i32, col: i32, parent_id: Option<i32>, body: &str) -> NewComment {
+    NewComment {
+      sheet_id: 1,
+      row,
+      col,
+      parent_id,
+      author: "alice".to_string(),
+      body: body.to_string(),
+    }
+  }
+
+  fn check_comments(store: &mut dyn SheetStore) {
+    let first = store
+      .add_comment(new_comment(0, 0, None, "why 2?"))
+      .unwrap();
+    let reply = store
+      .add_comment(new_comment(0, 0, Some(first.id), "typo"))
+      .unwrap();
+    let other = store.add_comment(new_comment(3, 3, None, "ok")).unwrap();
+    assert_eq!(reply.parent_id, Some(first.id));
+    assert!(!first.resolved);
+
+    let edited = store.edit_comment(reply.id, "not a typo").unwrap();
+    assert_eq!(edited.body, "not a typo");
+    assert!(store.resolve_comment(first.id, true).unwrap().resolved);
+    for missing in &[12345, 0, -1, i32::MIN] {
+      assert!(store.edit_comment(*missing, "nope").is_err());
+    }
+    assert!(store.get_comment(12345).unwrap().is_none());
+
+    // Swap A1 and D4, leave everything else alone.
+    store
+      .move_comments(1, &[((0, 0), (3, 3)), ((3, 3), (0, 0))])
+      .unwrap();
+    let comments = store.list_comments(1).unwrap();
+    let positions: Vec<(i32, i32, i32)> = comments.iter().map(|c| (c.id, c.row, c.col)).collect();
+    assert_eq!(
+      positions,
+      vec![(first.id, 3, 3), (reply.id, 3, 3), (other.id, 0, 0)]
+    );
+    assert_eq!(comments[1].body, "not a typo");
+    assert!(comments[0].resolved);
+    assert!(store.list_comments(2).unwrap().is_empty());
+  }
+
+  fn new_name(sheet_id: i32, name: &str, definition: &str) -> NewName {
+    NewName {
+      sheet_id,
+      name: name.to_string(),
+      definition: definition.to_string(),
+    }
+  }
+
+  fn check_names(store: &mut dyn SheetStore) {
+    let rate = store.add_name(new_name(1, "TaxRate", "0.2")).unwrap();
+    let total = store.add_name(new_name(1, "Total", "A1")).unwrap();
+    store.add_name(new_name(2, "Total", "B1")).unwrap();
+    assert!(store.add_name(new_name(1, "TOTAL", "A2")).is_err());
+    assert!(store.update_name(rate.id, "total", "0.2").is_err());
+
+    let renamed = store.update_name(rate.id, "VAT", "0.21").unwrap();
+    assert_eq!((renamed.id, renamed.name.as_str()), (rate.id, "VAT"));
+    assert_eq!(renamed.definition, "0.21");
+    assert!(store.update_name(12345, "Nope", "1").is_err());
+
+    store.delete_name(total.id).unwrap();
+    let names = store.list_names(1).unwrap();
+    let defined: Vec<(&str, &str)> = names
+      .iter()
+      .map(|n| (n.name.as_str(), n.definition.as_str()))
+      .collect();
+    assert_eq!(defined, vec![("VAT", "0.21")]);
+    assert_eq!(store.list_names(2).unwrap().len(), 1);
+  }
+
+  fn check_filters(store: &mut dyn SheetStore) {
+    assert_eq!(store.get_filter(1).unwrap(), None);
+    let mut filter = Filter {
+      range: Rect {
+        top: 0,
+        left: 0,
+        bottom: 9,
+        right: 2,
+      },
+      conditions: vec![FilterCondition {
+        col: 1,
+        criterion: ">10".to_string(),
+      }],
+    };
+    store.set_filter(1, Some(&filter)).unwrap();
+    assert_eq!(store.get_filter(1).unwrap().as_ref(), Some(&filter));
+    filter.conditions.clear();
+    store.set_filter(1, Some(&filter)).unwrap();
+    assert_eq!(store.get_filter(1).unwrap(), Some(filter));
+    assert_eq!(store.get_filter(2).unwrap(), None);
+    store.set_filter(1, None).unwrap();
+    assert_eq!(store.get_filter(1).unwrap(), None);
+  }
+
+  fn check_iterations(store: &mut dyn SheetStore) {
+    assert_eq!(store.get_iteration(1).unwrap(), None);
+    let mut iteration = Iteration {
+      max_iterations: 100,
+      tolerance: 0.001,
+    };
+    store.set_iteration(1, Some(iteration)).unwrap();
+    iteration.max_iterations = 10;
+    store.set_iteration(1, Some(iteration)).unwrap();
+    assert_eq!(store.get_iteration(1).unwrap(), Some(iteration));
+    assert_eq!(store.get_iteration(2).unwrap(), None);
+    store.set_iteration(1, None).unwrap();
+    assert_eq!(store.get_iteration(1).unwrap(), None);
+  }
+
+  #[test]
+  fn memory_store() {
+    check_store(&mut MemoryStore::new());
+  }
+
+  #[test]
+  fn sqlite_store() {
+    check_store(&mut SqliteStore::open(":memory:").unwrap());
+  }
+
+  #[test]
+  fn connect_rejects_unknown_scheme() {
+    assert!(connect("mysql://localhost").is_err());
+    assert!(connect("memory://").is_ok());
+  }
+}
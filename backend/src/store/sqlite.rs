@@ -0,0 +1,264 @@
+use super::{moved_comments, CellMove, SheetStore, StoreResult};
+use crate::models::{
+  Cell, Comment, Filter, Iteration, Name, NewCell, NewComment, NewName, NewRevision, Rect,
+  Revision,
+};
+use crate::schema::{cells, comments, filters, iterations, names, revisions};
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+// SQLite has its own set of migrations since the Postgres ones rely on
+// `SERIAL`. They are embedded in the binary and applied on open, so a
+// single-binary deployment doesn't need the diesel CLI.
+embed_migrations!("migrations_sqlite");
+
+/// Embedded SQLite store.
+pub struct SqliteStore {
+  db: SqliteConnection,
+}
+
+impl SqliteStore {
+  pub fn open(path: &str) -> StoreResult<SqliteStore> {
+    let db = SqliteConnection::establish(path)?;
+    embedded_migrations::run(&db)?;
+    Ok(SqliteStore { db })
+  }
+}
+
+impl SheetStore for SqliteStore {
+  fn load_sheet(&mut self, sheet_id: i32) -> StoreResult<Vec<Cell>> {
+    let cells = cells::table
+      .filter(cells::sheet_id.eq(sheet_id))
+      .load::<Cell>(&self.db)?;
+    Ok(cells)
+  }
+
+  fn load_range(&mut self, sheet_id: i32, rect: Rect) -> StoreResult<Vec<Cell>> {
+    let cells = cells::table
+      .filter(cells::sheet_id.eq(sheet_id))
+      .filter(cells::row.between(rect.top, rect.bottom))
+      .filter(cells::col.between(rect.left, rect.right))
+      .load::<Cell>(&self.db)?;
+    Ok(cells)
+  }
+
+  fn upsert_cell(&mut self, cell: NewCell) -> StoreResult<Cell> {
+    let db = &self.db;
+    let cell = db.transaction(|| upsert(db, &cell))?;
+    Ok(cell)
+  }
+
+  fn upsert_cells(&mut self, cells: Vec<NewCell>) -> StoreResult<Vec<Cell>> {
+    let db = &self.db;
+    let cells = db.transaction::<_, diesel::result::Error, _>(|| {
+      cells.iter().map(|cell| upsert(db, cell)).collect()
+    })?;
+    Ok(cells)
+  }
+
+  fn set_format(&mut self, sheet_id: i32, row: i32, col: i32, format: &str) -> StoreResult<Cell> {
+    let db = &self.db;
+    let cell = db.transaction::<_, diesel::result::Error, _>(|| {
+      let existing = cells::table
+        .filter(cells::sheet_id.eq(sheet_id))
+        .filter(cells::row.eq(row))
+        .filter(cells::col.eq(col));
+      let updated = diesel::update(existing)
+        .set(cells::format.eq(format))
+        .execute(db)?;
+      if updated == 0 {
+        diesel::insert_into(cells::table)
+          .values((
+            cells::sheet_id.eq(sheet_id),
+            cells::row.eq(row),
+            cells::col.eq(col),
+            cells::raw.eq(""),
+            cells::format.eq(format),
+          ))
+          .execute(db)?;
+      }
+      existing.first(db)
+    })?;
+    Ok(cell)
+  }
+
+  fn list_revisions(&mut self, sheet_id: i32) -> StoreResult<Vec<Revision>> {
+    let revisions = revisions::table
+      .filter(revisions::sheet_id.eq(sheet_id))
+      .order(revisions::id)
+      .load::<Revision>(&self.db)?;
+    Ok(revisions)
+  }
+
+  fn list_comments(&mut self, sheet_id: i32) -> StoreResult<Vec<Comment>> {
+    let comments = comments::table
+      .filter(comments::sheet_id.eq(sheet_id))
+      .order(comments::id)
+      .load::<Comment>(&self.db)?;
+    Ok(comments)
+  }
+
+  fn get_comment(&mut self, comment_id: i32) -> StoreResult<Option<Comment>> {
+    let comment = comments::table
+      .find(comment_id)
+      .first(&self.db)
+      .optional()?;
+    Ok(comment)
+  }
+
+  fn add_comment(&mut self, comment: NewComment) -> StoreResult<Comment> {
+    let db = &self.db;
+    let comment = db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::insert_into(comments::table)
+        .values(&comment)
+        .execute(db)?;
+      comments::table.order(comments::id.desc()).first(db)
+    })?;
+    Ok(comment)
+  }
+
+  fn edit_comment(&mut self, comment_id: i32, body: &str) -> StoreResult<Comment> {
+    let db = &self.db;
+    let comment = db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::update(comments::table.find(comment_id))
+        .set(comments::body.eq(body))
+        .execute(db)?;
+      comments::table.find(comment_id).first(db)
+    })?;
+    Ok(comment)
+  }
+
+  fn resolve_comment(&mut self, comment_id: i32, resolved: bool) -> StoreResult<Comment> {
+    let db = &self.db;
+    let comment = db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::update(comments::table.find(comment_id))
+        .set(comments::resolved.eq(resolved))
+        .execute(db)?;
+      comments::table.find(comment_id).first(db)
+    })?;
+    Ok(comment)
+  }
+
+  fn move_comments(&mut self, sheet_id: i32, moves: &[CellMove]) -> StoreResult<()> {
+    let existing = self.list_comments(sheet_id)?;
+    let db = &self.db;
+    db.transaction::<_, diesel::result::Error, _>(|| {
+      for (id, row, col) in moved_comments(&existing, moves) {
+        diesel::update(comments::table.find(id))
+          .set((comments::row.eq(row), comments::col.eq(col)))
+          .execute(db)?;
+      }
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  fn list_names(&mut self, sheet_id: i32) -> StoreResult<Vec<Name>> {
+    let names = names::table
+      .filter(names::sheet_id.eq(sheet_id))
+      .order(names::id)
+      .load::<Name>(&self.db)?;
+    Ok(names)
+  }
+
+  fn add_name(&mut self, name: NewName) -> StoreResult<Name> {
+    let db = &self.db;
+    let name = db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::insert_into(names::table)
+        .values(&name)
+        .execute(db)?;
+      names::table.order(names::id.desc()).first(db)
+    })?;
+    Ok(name)
+  }
+
+  fn update_name(&mut self, name_id: i32, name: &str, definition: &str) -> StoreResult<Name> {
+    let db = &self.db;
+    let name = db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::update(names::table.find(name_id))
+        .set((names::name.eq(name), names::definition.eq(definition)))
+        .execute(db)?;
+      names::table.find(name_id).first(db)
+    })?;
+    Ok(name)
+  }
+
+  fn delete_name(&mut self, name_id: i32) -> StoreResult<()> {
+    diesel::delete(names::table.find(name_id)).execute(&self.db)?;
+    Ok(())
+  }
+
+  fn get_filter(&mut self, sheet_id: i32) -> StoreResult<Option<Filter>> {
+    let filter = filters::table
+      .find(sheet_id)
+      .select(filters::filter)
+      .first::<String>(&self.db)
+      .optional()?;
+    match filter {
+      Some(filter) => Ok(Some(serde_json::from_str(&filter)?)),
+      None => Ok(None),
+    }
+  }
+
+  fn set_filter(&mut self, sheet_id: i32, filter: Option<&Filter>) -> StoreResult<()> {
+    let filter = filter.map(serde_json::to_string).transpose()?;
+    let db = &self.db;
+    db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::delete(filters::table.find(sheet_id)).execute(db)?;
+      if let Some(filter) = filter {
+        diesel::insert_into(filters::table)
+          .values((filters::sheet_id.eq(sheet_id), filters::filter.eq(filter)))
+          .execute(db)?;
+      }
+      Ok(())
+    })?;
+    Ok(())
+  }
+
+  fn get_iteration(&mut self, sheet_id: i32) -> StoreResult<Option<Iteration>> {
+    let iteration = iterations::table
+      .find(sheet_id)
+      .select((iterations::max_iterations, iterations::tolerance))
+      .first::<(i32, f64)>(&self.db)
+      .optional()?;
+    Ok(iteration.map(|(max_iterations, tolerance)| Iteration {
+      max_iterations,
+      tolerance,
+    }))
+  }
+
+  fn set_iteration(&mut self, sheet_id: i32, iteration: Option<Iteration>) -> StoreResult<()> {
+    let db = &self.db;
+    db.transaction::<_, diesel::result::Error, _>(|| {
+      diesel::delete(iterations::table.find(sheet_id)).execute(db)?;
+      if let Some(iteration) = iteration {
+        diesel::insert_into(iterations::table)
+          .values((
+            iterations::sheet_id.eq(sheet_id),
+            iterations::max_iterations.eq(iteration.max_iterations),
+            iterations::tolerance.eq(iteration.tolerance),
+          ))
+          .execute(db)?;
+      }
+      Ok(())
+    })?;
+    Ok(())
+  }
+}
+
+// Diesel doesn't support `ON CONFLICT` nor `RETURNING` for SQLite, so the
+// upsert is emulated, callers must run it inside a transaction.
+fn upsert(db: &SqliteConnection, cell: &NewCell) -> QueryResult<Cell> {
+  diesel::insert_into(revisions::table)
+    .values(&NewRevision::from(cell))
+    .execute(db)?;
+  let existing = cells::table
+    .filter(cells::sheet_id.eq(cell.sheet_id))
+    .filter(cells::row.eq(cell.row))
+    .filter(cells::col.eq(cell.col));
+  let updated = diesel::update(existing).set(cell).execute(db)?;
+  if updated == 0 {
+    diesel::insert_into(cells::table).values(cell).execute(db)?;
+  }
+  existing.first(db)
+}
@@ -0,0 +1,349 @@
+//! Viewport subscriptions let clients load big sheets lazily. A session
+//! subscribes to the rectangle it is showing and only receives the cells in
+//! it, plus every cell its formulas read from (transitively), since the
+//! client needs those to evaluate what it displays.
+
+use super::models::{Cell, Name, Rect};
+use super::names;
+use super::store::{SheetStore, StoreResult};
+use std::collections::HashSet;
+
+/// Capability clients advertise to opt into viewport subscriptions.
+pub const VIEWPORT: &str = "viewport";
+
+/// Most cells a viewport may cover.
+pub const MAX_VIEWPORT_CELLS: i64 = 10_000;
+
+/// Upper bound on the precedents tracked, and loaded, for a single
+/// subscription, so that a formula over a huge range can't make us load the
+/// whole sheet.
+const MAX_PRECEDENTS: usize = 100_000;
+/// Upper bound on the coordinates looked at each time precedents are
+/// followed, counting the ones already tracked or in the viewport.
+const MAX_VISITED: usize = 2 * MAX_PRECEDENTS;
+
+#[derive(Default)]
+pub struct Subscription {
+  viewport: Option<Rect>,
+  // Cells outside the viewport that visible formulas depend on.
+  precedents: HashSet<(i32, i32)>,
+}
+
+impl Subscription {
+  pub fn new() -> Subscription {
+    Default::default()
+  }
+
+  /// Whether the session holds, and must be kept up to date on, this cell.
+  pub fn contains(&self, row: i32, col: i32) -> bool {
+    self.viewport.is_some_and(|v| v.contains(row, col)) || self.precedents.contains(&(row, col))
+  }
+
+  /// Moves the subscription to `viewport`, returning the cells the session
+  /// doesn't hold yet.
+  pub fn subscribe(
+    &mut self,
+    store: &mut dyn SheetStore,
+    sheet_id: i32,
+    viewport: Rect,
+  ) -> StoreResult<Vec<Cell>> {
+    let visible = store.load_range(sheet_id, viewport)?;
+    let mut next = Subscription {
+      viewport: Some(viewport),
+      precedents: HashSet::new(),
+    };
+    let hidden = next.add_precedents(store, sheet_id, &visible)?;
+
+    let cells = visible
+      .into_iter()
+      .chain(hidden)
+      .filter(|c| !self.contains(c.row, c.col))
+      .collect();
+    *self = next;
+    Ok(cells)
+  }
+
+  /// Recomputes what the viewport reads from, e.g. after a name was
+  /// redefined, returning the cells the session doesn't hold yet.
+  pub fn refresh(&mut self, store: &mut dyn SheetStore, sheet_id: i32) -> StoreResult<Vec<Cell>> {
+    match self.viewport {
+      Some(viewport) => self.subscribe(store, sheet_id, viewport),
+      None => Ok(vec![]),
+    }
+  }
+
+  /// Starts tracking whatever the held `cells` now read from, returning the
+  /// newly tracked cells.
+  pub fn follow(
+    &mut self,
+    store: &mut dyn SheetStore,
+    sheet_id: i32,
+    cells: &[Cell],
+  ) -> StoreResult<Vec<Cell>> {
+    let held: Vec<Cell> = cells
+      .iter()
+      .filter(|c| self.contains(c.row, c.col))
+      .cloned()
+      .collect();
+    if held.is_empty() {
+      return Ok(vec![]);
+    }
+    self.add_precedents(store, sheet_id, &held)
+  }
+
+  fn add_precedents(
+    &mut self,
+    store: &mut dyn SheetStore,
+    sheet_id: i32,
+    cells: &[Cell],
+  ) -> StoreResult<Vec<Cell>> {
+    let names = store.list_names(sheet_id)?;
+    let mut loaded = vec![];
+    let mut loaded_ids = HashSet::new();
+    let mut pending: Vec<Rect> = cells
+      .iter()
+      .flat_map(|c| precedents(&c.raw, &names))
+      .collect();
+    let mut visited = 0;
+    while let Some(rect) = pending.pop() {
+      let mut is_new = false;
+      // Past the caps the rest of the rect is neither tracked nor loaded.
+      let mut tracked = rect;
+      for (row, col) in rect.coords() {
+        if self.precedents.len() >= MAX_PRECEDENTS || visited >= MAX_VISITED {
+          tracked.bottom = row;
+          break;
+        }
+        visited += 1;
+        // Empty cells are tracked too, so we hear about them once they're filled.
+        if !self.contains(row, col) {
+          self.precedents.insert((row, col));
+          is_new = true;
+        }
+      }
+      if !is_new {
+        continue;
+      }
+      for cell in store.load_range(sheet_id, tracked)? {
+        // The last row may only be tracked in part.
+        if !self.contains(cell.row, cell.col) || !loaded_ids.insert(cell.id) {
+          continue;
+        }
+        pending.extend(precedents(&cell.raw, &names));
+        loaded.push(cell);
+      }
+    }
+    Ok(loaded)
+  }
+}
+
+// Like `references`, but also follows the names the formula uses, and the
+// names those use when they're a `LAMBDA`.
+fn precedents(raw: &str, names: &[Name]) -> Vec<Rect> {
+  let mut rects = references(raw);
+  let mut followed: Vec<&Name> = vec![];
+  let mut pending = vec![raw.to_string()];
+  while let Some(raw) = pending.pop() {
+    for name in names.iter().filter(|n| names::uses_name(&raw, &n.name)) {
+      if followed.iter().any(|f| f.id == name.id) {
+        continue;
+      }
+      followed.push(name);
+      let definition = format!("={}", name.definition.trim().trim_start_matches('='));
+      rects.extend(references(&definition));
+      pending.push(definition);
+    }
+  }
+  rects
+}
+
+/// Returns the cells and ranges a formula refers to, e.g. `=A1+SUM(B1:B3)`
+/// yields `A1` and `B1:B3`. This doesn't run the real parser, it errs on the
+/// side of returning too much, which only costs us a few extra cells.
+pub fn references(raw: &str) -> Vec<Rect> {
+  if !raw.starts_with('=') {
+    return vec![];
+  }
+  let chars: Vec<char> = raw.chars().collect();
+  let mut refs = vec![];
+  let mut i = 1;
+  while i < chars.len() {
+    let c = chars[i];
+    if c == '"' {
+      // Skip string literals, they can contain anything.
+      i += 1;
+      while i < chars.len() && chars[i] != '"' {
+        i += 1;
+      }
+      i += 1;
+    } else if c.is_ascii_alphabetic() && !is_ident_char(chars[i - 1]) {
+      match coord(&chars, i) {
+        Some((row, col, end)) => {
+          let mut rect = Rect::cell(row, col);
+          i = end;
+          if chars.get(i) == Some(&':') {
+            if let Some((row2, col2, end)) = coord(&chars, i + 1) {
+              rect = Rect {
+                top: row.min(row2),
+                left: col.min(col2),
+                bottom: row.max(row2),
+                right: col.max(col2),
+              };
+              i = end;
+            }
+          }
+          refs.push(rect);
+        }
+        None => {
+          while i < chars.len() && is_ident_char(chars[i]) {
+            i += 1;
+          }
+        }
+      }
+    } else {
+      i += 1;
+    }
+  }
+  refs
+}
+
+fn is_ident_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// Parses `Letters Digits` starting at `start`, returning the 0-indexed row
+// and column and where the coordinate ends. Function names (`LOG10(`) and
+// other identifiers are rejected.
+fn coord(chars: &[char], start: usize) -> Option<(i32, i32, usize)> {
+  let mut i = start;
+  let mut col: i64 = 0;
+  while i < chars.len() && chars[i].is_ascii_alphabetic() {
+    let digit = chars[i].to_ascii_lowercase() as i64 - 'a' as i64 + 1;
+    col = col.saturating_mul(26).saturating_add(digit);
+    i += 1;
+  }
+  let digits_start = i;
+  let mut row: i64 = 0;
+  while i < chars.len() && chars[i].is_ascii_digit() {
+    row = row.saturating_mul(10).saturating_add(chars[i].to_digit(10).unwrap() as i64);
+    i += 1;
+  }
+  let followed_by_ident = i < chars.len() && (is_ident_char(chars[i]) || chars[i] == '(');
+  let out_of_range = row == 0 || row > i32::MAX as i64 || col > i32::MAX as i64;
+  if i == digits_start || followed_by_ident || out_of_range {
+    return None;
+  }
+  Some(((row - 1) as i32, (col - 1) as i32, i))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::models::NewCell;
+  use crate::store::MemoryStore;
+
+  #[test]
+  fn references_finds_cells_and_ranges() {
+    assert_eq!(references("A1"), vec![]);
+    assert_eq!(references("=1+2"), vec![]);
+    assert_eq!(references("=A1*b2"), vec![Rect::cell(0, 0), Rect::cell(1, 1)]);
+    assert_eq!(
+      references("=SUM(B3:A1)"),
+      vec![Rect {
+        top: 0,
+        left: 0,
+        bottom: 2,
+        right: 1,
+      }]
+    );
+    assert_eq!(references("=AA10"), vec![Rect::cell(9, 26)]);
+  }
+
+  #[test]
+  fn references_skips_functions_and_strings() {
+    assert_eq!(references("=LOG10(C1)"), vec![Rect::cell(0, 2)]);
+    assert_eq!(references("=\"A1\"&B1"), vec![Rect::cell(0, 1)]);
+    assert_eq!(references("=TaxRate*A1"), vec![Rect::cell(0, 0)]);
+  }
+
+  #[test]
+  fn precedents_stop_at_the_cap() {
+    let mut store = MemoryStore::new();
+    let cell = |row, col, raw: &str| NewCell {
+      sheet_id: 1,
+      row,
+      col,
+      raw: raw.to_string(),
+      format: None,
+    };
+    let last = MAX_PRECEDENTS as i32 - 1;
+    store.upsert_cell(cell(0, 1, "=SUM(A1:A200000)")).unwrap();
+    store.upsert_cell(cell(0, 0, "=C1")).unwrap();
+    store.upsert_cell(cell(last, 0, "1")).unwrap();
+    store.upsert_cell(cell(last + 1, 0, "2")).unwrap();
+
+    let mut subscription = Subscription::new();
+    let cells = subscription
+      .subscribe(&mut store, 1, Rect::cell(0, 1))
+      .unwrap();
+    let mut loaded: Vec<(i32, i32)> = cells.iter().map(|c| (c.row, c.col)).collect();
+    loaded.sort_unstable();
+    // `C1` is past the cap by the time `A1` is loaded.
+    assert_eq!(loaded, vec![(0, 0), (0, 1), (last, 0)]);
+    assert_eq!(subscription.precedents.len(), MAX_PRECEDENTS);
+    assert!(!subscription.contains(last + 1, 0));
+  }
+
+  #[test]
+  fn following_stops_after_visiting_the_cap() {
+    let mut store = MemoryStore::new();
+    let cell = |row, col, raw: &str| NewCell {
+      sheet_id: 1,
+      row,
+      col,
+      raw: raw.to_string(),
+      format: None,
+    };
+    let viewport = Rect {
+      top: 0,
+      left: 0,
+      bottom: 99,
+      right: 99,
+    };
+    let passes = MAX_VISITED / MAX_VIEWPORT_CELLS as usize;
+    // Followed last, once every other formula went over the viewport.
+    store.upsert_cell(cell(0, 0, "=SUM(A1:CV100)+CX1")).unwrap();
+    for col in 1..=passes as i32 {
+      store.upsert_cell(cell(0, col, "=SUM(A1:CV100)")).unwrap();
+    }
+    store.upsert_cell(cell(0, 101, "1")).unwrap();
+
+    let mut subscription = Subscription::new();
+    let cells = subscription.subscribe(&mut store, 1, viewport).unwrap();
+    assert_eq!(cells.len(), passes + 1);
+    assert!(!subscription.contains(0, 101));
+  }
+
+  #[test]
+  fn precedents_follow_names() {
+    let name = |name: &str, definition: &str| Name {
+      id: 1,
+      sheet_id: 1,
+      name: name.to_string(),
+      definition: definition.to_string(),
+    };
+    let names = vec![name("Prices", "=B1:B2"), name("Rate", "0.2")];
+    assert_eq!(
+      precedents("=SUM(prices)*Rate+A1", &names),
+      vec![
+        Rect::cell(0, 0),
+        Rect {
+          top: 0,
+          left: 1,
+          bottom: 1,
+          right: 1,
+        }
+      ]
+    );
+  }
+}
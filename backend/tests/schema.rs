@@ -0,0 +1,26 @@
+//! Keeps the published protocol schema in sync with the Rust types.
+//! Regenerate it with `UPDATE_SCHEMA=1 cargo test --test schema`.
+
+use backend::server::protocol_schema;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn published_schema_is_up_to_date() {
+  let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("protocol/schema.json");
+  let generated = serde_json::to_string_pretty(&protocol_schema()).unwrap() + "\n";
+
+  if env::var("UPDATE_SCHEMA").is_ok() {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, &generated).unwrap();
+    return;
+  }
+
+  let published = fs::read_to_string(&path).unwrap_or_default();
+  assert!(
+    published == generated,
+    "{} is out of date, run `UPDATE_SCHEMA=1 cargo test --test schema`",
+    path.display()
+  );
+}
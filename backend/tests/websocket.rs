@@ -0,0 +1,947 @@
+//! Drives the websocket protocol end to end against an in-memory store. The
+//! assertions are written against the raw JSON so that they catch changes to
+//! the wire format that `AppProvider.js` depends on, not just to the Rust types.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use actix::prelude::*;
+use actix_web::{test, App};
+use awc::ws::{Frame, Message};
+use backend::server::{WsServer, PROTOCOL_VERSION};
+use backend::session;
+use backend::store::MemoryStore;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Client = actix_codec::Framed<awc::BoxedSocket, awc::ws::Codec>;
+
+fn start_server() -> test::TestServer {
+  let server = WsServer::new(Box::new(MemoryStore::new())).start();
+  test::start(move || {
+    App::new()
+      .data(server.clone())
+      .configure(session::configure)
+  })
+}
+
+async fn connect(srv: &mut test::TestServer) -> Client {
+  let (_, client) = awc::Client::new()
+    .ws(srv.url("/ws/"))
+    .connect()
+    .await
+    .expect("failed to open websocket");
+  client
+}
+
+async fn send(client: &mut Client, req: Value) {
+  send_text(client, &req.to_string()).await;
+}
+
+async fn send_text(client: &mut Client, text: &str) {
+  client
+    .send(Message::Text(text.to_string()))
+    .await
+    .expect("failed to send request");
+}
+
+/// Returns the next frame sent by the server, skipping heartbeats.
+async fn recv_frame(client: &mut Client) -> Frame {
+  loop {
+    let frame = actix_rt::time::timeout(RESPONSE_TIMEOUT, client.next())
+      .await
+      .expect("timed out waiting for a frame")
+      .expect("websocket closed")
+      .expect("websocket error");
+    match frame {
+      Frame::Ping(_) | Frame::Pong(_) => continue,
+      frame => return frame,
+    }
+  }
+}
+
+/// Returns the next response sent by the server. Binary frames are decoded
+/// as MessagePack.
+async fn recv(client: &mut Client) -> Value {
+  match recv_frame(client).await {
+    Frame::Text(text) => serde_json::from_slice(&text).expect("response is not json"),
+    Frame::Binary(bin) => rmp_serde::from_slice(&bin).expect("response is not msgpack"),
+    other => panic!("unexpected frame {:?}", other),
+  }
+}
+
+fn participant_ids(resp: &Value) -> HashSet<i64> {
+  assert_eq!(resp["type"], "Participants");
+  resp["ids"]
+    .as_array()
+    .expect("ids is not an array")
+    .iter()
+    .map(|id| id.as_i64().unwrap())
+    .collect()
+}
+
+fn hello(protocol_version: u32, capabilities: &[&str]) -> Value {
+  json!({
+    "type": "Hello",
+    "protocol_version": protocol_version,
+    "capabilities": capabilities,
+  })
+}
+
+async fn recv_close(client: &mut Client) -> Option<awc::ws::CloseCode> {
+  match recv_frame(client).await {
+    Frame::Close(reason) => reason.map(|r| r.code),
+    other => panic!("expected close, got {:?}", other),
+  }
+}
+
+/// Connects a new client, completes the handshake and consumes its
+/// `Welcome`, `Connected`, `Comments`, `Names` and `Participants` responses,
+/// returning the assigned user id.
+async fn join(srv: &mut test::TestServer) -> (Client, i64, Value) {
+  let mut client = connect(srv).await;
+  send(&mut client, hello(PROTOCOL_VERSION, &[])).await;
+  assert_eq!(recv(&mut client).await["type"], "Welcome");
+  let connected = recv(&mut client).await;
+  assert_eq!(connected["type"], "Connected");
+  let user_id = connected["user_id"]
+    .as_i64()
+    .expect("user_id is not a number");
+  assert_eq!(recv(&mut client).await["type"], "Comments");
+  assert_eq!(recv(&mut client).await["type"], "Names");
+  let participants = recv(&mut client).await;
+  assert!(participant_ids(&participants).contains(&user_id));
+  (client, user_id, connected)
+}
+
+fn update_cell(user_id: i64, row: i32, col: i32, raw: &str) -> Value {
+  json!({
+    "type": "UpdateCell",
+    "user_id": user_id,
+    "sheet_id": 1,
+    "row": row,
+    "col": col,
+    "raw": raw,
+  })
+}
+
+#[actix_rt::test]
+async fn connect_sends_empty_sheet_and_participants() {
+  let mut srv = start_server();
+  let mut client = connect(&mut srv).await;
+  send(&mut client, hello(PROTOCOL_VERSION, &["from-the-future"])).await;
+
+  let welcome = recv(&mut client).await;
+  assert_eq!(welcome["type"], "Welcome");
+  assert_eq!(welcome["protocol_version"], PROTOCOL_VERSION);
+  // Capabilities the server doesn't know about are dropped.
+  assert_eq!(welcome["capabilities"], json!([]));
+
+  let connected = recv(&mut client).await;
+  assert_eq!(connected["type"], "Connected");
+  assert_eq!(connected["cells"], json!([]));
+  let user_id = connected["user_id"].as_i64().unwrap();
+
+  let comments = recv(&mut client).await;
+  assert_eq!(comments["type"], "Comments");
+  assert_eq!(comments["comments"], json!([]));
+
+  let names = recv(&mut client).await;
+  assert_eq!(names["type"], "Names");
+  assert_eq!(names["names"], json!([]));
+
+  let participants = recv(&mut client).await;
+  assert_eq!(
+    participant_ids(&participants),
+    vec![user_id].into_iter().collect()
+  );
+}
+
+#[actix_rt::test]
+async fn participants_are_broadcast_on_join_and_leave() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, bob_id, _) = join(&mut srv).await;
+
+  let both: HashSet<i64> = vec![alice_id, bob_id].into_iter().collect();
+  assert_eq!(participant_ids(&recv(&mut alice).await), both);
+
+  bob.close().await.unwrap();
+  let only_alice: HashSet<i64> = vec![alice_id].into_iter().collect();
+  assert_eq!(participant_ids(&recv(&mut alice).await), only_alice);
+}
+
+#[actix_rt::test]
+async fn cell_updates_are_broadcast_and_persisted() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  // Alice hears about Bob joining.
+  recv(&mut alice).await;
+
+  send(&mut alice, update_cell(alice_id, 2, 3, "=A1+1")).await;
+  for client in [&mut alice, &mut bob].iter_mut() {
+    let resp = recv(client).await;
+    assert_eq!(resp["type"], "CellUpdated");
+    assert_eq!(resp["user_id"], alice_id);
+    assert_eq!(resp["cell"]["sheet_id"], 1);
+    assert_eq!(resp["cell"]["row"], 2);
+    assert_eq!(resp["cell"]["col"], 3);
+    assert_eq!(resp["cell"]["raw"], "=A1+1");
+  }
+
+  // Overwriting a cell keeps a single entry for it.
+  send(&mut alice, update_cell(alice_id, 2, 3, "42")).await;
+  assert_eq!(recv(&mut alice).await["cell"]["raw"], "42");
+
+  let (_, _, connected) = join(&mut srv).await;
+  let cells = connected["cells"].as_array().unwrap();
+  assert_eq!(cells.len(), 1);
+  assert_eq!(cells[0]["row"], 2);
+  assert_eq!(cells[0]["col"], 3);
+  assert_eq!(cells[0]["raw"], "42");
+}
+
+#[actix_rt::test]
+async fn malformed_requests_only_error_the_sender() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  for bad in &[
+    "not json",
+    r#"{"type":"DoesNotExist"}"#,
+    r#"{"type":"UpdateCell","row":0}"#,
+  ] {
+    send_text(&mut alice, bad).await;
+    let resp = recv(&mut alice).await;
+    assert_eq!(resp["type"], "Error");
+    assert!(resp["message"]
+      .as_str()
+      .unwrap()
+      .contains("unable to parse"));
+  }
+
+  // The connection is still usable and Bob never saw the errors.
+  send(&mut alice, update_cell(alice_id, 0, 0, "ok")).await;
+  assert_eq!(recv(&mut alice).await["type"], "CellUpdated");
+  assert_eq!(recv(&mut bob).await["type"], "CellUpdated");
+}
+
+#[actix_rt::test]
+async fn incompatible_versions_are_rejected() {
+  let mut srv = start_server();
+  let mut client = connect(&mut srv).await;
+  send(&mut client, hello(PROTOCOL_VERSION + 1, &[])).await;
+
+  let rejected = recv(&mut client).await;
+  assert_eq!(rejected["type"], "Rejected");
+  assert_eq!(rejected["max_protocol_version"], PROTOCOL_VERSION);
+  assert_eq!(
+    recv_close(&mut client).await,
+    Some(awc::ws::CloseCode::Protocol)
+  );
+}
+
+#[actix_rt::test]
+async fn requests_before_hello_are_rejected() {
+  let mut srv = start_server();
+  let mut client = connect(&mut srv).await;
+  send(&mut client, update_cell(0, 0, 0, "too early")).await;
+
+  assert_eq!(recv(&mut client).await["type"], "Rejected");
+  assert_eq!(
+    recv_close(&mut client).await,
+    Some(awc::ws::CloseCode::Protocol)
+  );
+}
+
+#[actix_rt::test]
+async fn repeated_hello_is_an_error() {
+  let mut srv = start_server();
+  let (mut client, _, _) = join(&mut srv).await;
+  send(&mut client, hello(PROTOCOL_VERSION, &[])).await;
+
+  let resp = recv(&mut client).await;
+  assert_eq!(resp["type"], "Error");
+}
+
+#[actix_rt::test]
+async fn msgpack_sessions_use_binary_frames() {
+  let mut srv = start_server();
+  let (mut alice, _, _) = join(&mut srv).await;
+
+  let mut bob = connect(&mut srv).await;
+  send(&mut bob, hello(PROTOCOL_VERSION, &["msgpack"])).await;
+  let welcome = recv(&mut bob).await;
+  assert_eq!(welcome["capabilities"], json!(["msgpack"]));
+  let connected: Value = match recv_frame(&mut bob).await {
+    Frame::Binary(bin) => rmp_serde::from_slice(&bin).unwrap(),
+    other => panic!("expected a binary frame, got {:?}", other),
+  };
+  assert_eq!(connected["type"], "Connected");
+  let bob_id = connected["user_id"].as_i64().unwrap();
+  recv(&mut bob).await;
+  recv(&mut bob).await;
+  recv(&mut bob).await;
+  recv(&mut alice).await;
+
+  // Bob may send MessagePack requests, Alice keeps getting JSON.
+  let req = rmp_serde::to_vec_named(&update_cell(bob_id, 1, 1, "packed")).unwrap();
+  bob.send(Message::Binary(req.into())).await.unwrap();
+  assert_eq!(recv(&mut bob).await["cell"]["raw"], "packed");
+  match recv_frame(&mut alice).await {
+    Frame::Text(text) => {
+      let resp: Value = serde_json::from_slice(&text).unwrap();
+      assert_eq!(resp["user_id"], bob_id);
+      assert_eq!(resp["cell"]["raw"], "packed");
+    }
+    other => panic!("expected a text frame, got {:?}", other),
+  }
+
+  // JSON requests are still understood on a MessagePack session.
+  send(&mut bob, update_cell(bob_id, 1, 1, "json")).await;
+  assert_eq!(recv(&mut bob).await["cell"]["raw"], "json");
+  assert_eq!(recv(&mut alice).await["cell"]["raw"], "json");
+}
+
+#[actix_rt::test]
+async fn viewport_sessions_only_get_what_they_show() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  for (row, col, raw) in &[
+    (0, 0, "=C1+1"),
+    (0, 2, "=D50"),
+    (49, 3, "7"),
+    (10, 0, "hidden"),
+  ] {
+    send(&mut alice, update_cell(alice_id, *row, *col, raw)).await;
+    recv(&mut alice).await;
+  }
+
+  let mut bob = connect(&mut srv).await;
+  send(&mut bob, hello(PROTOCOL_VERSION, &["viewport"])).await;
+  assert_eq!(recv(&mut bob).await["capabilities"], json!(["viewport"]));
+  let connected = recv(&mut bob).await;
+  assert_eq!(connected["cells"], json!([]));
+  recv(&mut bob).await;
+  recv(&mut bob).await;
+  recv(&mut bob).await;
+  recv(&mut alice).await;
+
+  // A1 is visible, C1 and D50 are pulled in because A1 depends on them.
+  let viewport = json!({"top": 0, "left": 0, "bottom": 4, "right": 1});
+  send(&mut bob, json!({"type": "Subscribe", "viewport": viewport})).await;
+  let loaded = recv(&mut bob).await;
+  assert_eq!(loaded["type"], "CellsLoaded");
+  let mut raws: Vec<&str> = loaded["cells"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| c["raw"].as_str().unwrap())
+    .collect();
+  raws.sort();
+  assert_eq!(raws, vec!["7", "=C1+1", "=D50"]);
+
+  // Updates outside of the viewport and its precedents aren't sent to Bob.
+  send(&mut alice, update_cell(alice_id, 10, 0, "still hidden")).await;
+  recv(&mut alice).await;
+  send(&mut alice, update_cell(alice_id, 49, 3, "8")).await;
+  recv(&mut alice).await;
+  assert_eq!(recv(&mut bob).await["cell"]["raw"], "8");
+
+  // A visible formula gaining a reference loads it first.
+  send(&mut alice, update_cell(alice_id, 1, 1, "=A11")).await;
+  recv(&mut alice).await;
+  let loaded = recv(&mut bob).await;
+  assert_eq!(loaded["type"], "CellsLoaded");
+  assert_eq!(loaded["cells"][0]["raw"], "still hidden");
+  assert_eq!(recv(&mut bob).await["cell"]["raw"], "=A11");
+
+  // Scrolling only sends the cells Bob didn't have.
+  let viewport = json!({"top": 8, "left": 0, "bottom": 12, "right": 1});
+  send(&mut bob, json!({"type": "Subscribe", "viewport": viewport})).await;
+  let loaded = recv(&mut bob).await;
+  assert_eq!(loaded["cells"], json!([]));
+}
+
+#[actix_rt::test]
+async fn subscribe_requires_viewport_capability() {
+  let mut srv = start_server();
+  let (mut client, _, _) = join(&mut srv).await;
+  let viewport = json!({"top": 0, "left": 0, "bottom": 1, "right": 1});
+  send(
+    &mut client,
+    json!({"type": "Subscribe", "viewport": viewport}),
+  )
+  .await;
+  assert_eq!(recv(&mut client).await["type"], "Error");
+}
+
+#[actix_rt::test]
+async fn subscribe_rejects_oversized_viewports() {
+  let mut srv = start_server();
+  let mut client = connect(&mut srv).await;
+  send(&mut client, hello(PROTOCOL_VERSION, &["viewport"])).await;
+  for _ in 0..5 {
+    recv(&mut client).await;
+  }
+  let viewport = json!({"top": 0, "left": 0, "bottom": i32::MAX, "right": 25});
+  send(&mut client, json!({"type": "Subscribe", "viewport": viewport})).await;
+  assert_eq!(recv(&mut client).await["type"], "Error");
+
+  let viewport = json!({"top": 0, "left": 0, "bottom": 99, "right": 25});
+  send(&mut client, json!({"type": "Subscribe", "viewport": viewport})).await;
+  assert_eq!(recv(&mut client).await["type"], "CellsLoaded");
+}
+
+fn select(row: i32, col: i32) -> Value {
+  json!({
+    "type": "SelectionChanged",
+    "selection": {"row": row, "col": col, "range": null},
+  })
+}
+
+#[actix_rt::test]
+async fn participants_have_names_and_colors() {
+  let mut srv = start_server();
+  let mut alice = connect(&mut srv).await;
+  let mut hello_alice = hello(PROTOCOL_VERSION, &[]);
+  hello_alice["name"] = json!("  Alice ");
+  send(&mut alice, hello_alice).await;
+  for _ in 0..5 {
+    recv(&mut alice).await;
+  }
+  let (_, bob_id, _) = join(&mut srv).await;
+
+  let participants = recv(&mut alice).await;
+  let list = participants["participants"].as_array().unwrap();
+  assert_eq!(list.len(), 2);
+  let alice_entry = list.iter().find(|p| p["id"] != bob_id).unwrap();
+  let bob_entry = list.iter().find(|p| p["id"] == bob_id).unwrap();
+  assert_eq!(alice_entry["name"], "Alice");
+  assert!(bob_entry["name"].as_str().unwrap().starts_with("Guest"));
+  assert_ne!(alice_entry["color"], bob_entry["color"]);
+}
+
+#[actix_rt::test]
+async fn selections_are_throttled_and_cleared_on_leave() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  // The first selection goes out right away, the ones sent within the
+  // throttle window collapse into the latest.
+  send(&mut alice, select(0, 0)).await;
+  send(&mut alice, select(1, 1)).await;
+  send(&mut alice, select(2, 2)).await;
+  let first = recv(&mut bob).await;
+  assert_eq!(first["type"], "SelectionChanged");
+  assert_eq!(first["user_id"], alice_id);
+  assert_eq!(first["selection"]["row"], 0);
+  let last = recv(&mut bob).await;
+  assert_eq!(last["type"], "SelectionChanged");
+  assert_eq!(last["selection"]["row"], 2);
+  assert_eq!(last["selection"]["col"], 2);
+
+  // Newcomers see where everyone is.
+  let mut carol = connect(&mut srv).await;
+  send(&mut carol, hello(PROTOCOL_VERSION, &[])).await;
+  for expected in &["Welcome", "Connected", "Comments", "Names", "Participants"] {
+    assert_eq!(recv(&mut carol).await["type"], *expected);
+  }
+  let existing = recv(&mut carol).await;
+  assert_eq!(existing["type"], "SelectionChanged");
+  assert_eq!(existing["user_id"], alice_id);
+  assert_eq!(existing["selection"]["row"], 2);
+
+  // Bob hears about Carol, then about Alice leaving.
+  assert_eq!(recv(&mut bob).await["type"], "Participants");
+  alice.close().await.unwrap();
+  let cleared = recv(&mut bob).await;
+  assert_eq!(cleared["type"], "SelectionCleared");
+  assert_eq!(cleared["user_id"], alice_id);
+  assert_eq!(recv(&mut bob).await["type"], "Participants");
+}
+
+#[actix_rt::test]
+async fn comment_threads_are_broadcast_and_loaded_on_join() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  let add = json!({"type": "AddComment", "row": 1, "col": 2, "body": "source?"});
+  send(&mut alice, add).await;
+  let mut thread_id = 0;
+  for client in [&mut alice, &mut bob].iter_mut() {
+    let resp = recv(client).await;
+    assert_eq!(resp["type"], "CommentAdded");
+    assert_eq!(resp["user_id"], alice_id);
+    assert_eq!(resp["comment"]["body"], "source?");
+    assert_eq!(resp["comment"]["resolved"], false);
+    thread_id = resp["comment"]["id"].as_i64().unwrap();
+  }
+
+  // Replies land on the thread's cell, whatever the client says.
+  let reply = json!({
+    "type": "AddComment", "row": 9, "col": 9, "body": "Q3 report", "parent_id": thread_id,
+  });
+  send(&mut bob, reply).await;
+  let added = recv(&mut alice).await;
+  assert_eq!(added["comment"]["parent_id"], thread_id);
+  assert_eq!(
+    (
+      added["comment"]["row"].clone(),
+      added["comment"]["col"].clone()
+    ),
+    (json!(1), json!(2))
+  );
+  let reply_id = recv(&mut bob).await["comment"]["id"].as_i64().unwrap();
+
+  // Only threads get resolved.
+  let resolve_reply = json!({"type": "ResolveComment", "comment_id": reply_id, "resolved": true});
+  send(&mut bob, resolve_reply).await;
+  assert_eq!(recv(&mut bob).await["type"], "Error");
+  send(
+    &mut alice,
+    json!({"type": "AddComment", "row": 0, "col": 0, "body": "  "}),
+  )
+  .await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+
+  // Anyone may edit, not only the author.
+  let edit = json!({"type": "EditComment", "comment_id": thread_id, "body": "source please"});
+  send(&mut bob, edit).await;
+  assert_eq!(recv(&mut alice).await["comment"]["body"], "source please");
+  recv(&mut bob).await;
+  let resolve = json!({"type": "ResolveComment", "comment_id": thread_id, "resolved": true});
+  send(&mut bob, resolve).await;
+  let updated = recv(&mut alice).await;
+  assert_eq!(updated["type"], "CommentUpdated");
+  assert_eq!(updated["comment"]["resolved"], true);
+
+  let mut carol = connect(&mut srv).await;
+  send(&mut carol, hello(PROTOCOL_VERSION, &[])).await;
+  recv(&mut carol).await;
+  recv(&mut carol).await;
+  let comments = recv(&mut carol).await;
+  let bodies: Vec<&str> = comments["comments"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| c["body"].as_str().unwrap())
+    .collect();
+  assert_eq!(bodies, vec!["source please", "Q3 report"]);
+}
+
+#[actix_rt::test]
+async fn formats_are_broadcast_and_persisted() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  send(&mut alice, update_cell(alice_id, 0, 0, "0.25")).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+  let set_format = json!({"type": "SetFormat", "sheet_id": 1, "row": 0, "col": 0, "format": "0%"});
+  send(&mut alice, set_format).await;
+  let updated = recv(&mut bob).await;
+  assert_eq!(updated["type"], "CellUpdated");
+  assert_eq!(updated["cell"]["raw"], "0.25");
+  assert_eq!(updated["cell"]["format"], "0%");
+  recv(&mut alice).await;
+
+  // Formats land on the session's sheet whatever the client says.
+  let set_format = json!({"type": "SetFormat", "sheet_id": 2, "row": 1, "col": 0, "format": "0.0"});
+  send(&mut alice, set_format).await;
+  let updated = recv(&mut bob).await;
+  assert_eq!(updated["cell"]["sheet_id"], 1);
+  assert_eq!(updated["cell"]["format"], "0.0");
+  recv(&mut alice).await;
+
+  let set_format = json!({"type": "SetFormat", "row": -1, "col": 0, "format": "0%"});
+  send(&mut alice, set_format).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+  // Codes the clients can't parse are turned away, in batches too.
+  for format in &["\"oops", "[Red0", "0\\", "0;0;0;0;0"] {
+    let set_format = json!({"type": "SetFormat", "row": 0, "col": 0, "format": format});
+    send(&mut alice, set_format).await;
+    assert_eq!(recv(&mut alice).await["type"], "Error", "{}", format);
+  }
+  let cells = json!([{"row": 2, "col": 0, "raw": "1", "format": "[$€"}]);
+  send(&mut alice, json!({"type": "UpdateCells", "cells": cells})).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+  let set_format = json!({"type": "SetFormat", "row": 3, "col": 0, "format": "[€]\";\"0;\\;"});
+  send(&mut alice, set_format).await;
+  assert_eq!(recv(&mut bob).await["type"], "CellUpdated");
+  recv(&mut alice).await;
+
+  let (_, _, connected) = join(&mut srv).await;
+  assert_eq!(connected["cells"][0]["format"], "0%");
+}
+
+#[actix_rt::test]
+async fn names_are_broadcast_and_renames_rewrite_formulas() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  send(&mut alice, update_cell(alice_id, 0, 0, "=Rate*2+SUM(A2)")).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+
+  // Names are checked, and redefining ignores case.
+  let define = json!({"type": "DefineName", "name": "A2", "definition": "B1"});
+  send(&mut alice, define).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+  let define = json!({"type": "DefineName", "name": "Rate", "definition": "B1"});
+  send(&mut alice, define).await;
+  let defined = recv(&mut bob).await;
+  assert_eq!(defined["type"], "NameUpdated");
+  assert_eq!(defined["user_id"], alice_id);
+  assert_eq!(defined["name"]["definition"], "B1");
+  let name_id = defined["name"]["id"].as_i64().unwrap();
+  recv(&mut alice).await;
+  let define = json!({"type": "DefineName", "name": "RATE", "definition": "0.2"});
+  send(&mut alice, define).await;
+  let redefined = recv(&mut bob).await;
+  assert_eq!(redefined["name"]["id"], name_id);
+  assert_eq!(redefined["name"]["name"], "Rate");
+  recv(&mut alice).await;
+
+  let rename = json!({"type": "RenameName", "name_id": name_id, "name": "TaxRate"});
+  send(&mut bob, rename).await;
+  assert_eq!(recv(&mut alice).await["name"]["name"], "TaxRate");
+  let rewritten = recv(&mut alice).await;
+  assert_eq!(rewritten["type"], "CellUpdated");
+  assert_eq!(rewritten["cell"]["raw"], "=TaxRate*2+SUM(A2)");
+  recv(&mut bob).await;
+  recv(&mut bob).await;
+
+  let mut carol = connect(&mut srv).await;
+  send(&mut carol, hello(PROTOCOL_VERSION, &[])).await;
+  for expected in &["Welcome", "Connected", "Comments"] {
+    assert_eq!(recv(&mut carol).await["type"], *expected);
+  }
+  let names = recv(&mut carol).await;
+  assert_eq!(names["names"][0]["name"], "TaxRate");
+  assert_eq!(names["names"][0]["definition"], "0.2");
+  recv(&mut carol).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+
+  send(
+    &mut carol,
+    json!({"type": "DeleteName", "name_id": name_id}),
+  )
+  .await;
+  let deleted = recv(&mut alice).await;
+  assert_eq!(deleted["type"], "NameDeleted");
+  assert_eq!(deleted["name_id"], name_id);
+}
+
+#[actix_rt::test]
+async fn renaming_a_lambda_rewrites_its_calls() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+
+  send(
+    &mut alice,
+    update_cell(alice_id, 0, 0, "=Double(3)&\"Double\""),
+  )
+  .await;
+  recv(&mut alice).await;
+  let define = json!({"type": "DefineName", "name": "Double", "definition": "=LAMBDA(x, x*2)"});
+  send(&mut alice, define).await;
+  let name_id = recv(&mut alice).await["name"]["id"].as_i64().unwrap();
+  let definition = "LAMBDA(x, Double(Double(x)))";
+  let define = json!({"type": "DefineName", "name": "Quad", "definition": definition});
+  send(&mut alice, define).await;
+  recv(&mut alice).await;
+
+  let rename = json!({"type": "RenameName", "name_id": name_id, "name": "Twice"});
+  send(&mut alice, rename).await;
+  assert_eq!(recv(&mut alice).await["name"]["name"], "Twice");
+  let rewritten = recv(&mut alice).await;
+  assert_eq!(rewritten["type"], "CellUpdated");
+  assert_eq!(rewritten["cell"]["raw"], "=Twice(3)&\"Double\"");
+  let quad = recv(&mut alice).await;
+  assert_eq!(quad["type"], "NameUpdated");
+  assert_eq!(quad["name"]["name"], "Quad");
+  assert_eq!(quad["name"]["definition"], "=LAMBDA(x, Twice(Twice(x)))");
+}
+
+#[actix_rt::test]
+async fn participants_share_volatile_inputs() {
+  let mut srv = start_server();
+  let (mut alice, _, alice_connected) = join(&mut srv).await;
+  let (mut bob, bob_id, bob_connected) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  // Bob evaluates RAND() and NOW() like Alice already did.
+  let recalculation = &alice_connected["recalculation"];
+  assert!(recalculation["seed"].is_u64());
+  assert!(recalculation["now"].as_i64().unwrap() > 0);
+  assert_eq!(bob_connected["recalculation"], *recalculation);
+
+  send(&mut bob, json!({"type": "Recalculate"})).await;
+  let recalculated = recv(&mut alice).await;
+  assert_eq!(recalculated["type"], "Recalculated");
+  assert_eq!(recalculated["user_id"], bob_id);
+  assert!(recalculated["recalculation"]["now"].as_i64() >= recalculation["now"].as_i64());
+  assert_eq!(recv(&mut bob).await, recalculated);
+
+  // Later participants get the latest one.
+  let (_, _, carol_connected) = join(&mut srv).await;
+  assert_eq!(
+    carol_connected["recalculation"],
+    recalculated["recalculation"]
+  );
+}
+
+#[actix_rt::test]
+async fn sorting_moves_cells_comments_and_references() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  let cells = [
+    (0, 0, "b"),
+    (0, 1, "=A1&\"!\""),
+    (1, 0, "a"),
+    (1, 1, "=A2&\"!\""),
+    (0, 2, "=B2"),
+  ];
+  for (row, col, raw) in cells.iter() {
+    send(&mut alice, update_cell(alice_id, *row, *col, raw)).await;
+    recv(&mut alice).await;
+    recv(&mut bob).await;
+  }
+  let set_format = json!({"type": "SetFormat", "sheet_id": 1, "row": 0, "col": 0, "format": "0%"});
+  send(&mut alice, set_format).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+  let add = json!({"type": "AddComment", "row": 1, "col": 0, "body": "first"});
+  send(&mut alice, add).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+  let define = json!({"type": "DefineName", "name": "Top", "definition": "A1"});
+  send(&mut alice, define).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+
+  let range = json!({"top": 0, "left": 0, "bottom": 1, "right": 1});
+  let sort = json!({"type": "SortRange", "range": range, "order": [1, 1]});
+  send(&mut alice, sort).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+  let wide = json!({"top": 0, "left": 0, "bottom": 1, "right": i32::MAX});
+  let sort = json!({"type": "SortRange", "range": wide, "order": [1, 0]});
+  send(&mut alice, sort).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+  // Nothing is stored when a name is invalid, bob hears about the next sort
+  // only.
+  let sort = json!({
+    "type": "SortRange",
+    "range": range,
+    "order": [1, 0],
+    "cells": [{"row": 0, "col": 0, "raw": "a"}],
+    "names": {"A1": "=A2"},
+  });
+  send(&mut alice, sort).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+
+  // The client rewrote the references, the formulas of `B1:B2` read from
+  // their own row so they don't change.
+  let edits = json!([
+    {"row": 0, "col": 0, "raw": "a"},
+    {"row": 1, "col": 0, "raw": "b"},
+    {"row": 0, "col": 2, "raw": "=B1"},
+  ]);
+  let sort = json!({
+    "type": "SortRange",
+    "range": range,
+    "order": [1, 0],
+    "cells": edits,
+    "names": {"TOP": "=A2"},
+  });
+  send(&mut alice, sort).await;
+  // Every changed cell goes out at once, formats moving along.
+  let updated = recv(&mut bob).await;
+  assert_eq!(updated["type"], "CellsUpdated");
+  assert_eq!(updated["user_id"], alice_id);
+  let cells: Vec<Value> = updated["cells"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| json!([c["row"], c["col"], c["raw"], c["format"]]))
+    .collect();
+  assert_eq!(
+    cells,
+    vec![
+      json!([0, 0, "a", ""]),
+      json!([0, 2, "=B1", ""]),
+      json!([1, 0, "b", "0%"])
+    ]
+  );
+  let moved = recv(&mut bob).await;
+  assert_eq!(moved["type"], "CommentUpdated");
+  assert_eq!(
+    (&moved["comment"]["row"], &moved["comment"]["col"]),
+    (&json!(0), &json!(0))
+  );
+  let renamed = recv(&mut bob).await;
+  assert_eq!(renamed["type"], "NameUpdated");
+  assert_eq!(
+    (&renamed["name"]["name"], &renamed["name"]["definition"]),
+    (&json!("Top"), &json!("=A2"))
+  );
+}
+
+#[actix_rt::test]
+async fn filter_views_are_shared() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  let range = json!({"top": 0, "left": 0, "bottom": 9, "right": 2});
+  let filter = json!({"range": range, "conditions": [{"col": 5, "criterion": "x"}]});
+  send(&mut alice, json!({"type": "SetFilter", "filter": filter})).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+
+  let filter = json!({"range": range, "conditions": [{"col": 1, "criterion": ">10"}]});
+  send(&mut alice, json!({"type": "SetFilter", "filter": filter})).await;
+  let changed = recv(&mut bob).await;
+  assert_eq!(changed["type"], "FilterChanged");
+  assert_eq!(changed["user_id"], alice_id);
+  assert_eq!(changed["filter"], filter);
+  recv(&mut alice).await;
+
+  let mut carol = connect(&mut srv).await;
+  send(&mut carol, hello(PROTOCOL_VERSION, &[])).await;
+  for expected in &["Welcome", "Connected", "Comments", "Names"] {
+    assert_eq!(recv(&mut carol).await["type"], *expected);
+  }
+  let loaded = recv(&mut carol).await;
+  assert_eq!(loaded["type"], "Filter");
+  assert_eq!(loaded["filter"], filter);
+  recv(&mut carol).await;
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+
+  send(&mut bob, json!({"type": "SetFilter", "filter": null})).await;
+  let removed = recv(&mut carol).await;
+  assert_eq!(removed["type"], "FilterChanged");
+  assert_eq!(removed["filter"], Value::Null);
+}
+
+#[actix_rt::test]
+async fn iterative_calculation_is_a_sheet_setting() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, connected) = join(&mut srv).await;
+  assert_eq!(connected["iteration"], Value::Null);
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  let iteration = json!({"max_iterations": 0, "tolerance": 0.001});
+  send(&mut alice, json!({"type": "SetIteration", "iteration": iteration})).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+
+  let iteration = json!({"max_iterations": 50, "tolerance": 0.01});
+  send(&mut alice, json!({"type": "SetIteration", "iteration": iteration})).await;
+  let changed = recv(&mut bob).await;
+  assert_eq!(changed["type"], "IterationChanged");
+  assert_eq!(changed["user_id"], alice_id);
+  assert_eq!(changed["iteration"], iteration);
+  recv(&mut alice).await;
+
+  let (mut carol, _, connected) = join(&mut srv).await;
+  assert_eq!(connected["iteration"], iteration);
+  recv(&mut alice).await;
+  recv(&mut bob).await;
+
+  send(&mut bob, json!({"type": "SetIteration", "iteration": null})).await;
+  let off = recv(&mut carol).await;
+  assert_eq!(off["type"], "IterationChanged");
+  assert_eq!(off["iteration"], Value::Null);
+}
+
+#[actix_rt::test]
+async fn replace_all_is_one_update_that_can_be_undone() {
+  let mut srv = start_server();
+  let (mut alice, alice_id, _) = join(&mut srv).await;
+  let (mut bob, _, _) = join(&mut srv).await;
+  recv(&mut alice).await;
+
+  let edits = json!([
+    {"row": 0, "col": 0, "raw": "SKU-1"},
+    {"row": 1, "col": 0, "raw": "=\"sku-2\""},
+    {"row": 0, "col": 1, "raw": "other"},
+  ]);
+  send(&mut alice, json!({"type": "UpdateCells", "cells": edits})).await;
+  let updated = recv(&mut bob).await;
+  assert_eq!(updated["type"], "CellsUpdated");
+  assert_eq!(updated["user_id"], alice_id);
+  assert_eq!(updated["cells"].as_array().unwrap().len(), 3);
+  recv(&mut alice).await;
+
+  let query = json!({"text": "sku"});
+  send(&mut alice, json!({"type": "Find", "query": query})).await;
+  let found = recv(&mut alice).await;
+  assert_eq!(found["type"], "Found");
+  let raws: Vec<&Value> = found["cells"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| &c["raw"])
+    .collect();
+  assert_eq!(raws, vec!["SKU-1", "=\"sku-2\""]);
+
+  let replace = json!({"type": "ReplaceAll", "query": query, "replacement": "P"});
+  send(&mut alice, replace).await;
+  let updated = recv(&mut bob).await;
+  assert_eq!(updated["type"], "CellsUpdated");
+  let raws: Vec<&Value> = updated["cells"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| &c["raw"])
+    .collect();
+  assert_eq!(raws, vec!["P-1", "=\"P-2\""]);
+  assert_eq!(recv(&mut alice).await["type"], "CellsUpdated");
+  let replaced = recv(&mut alice).await;
+  assert_eq!(replaced["type"], "Replaced");
+  assert_eq!(
+    replaced["previous"],
+    json!([
+      {"row": 0, "col": 0, "raw": "SKU-1"},
+      {"row": 1, "col": 0, "raw": "=\"sku-2\""},
+    ])
+  );
+
+  let undo = json!({"type": "UpdateCells", "cells": replaced["previous"]});
+  send(&mut alice, undo).await;
+  let updated = recv(&mut bob).await;
+  assert_eq!(updated["cells"][0]["raw"], "SKU-1");
+  assert_eq!(updated["cells"][1]["raw"], "=\"sku-2\"");
+  recv(&mut alice).await;
+
+  let invalid = json!({"text": "(", "regex": true});
+  send(&mut alice, json!({"type": "Find", "query": invalid})).await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+  let negative = json!([{"row": -1, "col": 0, "raw": "x"}]);
+  send(
+    &mut alice,
+    json!({"type": "UpdateCells", "cells": negative}),
+  )
+  .await;
+  assert_eq!(recv(&mut alice).await["type"], "Error");
+}
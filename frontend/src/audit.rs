@@ -0,0 +1,86 @@
+//! Formula auditing: where a cell's value comes from and what it feeds.
+
+use super::expr::{ExprResult, ExprTree};
+use super::printer::Formula;
+use super::Spreadsheet;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+
+/// Cells linked to a cell, as `(row, col)` sorted row by row.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Links {
+  /// Cells the formula mentions, or that mention the cell.
+  pub direct: Vec<(usize, usize)>,
+  /// `direct` plus the cells linked to those, and so on.
+  pub all: Vec<(usize, usize)>,
+}
+
+/// A sub-expression of a formula with its value, e.g. `A1*2` is `10` because
+/// `A1` is `5` and `2` is `2`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Explanation {
+  /// The sub-expression as it would be typed into a formula.
+  pub expr: String,
+  /// `None` for ranges, they stand for their cells rather than a value.
+  pub value: Option<ExprResult>,
+  pub children: Vec<Explanation>,
+}
+
+// Which way to follow the links between cells.
+#[derive(Clone, Copy)]
+pub(super) enum Direction {
+  Outbound,
+  Inbound,
+}
+
+pub(super) fn links(ss: &Spreadsheet, start: usize, direction: Direction) -> Links {
+  let next = |idx: usize| match direction {
+    Direction::Outbound => &ss.cells[idx].outbound,
+    Direction::Inbound => &ss.cells[idx].inbound,
+  };
+  let direct: Vec<usize> = next(start).iter().copied().collect();
+  let mut seen: HashSet<usize> = direct.iter().copied().collect();
+  let mut pending: VecDeque<usize> = direct.iter().copied().collect();
+  while let Some(idx) = pending.pop_front() {
+    for linked in next(idx) {
+      if seen.insert(*linked) {
+        pending.push_back(*linked);
+      }
+    }
+  }
+  Links {
+    direct: sorted_coords(ss, direct),
+    all: sorted_coords(ss, seen),
+  }
+}
+
+fn sorted_coords(
+  ss: &Spreadsheet,
+  indexes: impl IntoIterator<Item = usize>,
+) -> Vec<(usize, usize)> {
+  let mut coords: Vec<(usize, usize)> = indexes
+    .into_iter()
+    .map(|idx| (idx / ss.width, idx % ss.width))
+    .collect();
+  coords.sort_unstable();
+  coords
+}
+
+pub(super) fn explain(ss: &Spreadsheet, tree: &ExprTree) -> Explanation {
+  let children = match tree {
+    ExprTree::Unary(u) => vec![explain(ss, &u.child)],
+    ExprTree::Binary(b) => vec![explain(ss, &b.left), explain(ss, &b.right)],
+    // Their arguments mean something only within them.
+    ExprTree::Func(f) if f.name == "LET" || f.name == "LAMBDA" => vec![],
+    ExprTree::Func(f) => f.args.iter().map(|arg| explain(ss, arg)).collect(),
+    _ => vec![],
+  };
+  let is_range = tree
+    .reference(ss)
+    .is_some_and(|r| r.height() > 1 || r.width() > 1);
+  Explanation {
+    expr: Formula(tree).to_string(),
+    value: if is_range { None } else { Some(tree.eval(ss)) },
+    children,
+  }
+}
@@ -0,0 +1,213 @@
+//! Dates are numbers, like in every other spreadsheet: the integer part is
+//! the count of days since 1899-12-30 and the fractional part the time of
+//! day. That keeps them working with plain arithmetic (`=A1+7`) and matches
+//! the serial numbers other tools import and export for dates after 1900-03-01.
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// Days between 1899-12-30 and the Unix epoch.
+const UNIX_EPOCH_SERIAL: i64 = 25569;
+const SECONDS_PER_DAY: f64 = 86400.;
+
+/// Broken down date and time of a serial number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+  pub year: i64,
+  /// 1 to 12.
+  pub month: u32,
+  /// 1 to 31.
+  pub day: u32,
+  pub hour: u32,
+  pub minute: u32,
+  pub second: u32,
+  /// 0 is Sunday.
+  pub weekday: u32,
+}
+
+impl DateTime {
+  /// Returns `None` for serials we can't show as a date, like negative ones.
+  pub fn from_serial(serial: f64) -> Option<DateTime> {
+    // Up to 9999-12-31, like other spreadsheets.
+    if !(0. ..2958466.).contains(&serial) {
+      return None;
+    }
+    // Round to the second first so 23:59:59.9 rolls over to the next day.
+    let total_seconds = (serial * SECONDS_PER_DAY).round() as i64;
+    let days = total_seconds.div_euclid(86400);
+    let seconds = total_seconds.rem_euclid(86400) as u32;
+    let (year, month, day) = civil_from_days(days - UNIX_EPOCH_SERIAL);
+    Some(DateTime {
+      year,
+      month,
+      day,
+      hour: seconds / 3600,
+      minute: seconds / 60 % 60,
+      second: seconds % 60,
+      weekday: weekday(days as f64),
+    })
+  }
+}
+
+/// Current local date and time as a serial number.
+pub fn now() -> f64 {
+  let millis = unix_millis();
+  local_serial(millis, utc_offset_minutes(millis))
+}
+
+/// UTC date and time of a Unix timestamp in milliseconds, as a serial
+/// number. Unlike `now` it doesn't depend on where it runs, so participants
+/// in different time zones agree on it.
+pub fn serial_from_unix_millis(millis: f64) -> f64 {
+  local_serial(millis, 0.)
+}
+
+// Date and time of a Unix timestamp in milliseconds where local time is
+// `utc_offset` minutes behind UTC, as a serial number.
+fn local_serial(millis: f64, utc_offset: f64) -> f64 {
+  let local = millis - utc_offset * 60_000.;
+  UNIX_EPOCH_SERIAL as f64 + local / (SECONDS_PER_DAY * 1000.)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+  type Date;
+
+  #[wasm_bindgen(constructor)]
+  fn new(millis: f64) -> Date;
+
+  #[wasm_bindgen(static_method_of = Date)]
+  fn now() -> f64;
+
+  #[wasm_bindgen(method, js_name = getTimezoneOffset)]
+  fn get_timezone_offset(this: &Date) -> f64;
+}
+
+#[cfg(target_arch = "wasm32")]
+fn unix_millis() -> f64 {
+  Date::now()
+}
+
+// Minutes to add to local time to get UTC, at the given time.
+#[cfg(target_arch = "wasm32")]
+fn utc_offset_minutes(millis: f64) -> f64 {
+  Date::new(millis).get_timezone_offset()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_millis() -> f64 {
+  use std::time::{SystemTime, UNIX_EPOCH};
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0., |d| d.as_millis() as f64)
+}
+
+// Outside of the browser we have no time zone database, use UTC.
+#[cfg(not(target_arch = "wasm32"))]
+fn utc_offset_minutes(_millis: f64) -> f64 {
+  0.
+}
+
+/// Serial number of midnight on the given date. Out of range months and
+/// days roll over into the next or previous year/month, `(2024, 13, 1)` is
+/// 2025-01-01 and `(2024, 3, 0)` is 2024-02-29.
+pub fn serial_from_ymd(year: i64, month: i64, day: i64) -> f64 {
+  let months = year * 12 + (month - 1);
+  let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+  (days_from_civil(year, month as u32, 1) + UNIX_EPOCH_SERIAL + day - 1) as f64
+}
+
+/// Fraction of a day for the given time of day.
+pub fn serial_from_hms(hour: i64, minute: i64, second: f64) -> f64 {
+  (hour as f64 * 3600. + minute as f64 * 60. + second) / SECONDS_PER_DAY
+}
+
+/// Year, month and day of a serial number, ignoring the time of day.
+pub fn ymd_from_serial(serial: f64) -> (i64, u32, u32) {
+  civil_from_days(serial.floor() as i64 - UNIX_EPOCH_SERIAL)
+}
+
+/// Day of the week of a serial number, 0 is Sunday.
+pub fn weekday(serial: f64) -> u32 {
+  // Serial 0 (1899-12-30) was a Saturday.
+  (serial.floor() as i64 + 6).rem_euclid(7) as u32
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+  (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+  match month {
+    2 if is_leap_year(year) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, see
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let yoe = year - era * 400;
+  let month = month as i64;
+  let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let doe = days - era * 146097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn serials_match_other_spreadsheets() {
+    assert_eq!(serial_from_ymd(1900, 3, 1), 61.);
+    assert_eq!(serial_from_ymd(2024, 3, 15), 45366.);
+    assert_eq!(ymd_from_serial(45366.75), (2024, 3, 15));
+    assert_eq!(serial_from_ymd(2024, 13, 1), serial_from_ymd(2025, 1, 1));
+    assert_eq!(serial_from_ymd(2024, 3, 0), serial_from_ymd(2024, 2, 29));
+    // 2024-03-15 18:00 UTC.
+    assert_eq!(serial_from_unix_millis(1_710_525_600_000.), 45366.75);
+  }
+
+  #[test]
+  fn shared_time_ignores_time_zones() {
+    // 2024-03-15 18:00 UTC, local time in Paris and in New York.
+    let millis = 1_710_525_600_000.;
+    let hour = |utc_offset| {
+      DateTime::from_serial(local_serial(millis, utc_offset))
+        .unwrap()
+        .hour
+    };
+    assert_eq!((hour(-60.), hour(240.)), (19, 14));
+    // What the backend hands out reads the same in both, as UTC.
+    assert_eq!(serial_from_unix_millis(millis), 45366.75);
+  }
+
+  #[test]
+  fn date_time_from_serial() {
+    let dt = DateTime::from_serial(45366. + serial_from_hms(15, 30, 5.)).unwrap();
+    assert_eq!((dt.year, dt.month, dt.day), (2024, 3, 15));
+    assert_eq!((dt.hour, dt.minute, dt.second), (15, 30, 5));
+    // A Friday.
+    assert_eq!(dt.weekday, 5);
+    assert!(DateTime::from_serial(-1.).is_none());
+  }
+}
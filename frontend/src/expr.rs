@@ -1,16 +1,25 @@
+use super::format;
+use super::functions;
 use super::parser::cell;
+use super::scope;
 use super::Spreadsheet;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::ops;
 
-#[derive(Clone)]
+/// A parsed cell. Prints back to the text it was parsed from, normalized,
+/// see the `printer` module.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
 pub enum ExprTree {
+  #[default]
   Empty,
   Error(String),
   Leaf(ValueNode),
   Unary(Box<UnaryNode>),
   Binary(Box<BinaryNode>),
+  Func(Box<FuncNode>),
 }
 
 impl ExprTree {
@@ -26,34 +35,249 @@ impl ExprTree {
       ExprTree::Empty => ExprResult::Text("".to_string()),
       ExprTree::Error(e) => ExprResult::Error(e.clone()),
       ExprTree::Leaf(ValueNode::Num(n)) => ExprResult::Num(*n),
-      ExprTree::Leaf(ValueNode::Coord(row, col)) => ss.get(*row, *col).out.clone(),
+      ExprTree::Leaf(ValueNode::Date(n, _)) => ExprResult::Num(*n),
+      ExprTree::Leaf(ValueNode::Coord(row, col)) => match ss.check_bounds(*row, *col) {
+        Ok(()) => ss.get(*row, *col).out.clone(),
+        Err(_) => ExprResult::Error("#REF!".to_string()),
+      },
       ExprTree::Leaf(ValueNode::Text(t)) => ExprResult::Text(t.clone()),
+      ExprTree::Leaf(ValueNode::Range(range)) => range.values(ss),
+      ExprTree::Leaf(ValueNode::Spill(row, col)) => match ss.spill_range(*row, *col) {
+        Some(range) => range.values(ss),
+        None => ExprResult::Error("#REF!".to_string()),
+      },
+      // `LET` variables and `LAMBDA` parameters shadow workbook names.
+      ExprTree::Leaf(ValueNode::Name(name)) => match (ss.scope.get(name), ss.name(name)) {
+        (Some(value), _) => value,
+        (None, Some(definition)) => ss.scope.outside(|| definition.eval(ss)),
+        (None, None) => ExprResult::Error("#NAME?".to_string()),
+      },
       ExprTree::Unary(u) => u.op.apply(u.child.eval(ss)),
       ExprTree::Binary(b) => b.op.apply(b.left.eval(ss), b.right.eval(ss)),
+      ExprTree::Func(f) => functions::call(&f.name, &f.args, ss),
     }
   }
 
   pub fn fill_outbound(&self, ss: &Spreadsheet, outbound: &mut HashSet<usize>) {
+    self.fill_outbound_from(ss, outbound, &mut HashSet::new());
+  }
+
+  // `followed` holds the uppercased names whose definitions were already
+  // followed, a `LAMBDA` can call itself.
+  fn fill_outbound_from(
+    &self,
+    ss: &Spreadsheet,
+    outbound: &mut HashSet<usize>,
+    followed: &mut HashSet<String>,
+  ) {
     match self {
       ExprTree::Empty => (),
       ExprTree::Error(_) => (),
       ExprTree::Leaf(ValueNode::Text(_)) => (),
       ExprTree::Leaf(ValueNode::Num(_)) => (),
+      ExprTree::Leaf(ValueNode::Date(..)) => (),
+      // References past the edges of the sheet are `#REF!`, they don't
+      // depend on anything.
       ExprTree::Leaf(ValueNode::Coord(row, col)) => {
-        outbound.insert(ss.get_index(*row, *col));
+        if ss.check_bounds(*row, *col).is_ok() {
+          outbound.insert(ss.get_index(*row, *col));
+        }
+      }
+      ExprTree::Leaf(ValueNode::Range(range)) => {
+        if range.in_sheet(ss) {
+          for (row, col) in range.coords(ss) {
+            outbound.insert(ss.get_index(row, col));
+          }
+        }
+      }
+      // The spilled cells change with the formula that spilled them.
+      ExprTree::Leaf(ValueNode::Spill(row, col)) => {
+        if ss.check_bounds(*row, *col).is_ok() {
+          outbound.insert(ss.get_index(*row, *col));
+        }
+      }
+      ExprTree::Leaf(ValueNode::Name(name)) => {
+        if let Some(definition) = follow(ss, name, followed) {
+          definition.fill_outbound_from(ss, outbound, followed);
+        }
+      }
+      ExprTree::Unary(u) => u.child.fill_outbound_from(ss, outbound, followed),
+      ExprTree::Binary(b) => {
+        b.left.fill_outbound_from(ss, outbound, followed);
+        b.right.fill_outbound_from(ss, outbound, followed);
+      }
+      ExprTree::Func(f) => {
+        // Calls a `LAMBDA` when it isn't a built-in function.
+        if let Some(definition) = follow(ss, &f.name, followed) {
+          definition.fill_outbound_from(ss, outbound, followed);
+        }
+        if let Some(range) = functions::implicit_range(&f.name, &f.args, ss) {
+          for (row, col) in range.coords(ss) {
+            outbound.insert(ss.get_index(row, col));
+          }
+        }
+        for arg in &f.args {
+          arg.fill_outbound_from(ss, outbound, followed);
+        }
+      }
+    }
+  }
+
+  /// The cells a reference stands for, following names. `None` for
+  /// anything but references, and for references past the edges of the
+  /// sheet, which evaluate to `#REF!`.
+  pub fn reference(&self, ss: &Spreadsheet) -> Option<Range> {
+    match self {
+      ExprTree::Leaf(ValueNode::Coord(row, col)) => {
+        Some(Range::new((*row, *col), (*row, *col))).filter(|r| r.in_sheet(ss))
+      }
+      ExprTree::Leaf(ValueNode::Range(range)) => Some(*range).filter(|r| r.in_sheet(ss)),
+      ExprTree::Leaf(ValueNode::Spill(row, col)) => ss.spill_range(*row, *col),
+      ExprTree::Leaf(ValueNode::Name(name)) if ss.scope.get(name).is_none() => {
+        ss.name(name)?.reference(ss)
+      }
+      _ => None,
+    }
+  }
+
+  /// Whether the tree calls a volatile function, see
+  /// `functions::is_volatile`, directly or through the names it uses.
+  pub fn is_volatile(&self, ss: &Spreadsheet) -> bool {
+    self.is_volatile_from(ss, &mut HashSet::new())
+  }
+
+  // Like `fill_outbound_from`.
+  fn is_volatile_from(&self, ss: &Spreadsheet, followed: &mut HashSet<String>) -> bool {
+    match self {
+      ExprTree::Leaf(ValueNode::Name(name)) => {
+        follow(ss, name, followed).is_some_and(|d| d.is_volatile_from(ss, followed))
+      }
+      ExprTree::Unary(u) => u.child.is_volatile_from(ss, followed),
+      ExprTree::Binary(b) => {
+        b.left.is_volatile_from(ss, followed) || b.right.is_volatile_from(ss, followed)
+      }
+      ExprTree::Func(f) => {
+        functions::is_volatile(&f.name)
+          || follow(ss, &f.name, followed).is_some_and(|d| d.is_volatile_from(ss, followed))
+          || f.args.iter().any(|a| a.is_volatile_from(ss, followed))
+      }
+      _ => false,
+    }
+  }
+
+  /// Whether `name` appears anywhere in the tree, ignoring case, including
+  /// as a function called like `=Double(2)`.
+  pub fn uses_name(&self, name: &str) -> bool {
+    match self {
+      ExprTree::Leaf(ValueNode::Name(n)) => n.eq_ignore_ascii_case(name),
+      ExprTree::Unary(u) => u.child.uses_name(name),
+      ExprTree::Binary(b) => b.left.uses_name(name) || b.right.uses_name(name),
+      ExprTree::Func(f) => {
+        f.name.eq_ignore_ascii_case(name) || f.args.iter().any(|arg| arg.uses_name(name))
+      }
+      _ => false,
+    }
+  }
+
+  /// Makes references follow the cells they point to, `moves` mapping the
+  /// `(row, col)` of each cell that moved to where it went. Returns whether
+  /// anything changed.
+  pub fn move_references(&mut self, moves: &HashMap<(usize, usize), (usize, usize)>) -> bool {
+    match self {
+      ExprTree::Leaf(ValueNode::Coord(row, col)) | ExprTree::Leaf(ValueNode::Spill(row, col)) => {
+        match moves.get(&(*row, *col)) {
+          Some(&(to_row, to_col)) => {
+            *row = to_row;
+            *col = to_col;
+            true
+          }
+          None => false,
+        }
       }
-      ExprTree::Unary(u) => u.child.fill_outbound(ss, outbound),
+      ExprTree::Leaf(ValueNode::Range(range)) => match range.moved(moves) {
+        Some(moved) => {
+          *range = moved;
+          true
+        }
+        None => false,
+      },
+      ExprTree::Unary(u) => u.child.move_references(moves),
       ExprTree::Binary(b) => {
-        b.left.fill_outbound(ss, outbound);
-        b.right.fill_outbound(ss, outbound);
+        let left = b.left.move_references(moves);
+        let right = b.right.move_references(moves);
+        left || right
       }
+      ExprTree::Func(f) => {
+        let mut moved = false;
+        for arg in &mut f.args {
+          moved |= arg.move_references(moves);
+        }
+        moved
+      }
+      _ => false,
+    }
+  }
+
+  /// Moves every reference by `rows` and `cols`, like copying the formula
+  /// to another cell. Returns whether anything changed, `None` when a
+  /// reference would end up outside a sheet whose height and width are
+  /// `bounds`.
+  pub fn offset_references(
+    &mut self,
+    rows: isize,
+    cols: isize,
+    bounds: (usize, usize),
+  ) -> Option<bool> {
+    let (height, width) = bounds;
+    let offset = |(row, col): (usize, usize)| {
+      let row = row as isize + rows;
+      let col = col as isize + cols;
+      if row < 0 || col < 0 || row as usize >= height || col as usize >= width {
+        None
+      } else {
+        Some((row as usize, col as usize))
+      }
+    };
+    match self {
+      ExprTree::Leaf(ValueNode::Coord(row, col)) | ExprTree::Leaf(ValueNode::Spill(row, col)) => {
+        let (to_row, to_col) = offset((*row, *col))?;
+        *row = to_row;
+        *col = to_col;
+        Some(rows != 0 || cols != 0)
+      }
+      ExprTree::Leaf(ValueNode::Range(range)) => {
+        *range = Range::new(offset(range.start)?, offset(range.end)?);
+        Some(rows != 0 || cols != 0)
+      }
+      ExprTree::Unary(u) => u.child.offset_references(rows, cols, bounds),
+      ExprTree::Binary(b) => {
+        let left = b.left.offset_references(rows, cols, bounds)?;
+        let right = b.right.offset_references(rows, cols, bounds)?;
+        Some(left || right)
+      }
+      ExprTree::Func(f) => {
+        let mut moved = false;
+        for arg in &mut f.args {
+          moved |= arg.offset_references(rows, cols, bounds)?;
+        }
+        Some(moved)
+      }
+      _ => Some(false),
     }
   }
 }
 
-impl Default for ExprTree {
-  fn default() -> Self {
-    ExprTree::Empty
+// The definition of `name`, unless it was already followed.
+fn follow<'a>(
+  ss: &'a Spreadsheet,
+  name: &str,
+  followed: &mut HashSet<String>,
+) -> Option<&'a ExprTree> {
+  let definition = ss.name(name)?;
+  if followed.insert(name.to_ascii_uppercase()) {
+    Some(definition)
+  } else {
+    None
   }
 }
 
@@ -63,6 +287,61 @@ pub enum ExprResult {
   Num(f64),
   Text(String),
   Error(String),
+  /// Rows of values, at least one of one value, e.g. from `=A1:B3*2`. Cells
+  /// spill them over the cells below and to their right, see `spill`.
+  Array(Vec<Vec<ExprResult>>),
+  /// A function made with `LAMBDA`. Cells can't show one, they show
+  /// `#CALC!` instead.
+  Lambda(Box<Lambda>),
+}
+
+/// `LAMBDA(x, y, x*y)`, called like `=Product(2, 3)` once defined as a name
+/// or like `=LET(f, LAMBDA(x, x*2), f(3))`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lambda {
+  /// Uppercased parameter names.
+  pub params: Vec<String>,
+  pub body: ExprTree,
+  /// What was in scope where the lambda was made. The body sees that rather
+  /// than what's in scope where it's called, see `scope`.
+  pub captured: scope::Bindings,
+}
+
+impl ExprResult {
+  /// The value as text, numbers show as they do without a format. Arrays
+  /// show their first value.
+  pub fn as_text(&self) -> String {
+    match self {
+      ExprResult::Num(n) => format::general(*n),
+      ExprResult::Text(t) | ExprResult::Error(t) => t.clone(),
+      ExprResult::Array(rows) => rows[0][0].as_text(),
+      ExprResult::Lambda(_) => "#CALC!".to_string(),
+    }
+  }
+
+  /// The rows of an array, or a single row of one value for anything else.
+  pub fn into_rows(self) -> Vec<Vec<ExprResult>> {
+    match self {
+      ExprResult::Array(rows) => rows,
+      value => vec![vec![value]],
+    }
+  }
+
+  /// The first value of an array, or the value itself.
+  pub fn top_left(self) -> ExprResult {
+    match self {
+      ExprResult::Array(rows) => rows.into_iter().next().unwrap().swap_remove(0),
+      value => value,
+    }
+  }
+
+  /// Number of rows and columns, `(1, 1)` for anything but arrays.
+  pub fn size(&self) -> (usize, usize) {
+    match self {
+      ExprResult::Array(rows) => (rows.len(), rows[0].len()),
+      _ => (1, 1),
+    }
+  }
 }
 
 impl PartialEq for ExprResult {
@@ -71,6 +350,8 @@ impl PartialEq for ExprResult {
       (ExprResult::Num(n1), ExprResult::Num(n2)) => n1 == n2,
       (ExprResult::Text(t1), ExprResult::Text(t2)) => t1 == t2,
       (ExprResult::Error(e1), ExprResult::Error(e2)) => e1 == e2,
+      (ExprResult::Array(a1), ExprResult::Array(a2)) => a1 == a2,
+      (ExprResult::Lambda(l1), ExprResult::Lambda(l2)) => l1 == l2,
       _ => false,
     }
   }
@@ -80,10 +361,12 @@ impl ops::Add<ExprResult> for ExprResult {
   type Output = ExprResult;
 
   fn add(self, rhs: ExprResult) -> Self::Output {
-    match (&self, &rhs) {
+    match (self, rhs) {
       (ExprResult::Num(n1), ExprResult::Num(n2)) => ExprResult::Num(n1 + n2),
       (ExprResult::Text(t1), ExprResult::Text(t2)) => ExprResult::Text(format!("{}{}", t1, t2)),
-      _ => ExprResult::Error(format!("can't add {:?} with {:?}", self, rhs)),
+      // Errors carry through, so a cell shows the error it's downstream of.
+      (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => e,
+      (lhs, rhs) => ExprResult::Error(format!("can't add {:?} with {:?}", lhs, rhs)),
     }
   }
 }
@@ -92,9 +375,10 @@ impl ops::Sub<ExprResult> for ExprResult {
   type Output = ExprResult;
 
   fn sub(self, rhs: ExprResult) -> Self::Output {
-    match (&self, &rhs) {
+    match (self, rhs) {
       (ExprResult::Num(n1), ExprResult::Num(n2)) => ExprResult::Num(n1 - n2),
-      _ => ExprResult::Error(format!("can't sub {:?} with {:?}", self, rhs)),
+      (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => e,
+      (lhs, rhs) => ExprResult::Error(format!("can't sub {:?} with {:?}", lhs, rhs)),
     }
   }
 }
@@ -103,9 +387,10 @@ impl ops::Mul<ExprResult> for ExprResult {
   type Output = ExprResult;
 
   fn mul(self, rhs: ExprResult) -> Self::Output {
-    match (&self, &rhs) {
+    match (self, rhs) {
       (ExprResult::Num(n1), ExprResult::Num(n2)) => ExprResult::Num(n1 * n2),
-      _ => ExprResult::Error(format!("can't mul {:?} with {:?}", self, rhs)),
+      (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => e,
+      (lhs, rhs) => ExprResult::Error(format!("can't mul {:?} with {:?}", lhs, rhs)),
     }
   }
 }
@@ -114,61 +399,287 @@ impl ops::Div<ExprResult> for ExprResult {
   type Output = ExprResult;
 
   fn div(self, rhs: ExprResult) -> Self::Output {
-    match (&self, &rhs) {
+    match (self, rhs) {
       (ExprResult::Num(n1), ExprResult::Num(n2)) => ExprResult::Num(n1 / n2),
-      _ => ExprResult::Error(format!("can't div {:?} with {:?}", self, rhs)),
+      (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => e,
+      (lhs, rhs) => ExprResult::Error(format!("can't div {:?} with {:?}", lhs, rhs)),
     }
   }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
 pub enum ValueNode {
   Text(String),
   Num(f64),
+  /// A date or time typed in as such, e.g. `2024-03-15`. Evaluates to its
+  /// serial number, the format is applied to cells without one.
+  Date(f64, String),
   Coord(usize, usize),
+  Range(Range),
+  /// A defined name, as typed. See `Spreadsheet::define_name`.
+  Name(String),
+  /// `A1#`, the cells the formula in `A1` spilled over.
+  Spill(usize, usize),
+}
+
+/// A rectangle of cells, both corners included.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+  /// Top left corner, as `(row, col)`.
+  pub start: (usize, usize),
+  /// Bottom right corner.
+  pub end: (usize, usize),
 }
 
-#[derive(Clone)]
+impl Range {
+  /// Range between any two opposite corners.
+  pub fn new(a: (usize, usize), b: (usize, usize)) -> Range {
+    Range {
+      start: (a.0.min(b.0), a.1.min(b.1)),
+      end: (a.0.max(b.0), a.1.max(b.1)),
+    }
+  }
+
+  /// Whether the whole range is inside the sheet.
+  pub fn in_sheet(&self, ss: &Spreadsheet) -> bool {
+    self.end.0 < ss.height() && self.end.1 < ss.width()
+  }
+
+  /// The part of the range inside the sheet, if any.
+  pub fn clip(&self, ss: &Spreadsheet) -> Option<Range> {
+    if self.start.0 >= ss.height() || self.start.1 >= ss.width() {
+      return None;
+    }
+    let end = (
+      self.end.0.min(ss.height() - 1),
+      self.end.1.min(ss.width() - 1),
+    );
+    Some(Range::new(self.start, end))
+  }
+
+  pub fn height(&self) -> usize {
+    self.end.0 - self.start.0 + 1
+  }
+
+  pub fn width(&self) -> usize {
+    self.end.1 - self.start.1 + 1
+  }
+
+  /// Values of the cells as an array, or just the value of a single cell.
+  /// `#REF!` if the range goes past the edges of the sheet.
+  pub fn values(&self, ss: &Spreadsheet) -> ExprResult {
+    if !self.in_sheet(ss) {
+      return ExprResult::Error("#REF!".to_string());
+    }
+    if self.height() == 1 && self.width() == 1 {
+      return ss.get(self.start.0, self.start.1).out().clone();
+    }
+    let rows = (self.start.0..=self.end.0)
+      .map(|row| {
+        (self.start.1..=self.end.1)
+          .map(|col| ss.get(row, col).out().clone())
+          .collect()
+      })
+      .collect();
+    ExprResult::Array(rows)
+  }
+
+  /// Where the range goes when its cells move, see
+  /// `ExprTree::move_references`. It only follows cells that all moved the
+  /// same way, like a row of a sorted range. Otherwise it stays, a range
+  /// over a whole sorted range still covers the same cells.
+  pub fn moved(&self, moves: &HashMap<(usize, usize), (usize, usize)>) -> Option<Range> {
+    if self.height() * self.width() > moves.len() {
+      return None;
+    }
+    let offset = |(row, col): (usize, usize)| {
+      let (to_row, to_col) = moves.get(&(row, col))?;
+      Some((
+        *to_row as isize - row as isize,
+        *to_col as isize - col as isize,
+      ))
+    };
+    let (rows, cols) = offset(self.start)?;
+    for row in self.start.0..=self.end.0 {
+      for col in self.start.1..=self.end.1 {
+        if offset((row, col)) != Some((rows, cols)) {
+          return None;
+        }
+      }
+    }
+    let shift = |(row, col): (usize, usize)| {
+      (
+        (row as isize + rows) as usize,
+        (col as isize + cols) as usize,
+      )
+    };
+    Some(Range::new(shift(self.start), shift(self.end)))
+  }
+
+  /// Coordinates of the cells row by row, leaving out any past the edges of
+  /// the sheet.
+  pub fn coords(&self, ss: &Spreadsheet) -> impl Iterator<Item = (usize, usize)> {
+    let (start, end) = match self.clip(ss) {
+      Some(clipped) => (clipped.start, clipped.end),
+      // An empty iterator of the same type.
+      None => ((1, 0), (0, 0)),
+    };
+    (start.0..=end.0).flat_map(move |row| (start.1..=end.1).map(move |col| (row, col)))
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FuncNode {
+  /// Uppercased.
+  pub name: String,
+  pub args: Vec<ExprTree>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct UnaryNode {
   pub op: UnaryOp,
   pub child: ExprTree,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum UnaryOp {
-  Not,
+  Neg,
+  Plus,
+  Percent,
 }
 
 impl UnaryOp {
   pub fn apply(&self, val: ExprResult) -> ExprResult {
+    if let ExprResult::Array(rows) = val {
+      let rows = rows
+        .into_iter()
+        .map(|row| row.into_iter().map(|v| self.apply(v)).collect())
+        .collect();
+      return ExprResult::Array(rows);
+    }
     match self {
-      UnaryOp::Not => ExprResult::Num(-1.) * val,
+      UnaryOp::Neg => ExprResult::Num(-1.) * val,
+      // Leaves anything as it is, text included.
+      UnaryOp::Plus => val,
+      UnaryOp::Percent => val / ExprResult::Num(100.),
     }
   }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct BinaryNode {
   pub op: BinaryOp,
   pub left: ExprTree,
   pub right: ExprTree,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOp {
   Sum,
   Sub,
   Mul,
   Div,
+  Pow,
+  Concat,
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
 }
 
 impl BinaryOp {
   pub fn apply(&self, val1: ExprResult, val2: ExprResult) -> ExprResult {
+    if matches!(val1, ExprResult::Array(_)) || matches!(val2, ExprResult::Array(_)) {
+      return broadcast(val1, val2, |v1, v2| self.apply(v1, v2));
+    }
     match self {
       BinaryOp::Sum => val1 + val2,
       BinaryOp::Sub => val1 - val2,
       BinaryOp::Mul => val1 * val2,
       BinaryOp::Div => val1 / val2,
+      BinaryOp::Pow => match (&val1, &val2) {
+        (ExprResult::Num(n1), ExprResult::Num(n2)) => {
+          let n = n1.powf(*n2);
+          // 0^0 is undefined, and so are even roots of negative numbers.
+          if (*n1 == 0. && *n2 == 0.) || !n.is_finite() {
+            ExprResult::Error("#NUM!".to_string())
+          } else {
+            ExprResult::Num(n)
+          }
+        }
+        (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => e.clone(),
+        _ => ExprResult::Error(format!("can't pow {:?} with {:?}", val1, val2)),
+      },
+      BinaryOp::Concat => match (val1, val2) {
+        (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => e,
+        (val1, val2) => ExprResult::Text(val1.as_text() + &val2.as_text()),
+      },
+      BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+        match compare(&val1, &val2) {
+          Ok(ordering) => {
+            let holds = match self {
+              BinaryOp::Eq => ordering == Ordering::Equal,
+              BinaryOp::Ne => ordering != Ordering::Equal,
+              BinaryOp::Lt => ordering == Ordering::Less,
+              BinaryOp::Le => ordering != Ordering::Greater,
+              BinaryOp::Gt => ordering == Ordering::Greater,
+              _ => ordering != Ordering::Less,
+            };
+            ExprResult::Num(if holds { 1. } else { 0. })
+          }
+          Err(e) => e,
+        }
+      }
     }
   }
 }
+
+// Orders two values the way comparisons do. Numbers sort before text, text
+// compares ignoring case, and an empty cell is 0 next to a number.
+fn compare(val1: &ExprResult, val2: &ExprResult) -> Result<Ordering, ExprResult> {
+  match (val1, val2) {
+    (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => Err(e.clone()),
+    (ExprResult::Num(n1), ExprResult::Num(n2)) => Ok(n1.partial_cmp(n2).unwrap_or(Ordering::Equal)),
+    (ExprResult::Num(n), ExprResult::Text(t)) if t.is_empty() => compare(val1, &ExprResult::Num(0.)),
+    (ExprResult::Text(t), ExprResult::Num(_)) if t.is_empty() => compare(&ExprResult::Num(0.), val2),
+    (ExprResult::Num(_), ExprResult::Text(_)) => Ok(Ordering::Less),
+    (ExprResult::Text(_), ExprResult::Num(_)) => Ok(Ordering::Greater),
+    (ExprResult::Text(t1), ExprResult::Text(t2)) => {
+      Ok(t1.to_lowercase().cmp(&t2.to_lowercase()))
+    }
+    _ => Err(ExprResult::Error("#VALUE!".to_string())),
+  }
+}
+
+// Applies `op` to the values of two arrays pairwise, or of an array and a
+// single value. A single row or column stretches to the size of the other
+// array, positions only one of the arrays has are `#N/A`.
+fn broadcast(
+  val1: ExprResult,
+  val2: ExprResult,
+  op: impl Fn(ExprResult, ExprResult) -> ExprResult,
+) -> ExprResult {
+  let (rows1, rows2) = (val1.into_rows(), val2.into_rows());
+  let height = rows1.len().max(rows2.len());
+  let width = rows1[0].len().max(rows2[0].len());
+  let at = |rows: &[Vec<ExprResult>], row: usize, col: usize| {
+    let row = if rows.len() == 1 { 0 } else { row };
+    let col = if rows[0].len() == 1 { 0 } else { col };
+    rows
+      .get(row)
+      .and_then(|r| r.get(col))
+      .cloned()
+      .unwrap_or_else(|| ExprResult::Error("#N/A".to_string()))
+  };
+  let rows = (0..height)
+    .map(|row| {
+      (0..width)
+        .map(|col| op(at(&rows1, row, col), at(&rows2, row, col)))
+        .collect()
+    })
+    .collect();
+  ExprResult::Array(rows)
+}
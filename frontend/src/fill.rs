@@ -0,0 +1,457 @@
+//! Autofill, see `Spreadsheet::fill_range`. Each row or column of the
+//! source is extended on its own. Series carry on: numbers (`1, 2` gives
+//! `3, 4`, and uneven steps follow their trend), dates by days, months or
+//! years, weekday and month names, and text ending with a number like
+//! `Item 1`. Anything else is repeated, formulas having their references
+//! moved along. Like in other spreadsheets, a lone number is copied while a
+//! lone date, name or `Item 1` counts up.
+
+use super::date;
+use super::expr::{ExprTree, Range, ValueNode};
+use super::{Edit, Spreadsheet};
+
+/// Names that follow each other, all matched ignoring case.
+const LISTS: &[&[&str]] = &[
+  &[
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+  ],
+  &["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+  &[
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+  ],
+  &[
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ],
+];
+/// Most decimals numbers of a series get, past that it's rounding noise.
+const MAX_DECIMALS: usize = 15;
+
+/// What the cells filled from `source` get. `target` must extend `source`
+/// down, up, right or left: it has the same columns (or rows) and either
+/// contains `source` or is right next to it.
+pub fn fill(ss: &Spreadsheet, source: Range, target: Range) -> Result<Vec<Edit>, String> {
+  let (vertical, forward) =
+    direction(source, target).ok_or_else(|| format!("{} doesn't extend {}", target, source))?;
+  let axis = |(row, col): (usize, usize)| if vertical { row } else { col };
+  let (first, last) = (axis(source.start), axis(source.end));
+  // Where the filled cells are relative to the first cell of the source,
+  // from the nearest.
+  let filled: Vec<isize> = if forward {
+    (last + 1..=axis(target.end))
+      .map(|p| (p - first) as isize)
+      .collect()
+  } else {
+    (axis(target.start)..first)
+      .rev()
+      .map(|p| p as isize - first as isize)
+      .collect()
+  };
+  let lines = if vertical {
+    source.start.1..=source.end.1
+  } else {
+    source.start.0..=source.end.0
+  };
+
+  let mut edits = vec![];
+  for line in lines {
+    let coord = |i: isize| {
+      let p = (first as isize + i) as usize;
+      if vertical {
+        (p, line)
+      } else {
+        (line, p)
+      }
+    };
+    let raws: Vec<&str> = (first..=last)
+      .map(|p| {
+        let (row, col) = coord((p - first) as isize);
+        ss.get(row, col).raw()
+      })
+      .collect();
+    let series = Series::detect(&raws);
+    for i in &filled {
+      let raw = match &series {
+        Some(series) => series.value(*i),
+        None => repeated(ss, &raws, *i, vertical)?,
+      };
+      let (row, col) = coord(*i);
+      edits.push(Edit { row, col, raw });
+    }
+  }
+  Ok(edits)
+}
+
+// Whether `target` extends `source` vertically rather than horizontally, and
+// forward (down or right) rather than backward.
+fn direction(source: Range, target: Range) -> Option<(bool, bool)> {
+  let same_cols = (source.start.1, source.end.1) == (target.start.1, target.end.1);
+  let same_rows = (source.start.0, source.end.0) == (target.start.0, target.end.0);
+  if same_cols && !same_rows {
+    extends(
+      (source.start.0, source.end.0),
+      (target.start.0, target.end.0),
+    )
+    .map(|f| (true, f))
+  } else if same_rows && !same_cols {
+    extends(
+      (source.start.1, source.end.1),
+      (target.start.1, target.end.1),
+    )
+    .map(|f| (false, f))
+  } else {
+    None
+  }
+}
+
+// Same as `direction` along one axis, the spans being `(first, last)`.
+fn extends(source: (usize, usize), target: (usize, usize)) -> Option<bool> {
+  if target.1 > source.1 && (target.0 == source.0 || target.0 == source.1 + 1) {
+    Some(true)
+  } else if target.0 < source.0 && (target.1 == source.1 || target.1 + 1 == source.0) {
+    Some(false)
+  } else {
+    None
+  }
+}
+
+// What the `i`th cell gets when the source repeats, formulas being moved
+// like copied cells.
+fn repeated(ss: &Spreadsheet, raws: &[&str], i: isize, vertical: bool) -> Result<String, String> {
+  let j = i.rem_euclid(raws.len() as isize);
+  let raw = raws[j as usize];
+  let mut expr = ExprTree::new(raw);
+  let (rows, cols) = if vertical { (i - j, 0) } else { (0, i - j) };
+  match expr.offset_references(rows, cols, (ss.height(), ss.width())) {
+    Some(true) => Ok(expr.to_string()),
+    Some(false) => Ok(raw.to_string()),
+    None => Err(format!(
+      "{} would refer to cells past the edge of the sheet",
+      raw
+    )),
+  }
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+  Upper,
+  Lower,
+  AsListed,
+}
+
+impl Case {
+  fn of(text: &str) -> Case {
+    if !text.chars().any(char::is_lowercase) {
+      Case::Upper
+    } else if !text.chars().any(char::is_uppercase) {
+      Case::Lower
+    } else {
+      Case::AsListed
+    }
+  }
+
+  fn apply(self, text: &str) -> String {
+    match self {
+      Case::Upper => text.to_uppercase(),
+      Case::Lower => text.to_lowercase(),
+      Case::AsListed => text.to_string(),
+    }
+  }
+}
+
+// The `i`th value of a series is the value of the `i`th cell from the first
+// cell of the source, `i` being negative when filling backward.
+enum Series {
+  /// `start + step * i`, a date typed like `code` when there's one.
+  Linear {
+    start: f64,
+    step: f64,
+    decimals: usize,
+    code: Option<String>,
+  },
+  /// Dates `step` months apart, on `day` or the last day of shorter months.
+  Months { start: i64, step: i64, day: u32 },
+  Names {
+    list: &'static [&'static str],
+    start: isize,
+    step: isize,
+    case: Case,
+  },
+  /// `prefix` followed by `start + step * i`, padded with zeros to `width`.
+  Numbered {
+    prefix: String,
+    start: i64,
+    step: i64,
+    width: usize,
+  },
+}
+
+impl Series {
+  fn detect(raws: &[&str]) -> Option<Series> {
+    let trees: Vec<ExprTree> = raws.iter().map(|raw| ExprTree::new(raw)).collect();
+    let nums: Option<Vec<f64>> = trees
+      .iter()
+      .map(|tree| match tree {
+        ExprTree::Leaf(ValueNode::Num(n)) => Some(*n),
+        _ => None,
+      })
+      .collect();
+    if let Some(nums) = nums {
+      return if nums.len() > 1 {
+        Some(linear(&nums, None))
+      } else {
+        None
+      };
+    }
+    let dates: Option<Vec<(f64, &str)>> = trees
+      .iter()
+      .map(|tree| match tree {
+        ExprTree::Leaf(ValueNode::Date(serial, code)) => Some((*serial, code.as_str())),
+        _ => None,
+      })
+      .collect();
+    if let Some(dates) = dates {
+      let code = dates[0].1;
+      if dates.iter().any(|(_, c)| *c != code) {
+        return None;
+      }
+      let serials: Vec<f64> = dates.iter().map(|(serial, _)| *serial).collect();
+      return Some(months(&serials, code).unwrap_or_else(|| {
+        if serials.len() > 1 {
+          linear(&serials, Some(code))
+        } else {
+          // A day after a date, an hour after a time.
+          let step = if code.starts_with("yyyy") {
+            1.
+          } else {
+            1. / 24.
+          };
+          Series::Linear {
+            start: serials[0],
+            step,
+            decimals: MAX_DECIMALS,
+            code: Some(code.to_string()),
+          }
+        }
+      }));
+    }
+    let texts: Vec<&str> = trees
+      .iter()
+      .map(|tree| match tree {
+        ExprTree::Leaf(ValueNode::Text(t)) => Some(t.as_str()),
+        _ => None,
+      })
+      .collect::<Option<_>>()?;
+    names(&texts).or_else(|| numbered(&texts))
+  }
+
+  fn value(&self, i: isize) -> String {
+    match self {
+      Series::Linear {
+        start,
+        step,
+        decimals,
+        code,
+      } => {
+        let value = round(start + step * i as f64, *decimals);
+        let leaf = match code {
+          Some(code) => ValueNode::Date(value, code.clone()),
+          None => ValueNode::Num(value),
+        };
+        ExprTree::Leaf(leaf).to_string()
+      }
+      Series::Months { start, step, day } => {
+        let months = start + step * i as i64;
+        let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+        let day = (*day).min(date::days_in_month(year, month));
+        let serial = date::serial_from_ymd(year, month as i64, day as i64);
+        ExprTree::Leaf(ValueNode::Date(serial, "yyyy-mm-dd".to_string())).to_string()
+      }
+      Series::Names {
+        list,
+        start,
+        step,
+        case,
+      } => {
+        let index = (start + step * i).rem_euclid(list.len() as isize);
+        case.apply(list[index as usize])
+      }
+      Series::Numbered {
+        prefix,
+        start,
+        step,
+        width,
+      } => {
+        // Counting back past 0 goes up again, like other spreadsheets.
+        let n = (start + step * i as i64).abs();
+        format!("{}{:0width$}", prefix, n, width = width)
+      }
+    }
+  }
+}
+
+// The trend of `values`, exact when they are evenly spaced.
+fn linear(values: &[f64], code: Option<&str>) -> Series {
+  let (start, step) = trend(values);
+  let even = values
+    .windows(3)
+    .all(|w| (w[2] - 2. * w[1] + w[0]).abs() <= 1e-9 * (w[1].abs() + 1.));
+  // Evenly spaced values keep their decimals, `0.1, 0.2` gives `0.3`.
+  let decimals = if even {
+    values.iter().map(|v| decimals(*v)).max().unwrap_or(0)
+  } else {
+    MAX_DECIMALS
+  };
+  Series::Linear {
+    start,
+    step,
+    decimals,
+    code: code.map(str::to_string),
+  }
+}
+
+// Least squares line through the `(i, values[i])`, as `(intercept, slope)`.
+fn trend(values: &[f64]) -> (f64, f64) {
+  let n = values.len() as f64;
+  let mean_x = (n - 1.) / 2.;
+  let mean_y = values.iter().sum::<f64>() / n;
+  let (mut sxy, mut sxx) = (0., 0.);
+  for (i, y) in values.iter().enumerate() {
+    let dx = i as f64 - mean_x;
+    sxy += dx * (y - mean_y);
+    sxx += dx * dx;
+  }
+  let slope = if sxx == 0. { 0. } else { sxy / sxx };
+  (mean_y - slope * mean_x, slope)
+}
+
+fn decimals(value: f64) -> usize {
+  let text = value.to_string();
+  let decimals = text
+    .split_once('.')
+    .map_or(0, |(_, fraction)| fraction.len());
+  decimals.min(MAX_DECIMALS)
+}
+
+fn round(value: f64, decimals: usize) -> f64 {
+  let factor = 10f64.powi(decimals as i32);
+  // Past 2^53 scaled values lose digits instead, there's nothing left to
+  // round.
+  if (value * factor).abs() < 9e15 {
+    (value * factor).round() / factor
+  } else {
+    value
+  }
+}
+
+// Whole dates the same number of months apart, on the same day.
+fn months(serials: &[f64], code: &str) -> Option<Series> {
+  if code != "yyyy-mm-dd" || serials.len() < 2 {
+    return None;
+  }
+  let dates: Vec<(i64, u32, u32)> = serials.iter().map(|s| date::ymd_from_serial(*s)).collect();
+  let day = dates[0].2;
+  let months: Vec<i64> = dates
+    .iter()
+    .map(|(year, month, _)| year * 12 + *month as i64 - 1)
+    .collect();
+  let step = months[1] - months[0];
+  let regular = step != 0
+    && dates.iter().all(|(_, _, d)| *d == day)
+    && months.windows(2).all(|w| w[1] - w[0] == step);
+  if regular {
+    Some(Series::Months {
+      start: months[0],
+      step,
+      day,
+    })
+  } else {
+    None
+  }
+}
+
+// Weekday or month names, evenly spaced.
+fn names(texts: &[&str]) -> Option<Series> {
+  LISTS.iter().find_map(|list| {
+    let indexes: Vec<isize> = texts
+      .iter()
+      .map(|t| {
+        let index = list.iter().position(|name| name.eq_ignore_ascii_case(t))?;
+        Some(index as isize)
+      })
+      .collect::<Option<_>>()?;
+    let len = list.len() as isize;
+    let step = match indexes.get(1) {
+      Some(second) => (second - indexes[0]).rem_euclid(len),
+      None => 1,
+    };
+    if indexes
+      .windows(2)
+      .any(|w| (w[1] - w[0]).rem_euclid(len) != step)
+    {
+      return None;
+    }
+    Some(Series::Names {
+      list,
+      start: indexes[0],
+      step,
+      case: Case::of(texts[0]),
+    })
+  })
+}
+
+// Text ending with evenly spaced numbers, after the same prefix.
+fn numbered(texts: &[&str]) -> Option<Series> {
+  let parts: Vec<(&str, &str)> = texts
+    .iter()
+    .map(|t| {
+      let prefix = t.trim_end_matches(|c: char| c.is_ascii_digit());
+      let digits = &t[prefix.len()..];
+      if digits.is_empty() || digits.len() > 15 {
+        None
+      } else {
+        Some((prefix, digits))
+      }
+    })
+    .collect::<Option<_>>()?;
+  let prefix = parts[0].0;
+  if parts.iter().any(|(p, _)| *p != prefix) {
+    return None;
+  }
+  let numbers: Vec<i64> = parts.iter().map(|(_, n)| n.parse().unwrap()).collect();
+  let step = match numbers.get(1) {
+    Some(second) => second - numbers[0],
+    None => 1,
+  };
+  if numbers.windows(2).any(|w| w[1] - w[0] != step) {
+    return None;
+  }
+  // `Item 01` keeps its zeros.
+  let width = if parts[0].1.starts_with('0') {
+    parts[0].1.len()
+  } else {
+    0
+  };
+  Some(Series::Numbered {
+    prefix: prefix.to_string(),
+    start: numbers[0],
+    step,
+    width,
+  })
+}
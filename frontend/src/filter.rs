@@ -0,0 +1,42 @@
+//! Filter views hide the rows of a range whose values don't meet criteria,
+//! without changing any cell. Criteria are written like those of `COUNTIF`,
+//! e.g. `">10"`, `"East"` or `"ab*"`.
+
+use super::expr::Range;
+use super::functions::Criterion;
+use super::Spreadsheet;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Filter {
+  /// The first row holds the headers, it's never hidden.
+  pub range: Range,
+  pub conditions: Vec<Condition>,
+}
+
+/// Rows are shown when the value in column `col` meets `criterion`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+  pub col: usize,
+  pub criterion: String,
+}
+
+/// The rows below the headers whose values don't meet every condition.
+pub fn hidden_rows(ss: &Spreadsheet, filter: &Filter) -> Vec<usize> {
+  let criteria: Vec<(usize, Criterion)> = filter
+    .conditions
+    .iter()
+    .map(|c| (c.col, Criterion::parse(&c.criterion)))
+    .collect();
+  let matchers: Vec<_> = criteria
+    .iter()
+    .map(|(col, criterion)| (*col, criterion.matcher()))
+    .collect();
+  (filter.range.start.0 + 1..=filter.range.end.0)
+    .filter(|row| {
+      !matchers
+        .iter()
+        .all(|(col, passes)| passes(ss.get(*row, *col).out()))
+    })
+    .collect()
+}
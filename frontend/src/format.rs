@@ -0,0 +1,700 @@
+//! Number formats using the same format codes as other spreadsheets, e.g.
+//! `#,##0.00`, `0%`, `$#,##0;($#,##0)` or `yyyy-mm-dd`. A code has up to four
+//! `;` separated sections: positive numbers, negative numbers, zero and text.
+
+use super::date::DateTime;
+use super::expr::ExprResult;
+use std::iter;
+
+const MONTHS: [&str; 12] = [
+  "January",
+  "February",
+  "March",
+  "April",
+  "May",
+  "June",
+  "July",
+  "August",
+  "September",
+  "October",
+  "November",
+  "December",
+];
+const WEEKDAYS: [&str; 7] = [
+  "Sunday",
+  "Monday",
+  "Tuesday",
+  "Wednesday",
+  "Thursday",
+  "Friday",
+  "Saturday",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Literal(String),
+  /// `0`, `#` or `?`.
+  Digit(char),
+  Point,
+  Comma,
+  Percent,
+  /// Scientific notation, `true` for `E+` which always shows the sign.
+  Exponent(bool),
+  /// `@`, replaced by the text in a text section.
+  Text,
+  General,
+  /// A run of `y`, `m`, `d`, `h` or `s`, lowercased, e.g. `"yyyy"`.
+  Date(String),
+  /// Elapsed time, `[h]`, `[mm]` or `[ss]`, for durations over a day.
+  Elapsed(String),
+  /// `AM/PM`, or `A/P` when `false`.
+  AmPm(bool),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Section {
+  tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Format {
+  sections: Vec<Section>,
+}
+
+impl Format {
+  /// Parses a format code. An empty code, or `General`, shows numbers as they are.
+  pub fn parse(code: &str) -> Result<Format, String> {
+    let mut sections = vec![];
+    let mut tokens = vec![];
+    let chars: Vec<char> = code.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+      let c = chars[i];
+      i += 1;
+      let token = match c {
+        ';' => {
+          sections.push(Section {
+            tokens: std::mem::take(&mut tokens),
+          });
+          continue;
+        }
+        '"' => {
+          let end = (i..chars.len())
+            .find(|j| chars[*j] == '"')
+            .ok_or_else(|| format!("unterminated quote in {}", code))?;
+          let literal = chars[i..end].iter().collect();
+          i = end + 1;
+          Token::Literal(literal)
+        }
+        '\\' => {
+          let escaped = *chars
+            .get(i)
+            .ok_or_else(|| format!("dangling escape in {}", code))?;
+          i += 1;
+          Token::Literal(escaped.to_string())
+        }
+        // `_x` pads with the width of `x`, `*x` fills the cell with `x`.
+        // Neither means much outside of a fixed width grid.
+        '_' | '*' => {
+          i += 1;
+          Token::Literal(if c == '_' {
+            " ".to_string()
+          } else {
+            String::new()
+          })
+        }
+        '[' => {
+          let end = (i..chars.len())
+            .find(|j| chars[*j] == ']')
+            .ok_or_else(|| format!("unterminated bracket in {}", code))?;
+          let inner: String = chars[i..end].iter().collect();
+          i = end + 1;
+          // `[$€-407]` is a currency symbol plus a locale, colors and
+          // conditions aren't supported and are ignored.
+          let lower = inner.to_ascii_lowercase();
+          match inner.strip_prefix('$') {
+            Some(currency) => Token::Literal(currency.split('-').next().unwrap_or("").to_string()),
+            None
+              if lower
+                .chars()
+                .next()
+                .is_some_and(|first| "hms".contains(first) && lower.chars().all(|c| c == first)) =>
+            {
+              Token::Elapsed(lower)
+            }
+            None => continue,
+          }
+        }
+        '0' | '#' | '?' => Token::Digit(c),
+        '.' if !tokens.contains(&Token::Point) => Token::Point,
+        ',' => Token::Comma,
+        '%' => Token::Percent,
+        '@' => Token::Text,
+        'E' | 'e' if matches!(chars.get(i), Some('+') | Some('-')) => {
+          i += 1;
+          Token::Exponent(chars[i - 1] == '+')
+        }
+        'y' | 'Y' | 'm' | 'M' | 'd' | 'D' | 'h' | 'H' | 's' | 'S' => {
+          let lower = c.to_ascii_lowercase();
+          let mut run = lower.to_string();
+          while i < chars.len() && chars[i].to_ascii_lowercase() == lower {
+            run.push(lower);
+            i += 1;
+          }
+          Token::Date(run)
+        }
+        _ if starts_with_ignore_case(&chars[i - 1..], "AM/PM") => {
+          i += 4;
+          Token::AmPm(true)
+        }
+        _ if starts_with_ignore_case(&chars[i - 1..], "A/P") => {
+          i += 2;
+          Token::AmPm(false)
+        }
+        _ if starts_with_ignore_case(&chars[i - 1..], "General") => {
+          i += 6;
+          Token::General
+        }
+        _ => Token::Literal(c.to_string()),
+      };
+      tokens.push(token);
+    }
+    sections.push(Section { tokens });
+    if sections.len() > 4 {
+      return Err(format!("{} has more than 4 sections", code));
+    }
+    if sections.len() == 1 && sections[0].tokens.is_empty() {
+      sections.clear();
+    }
+    Ok(Format { sections })
+  }
+
+  /// Returns what the cell shows for `value`.
+  pub fn display(&self, value: &ExprResult) -> String {
+    match value {
+      ExprResult::Num(n) => self.display_num(*n),
+      ExprResult::Text(t) => match self.sections.get(3) {
+        Some(section) => section.render_text(t),
+        // A single section with `@` is a text format.
+        None if self.sections.len() == 1 && self.sections[0].tokens.contains(&Token::Text) => {
+          self.sections[0].render_text(t)
+        }
+        None => t.clone(),
+      },
+      ExprResult::Error(e) => e.clone(),
+      ExprResult::Array(rows) => self.display(&rows[0][0]),
+      ExprResult::Lambda(_) => "#CALC!".to_string(),
+    }
+  }
+
+  fn display_num(&self, n: f64) -> String {
+    if !n.is_finite() {
+      return general(n);
+    }
+    let sections = &self.sections;
+    match sections.len() {
+      0 => general(n),
+      1 => sections[0].render_num(n, true),
+      _ if n < 0. => sections[1].render_num(-n, false),
+      len if n == 0. && len >= 3 => sections[2].render_num(n, false),
+      _ => sections[0].render_num(n, true),
+    }
+  }
+}
+
+impl Section {
+  fn is_date(&self) -> bool {
+    self
+      .tokens
+      .iter()
+      .any(|t| matches!(t, Token::Date(_) | Token::Elapsed(_)))
+  }
+
+  fn render_text(&self, text: &str) -> String {
+    self
+      .tokens
+      .iter()
+      .map(|t| match t {
+        Token::Text => text.to_string(),
+        Token::Literal(l) => l.clone(),
+        _ => String::new(),
+      })
+      .collect()
+  }
+
+  // `signed` is false for sections dedicated to negative numbers, which
+  // bring their own sign (or parentheses).
+  fn render_num(&self, n: f64, signed: bool) -> String {
+    // Numbers in text formats show as they are.
+    if self.tokens.contains(&Token::Text) {
+      return self.render_text(&general(if signed { n } else { n.abs() }));
+    }
+    if self.is_date() {
+      return self.render_date(n);
+    }
+    if self.tokens.contains(&Token::General) {
+      return self
+        .tokens
+        .iter()
+        .map(|t| match t {
+          Token::General => general(if signed { n } else { n.abs() }),
+          Token::Literal(l) => l.clone(),
+          _ => String::new(),
+        })
+        .collect();
+    }
+
+    let layout = Layout::new(&self.tokens);
+    let mut value = n.abs() * 100f64.powi(layout.percents) / 1000f64.powi(layout.scale);
+    let mut exponent = 0;
+    if layout.exponent.is_some() && value != 0. {
+      let int_digits = layout.int_digits.max(1) as i32;
+      exponent = value.log10().floor() as i32 - (int_digits - 1);
+      value /= 10f64.powi(exponent);
+      // Rounding may push the mantissa to the next power of ten.
+      let rounded: f64 = round_half_up(value, layout.frac_digits)
+        .parse()
+        .unwrap_or(value);
+      if rounded >= 10f64.powi(int_digits) {
+        exponent += 1;
+        value /= 10.;
+      }
+    }
+    let rounded = round_half_up(value, layout.frac_digits);
+    let (int_str, frac_str) = match rounded.split_once('.') {
+      Some((int_str, frac_str)) => (int_str, frac_str),
+      None => (rounded.as_str(), ""),
+    };
+    let is_zero = rounded.chars().all(|c| c == '0' || c == '.');
+    // With no digits left of the point `#` placeholders show nothing for 0.
+    let int_digits: Vec<char> = if int_str == "0" {
+      vec![]
+    } else {
+      int_str.chars().collect()
+    };
+
+    let mut out = String::new();
+    if signed && n < 0. && !is_zero {
+      out.push('-');
+    }
+    out += &self.render_int(&layout, &int_digits);
+    out += &self.render_rest(&layout, frac_str, exponent);
+    out
+  }
+
+  // Everything up to the decimal point, with digits filled right to left.
+  fn render_int(&self, layout: &Layout, digits: &[char]) -> String {
+    let end = layout
+      .point
+      .or(layout.exponent)
+      .unwrap_or(self.tokens.len());
+    let first_digit = self.tokens[..end]
+      .iter()
+      .position(|t| matches!(t, Token::Digit(_)));
+    let mut reversed: Vec<String> = vec![];
+    let mut pos = 0;
+    for (i, token) in self.tokens[..end].iter().enumerate().rev() {
+      match token {
+        Token::Digit(placeholder) => {
+          let emit = |pos: usize, c: Option<char>, reversed: &mut Vec<String>| {
+            let c = match (c, placeholder) {
+              (Some(c), _) => c,
+              (None, '0') => '0',
+              (None, '?') => ' ',
+              (None, _) => return,
+            };
+            if layout.thousands && pos > 0 && pos.is_multiple_of(3) && c != ' ' {
+              reversed.push(",".to_string());
+            }
+            reversed.push(c.to_string());
+          };
+          let digit = digits.len().checked_sub(pos + 1).map(|i| digits[i]);
+          emit(pos, digit, &mut reversed);
+          pos += 1;
+          // The leftmost placeholder takes every digit that didn't fit.
+          if Some(i) == first_digit {
+            while pos < digits.len() {
+              emit(pos, Some(digits[digits.len() - pos - 1]), &mut reversed);
+              pos += 1;
+            }
+          }
+        }
+        _ => reversed.push(render_literal(token)),
+      }
+    }
+    reversed.into_iter().rev().collect()
+  }
+
+  // The decimal point, the fraction and the exponent.
+  fn render_rest(&self, layout: &Layout, frac: &str, exponent: i32) -> String {
+    let start = layout
+      .point
+      .or(layout.exponent)
+      .unwrap_or(self.tokens.len());
+    let frac: Vec<char> = frac.chars().collect();
+    let significant = frac.iter().rposition(|c| *c != '0').map_or(0, |i| i + 1);
+    let exponent_digits: Vec<char> = exponent.abs().to_string().chars().collect();
+    let exponent_placeholders = layout.exponent.map_or(0, |e| {
+      self.tokens[e..]
+        .iter()
+        .filter(|t| matches!(t, Token::Digit(_)))
+        .count()
+    });
+
+    let mut out = String::new();
+    let mut frac_pos = 0;
+    let mut exp_pos = 0;
+    for (i, token) in self.tokens.iter().enumerate().skip(start) {
+      let in_exponent = layout.exponent.is_some_and(|e| i > e);
+      match token {
+        Token::Point => out.push('.'),
+        Token::Exponent(plus) => {
+          out.push('E');
+          if exponent < 0 {
+            out.push('-');
+          } else if *plus {
+            out.push('+');
+          }
+        }
+        Token::Digit(placeholder) if in_exponent => {
+          // Pad the exponent on the left, extra digits go to the first placeholder.
+          let padding = exponent_placeholders.saturating_sub(exponent_digits.len());
+          if exp_pos < padding {
+            if *placeholder == '0' {
+              out.push('0');
+            }
+          } else if exp_pos == padding {
+            out.extend(exponent_digits.iter());
+          }
+          exp_pos += 1;
+        }
+        Token::Digit(placeholder) => {
+          match (frac.get(frac_pos), placeholder) {
+            (Some(c), _) if frac_pos < significant => out.push(*c),
+            (_, '0') => out.push('0'),
+            (_, '?') => out.push(' '),
+            _ => (),
+          }
+          frac_pos += 1;
+        }
+        _ => out += &render_literal(token),
+      }
+    }
+    out
+  }
+
+  fn render_date(&self, n: f64) -> String {
+    // Durations can go past the last date we know how to show.
+    let time_of_day = if self.tokens.iter().any(|t| matches!(t, Token::Elapsed(_))) {
+      n.fract()
+    } else {
+      n
+    };
+    let dt = match DateTime::from_serial(time_of_day) {
+      Some(dt) => dt,
+      None => return "#####".to_string(),
+    };
+    let has_ampm = self.tokens.iter().any(|t| matches!(t, Token::AmPm(_)));
+    let hour = if has_ampm {
+      (dt.hour + 11) % 12 + 1
+    } else {
+      dt.hour
+    };
+
+    let mut out = String::new();
+    for (i, token) in self.tokens.iter().enumerate() {
+      match token {
+        Token::Date(run) => {
+          let len = run.len();
+          let part = match &run[..1] {
+            "y" if len <= 2 => format!("{:02}", dt.year.rem_euclid(100)),
+            "y" => format!("{:04}", dt.year),
+            "m" if self.is_minute(i) => pad(dt.minute, len),
+            "m" if len <= 2 => pad(dt.month, len),
+            "m" if len == 3 => MONTHS[dt.month as usize - 1][..3].to_string(),
+            "m" if len == 4 => MONTHS[dt.month as usize - 1].to_string(),
+            "m" => MONTHS[dt.month as usize - 1][..1].to_string(),
+            "d" if len <= 2 => pad(dt.day, len),
+            "d" if len == 3 => WEEKDAYS[dt.weekday as usize][..3].to_string(),
+            "d" => WEEKDAYS[dt.weekday as usize].to_string(),
+            "h" => pad(hour, len),
+            _ => pad(dt.second, len),
+          };
+          out += &part;
+        }
+        Token::Elapsed(run) => {
+          // Round to the second first, like the time of day is.
+          let seconds = (n * 86400.).round();
+          let whole = match &run[..1] {
+            "h" => (seconds / 3600.).floor(),
+            "m" => (seconds / 60.).floor(),
+            _ => seconds,
+          };
+          out += &pad(whole as u32, run.len());
+        }
+        Token::AmPm(full) => {
+          let am = dt.hour < 12;
+          out += match (am, full) {
+            (true, true) => "AM",
+            (false, true) => "PM",
+            (true, false) => "A",
+            (false, false) => "P",
+          };
+        }
+        Token::Comma => out.push(','),
+        _ => out += &render_literal(token),
+      }
+    }
+    out
+  }
+
+  // `m` means minutes right after hours or right before seconds.
+  fn is_minute(&self, i: usize) -> bool {
+    let is_date = |t: &&Token| matches!(t, Token::Date(_) | Token::Elapsed(_));
+    let prev = self.tokens[..i].iter().rev().find(is_date);
+    let next = self.tokens[i + 1..].iter().find(is_date);
+    matches!(prev, Some(Token::Date(r)) | Some(Token::Elapsed(r)) if r.starts_with('h'))
+      || matches!(next, Some(Token::Date(r)) | Some(Token::Elapsed(r)) if r.starts_with('s'))
+  }
+}
+
+// Where the interesting tokens of a numeric section are.
+struct Layout {
+  point: Option<usize>,
+  exponent: Option<usize>,
+  int_digits: usize,
+  frac_digits: usize,
+  percents: i32,
+  // Commas after the last integer placeholder divide by 1000 each.
+  scale: i32,
+  // A comma between integer placeholders turns on thousands separators.
+  thousands: bool,
+}
+
+impl Layout {
+  fn new(tokens: &[Token]) -> Layout {
+    let point = tokens.iter().position(|t| *t == Token::Point);
+    let exponent = tokens.iter().position(|t| matches!(t, Token::Exponent(_)));
+    let int_end = point.or(exponent).unwrap_or(tokens.len());
+    let frac_end = exponent.unwrap_or(tokens.len());
+    let is_digit = |t: &Token| matches!(t, Token::Digit(_));
+
+    let int_tokens = &tokens[..int_end];
+    let first = int_tokens.iter().position(is_digit);
+    let last = int_tokens.iter().rposition(is_digit);
+    let mut thousands = false;
+    let mut scale = 0;
+    for (i, token) in int_tokens.iter().enumerate() {
+      if *token != Token::Comma {
+        continue;
+      }
+      match (first, last) {
+        (Some(first), Some(last)) if first < i && i < last => thousands = true,
+        (Some(_), Some(last)) if i > last => scale += 1,
+        _ => (),
+      }
+    }
+
+    Layout {
+      point,
+      exponent,
+      int_digits: int_tokens.iter().filter(|t| is_digit(t)).count(),
+      frac_digits: point.map_or(0, |p| {
+        tokens[p..frac_end].iter().filter(|t| is_digit(t)).count()
+      }),
+      percents: tokens.iter().filter(|t| **t == Token::Percent).count() as i32,
+      scale,
+      thousands,
+    }
+  }
+}
+
+// `value` with `decimals` digits after the point. `format!` rounds the
+// binary value half to even, spreadsheets round what the value reads as
+// half away from zero: 1.005 is stored as 1.00499... but shows as 1.01.
+fn round_half_up(value: f64, decimals: usize) -> String {
+  if !value.is_finite() || value < 0. {
+    return format!("{:.*}", decimals, value);
+  }
+  // The shortest decimal reading back as `value`, never in exponent form.
+  let shortest = value.to_string();
+  let (int_str, frac_str) = shortest.split_once('.').unwrap_or((&shortest, ""));
+  let mut digits: Vec<u8> = int_str
+    .bytes()
+    .chain(frac_str.bytes().chain(iter::repeat(b'0')).take(decimals))
+    .map(|b| b - b'0')
+    .collect();
+  if frac_str
+    .as_bytes()
+    .get(decimals)
+    .is_some_and(|d| *d >= b'5')
+  {
+    // Nines carry over to the first other digit, or to a new one.
+    let carry = digits.iter().rposition(|d| *d != 9);
+    let start = carry.map_or(0, |i| i + 1);
+    for d in &mut digits[start..] {
+      *d = 0;
+    }
+    match carry {
+      Some(i) => digits[i] += 1,
+      None => digits.insert(0, 1),
+    }
+  }
+  let mut out: String = digits.iter().map(|d| (b'0' + d) as char).collect();
+  if decimals > 0 {
+    out.insert(out.len() - decimals, '.');
+  }
+  out
+}
+
+fn render_literal(token: &Token) -> String {
+  match token {
+    Token::Literal(l) => l.clone(),
+    Token::Percent => "%".to_string(),
+    Token::Point => ".".to_string(),
+    // Thousands and scaling commas are handled by the layout.
+    _ => String::new(),
+  }
+}
+
+fn pad(n: u32, len: usize) -> String {
+  if len >= 2 {
+    format!("{:02}", n)
+  } else {
+    n.to_string()
+  }
+}
+
+fn starts_with_ignore_case(chars: &[char], pattern: &str) -> bool {
+  chars.len() >= pattern.len()
+    && chars
+      .iter()
+      .zip(pattern.chars())
+      .all(|(c, p)| c.eq_ignore_ascii_case(&p))
+}
+
+/// How numbers show without a format: integers as they are, everything else
+/// with up to 10 decimals so that `0.1+0.2` shows `0.3`.
+pub fn general(n: f64) -> String {
+  if n.is_nan() {
+    return "NaN".to_string();
+  }
+  if n.is_infinite() {
+    return if n > 0. { "Infinity" } else { "-Infinity" }.to_string();
+  }
+  if n.fract() == 0. && n.abs() < 1e15 {
+    return format!("{}", n);
+  }
+  if n.abs() >= 1e15 || n.abs() < 1e-9 {
+    return format!("{:e}", n);
+  }
+  let s = format!("{:.10}", n);
+  let s = s.trim_end_matches('0').trim_end_matches('.');
+  if s == "-0" {
+    "0".to_string()
+  } else {
+    s.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn fmt(code: &str, n: f64) -> String {
+    Format::parse(code).unwrap().display(&ExprResult::Num(n))
+  }
+
+  #[test]
+  fn general_numbers() {
+    assert_eq!(fmt("", 42.), "42");
+    assert_eq!(fmt("General", 0.1 + 0.2), "0.3");
+    assert_eq!(fmt("", -1.5), "-1.5");
+    assert_eq!(fmt("0.0 \"units\"", 3.), "3.0 units");
+  }
+
+  #[test]
+  fn decimals_and_thousands() {
+    assert_eq!(fmt("0.00", 12.3456), "12.35");
+    assert_eq!(fmt("#,##0.00", 1234567.891), "1,234,567.89");
+    assert_eq!(fmt("#,##0", 999.5), "1,000");
+    assert_eq!(fmt("#,##0", -1234.), "-1,234");
+    assert_eq!(fmt("0,000", 5.), "0,005");
+    assert_eq!(fmt("#.##", 0.5), ".5");
+    assert_eq!(fmt("0.0#", 2.), "2.0");
+    assert_eq!(fmt("#,##0,\"K\"", 1234567.), "1,235K");
+    assert_eq!(fmt("000-0000", 5551234.), "555-1234");
+  }
+
+  #[test]
+  fn currency_and_percent() {
+    assert_eq!(fmt("$#,##0.00", 1234.5), "$1,234.50");
+    assert_eq!(fmt("$#,##0;($#,##0)", -1234.), "($1,234)");
+    assert_eq!(fmt("$#,##0;($#,##0);\"-\"", 0.), "-");
+    assert_eq!(fmt("[$€-407]#,##0.00", 12.), "€12.00");
+    assert_eq!(fmt("0%", 0.256), "26%");
+    assert_eq!(fmt("0.0%", -0.0125), "-1.3%");
+    assert_eq!(fmt("0.00", 1.005), "1.01");
+  }
+
+  #[test]
+  fn large_numbers() {
+    assert_eq!(fmt("#,##0", 5e12), "5,000,000,000,000");
+    assert_eq!(fmt("0", 123456789012345.), "123456789012345");
+    assert_eq!(fmt("0.00", 1e10), "10000000000.00");
+    assert_eq!(fmt("#,##0", 1e20), "100,000,000,000,000,000,000");
+    assert_eq!(fmt("0", 999999999999.5), "1000000000000");
+    assert_eq!(fmt("0.0", 1234567890123.45), "1234567890123.5");
+  }
+
+  #[test]
+  fn scientific() {
+    assert_eq!(fmt("0.00E+00", 12345.), "1.23E+04");
+    assert_eq!(fmt("0.00E+00", 0.00012), "1.20E-04");
+    assert_eq!(fmt("0.0E+0", 99999.), "1.0E+5");
+  }
+
+  #[test]
+  fn dates_and_times() {
+    // 2024-03-15 15:30:05
+    let serial = 45366. + (15. * 3600. + 30. * 60. + 5.) / 86400.;
+    assert_eq!(fmt("yyyy-mm-dd", serial), "2024-03-15");
+    assert_eq!(fmt("d mmm yy", serial), "15 Mar 24");
+    assert_eq!(fmt("dddd, mmmm d", serial), "Friday, March 15");
+    assert_eq!(fmt("hh:mm:ss", serial), "15:30:05");
+    assert_eq!(fmt("h:mm AM/PM", serial), "3:30 PM");
+    assert_eq!(fmt("m/d/yyyy h:mm", serial), "3/15/2024 15:30");
+    assert_eq!(fmt("yyyy-mm-dd", -1.), "#####");
+    assert_eq!(fmt("[h]:mm", 1.5 + 1. / 96.), "36:15");
+    assert_eq!(fmt("[mm]:ss", 0.5), "720:00");
+  }
+
+  #[test]
+  fn text_sections() {
+    let format = Format::parse("0.00;-0.00;0;\"note: \"@").unwrap();
+    assert_eq!(
+      format.display(&ExprResult::Text("hi".to_string())),
+      "note: hi"
+    );
+    let format = Format::parse("0.00").unwrap();
+    assert_eq!(format.display(&ExprResult::Text("hi".to_string())), "hi");
+    // Numbers in a text format show unchanged.
+    assert_eq!(fmt("@", 1234.5), "1234.5");
+    assert_eq!(fmt("\"id \"@", -7.), "id -7");
+  }
+
+  #[test]
+  fn unsupported_brackets_are_ignored() {
+    assert_eq!(fmt("[Red]0.0", 1.), "1.0");
+    assert_eq!(fmt("[€]#,##0", 1234.), "1,234");
+    assert_eq!(fmt("[ñ]0", 7.), "7");
+    assert_eq!(fmt("[hñ]0", 7.), "7");
+  }
+
+  #[test]
+  fn invalid_codes() {
+    assert!(Format::parse("\"unterminated").is_err());
+    assert!(Format::parse("0;0;0;0;0").is_err());
+    assert!(Format::parse("[Red0").is_err());
+  }
+}
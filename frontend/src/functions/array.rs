@@ -0,0 +1,184 @@
+//! Functions returning arrays, which spill over the cells next to the
+//! formula (see `spill`).
+
+use super::{array, bool_arg, check_arity, num, num_error, value_error, FnResult};
+use crate::expr::{ExprResult, ExprTree};
+use crate::Spreadsheet;
+use std::cmp::Ordering;
+
+/// Most values an array can have, arrays that big can't be shown anyway.
+const MAX_ARRAY_LEN: usize = 1 << 20;
+
+pub fn sequence(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // SEQUENCE(rows, [columns], [start], [step]), filled row by row.
+  check_arity(args, 1, 4)?;
+  let rows = num(&args[0], ss)?.trunc();
+  let cols = args.get(1).map_or(Ok(1.), |arg| num(arg, ss))?.trunc();
+  let start = args.get(2).map_or(Ok(1.), |arg| num(arg, ss))?;
+  let step = args.get(3).map_or(Ok(1.), |arg| num(arg, ss))?;
+  if rows < 1. || cols < 1. {
+    return Err(value_error());
+  }
+  if rows * cols > MAX_ARRAY_LEN as f64 {
+    return Err(num_error());
+  }
+  let (rows, cols) = (rows as usize, cols as usize);
+  let values = (0..rows)
+    .map(|row| {
+      (0..cols)
+        .map(|col| ExprResult::Num(start + step * (row * cols + col) as f64))
+        .collect()
+    })
+    .collect();
+  Ok(ExprResult::Array(values))
+}
+
+pub fn sort(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // SORT(array, [sort_index], [sort_order], [by_col]). Sorts the rows by
+  // their `sort_index`th value, or the columns with `by_col`. Order 1 is
+  // ascending and -1 descending.
+  check_arity(args, 1, 4)?;
+  let by_col = args.get(3).map_or(Ok(false), |arg| bool_arg(arg, ss))?;
+  let mut rows = array(&args[0], ss)?;
+  if by_col {
+    rows = transpose(rows);
+  }
+  let index = args.get(1).map_or(Ok(1.), |arg| num(arg, ss))?.trunc();
+  if index < 1. || index > rows[0].len() as f64 {
+    return Err(value_error());
+  }
+  let index = index as usize - 1;
+  let descending = match args.get(2).map_or(Ok(1.), |arg| num(arg, ss))? as i64 {
+    1 => false,
+    -1 => true,
+    _ => return Err(value_error()),
+  };
+  rows.sort_by(|a, b| {
+    let ord = sort_order(&a[index], &b[index]);
+    if descending {
+      ord.reverse()
+    } else {
+      ord
+    }
+  });
+  if by_col {
+    rows = transpose(rows);
+  }
+  Ok(ExprResult::Array(rows))
+}
+
+pub fn filter(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // FILTER(array, include, [if_empty]). `include` is a column with a yes or
+  // no for every row, or a row with one for every column.
+  check_arity(args, 2, 3)?;
+  let rows = array(&args[0], ss)?;
+  let include = array(&args[1], ss)?;
+  let keep = |values: Vec<&ExprResult>| -> Result<Vec<bool>, ExprResult> {
+    values.into_iter().map(truthy).collect()
+  };
+  let filtered: Vec<Vec<ExprResult>> = if include[0].len() == 1 && include.len() == rows.len() {
+    let keep = keep(include.iter().map(|row| &row[0]).collect())?;
+    rows
+      .into_iter()
+      .zip(keep)
+      .filter(|(_, keep)| *keep)
+      .map(|(row, _)| row)
+      .collect()
+  } else if include.len() == 1 && include[0].len() == rows[0].len() {
+    let keep = keep(include[0].iter().collect())?;
+    rows
+      .into_iter()
+      .map(|row| {
+        row
+          .into_iter()
+          .zip(&keep)
+          .filter(|(_, keep)| **keep)
+          .map(|(value, _)| value)
+          .collect()
+      })
+      .collect()
+  } else {
+    return Err(value_error());
+  };
+  if filtered.is_empty() || filtered[0].is_empty() {
+    return match args.get(2) {
+      Some(if_empty) => Ok(if_empty.eval(ss)),
+      None => Err(ExprResult::Error("#CALC!".to_string())),
+    };
+  }
+  Ok(ExprResult::Array(filtered))
+}
+
+pub fn unique(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // UNIQUE(array, [by_col], [exactly_once]). Keeps the first of each
+  // distinct row (or column), ignoring case, or with `exactly_once` only
+  // the ones that aren't repeated.
+  check_arity(args, 1, 3)?;
+  let by_col = args.get(1).map_or(Ok(false), |arg| bool_arg(arg, ss))?;
+  let exactly_once = args.get(2).map_or(Ok(false), |arg| bool_arg(arg, ss))?;
+  let mut rows = array(&args[0], ss)?;
+  if by_col {
+    rows = transpose(rows);
+  }
+  let same = |a: &[ExprResult], b: &[ExprResult]| {
+    a.iter()
+      .zip(b)
+      .all(|(a, b)| sort_order(a, b) == Ordering::Equal)
+  };
+  let mut distinct: Vec<(Vec<ExprResult>, usize)> = vec![];
+  for row in rows {
+    match distinct.iter_mut().find(|(seen, _)| same(seen, &row)) {
+      Some((_, count)) => *count += 1,
+      None => distinct.push((row, 1)),
+    }
+  }
+  let mut rows: Vec<Vec<ExprResult>> = distinct
+    .into_iter()
+    .filter(|(_, count)| !exactly_once || *count == 1)
+    .map(|(row, _)| row)
+    .collect();
+  if rows.is_empty() {
+    return Err(ExprResult::Error("#CALC!".to_string()));
+  }
+  if by_col {
+    rows = transpose(rows);
+  }
+  Ok(ExprResult::Array(rows))
+}
+
+/// Numbers, then text ignoring case, then errors, then blanks.
+fn sort_order(a: &ExprResult, b: &ExprResult) -> Ordering {
+  let rank = |value: &ExprResult| match value {
+    ExprResult::Num(_) => 0,
+    ExprResult::Text(t) if !t.is_empty() => 1,
+    ExprResult::Error(_) | ExprResult::Array(_) | ExprResult::Lambda(_) => 2,
+    ExprResult::Text(_) => 3,
+  };
+  match (a, b) {
+    (ExprResult::Num(a), ExprResult::Num(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+    (ExprResult::Text(a), ExprResult::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+    (ExprResult::Error(a), ExprResult::Error(b)) => a.cmp(b),
+    _ => rank(a).cmp(&rank(b)),
+  }
+}
+
+fn truthy(value: &ExprResult) -> Result<bool, ExprResult> {
+  match value {
+    ExprResult::Num(n) => Ok(*n != 0.),
+    ExprResult::Text(t) if t.is_empty() || t.eq_ignore_ascii_case("FALSE") => Ok(false),
+    ExprResult::Text(t) if t.eq_ignore_ascii_case("TRUE") => Ok(true),
+    e @ ExprResult::Error(_) => Err(e.clone()),
+    _ => Err(value_error()),
+  }
+}
+
+fn transpose(rows: Vec<Vec<ExprResult>>) -> Vec<Vec<ExprResult>> {
+  let width = rows[0].len();
+  let mut columns: Vec<Vec<ExprResult>> = (0..width).map(|_| vec![]).collect();
+  for row in rows {
+    for (col, value) in row.into_iter().enumerate() {
+      columns[col].push(value);
+    }
+  }
+  columns
+}
@@ -0,0 +1,283 @@
+//! Conditional aggregates like `SUMIF`. A criterion is a value cells are
+//! compared to: a number, text with the wildcards of `Pattern` like `"ab*"`,
+//! or either after an operator like `">10"` or `"<>x"`.
+
+use super::text::Pattern;
+use super::{check_arity, div_error, value_error, FnResult};
+use crate::expr::{ExprResult, ExprTree, Range};
+use crate::parser::date_time_value;
+use crate::Spreadsheet;
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl Op {
+  fn accepts(self, ordering: Ordering) -> bool {
+    match self {
+      Op::Eq => ordering == Ordering::Equal,
+      Op::Ne => ordering != Ordering::Equal,
+      Op::Lt => ordering == Ordering::Less,
+      Op::Le => ordering != Ordering::Greater,
+      Op::Gt => ordering == Ordering::Greater,
+      Op::Ge => ordering != Ordering::Less,
+    }
+  }
+}
+
+enum Operand {
+  Num(f64),
+  /// Lowercased, to ignore case.
+  Text(String),
+}
+
+/// What values are compared to, see the module documentation.
+pub(crate) struct Criterion {
+  op: Op,
+  operand: Operand,
+}
+
+impl Criterion {
+  fn new(value: ExprResult) -> Result<Criterion, ExprResult> {
+    match value {
+      ExprResult::Num(n) => Ok(Criterion {
+        op: Op::Eq,
+        operand: Operand::Num(n),
+      }),
+      ExprResult::Text(t) => Ok(Criterion::parse(&t)),
+      e @ ExprResult::Error(_) => Err(e),
+      ExprResult::Array(_) | ExprResult::Lambda(_) => Err(value_error()),
+    }
+  }
+
+  /// A criterion written as text, like `">10"`.
+  pub(crate) fn parse(t: &str) -> Criterion {
+    // Longest operators first, `<` is a prefix of `<=`.
+    let ops = [
+      ("<=", Op::Le),
+      (">=", Op::Ge),
+      ("<>", Op::Ne),
+      ("<", Op::Lt),
+      (">", Op::Gt),
+      ("=", Op::Eq),
+    ];
+    let (op, rest) = ops
+      .iter()
+      .find_map(|(prefix, op)| Some((*op, t.strip_prefix(prefix)?)))
+      .unwrap_or((Op::Eq, t));
+    let operand = match number(rest) {
+      Some(n) => Operand::Num(n),
+      None => Operand::Text(rest.to_lowercase()),
+    };
+    Criterion { op, operand }
+  }
+
+  /// Whether values pass, made once per criterion so that the pattern is
+  /// only parsed once.
+  pub(crate) fn matcher(&self) -> impl Fn(&ExprResult) -> bool + '_ {
+    let pattern = match &self.operand {
+      Operand::Text(t) => Some(Pattern::new(t)),
+      Operand::Num(_) => None,
+    };
+    move |value| {
+      let ordering = match (&self.operand, value) {
+        (Operand::Num(n), ExprResult::Num(m)) => m.partial_cmp(n),
+        // Numbers typed as text count, like `"10"` for `10`.
+        (Operand::Num(n), ExprResult::Text(t)) if self.op == Op::Eq || self.op == Op::Ne => {
+          number(t).map(|m| m.partial_cmp(n).unwrap_or(Ordering::Less))
+        }
+        (Operand::Text(t), ExprResult::Text(v)) if self.op == Op::Eq || self.op == Op::Ne => {
+          // Empty cells only match an empty criterion, not `*`.
+          let is_match = if v.is_empty() {
+            t.is_empty()
+          } else {
+            pattern.as_ref().unwrap().is_match(&v.to_lowercase())
+          };
+          Some(if is_match {
+            Ordering::Equal
+          } else {
+            Ordering::Less
+          })
+        }
+        (Operand::Text(t), ExprResult::Text(v)) if !v.is_empty() => Some(v.to_lowercase().cmp(t)),
+        _ => None,
+      };
+      match ordering {
+        Some(ordering) => self.op.accepts(ordering),
+        // Values of another kind only pass `<>`.
+        None => self.op == Op::Ne,
+      }
+    }
+  }
+}
+
+fn number(t: &str) -> Option<f64> {
+  let t = t.trim();
+  if t.is_empty() {
+    return None;
+  }
+  t.parse().ok().or_else(|| date_time_value(t))
+}
+
+// The values of a range or an array, row by row, and its size.
+struct Grid {
+  size: (usize, usize),
+  values: Vec<ExprResult>,
+}
+
+impl Grid {
+  fn new(arg: &ExprTree, ss: &Spreadsheet) -> Result<Grid, ExprResult> {
+    match arg.reference(ss) {
+      Some(range) => Grid::from_range(range, ss),
+      None => match arg.eval(ss) {
+        e @ ExprResult::Error(_) => Err(e),
+        value => Ok(Grid {
+          size: value.size(),
+          values: value.into_rows().into_iter().flatten().collect(),
+        }),
+      },
+    }
+  }
+
+  fn from_range(range: Range, ss: &Spreadsheet) -> Result<Grid, ExprResult> {
+    let range = range
+      .clip(ss)
+      .ok_or_else(|| ExprResult::Error("#REF!".to_string()))?;
+    Ok(Grid {
+      size: (range.height(), range.width()),
+      values: range
+        .coords(ss)
+        .map(|(row, col)| ss.get(row, col).out().clone())
+        .collect(),
+    })
+  }
+}
+
+/// The cells summed or averaged by `SUMIF` or `AVERAGEIF`, when their third
+/// argument is a reference: as many as the cells compared, from the top left
+/// of that reference, `SUMIF(A1:A3, ">0", B1)` sums `B1:B3`.
+pub fn resized_range(args: &[ExprTree], ss: &Spreadsheet) -> Option<Range> {
+  let compared = args.first()?.reference(ss)?;
+  let start = args.get(2)?.reference(ss)?.start;
+  let end = (
+    start.0 + compared.height() - 1,
+    start.1 + compared.width() - 1,
+  );
+  Some(Range::new(start, end))
+}
+
+// Which of the values of the `(range, criterion)` pairs of `args` meet all
+// the criteria. The ranges must have the same size, `size` if given.
+fn matches(
+  args: &[ExprTree],
+  mut size: Option<(usize, usize)>,
+  ss: &Spreadsheet,
+) -> Result<Vec<bool>, ExprResult> {
+  if args.is_empty() || !args.len().is_multiple_of(2) {
+    return Err(value_error());
+  }
+  let mut res: Vec<bool> = vec![];
+  for pair in args.chunks(2) {
+    let grid = Grid::new(&pair[0], ss)?;
+    let criterion = Criterion::new(pair[1].eval(ss))?;
+    if *size.get_or_insert(grid.size) != grid.size {
+      return Err(value_error());
+    }
+    let passed = grid.values.iter().map(criterion.matcher());
+    res = if res.is_empty() {
+      passed.collect()
+    } else {
+      res.into_iter().zip(passed).map(|(a, b)| a && b).collect()
+    };
+  }
+  Ok(res)
+}
+
+// The numbers of `values` that matched, skipping text and empty cells.
+fn matched_numbers(values: Vec<ExprResult>, matched: &[bool]) -> Result<Vec<f64>, ExprResult> {
+  let mut nums = vec![];
+  for (value, _) in values.into_iter().zip(matched).filter(|(_, m)| **m) {
+    match value {
+      ExprResult::Num(n) => nums.push(n),
+      e @ ExprResult::Error(_) => return Err(e),
+      _ => (),
+    }
+  }
+  Ok(nums)
+}
+
+// The values to sum or average for `SUMIF` and `AVERAGEIF`, see
+// `resized_range`.
+fn aggregated(args: &[ExprTree], ss: &Spreadsheet) -> Result<Vec<f64>, ExprResult> {
+  check_arity(args, 2, 3)?;
+  let matched = matches(&args[..2], None, ss)?;
+  let values = match (args.get(2), resized_range(args, ss)) {
+    (_, Some(range)) => Grid::from_range(range, ss)?,
+    (Some(arg), None) => Grid::new(arg, ss)?,
+    (None, None) => Grid::new(&args[0], ss)?,
+  };
+  if values.values.len() != matched.len() {
+    return Err(value_error());
+  }
+  matched_numbers(values.values, &matched)
+}
+
+// The values to sum or average for `SUMIFS` and `AVERAGEIFS`, the first
+// argument.
+fn aggregated_ifs(args: &[ExprTree], ss: &Spreadsheet) -> Result<Vec<f64>, ExprResult> {
+  check_arity(args, 3, usize::MAX)?;
+  let values = Grid::new(&args[0], ss)?;
+  let matched = matches(&args[1..], Some(values.size), ss)?;
+  matched_numbers(values.values, &matched)
+}
+
+/// COUNTIF(range, criterion)
+pub fn countif(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  countifs(args, ss)
+}
+
+/// COUNTIFS(range1, criterion1, [range2, criterion2, ...]), the ranges
+/// having the same size.
+pub fn countifs(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  let matched = matches(args, None, ss)?;
+  Ok(ExprResult::Num(
+    matched.iter().filter(|m| **m).count() as f64
+  ))
+}
+
+/// SUMIF(range, criterion, [sum_range])
+pub fn sumif(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  Ok(ExprResult::Num(aggregated(args, ss)?.iter().sum()))
+}
+
+/// SUMIFS(sum_range, range1, criterion1, [range2, criterion2, ...])
+pub fn sumifs(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  Ok(ExprResult::Num(aggregated_ifs(args, ss)?.iter().sum()))
+}
+
+/// AVERAGEIF(range, criterion, [average_range])
+pub fn averageif(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  average(aggregated(args, ss)?)
+}
+
+/// AVERAGEIFS(average_range, range1, criterion1, [range2, criterion2, ...])
+pub fn averageifs(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  average(aggregated_ifs(args, ss)?)
+}
+
+fn average(nums: Vec<f64>) -> FnResult {
+  if nums.is_empty() {
+    return Err(div_error());
+  }
+  Ok(ExprResult::Num(
+    nums.iter().sum::<f64>() / nums.len() as f64,
+  ))
+}
@@ -0,0 +1,205 @@
+//! Date and time functions, working on serial numbers (see `date`).
+
+use super::{check_arity, num, num_error, text, to_num, values, FnResult};
+use crate::date::{self, DateTime};
+use crate::expr::{ExprResult, ExprTree};
+use crate::Spreadsheet;
+
+pub fn today(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 0, 0)?;
+  Ok(ExprResult::Num(ss.now().floor()))
+}
+
+pub fn now(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 0, 0)?;
+  Ok(ExprResult::Num(ss.now()))
+}
+
+pub fn date(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 3)?;
+  let mut year = num(&args[0], ss)?.trunc() as i64;
+  let month = num(&args[1], ss)?.trunc() as i64;
+  let day = num(&args[2], ss)?.trunc() as i64;
+  // Two digit years and the like are offsets from 1900.
+  if (0..1900).contains(&year) {
+    year += 1900;
+  }
+  serial(date::serial_from_ymd(year, month, day))
+}
+
+pub fn time(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 3)?;
+  let hour = num(&args[0], ss)?.trunc() as i64;
+  let minute = num(&args[1], ss)?.trunc() as i64;
+  let second = num(&args[2], ss)?.trunc();
+  let seconds = hour as f64 * 3600. + minute as f64 * 60. + second;
+  if seconds < 0. {
+    return Err(num_error());
+  }
+  // Like a clock, TIME wraps around at midnight.
+  Ok(ExprResult::Num(date::serial_from_hms(
+    0,
+    0,
+    seconds % 86400.,
+  )))
+}
+
+pub fn year(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(date_time(&args[0], ss)?.year as f64))
+}
+
+pub fn month(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(date_time(&args[0], ss)?.month as f64))
+}
+
+pub fn day(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(date_time(&args[0], ss)?.day as f64))
+}
+
+pub fn hour(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(date_time(&args[0], ss)?.hour as f64))
+}
+
+pub fn minute(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(date_time(&args[0], ss)?.minute as f64))
+}
+
+pub fn second(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(date_time(&args[0], ss)?.second as f64))
+}
+
+pub fn edate(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // EDATE(start, months): same day, `months` later, clamped to the end of
+  // shorter months.
+  check_arity(args, 2, 2)?;
+  let start = date_time(&args[0], ss)?;
+  let months = num(&args[1], ss)?.trunc() as i64;
+  let (year, month) = add_months(start.year, start.month, months);
+  let day = start.day.min(date::days_in_month(year, month));
+  serial(date::serial_from_ymd(year, month as i64, day as i64))
+}
+
+pub fn eomonth(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // EOMONTH(start, months): last day of the month `months` later.
+  check_arity(args, 2, 2)?;
+  let start = date_time(&args[0], ss)?;
+  let months = num(&args[1], ss)?.trunc() as i64;
+  let (year, month) = add_months(start.year, start.month, months);
+  serial(date::serial_from_ymd(year, month as i64 + 1, 0))
+}
+
+pub fn networkdays(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // NETWORKDAYS(start, end, [holidays]): Mondays to Fridays between both
+  // dates, inclusive, that aren't holidays. Negative if `end` comes first.
+  check_arity(args, 2, 3)?;
+  let start = date_time(&args[0], ss)?;
+  let end = date_time(&args[1], ss)?;
+  let mut holidays = vec![];
+  if let Some(arg) = args.get(2) {
+    for value in values(arg, ss) {
+      match value {
+        // Blank cells in the holiday list are fine.
+        ExprResult::Text(t) if t.is_empty() => (),
+        value => holidays.push(to_num(value)?.floor()),
+      }
+    }
+  }
+
+  holidays.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  holidays.dedup();
+
+  let start = day_serial(&start);
+  let end = day_serial(&end);
+  let (from, to, sign) = if start <= end {
+    (start, end, 1.)
+  } else {
+    (end, start, -1.)
+  };
+  let is_weekday = |d: f64| !matches!(date::weekday(d), 0 | 6);
+  // Whole weeks have five workdays each, only the rest needs walking.
+  let days = (to - from) as i64 + 1;
+  let mut count = days / 7 * 5;
+  let mut d = from + (days / 7 * 7) as f64;
+  while d <= to {
+    if is_weekday(d) {
+      count += 1;
+    }
+    d += 1.;
+  }
+  let off = holidays
+    .iter()
+    .filter(|h| (from..=to).contains(*h) && is_weekday(**h))
+    .count() as i64;
+  Ok(ExprResult::Num(sign * (count - off) as f64))
+}
+
+pub fn datedif(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // DATEDIF(start, end, unit): whole years ("Y"), months ("M") or days
+  // ("D") between two dates, or what's left over after whole months ("MD"),
+  // years ("YM") or years in days ("YD").
+  check_arity(args, 3, 3)?;
+  let start = date_time(&args[0], ss)?;
+  let end = date_time(&args[1], ss)?;
+  let unit = text(&args[2], ss)?.to_ascii_uppercase();
+  if day_serial(&start) > day_serial(&end) {
+    return Err(num_error());
+  }
+
+  let mut months = (end.year - start.year) * 12 + end.month as i64 - start.month as i64;
+  if end.day < start.day {
+    months -= 1;
+  }
+  let diff = match unit.as_str() {
+    "Y" => months / 12,
+    "M" => months,
+    "D" => (day_serial(&end) - day_serial(&start)) as i64,
+    "YM" => months % 12,
+    "MD" => {
+      if end.day >= start.day {
+        (end.day - start.day) as i64
+      } else {
+        // Days from `start`'s day in the month before `end`.
+        let (year, month) = add_months(end.year, end.month, -1);
+        (date::days_in_month(year, month) as i64 - start.day as i64).max(0) + end.day as i64
+      }
+    }
+    "YD" => {
+      let mut year = end.year;
+      if (end.month, end.day) < (start.month, start.day) {
+        year -= 1;
+      }
+      let day = start.day.min(date::days_in_month(year, start.month));
+      let anniversary = date::serial_from_ymd(year, start.month as i64, day as i64);
+      (day_serial(&end) - anniversary) as i64
+    }
+    _ => return Err(num_error()),
+  };
+  Ok(ExprResult::Num(diff as f64))
+}
+
+fn date_time(arg: &ExprTree, ss: &Spreadsheet) -> Result<DateTime, ExprResult> {
+  let serial = num(arg, ss)?;
+  DateTime::from_serial(serial).ok_or_else(num_error)
+}
+
+fn day_serial(dt: &DateTime) -> f64 {
+  date::serial_from_ymd(dt.year, dt.month as i64, dt.day as i64)
+}
+
+fn add_months(year: i64, month: u32, months: i64) -> (i64, u32) {
+  let total = year * 12 + month as i64 - 1 + months;
+  (total.div_euclid(12), total.rem_euclid(12) as u32 + 1)
+}
+
+fn serial(serial: f64) -> FnResult {
+  if DateTime::from_serial(serial).is_none() {
+    return Err(num_error());
+  }
+  Ok(ExprResult::Num(serial))
+}
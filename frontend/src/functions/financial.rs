@@ -0,0 +1,197 @@
+//! Financial functions. Money paid out is negative and money received is
+//! positive, and payments are due at the end of each period unless `type`
+//! is 1, then at the beginning.
+
+use super::{check_arity, div_error, finite, num, num_error, numbers, FnResult};
+use crate::expr::{ExprResult, ExprTree};
+use crate::Spreadsheet;
+use std::slice;
+
+/// How many steps `IRR`, `XIRR` and `RATE` take before giving up with
+/// `#NUM!`.
+const MAX_ITERATIONS: usize = 100;
+
+/// PMT(rate, nper, pv, [fv], [type]), the payment per period of a loan.
+pub fn pmt(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 5)?;
+  let rate = num(&args[0], ss)?;
+  let nper = num(&args[1], ss)?;
+  let pv = num(&args[2], ss)?;
+  let (fv, due) = optional_fv_and_type(&args[3..], ss)?;
+  if rate == 0. {
+    return finite(-(pv + fv) / nper);
+  }
+  let growth = (1. + rate).powf(nper);
+  finite(-(fv + pv * growth) * rate / ((1. + rate * due) * (growth - 1.)))
+}
+
+/// PV(rate, nper, pmt, [fv], [type]), what a series of payments is worth
+/// today.
+pub fn pv(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 5)?;
+  let rate = num(&args[0], ss)?;
+  let nper = num(&args[1], ss)?;
+  let pmt = num(&args[2], ss)?;
+  let (fv, due) = optional_fv_and_type(&args[3..], ss)?;
+  if rate == 0. {
+    return finite(-(fv + pmt * nper));
+  }
+  let growth = (1. + rate).powf(nper);
+  finite(-(fv + pmt * (1. + rate * due) * (growth - 1.) / rate) / growth)
+}
+
+/// FV(rate, nper, pmt, [pv], [type]), what an investment is worth after
+/// `nper` periods.
+pub fn fv(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 5)?;
+  let rate = num(&args[0], ss)?;
+  let nper = num(&args[1], ss)?;
+  let pmt = num(&args[2], ss)?;
+  let (pv, due) = optional_fv_and_type(&args[3..], ss)?;
+  if rate == 0. {
+    return finite(-(pv + pmt * nper));
+  }
+  let growth = (1. + rate).powf(nper);
+  finite(-(pv * growth + pmt * (1. + rate * due) * (growth - 1.) / rate))
+}
+
+/// NPV(rate, value1, ...), the values being received at the end of
+/// consecutive periods, the first one a period from now.
+pub fn npv(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, usize::MAX)?;
+  let rate = num(&args[0], ss)?;
+  if rate == -1. {
+    return Err(div_error());
+  }
+  let values = numbers(&args[1..], ss)?;
+  finite(net_present_value(rate, &values, 1.))
+}
+
+/// IRR(values, [guess]), the rate for which the `NPV` of values received a
+/// period apart, starting now, is zero.
+pub fn irr(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 2)?;
+  let values = numbers(slice::from_ref(&args[0]), ss)?;
+  let guess = args.get(1).map_or(Ok(0.1), |arg| num(arg, ss))?;
+  check_cash_flows(&values)?;
+  let rate = solve(|rate| net_present_value(rate, &values, 0.), guess)?;
+  Ok(ExprResult::Num(rate))
+}
+
+/// XNPV(rate, values, dates), like `NPV` for values received at the given
+/// dates, discounted yearly from the first one.
+pub fn xnpv(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 3)?;
+  let rate = num(&args[0], ss)?;
+  let flows = dated_cash_flows(&args[1], &args[2], ss)?;
+  if rate <= -1. {
+    return Err(num_error());
+  }
+  finite(dated_present_value(rate, &flows))
+}
+
+/// XIRR(values, dates, [guess]), like `IRR` for values received at the
+/// given dates.
+pub fn xirr(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 3)?;
+  let flows = dated_cash_flows(&args[0], &args[1], ss)?;
+  let guess = args.get(2).map_or(Ok(0.1), |arg| num(arg, ss))?;
+  let values: Vec<f64> = flows.iter().map(|(value, _)| *value).collect();
+  check_cash_flows(&values)?;
+  let rate = solve(|rate| dated_present_value(rate, &flows), guess)?;
+  Ok(ExprResult::Num(rate))
+}
+
+/// RATE(nper, pmt, pv, [fv], [type], [guess]), the interest rate per period
+/// of a loan or an investment.
+pub fn rate(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 3, 6)?;
+  let nper = num(&args[0], ss)?;
+  let pmt = num(&args[1], ss)?;
+  let pv = num(&args[2], ss)?;
+  let (fv, due) = optional_fv_and_type(&args[3..args.len().min(5)], ss)?;
+  let guess = args.get(5).map_or(Ok(0.1), |arg| num(arg, ss))?;
+  let balance = |rate: f64| {
+    if rate == 0. {
+      return pv + pmt * nper + fv;
+    }
+    let growth = (1. + rate).powf(nper);
+    pv * growth + pmt * (1. + rate * due) * (growth - 1.) / rate + fv
+  };
+  Ok(ExprResult::Num(solve(balance, guess)?))
+}
+
+// The optional `fv` (or `pv`) and `type` arguments, 0 when missing. `type`
+// is 1 for payments due at the beginning of periods.
+fn optional_fv_and_type(args: &[ExprTree], ss: &Spreadsheet) -> Result<(f64, f64), ExprResult> {
+  let value = args.first().map_or(Ok(0.), |arg| num(arg, ss))?;
+  let due = args.get(1).map_or(Ok(0.), |arg| num(arg, ss))?;
+  Ok((value, if due != 0. { 1. } else { 0. }))
+}
+
+// Values discounted by `rate` per period, the first one `first` periods
+// from now.
+fn net_present_value(rate: f64, values: &[f64], first: f64) -> f64 {
+  values
+    .iter()
+    .enumerate()
+    .map(|(i, value)| value / (1. + rate).powf(first + i as f64))
+    .sum()
+}
+
+// Values and the dates they're received at. Dates can't come before the
+// first one.
+fn dated_cash_flows(
+  values: &ExprTree,
+  dates: &ExprTree,
+  ss: &Spreadsheet,
+) -> Result<Vec<(f64, f64)>, ExprResult> {
+  let values = numbers(slice::from_ref(values), ss)?;
+  let dates = numbers(slice::from_ref(dates), ss)?;
+  if values.len() != dates.len() || values.is_empty() {
+    return Err(num_error());
+  }
+  let start = dates[0].trunc();
+  if dates.iter().any(|date| date.trunc() < start) {
+    return Err(num_error());
+  }
+  let years = dates.iter().map(|date| (date.trunc() - start) / 365.);
+  Ok(values.into_iter().zip(years).collect())
+}
+
+fn dated_present_value(rate: f64, flows: &[(f64, f64)]) -> f64 {
+  flows
+    .iter()
+    .map(|(value, years)| value / (1. + rate).powf(*years))
+    .sum()
+}
+
+// A rate of return needs money both paid and received.
+fn check_cash_flows(values: &[f64]) -> Result<(), ExprResult> {
+  if values.iter().any(|v| *v > 0.) && values.iter().any(|v| *v < 0.) {
+    Ok(())
+  } else {
+    Err(num_error())
+  }
+}
+
+// Newton's method, finding where `f` is zero starting from `guess`. The
+// derivative is estimated from a nearby point, it's as good as the exact one
+// for the smooth functions of this module.
+fn solve(f: impl Fn(f64) -> f64, guess: f64) -> Result<f64, ExprResult> {
+  let mut x = guess;
+  for _ in 0..MAX_ITERATIONS {
+    let y = f(x);
+    let h = 1e-6 * x.abs().max(1.);
+    let slope = (f(x + h) - f(x - h)) / (2. * h);
+    let step = y / slope;
+    if !step.is_finite() {
+      break;
+    }
+    x -= step;
+    if step.abs() <= 1e-12 * x.abs().max(1.) {
+      return Ok(x);
+    }
+  }
+  Err(num_error())
+}
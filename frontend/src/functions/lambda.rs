@@ -0,0 +1,79 @@
+//! `LET` and `LAMBDA`, for formulas with variables and functions defined in
+//! the sheet, see `scope`.
+
+use super::{check_arity, num_error, value_error, FnResult};
+use crate::expr::{ExprResult, ExprTree, Lambda, ValueNode};
+use crate::Spreadsheet;
+
+// LET(name1, value1, [name2, value2, ...], calculation)
+pub fn let_fn(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  if args.len() < 3 || args.len().is_multiple_of(2) {
+    return Err(value_error());
+  }
+  let (calculation, pairs) = args.split_last().unwrap();
+  let mut bindings = vec![];
+  for pair in pairs.chunks(2) {
+    let name = variable(&pair[0])?;
+    // Each value sees the variables before it.
+    let value = ss.scope.with(bindings.clone(), || pair[1].eval(ss));
+    bindings.push((name, value));
+  }
+  Ok(ss.scope.with(bindings, || calculation.eval(ss)))
+}
+
+// LAMBDA([param1, ...], calculation)
+pub fn lambda(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let (body, params) = args.split_last().unwrap();
+  let params = params
+    .iter()
+    .map(variable)
+    .collect::<Result<Vec<String>, ExprResult>>()?;
+  if (1..params.len()).any(|i| params[..i].contains(&params[i])) {
+    return Err(value_error());
+  }
+  Ok(ExprResult::Lambda(Box::new(Lambda {
+    params,
+    body: body.clone(),
+    captured: ss.scope.bindings(),
+  })))
+}
+
+/// Calls the lambda `name` stands for, a `LET` variable, a parameter or a
+/// workbook name, when it isn't a built-in function.
+pub fn call_named(name: &str, args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  let value = match (ss.scope.get(name), ss.name(name)) {
+    (Some(value), _) => value,
+    (None, Some(definition)) => ss.scope.outside(|| definition.eval(ss)),
+    (None, None) => return Err(ExprResult::Error("#NAME?".to_string())),
+  };
+  let lambda = match value {
+    ExprResult::Lambda(lambda) => *lambda,
+    e @ ExprResult::Error(_) => return Err(e),
+    _ => return Err(value_error()),
+  };
+  if args.len() != lambda.params.len() {
+    return Err(value_error());
+  }
+  // Arguments are evaluated where the lambda is called.
+  let mut bindings = lambda.captured;
+  bindings.extend(
+    lambda
+      .params
+      .into_iter()
+      .zip(args.iter().map(|arg| arg.eval(ss))),
+  );
+  let body = &lambda.body;
+  ss.scope
+    .call(bindings, || body.eval(ss))
+    .ok_or_else(num_error)
+}
+
+// The uppercased name of a `LET` variable or a parameter, written like a
+// name.
+fn variable(arg: &ExprTree) -> Result<String, ExprResult> {
+  match arg {
+    ExprTree::Leaf(ValueNode::Name(name)) => Ok(name.to_ascii_uppercase()),
+    _ => Err(value_error()),
+  }
+}
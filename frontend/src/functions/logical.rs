@@ -0,0 +1,18 @@
+//! Functions that choose what to evaluate.
+
+use super::{bool_arg, check_arity, FnResult};
+use crate::expr::{ExprResult, ExprTree};
+use crate::Spreadsheet;
+
+// IF(condition, value_if_true, [value_if_false]). Only the branch taken is
+// evaluated, so that recursive lambdas can stop.
+pub fn if_fn(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 3)?;
+  if bool_arg(&args[0], ss)? {
+    Ok(args[1].eval(ss))
+  } else if let Some(otherwise) = args.get(2) {
+    Ok(otherwise.eval(ss))
+  } else {
+    Ok(ExprResult::Num(0.))
+  }
+}
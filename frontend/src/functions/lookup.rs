@@ -0,0 +1,294 @@
+//! Lookup and reference functions. Every cell of the ranges they search is a
+//! dependency (see `ExprTree::fill_outbound`), so results follow table edits.
+
+use super::text::Pattern;
+use super::{check_arity, not_found, num, value_error, FnResult};
+use crate::expr::{ExprResult, ExprTree, Range};
+use crate::Spreadsheet;
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Match {
+  Exact,
+  /// Exact, with `?`, `*` and `~` wildcards for text.
+  Wildcard,
+  /// Exact, or else the largest value below the one looked up.
+  NextSmaller,
+  /// Exact, or else the smallest value above the one looked up.
+  NextLarger,
+}
+
+pub fn vlookup(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // VLOOKUP(value, table, column, [approximate]) looks `value` up in the
+  // first column of `table`, returning the same row in `column`.
+  check_arity(args, 3, 4)?;
+  table_lookup(args, ss, false)
+}
+
+pub fn hlookup(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // HLOOKUP(value, table, row, [approximate]), VLOOKUP on its side.
+  check_arity(args, 3, 4)?;
+  table_lookup(args, ss, true)
+}
+
+pub fn xlookup(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // XLOOKUP(value, lookup, results, [if_not_found], [match_mode],
+  // [search_mode]). Match modes are 0 exact, -1 exact or next smaller, 1
+  // exact or next larger and 2 wildcards, search modes 1 from the first
+  // item and -1 from the last one.
+  check_arity(args, 3, 6)?;
+  let needle = scalar(&args[0], ss)?;
+  let lookup_range = range_arg(&args[1], ss)?;
+  let lookup = vector(lookup_range);
+  let results = range_arg(&args[2], ss)?;
+  // Looking down a column returns the whole row of `results` that matched,
+  // looking along a row the whole column.
+  let horizontal = lookup_range.height() == 1;
+  let len = if horizontal {
+    results.width()
+  } else {
+    results.height()
+  };
+  if lookup.len() != len {
+    return Err(value_error());
+  }
+  let mode = match optional_int(args.get(4), ss, 0)? {
+    0 => Match::Exact,
+    -1 => Match::NextSmaller,
+    1 => Match::NextLarger,
+    2 => Match::Wildcard,
+    _ => return Err(value_error()),
+  };
+  let reverse = match optional_int(args.get(5), ss, 1)? {
+    1 | 2 => false,
+    -1 | -2 => true,
+    _ => return Err(value_error()),
+  };
+
+  let haystack = cell_values(&lookup, ss);
+  match find(&needle, &haystack, mode, reverse) {
+    Some(i) if horizontal => {
+      let col = results.start.1 + i;
+      Ok(Range::new((results.start.0, col), (results.end.0, col)).values(ss))
+    }
+    Some(i) => {
+      let row = results.start.0 + i;
+      Ok(Range::new((row, results.start.1), (row, results.end.1)).values(ss))
+    }
+    None => match args.get(3) {
+      Some(if_not_found) => Ok(if_not_found.eval(ss)),
+      None => Err(not_found()),
+    },
+  }
+}
+
+pub fn index(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // INDEX(range, row, [column]), both 1-based. A single row or column takes
+  // just the position along it.
+  check_arity(args, 2, 3)?;
+  let range = range_arg(&args[0], ss)?;
+  let mut row = optional_int(args.get(1), ss, 1)?;
+  let mut col = optional_int(args.get(2), ss, 1)?;
+  if args.len() == 2 && range.height() == 1 {
+    col = row;
+    row = 1;
+  }
+  if row < 1 || col < 1 {
+    return Err(value_error());
+  }
+  let (row, col) = (row as usize - 1, col as usize - 1);
+  if row >= range.height() || col >= range.width() {
+    return Err(ref_error());
+  }
+  Ok(cell_value((range.start.0 + row, range.start.1 + col), ss))
+}
+
+pub fn match_fn(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // MATCH(value, range, [type]) gives the 1-based position of `value`. Type
+  // 1 wants ascending values and finds the largest one below or equal, -1
+  // wants descending ones and finds the smallest one above or equal, 0
+  // finds an exact match and takes wildcards.
+  check_arity(args, 2, 3)?;
+  let needle = scalar(&args[0], ss)?;
+  let haystack = cell_values(&vector(range_arg(&args[1], ss)?), ss);
+  let found = match optional_int(args.get(2), ss, 1)? {
+    0 => find(&needle, &haystack, Match::Wildcard, false),
+    t if t > 0 => sorted_find(&needle, &haystack, Ordering::Less),
+    _ => sorted_find(&needle, &haystack, Ordering::Greater),
+  };
+  found
+    .map(|i| ExprResult::Num(i as f64 + 1.))
+    .ok_or_else(not_found)
+}
+
+fn table_lookup(args: &[ExprTree], ss: &Spreadsheet, horizontal: bool) -> FnResult {
+  let needle = scalar(&args[0], ss)?;
+  let table = range_arg(&args[1], ss)?;
+  let offset = num(&args[2], ss)?.trunc();
+  let approximate = match args.get(3) {
+    Some(arg) => super::bool_arg(arg, ss)?,
+    None => true,
+  };
+  let size = if horizontal {
+    table.height()
+  } else {
+    table.width()
+  };
+  if offset < 1. {
+    return Err(value_error());
+  }
+  if offset > size as f64 {
+    return Err(ref_error());
+  }
+  let offset = offset as usize - 1;
+
+  let keys: Vec<(usize, usize)> = if horizontal {
+    (table.start.1..=table.end.1)
+      .map(|col| (table.start.0, col))
+      .collect()
+  } else {
+    (table.start.0..=table.end.0)
+      .map(|row| (row, table.start.1))
+      .collect()
+  };
+  let haystack = cell_values(&keys, ss);
+  let found = if approximate {
+    sorted_find(&needle, &haystack, Ordering::Less)
+  } else {
+    find(&needle, &haystack, Match::Wildcard, false)
+  };
+  let (row, col) = keys[found.ok_or_else(not_found)?];
+  let coord = if horizontal {
+    (row + offset, col)
+  } else {
+    (row, col + offset)
+  };
+  Ok(cell_value(coord, ss))
+}
+
+/// Position of `needle` in `haystack`, in any order.
+fn find(needle: &ExprResult, haystack: &[ExprResult], mode: Match, reverse: bool) -> Option<usize> {
+  let pattern = match (mode, needle) {
+    (Match::Wildcard, ExprResult::Text(t)) => Some(t.to_lowercase()),
+    _ => None,
+  };
+  let pattern = pattern.as_deref().map(Pattern::new);
+  let mut order: Vec<usize> = (0..haystack.len()).collect();
+  if reverse {
+    order.reverse();
+  }
+
+  let mut best: Option<usize> = None;
+  for i in order {
+    let value = &haystack[i];
+    if let Some(pattern) = &pattern {
+      match value {
+        ExprResult::Text(t) if pattern.is_match(&t.to_lowercase()) => return Some(i),
+        _ => continue,
+      }
+    }
+    let ord = match compare(value, needle) {
+      Some(ord) => ord,
+      None => continue,
+    };
+    let wanted = match mode {
+      Match::NextSmaller => Ordering::Less,
+      Match::NextLarger => Ordering::Greater,
+      _ => Ordering::Equal,
+    };
+    if ord == Ordering::Equal {
+      return Some(i);
+    }
+    // Keep the closest value on the wanted side.
+    if ord == wanted {
+      let closer = best.is_none_or(|b| compare(value, &haystack[b]) == Some(wanted.reverse()));
+      if closer {
+        best = Some(i);
+      }
+    }
+  }
+  best
+}
+
+/// Position of `needle` in a `haystack` sorted so that values go from
+/// `before` to after it: the last value before or equal to `needle`.
+fn sorted_find(needle: &ExprResult, haystack: &[ExprResult], before: Ordering) -> Option<usize> {
+  let mut found = None;
+  for (i, value) in haystack.iter().enumerate() {
+    match compare(value, needle) {
+      Some(Ordering::Equal) => found = Some(i),
+      Some(ord) if ord == before => found = Some(i),
+      // Past where `needle` would be.
+      Some(_) => break,
+      // Blanks and values of another type don't break the order.
+      None => (),
+    }
+  }
+  found
+}
+
+/// Orders numbers against numbers and text against text, ignoring case.
+/// Anything else, blank cells included, doesn't compare.
+fn compare(a: &ExprResult, b: &ExprResult) -> Option<Ordering> {
+  match (a, b) {
+    (ExprResult::Num(a), ExprResult::Num(b)) => a.partial_cmp(b),
+    (ExprResult::Text(a), ExprResult::Text(b)) if !a.is_empty() && !b.is_empty() => {
+      Some(a.to_lowercase().cmp(&b.to_lowercase()))
+    }
+    _ => None,
+  }
+}
+
+/// The value looked up, which can't be an error.
+fn scalar(arg: &ExprTree, ss: &Spreadsheet) -> FnResult {
+  match arg.eval(ss) {
+    e @ ExprResult::Error(_) => Err(e),
+    value => Ok(value),
+  }
+}
+
+// Errors such as the `#REF!` of a range past the edges of the sheet come
+// through, anything else that isn't a reference is a `#VALUE!`.
+fn range_arg(arg: &ExprTree, ss: &Spreadsheet) -> Result<Range, ExprResult> {
+  arg.reference(ss).ok_or_else(|| match arg.eval(ss) {
+    e @ ExprResult::Error(_) => e,
+    _ => value_error(),
+  })
+}
+
+/// Cells of a single row or column range. For wider ones, the first column.
+fn vector(range: Range) -> Vec<(usize, usize)> {
+  if range.height() == 1 {
+    (range.start.1..=range.end.1)
+      .map(|col| (range.start.0, col))
+      .collect()
+  } else {
+    (range.start.0..=range.end.0)
+      .map(|row| (row, range.start.1))
+      .collect()
+  }
+}
+
+fn cell_values(coords: &[(usize, usize)], ss: &Spreadsheet) -> Vec<ExprResult> {
+  coords.iter().map(|coord| cell_value(*coord, ss)).collect()
+}
+
+/// Cells past the edges of the sheet are blank.
+fn cell_value((row, col): (usize, usize), ss: &Spreadsheet) -> ExprResult {
+  if row < ss.height() && col < ss.width() {
+    ss.get(row, col).out().clone()
+  } else {
+    ExprResult::Text("".to_string())
+  }
+}
+
+fn optional_int(arg: Option<&ExprTree>, ss: &Spreadsheet, default: i64) -> Result<i64, ExprResult> {
+  match arg {
+    Some(arg) => Ok(num(arg, ss)?.trunc() as i64),
+    None => Ok(default),
+  }
+}
+
+fn ref_error() -> ExprResult {
+  ExprResult::Error("#REF!".to_string())
+}
@@ -0,0 +1,133 @@
+//! Math functions.
+
+use super::{check_arity, div_error, finite, num, num_error, numbers, FnResult};
+use crate::expr::{ExprResult, ExprTree};
+use crate::Spreadsheet;
+
+pub fn rand(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 0, 0)?;
+  Ok(ExprResult::Num(ss.rng.next_f64()))
+}
+
+/// A whole number between `bottom` and `top`, both included.
+pub fn randbetween(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let bottom = num(&args[0], ss)?.ceil();
+  let top = num(&args[1], ss)?.floor();
+  if bottom > top {
+    return Err(num_error());
+  }
+  let n = bottom + (ss.rng.next_f64() * (top - bottom + 1.)).floor();
+  Ok(ExprResult::Num(n))
+}
+
+pub fn sum(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  Ok(ExprResult::Num(numbers(args, ss)?.iter().sum()))
+}
+
+pub fn abs(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Num(num(&args[0], ss)?.abs()))
+}
+
+pub fn sqrt(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  let n = num(&args[0], ss)?;
+  if n < 0. {
+    return Err(num_error());
+  }
+  Ok(ExprResult::Num(n.sqrt()))
+}
+
+pub fn power(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let base = num(&args[0], ss)?;
+  let exponent = num(&args[1], ss)?;
+  if base == 0. && exponent < 0. {
+    return Err(div_error());
+  }
+  finite(base.powf(exponent))
+}
+
+pub fn ln(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  let n = num(&args[0], ss)?;
+  if n <= 0. {
+    return Err(num_error());
+  }
+  Ok(ExprResult::Num(n.ln()))
+}
+
+/// Logarithm in base 10 unless another base is given.
+pub fn log(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 2)?;
+  let n = num(&args[0], ss)?;
+  let base = args.get(1).map_or(Ok(10.), |arg| num(arg, ss))?;
+  if n <= 0. || base <= 0. {
+    return Err(num_error());
+  }
+  if base == 1. {
+    return Err(div_error());
+  }
+  Ok(ExprResult::Num(n.ln() / base.ln()))
+}
+
+/// The remainder has the sign of the divisor, `MOD(-3, 2)` is 1.
+pub fn mod_fn(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let n = num(&args[0], ss)?;
+  let divisor = num(&args[1], ss)?;
+  if divisor == 0. {
+    return Err(div_error());
+  }
+  Ok(ExprResult::Num(n - divisor * (n / divisor).floor()))
+}
+
+/// Halves round away from zero.
+pub fn round(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  round_with(args, ss, f64::round)
+}
+
+/// Rounds away from zero.
+pub fn roundup(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  round_with(args, ss, f64::ceil)
+}
+
+/// Rounds towards zero.
+pub fn rounddown(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  round_with(args, ss, f64::trunc)
+}
+
+// Rounds to a number of digits after the decimal point, before it when
+// negative, applying `round` to the absolute value.
+fn round_with(args: &[ExprTree], ss: &Spreadsheet, round: fn(f64) -> f64) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let n = num(&args[0], ss)?;
+  let digits = num(&args[1], ss)?.trunc().clamp(-308., 308.) as i32;
+  let scale = 10f64.powi(digits.abs());
+  let scaled = if digits >= 0 {
+    n.abs() * scale
+  } else {
+    n.abs() / scale
+  };
+  if !scaled.is_finite() {
+    // More digits than a number has.
+    return Ok(ExprResult::Num(n));
+  }
+  // Other spreadsheets round numbers as they show them, `ROUND(2.675, 2)`
+  // is 2.68 although the closest `f64` to 2.675 is a little below it.
+  let shown: f64 = format!("{:.14e}", scaled).parse().unwrap();
+  let rounded = round(shown);
+  let rounded = if digits >= 0 {
+    rounded / scale
+  } else {
+    rounded * scale
+  };
+  // Without a `-0` for what rounds to zero.
+  Ok(ExprResult::Num(if n < 0. && rounded != 0. {
+    -rounded
+  } else {
+    rounded
+  }))
+}
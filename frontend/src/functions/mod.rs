@@ -0,0 +1,247 @@
+//! Built-in functions. Arguments are handed over unevaluated so that each
+//! function decides what to evaluate, and how to coerce it.
+
+mod array;
+mod criteria;
+mod datetime;
+mod financial;
+mod lambda;
+mod logical;
+mod lookup;
+mod math;
+mod stats;
+mod text;
+
+use super::expr::{ExprResult, ExprTree, Range};
+use super::parser::date_time_value;
+use super::Spreadsheet;
+
+pub(crate) use criteria::Criterion;
+
+/// `Err` carries the error value the call evaluates to, so that helpers can
+/// bail out with `?`.
+type FnResult = Result<ExprResult, ExprResult>;
+
+pub fn call(name: &str, args: &[ExprTree], ss: &Spreadsheet) -> ExprResult {
+  let res = match name {
+    "ABS" => math::abs(args, ss),
+    "AVERAGE" => stats::average(args, ss),
+    "AVERAGEIF" => criteria::averageif(args, ss),
+    "AVERAGEIFS" => criteria::averageifs(args, ss),
+    "CONCAT" => text::concat(args, ss),
+    "CORREL" => stats::correl(args, ss),
+    "COUNT" => stats::count(args, ss),
+    "COUNTIF" => criteria::countif(args, ss),
+    "COUNTIFS" => criteria::countifs(args, ss),
+    "DATE" => datetime::date(args, ss),
+    "DATEDIF" => datetime::datedif(args, ss),
+    "DAY" => datetime::day(args, ss),
+    "EDATE" => datetime::edate(args, ss),
+    "EOMONTH" => datetime::eomonth(args, ss),
+    "FILTER" => array::filter(args, ss),
+    "FIND" => text::find(args, ss),
+    "FV" => financial::fv(args, ss),
+    "HLOOKUP" => lookup::hlookup(args, ss),
+    "HOUR" => datetime::hour(args, ss),
+    "IF" => logical::if_fn(args, ss),
+    "INDEX" => lookup::index(args, ss),
+    "IRR" => financial::irr(args, ss),
+    "LAMBDA" => lambda::lambda(args, ss),
+    "LEFT" => text::left(args, ss),
+    "LEN" => text::len(args, ss),
+    "LET" => lambda::let_fn(args, ss),
+    "LN" => math::ln(args, ss),
+    "LOG" => math::log(args, ss),
+    "LOWER" => text::lower(args, ss),
+    "MATCH" => lookup::match_fn(args, ss),
+    "MAX" => stats::max(args, ss),
+    "MEDIAN" => stats::median(args, ss),
+    "MID" => text::mid(args, ss),
+    "MIN" => stats::min(args, ss),
+    "MINUTE" => datetime::minute(args, ss),
+    "MOD" => math::mod_fn(args, ss),
+    "MODE" => stats::mode(args, ss),
+    "MONTH" => datetime::month(args, ss),
+    "NETWORKDAYS" => datetime::networkdays(args, ss),
+    "NOW" => datetime::now(args, ss),
+    "NPV" => financial::npv(args, ss),
+    "PERCENTILE" => stats::percentile(args, ss),
+    "PMT" => financial::pmt(args, ss),
+    "POWER" => math::power(args, ss),
+    "PV" => financial::pv(args, ss),
+    "QUARTILE" => stats::quartile(args, ss),
+    "RAND" => math::rand(args, ss),
+    "RANDBETWEEN" => math::randbetween(args, ss),
+    "RANK" => stats::rank(args, ss),
+    "RATE" => financial::rate(args, ss),
+    "RIGHT" => text::right(args, ss),
+    "ROUND" => math::round(args, ss),
+    "ROUNDDOWN" => math::rounddown(args, ss),
+    "ROUNDUP" => math::roundup(args, ss),
+    "SEARCH" => text::search(args, ss),
+    "SECOND" => datetime::second(args, ss),
+    "SEQUENCE" => array::sequence(args, ss),
+    "SORT" => array::sort(args, ss),
+    "SPLIT" => text::split(args, ss),
+    "SQRT" => math::sqrt(args, ss),
+    "STDEV" => stats::stdev(args, ss),
+    "SUBSTITUTE" => text::substitute(args, ss),
+    "SUM" => math::sum(args, ss),
+    "SUMIF" => criteria::sumif(args, ss),
+    "SUMIFS" => criteria::sumifs(args, ss),
+    "TEXT" => text::text_fn(args, ss),
+    "TEXTJOIN" => text::textjoin(args, ss),
+    "TIME" => datetime::time(args, ss),
+    "TODAY" => datetime::today(args, ss),
+    "TRIM" => text::trim(args, ss),
+    "UNIQUE" => array::unique(args, ss),
+    "UPPER" => text::upper(args, ss),
+    "VALUE" => text::value(args, ss),
+    "VAR" => stats::var(args, ss),
+    "VLOOKUP" => lookup::vlookup(args, ss),
+    "XIRR" => financial::xirr(args, ss),
+    "XLOOKUP" => lookup::xlookup(args, ss),
+    "XNPV" => financial::xnpv(args, ss),
+    "YEAR" => datetime::year(args, ss),
+    _ => lambda::call_named(name, args, ss),
+  };
+  res.unwrap_or_else(|e| e)
+}
+
+/// Cells a call reads besides those its arguments refer to, they're its
+/// precedents too.
+pub fn implicit_range(name: &str, args: &[ExprTree], ss: &Spreadsheet) -> Option<Range> {
+  match name {
+    "AVERAGEIF" | "SUMIF" => criteria::resized_range(args, ss),
+    _ => None,
+  }
+}
+
+/// Whether the function can return something else with the same arguments,
+/// like `NOW()`. Cells calling one are re-evaluated by
+/// `Spreadsheet::recalculate_volatile`.
+pub fn is_volatile(name: &str) -> bool {
+  matches!(name, "NOW" | "RAND" | "RANDBETWEEN" | "TODAY")
+}
+
+fn value_error() -> ExprResult {
+  ExprResult::Error("#VALUE!".to_string())
+}
+
+fn num_error() -> ExprResult {
+  ExprResult::Error("#NUM!".to_string())
+}
+
+fn div_error() -> ExprResult {
+  ExprResult::Error("#DIV/0!".to_string())
+}
+
+fn not_found() -> ExprResult {
+  ExprResult::Error("#N/A".to_string())
+}
+
+/// Overflows and results that aren't real numbers are `#NUM!`.
+fn finite(n: f64) -> FnResult {
+  if n.is_finite() {
+    Ok(ExprResult::Num(n))
+  } else {
+    Err(num_error())
+  }
+}
+
+fn check_arity(args: &[ExprTree], min: usize, max: usize) -> Result<(), ExprResult> {
+  if args.len() < min || args.len() > max {
+    return Err(value_error());
+  }
+  Ok(())
+}
+
+/// Evaluates `arg` as a number. Text is parsed, so `"12"` and `"2024-03-15"`
+/// work too, and empty cells count as zero.
+fn num(arg: &ExprTree, ss: &Spreadsheet) -> Result<f64, ExprResult> {
+  to_num(arg.eval(ss))
+}
+
+fn to_num(value: ExprResult) -> Result<f64, ExprResult> {
+  match value {
+    ExprResult::Num(n) => Ok(n),
+    ExprResult::Text(t) if t.trim().is_empty() => Ok(0.),
+    ExprResult::Text(t) => {
+      let t = t.trim();
+      t.parse()
+        .ok()
+        .or_else(|| date_time_value(t))
+        .ok_or_else(value_error)
+    }
+    e @ ExprResult::Error(_) => Err(e),
+    ExprResult::Array(_) | ExprResult::Lambda(_) => Err(value_error()),
+  }
+}
+
+/// Evaluates `arg` as rows of values: those of an array or range, or the
+/// value of anything else as one row of one value.
+fn array(arg: &ExprTree, ss: &Spreadsheet) -> Result<Vec<Vec<ExprResult>>, ExprResult> {
+  match arg.eval(ss) {
+    e @ ExprResult::Error(_) => Err(e),
+    value => Ok(value.into_rows()),
+  }
+}
+
+/// Evaluates `arg` as text.
+fn text(arg: &ExprTree, ss: &Spreadsheet) -> Result<String, ExprResult> {
+  match arg.eval(ss) {
+    ExprResult::Num(n) => Ok(super::format::general(n)),
+    ExprResult::Text(t) => Ok(t),
+    e @ ExprResult::Error(_) => Err(e),
+    ExprResult::Array(_) | ExprResult::Lambda(_) => Err(value_error()),
+  }
+}
+
+/// Evaluates `arg` as a yes or no, numbers are true unless zero.
+fn bool_arg(arg: &ExprTree, ss: &Spreadsheet) -> Result<bool, ExprResult> {
+  match arg.eval(ss) {
+    ExprResult::Num(n) => Ok(n != 0.),
+    ExprResult::Text(t) if t.eq_ignore_ascii_case("TRUE") => Ok(true),
+    ExprResult::Text(t) if t.eq_ignore_ascii_case("FALSE") => Ok(false),
+    ExprResult::Text(_) | ExprResult::Array(_) | ExprResult::Lambda(_) => Err(value_error()),
+    e @ ExprResult::Error(_) => Err(e),
+  }
+}
+
+/// The numbers aggregates like `SUM` work on: those of ranges and arrays,
+/// skipping text and empty cells, and the other arguments as numbers.
+fn numbers(args: &[ExprTree], ss: &Spreadsheet) -> Result<Vec<f64>, ExprResult> {
+  let mut nums = vec![];
+  for arg in args {
+    let collection = match arg.reference(ss) {
+      Some(_) => values(arg, ss),
+      None => match arg.eval(ss) {
+        ExprResult::Array(rows) => rows.into_iter().flatten().collect(),
+        value => {
+          nums.push(to_num(value)?);
+          continue;
+        }
+      },
+    };
+    for value in collection {
+      match value {
+        ExprResult::Num(n) => nums.push(n),
+        e @ ExprResult::Error(_) => return Err(e),
+        _ => (),
+      }
+    }
+  }
+  Ok(nums)
+}
+
+/// Every value `arg` stands for: the cells of a range or the values of an
+/// array row by row, or the value of anything else.
+fn values(arg: &ExprTree, ss: &Spreadsheet) -> Vec<ExprResult> {
+  match arg.reference(ss) {
+    Some(range) => range
+      .coords(ss)
+      .map(|(row, col)| ss.get(row, col).out().clone())
+      .collect(),
+    None => arg.eval(ss).into_rows().into_iter().flatten().collect(),
+  }
+}
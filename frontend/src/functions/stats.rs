@@ -0,0 +1,199 @@
+//! Statistical functions. Like other spreadsheets, they skip the text and
+//! empty cells of ranges, see `numbers`.
+
+use super::{check_arity, div_error, not_found, num, num_error, numbers, to_num, values, FnResult};
+use crate::expr::{ExprResult, ExprTree};
+use crate::Spreadsheet;
+use std::slice;
+
+pub fn average(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let nums = numbers(args, ss)?;
+  if nums.is_empty() {
+    return Err(div_error());
+  }
+  Ok(ExprResult::Num(mean(&nums)))
+}
+
+/// Counts the numbers, unlike other functions errors are skipped too.
+pub fn count(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let mut n = 0;
+  for arg in args {
+    n += match arg.reference(ss) {
+      Some(_) => values(arg, ss)
+        .iter()
+        .filter(|v| matches!(v, ExprResult::Num(_)))
+        .count(),
+      None => match arg.eval(ss) {
+        ExprResult::Array(rows) => rows
+          .iter()
+          .flatten()
+          .filter(|v| matches!(v, ExprResult::Num(_)))
+          .count(),
+        value => to_num(value).is_ok() as usize,
+      },
+    };
+  }
+  Ok(ExprResult::Num(n as f64))
+}
+
+/// 0 without numbers.
+pub fn min(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let nums = numbers(args, ss)?;
+  Ok(ExprResult::Num(
+    nums.into_iter().reduce(f64::min).unwrap_or(0.),
+  ))
+}
+
+/// 0 without numbers.
+pub fn max(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let nums = numbers(args, ss)?;
+  Ok(ExprResult::Num(
+    nums.into_iter().reduce(f64::max).unwrap_or(0.),
+  ))
+}
+
+pub fn median(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let nums = sorted(numbers(args, ss)?)?;
+  Ok(ExprResult::Num(interpolate(&nums, 0.5)))
+}
+
+/// The number repeated most, the first one in case of a tie. `#N/A` when
+/// none is repeated.
+pub fn mode(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let nums = numbers(args, ss)?;
+  let mut best = None;
+  let mut best_count = 1;
+  for (i, n) in nums.iter().enumerate() {
+    let count = nums[i..].iter().filter(|m| *m == n).count();
+    if count > best_count {
+      best = Some(*n);
+      best_count = count;
+    }
+  }
+  best.map(ExprResult::Num).ok_or_else(not_found)
+}
+
+/// Variance of a sample.
+pub fn var(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  Ok(ExprResult::Num(variance(&numbers(args, ss)?)?))
+}
+
+/// Standard deviation of a sample.
+pub fn stdev(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  Ok(ExprResult::Num(variance(&numbers(args, ss)?)?.sqrt()))
+}
+
+/// PERCENTILE(array, k), interpolating between the closest values for a `k`
+/// between 0 and 1.
+pub fn percentile(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let nums = sorted(numbers(slice::from_ref(&args[0]), ss)?)?;
+  let k = num(&args[1], ss)?;
+  if !(0. ..=1.).contains(&k) {
+    return Err(num_error());
+  }
+  Ok(ExprResult::Num(interpolate(&nums, k)))
+}
+
+/// QUARTILE(array, quart), from 0 for the minimum to 4 for the maximum.
+pub fn quartile(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let nums = sorted(numbers(slice::from_ref(&args[0]), ss)?)?;
+  let quart = num(&args[1], ss)?.trunc();
+  if !(0. ..=4.).contains(&quart) {
+    return Err(num_error());
+  }
+  Ok(ExprResult::Num(interpolate(&nums, quart / 4.)))
+}
+
+/// Pearson correlation of two ranges of the same size. Pairs missing a
+/// number on either side are skipped.
+pub fn correl(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 2)?;
+  let xs = values(&args[0], ss);
+  let ys = values(&args[1], ss);
+  if xs.len() != ys.len() {
+    return Err(not_found());
+  }
+  let mut pairs = vec![];
+  for pair in xs.into_iter().zip(ys) {
+    match pair {
+      (ExprResult::Num(x), ExprResult::Num(y)) => pairs.push((x, y)),
+      (e @ ExprResult::Error(_), _) | (_, e @ ExprResult::Error(_)) => return Err(e),
+      _ => (),
+    }
+  }
+  let n = pairs.len() as f64;
+  let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+  let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+  let (mut cov, mut var_x, mut var_y) = (0., 0., 0.);
+  for (x, y) in pairs {
+    cov += (x - mean_x) * (y - mean_y);
+    var_x += (x - mean_x).powi(2);
+    var_y += (y - mean_y).powi(2);
+  }
+  let res = cov / (var_x * var_y).sqrt();
+  if !res.is_finite() {
+    return Err(div_error());
+  }
+  Ok(ExprResult::Num(res))
+}
+
+/// RANK(number, ref, [order]), 1 for the largest number of `ref` unless
+/// `order` is non-zero, then for the smallest. Ties share the best rank.
+pub fn rank(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 2, 3)?;
+  let n = num(&args[0], ss)?;
+  let nums = numbers(slice::from_ref(&args[1]), ss)?;
+  let ascending = args.get(2).map_or(Ok(0.), |arg| num(arg, ss))? != 0.;
+  if !nums.contains(&n) {
+    return Err(not_found());
+  }
+  let before = nums
+    .iter()
+    .filter(|m| if ascending { **m < n } else { **m > n })
+    .count();
+  Ok(ExprResult::Num((before + 1) as f64))
+}
+
+fn mean(nums: &[f64]) -> f64 {
+  nums.iter().sum::<f64>() / nums.len() as f64
+}
+
+// Sample variance, `#DIV/0!` with fewer than two numbers.
+fn variance(nums: &[f64]) -> Result<f64, ExprResult> {
+  if nums.len() < 2 {
+    return Err(div_error());
+  }
+  let mean = mean(nums);
+  let squares: f64 = nums.iter().map(|n| (n - mean).powi(2)).sum();
+  Ok(squares / (nums.len() - 1) as f64)
+}
+
+// `#NUM!` without numbers.
+fn sorted(mut nums: Vec<f64>) -> Result<Vec<f64>, ExprResult> {
+  if nums.is_empty() {
+    return Err(num_error());
+  }
+  nums.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+  Ok(nums)
+}
+
+// The value a fraction `k` of the way through sorted numbers, interpolating
+// between the two closest.
+fn interpolate(nums: &[f64], k: f64) -> f64 {
+  let pos = k * (nums.len() - 1) as f64;
+  let below = pos.floor() as usize;
+  match nums.get(below + 1) {
+    Some(above) => nums[below] + (pos - below as f64) * (above - nums[below]),
+    None => nums[below],
+  }
+}
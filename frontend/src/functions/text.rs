@@ -0,0 +1,373 @@
+//! Text functions. Positions and lengths count graphemes, what a reader
+//! would call characters, so `LEN("👍🏽")` is 1 and `LEFT` never splits an
+//! accent from its letter.
+
+use super::{bool_arg, check_arity, num, text, to_num, value_error, values, FnResult};
+use crate::expr::{ExprResult, ExprTree};
+use crate::format::Format;
+use crate::Spreadsheet;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub fn len(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  let t = text(&args[0], ss)?;
+  Ok(ExprResult::Num(t.graphemes(true).count() as f64))
+}
+
+pub fn left(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 2)?;
+  let t = text(&args[0], ss)?;
+  let n = count_arg(args.get(1), ss)?;
+  Ok(ExprResult::Text(t.graphemes(true).take(n).collect()))
+}
+
+pub fn right(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 2)?;
+  let t = text(&args[0], ss)?;
+  let n = count_arg(args.get(1), ss)?;
+  let graphemes: Vec<&str> = t.graphemes(true).collect();
+  let start = graphemes.len().saturating_sub(n);
+  Ok(ExprResult::Text(graphemes[start..].concat()))
+}
+
+pub fn mid(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // MID(text, start, count), `start` is 1-based.
+  check_arity(args, 3, 3)?;
+  let t = text(&args[0], ss)?;
+  let start = num(&args[1], ss)?.trunc();
+  let n = count_arg(args.get(2), ss)?;
+  if start < 1. {
+    return Err(value_error());
+  }
+  let skip = start as usize - 1;
+  Ok(ExprResult::Text(
+    t.graphemes(true).skip(skip).take(n).collect(),
+  ))
+}
+
+pub fn upper(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Text(text(&args[0], ss)?.to_uppercase()))
+}
+
+pub fn lower(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, 1)?;
+  Ok(ExprResult::Text(text(&args[0], ss)?.to_lowercase()))
+}
+
+pub fn trim(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // Drops leading and trailing spaces and collapses runs of them. Like other
+  // spreadsheets only plain spaces count, tabs and line breaks stay.
+  check_arity(args, 1, 1)?;
+  let t = text(&args[0], ss)?;
+  let words: Vec<&str> = t.split(' ').filter(|w| !w.is_empty()).collect();
+  Ok(ExprResult::Text(words.join(" ")))
+}
+
+pub fn substitute(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // SUBSTITUTE(text, old, new, [instance]) replaces every occurrence of
+  // `old`, or only the `instance`th one.
+  check_arity(args, 3, 4)?;
+  let t = text(&args[0], ss)?;
+  let old = text(&args[1], ss)?;
+  let new = text(&args[2], ss)?;
+  if old.is_empty() {
+    return Ok(ExprResult::Text(t));
+  }
+  let instance = match args.get(3) {
+    Some(arg) => {
+      let n = num(arg, ss)?.trunc();
+      if n < 1. {
+        return Err(value_error());
+      }
+      n as usize
+    }
+    None => return Ok(ExprResult::Text(t.replace(&old, &new))),
+  };
+  match t.match_indices(&old).nth(instance - 1) {
+    Some((at, _)) => Ok(ExprResult::Text(format!(
+      "{}{}{}",
+      &t[..at],
+      new,
+      &t[at + old.len()..]
+    ))),
+    None => Ok(ExprResult::Text(t)),
+  }
+}
+
+pub fn find(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // FIND(needle, text, [start]) is case sensitive, `#VALUE!` if not found.
+  check_arity(args, 2, 3)?;
+  let needle = text(&args[0], ss)?;
+  let t = text(&args[1], ss)?;
+  let pattern: Vec<Token> = needle.graphemes(true).map(Token::Literal).collect();
+  position(&pattern, &t, args.get(2), ss)
+}
+
+pub fn search(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // SEARCH(needle, text, [start]) ignores case and takes wildcards, see
+  // `Pattern`.
+  check_arity(args, 2, 3)?;
+  let needle = text(&args[0], ss)?.to_lowercase();
+  let t = text(&args[1], ss)?.to_lowercase();
+  position(&Pattern::new(&needle).tokens, &t, args.get(2), ss)
+}
+
+pub fn text_fn(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // TEXT(value, format) renders like a cell with that number format would.
+  check_arity(args, 2, 2)?;
+  let value = match args[0].eval(ss) {
+    ExprResult::Text(t) => {
+      to_num(ExprResult::Text(t.clone())).map_or(ExprResult::Text(t), ExprResult::Num)
+    }
+    value => value,
+  };
+  if let ExprResult::Error(_) = value {
+    return Err(value);
+  }
+  let format = Format::parse(&text(&args[1], ss)?).map_err(|_| value_error())?;
+  Ok(ExprResult::Text(format.display(&value)))
+}
+
+pub fn value(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // VALUE(text) reads numbers the way they're usually written: with
+  // thousands separators, a currency sign or as a percentage.
+  check_arity(args, 1, 1)?;
+  let t = match args[0].eval(ss) {
+    ExprResult::Text(t) => t,
+    value => return to_num(value).map(ExprResult::Num),
+  };
+  let t = t.trim();
+  let (t, scale) = match t.strip_suffix('%') {
+    Some(t) => (t, 0.01),
+    None => (t, 1.),
+  };
+  let cleaned: String = t
+    .chars()
+    .filter(|c| !matches!(c, ',' | '$' | '€' | '£'))
+    .collect();
+  let n = to_num(ExprResult::Text(cleaned))?;
+  Ok(ExprResult::Num(n * scale))
+}
+
+pub fn concat(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  check_arity(args, 1, usize::MAX)?;
+  let mut joined = String::new();
+  for arg in args {
+    for value in values(arg, ss) {
+      if let ExprResult::Error(_) = value {
+        return Err(value);
+      }
+      joined += &value.as_text();
+    }
+  }
+  Ok(ExprResult::Text(joined))
+}
+
+pub fn textjoin(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // TEXTJOIN(delimiter, ignore_empty, text...)
+  check_arity(args, 3, usize::MAX)?;
+  let delimiter = text(&args[0], ss)?;
+  let ignore_empty = bool_arg(&args[1], ss)?;
+  let mut parts = vec![];
+  for arg in &args[2..] {
+    for value in values(arg, ss) {
+      if let ExprResult::Error(_) = value {
+        return Err(value);
+      }
+      let part = value.as_text();
+      if !(ignore_empty && part.is_empty()) {
+        parts.push(part);
+      }
+    }
+  }
+  Ok(ExprResult::Text(parts.join(&delimiter)))
+}
+
+pub fn split(args: &[ExprTree], ss: &Spreadsheet) -> FnResult {
+  // SPLIT(text, delimiter, [split_by_each], [remove_empty]). The parts
+  // spill over the cells to the right.
+  check_arity(args, 2, 4)?;
+  let parts = split_parts(args, ss)?;
+  if parts.is_empty() {
+    return Ok(ExprResult::Text("".to_string()));
+  }
+  Ok(ExprResult::Array(vec![parts
+    .into_iter()
+    .map(ExprResult::Text)
+    .collect()]))
+}
+
+fn split_parts(args: &[ExprTree], ss: &Spreadsheet) -> Result<Vec<String>, ExprResult> {
+  let t = text(&args[0], ss)?;
+  let delimiter = text(&args[1], ss)?;
+  let split_by_each = args.get(2).map_or(Ok(true), |arg| bool_arg(arg, ss))?;
+  let remove_empty = args.get(3).map_or(Ok(true), |arg| bool_arg(arg, ss))?;
+  if delimiter.is_empty() {
+    return Err(value_error());
+  }
+  let parts: Vec<&str> = if split_by_each {
+    // Every grapheme of the delimiter is a delimiter on its own.
+    let delimiters: Vec<&str> = delimiter.graphemes(true).collect();
+    let mut parts = vec![];
+    let mut start = 0;
+    for (at, g) in t.grapheme_indices(true) {
+      if delimiters.contains(&g) {
+        parts.push(&t[start..at]);
+        start = at + g.len();
+      }
+    }
+    parts.push(&t[start..]);
+    parts
+  } else {
+    t.split(delimiter.as_str()).collect()
+  };
+  Ok(
+    parts
+      .into_iter()
+      .filter(|p| !(remove_empty && p.is_empty()))
+      .map(str::to_string)
+      .collect(),
+  )
+}
+
+/// `None` means 1, the default for `LEFT` and `RIGHT`.
+fn count_arg(arg: Option<&ExprTree>, ss: &Spreadsheet) -> Result<usize, ExprResult> {
+  let n = match arg {
+    Some(arg) => num(arg, ss)?.trunc(),
+    None => 1.,
+  };
+  if n < 0. {
+    return Err(value_error());
+  }
+  Ok(n as usize)
+}
+
+/// 1-based position of the first match of `pattern` in `t`, at or after the
+/// `start` argument.
+fn position(pattern: &[Token], t: &str, start: Option<&ExprTree>, ss: &Spreadsheet) -> FnResult {
+  let graphemes: Vec<&str> = t.graphemes(true).collect();
+  let start = match start {
+    Some(arg) => num(arg, ss)?.trunc(),
+    None => 1.,
+  };
+  if start < 1. || start > graphemes.len() as f64 + 1. {
+    return Err(value_error());
+  }
+  (start as usize - 1..=graphemes.len())
+    .find(|&at| matches(pattern, &graphemes[at..], false))
+    .map(|at| ExprResult::Num(at as f64 + 1.))
+    .ok_or_else(value_error)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'a> {
+  Literal(&'a str),
+  /// `?`, any single grapheme.
+  One,
+  /// `*`, any run of graphemes, even an empty one.
+  Any,
+}
+
+/// Wildcard pattern as used by `SEARCH` and lookups: `?` matches any single
+/// character, `*` any run of characters and `~` escapes the next one.
+pub struct Pattern<'a> {
+  tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Pattern<'a> {
+  pub fn new(pattern: &'a str) -> Pattern<'a> {
+    let mut tokens = vec![];
+    let mut graphemes = pattern.graphemes(true);
+    while let Some(g) = graphemes.next() {
+      tokens.push(match g {
+        "?" => Token::One,
+        "*" => Token::Any,
+        "~" => Token::Literal(graphemes.next().unwrap_or("~")),
+        g => Token::Literal(g),
+      });
+    }
+    Pattern { tokens }
+  }
+
+  /// Whether the whole of `t` matches. Callers lowercase both sides to
+  /// ignore case.
+  pub fn is_match(&self, t: &str) -> bool {
+    let graphemes: Vec<&str> = t.graphemes(true).collect();
+    matches(&self.tokens, &graphemes, true)
+  }
+}
+
+/// Whether `pattern` matches the start of `t`, or all of it if `whole`.
+///
+/// On a mismatch only the last `*` takes one more grapheme and matching
+/// resumes after it, earlier ones never need to: whatever they'd take the
+/// last one can take too. This keeps it to `O(pattern * t)`.
+fn matches(pattern: &[Token], t: &[&str], whole: bool) -> bool {
+  let (mut p, mut i) = (0, 0);
+  // Where matching resumes after the last `*` seen, in `pattern` and `t`.
+  let mut last_any = None;
+  loop {
+    if p == pattern.len() && (!whole || i == t.len()) {
+      return true;
+    }
+    match pattern.get(p) {
+      Some(Token::Any) => {
+        p += 1;
+        last_any = Some((p, i));
+        continue;
+      }
+      Some(Token::One) if i < t.len() => {
+        p += 1;
+        i += 1;
+        continue;
+      }
+      Some(Token::Literal(l)) if t.get(i) == Some(l) => {
+        p += 1;
+        i += 1;
+        continue;
+      }
+      _ => (),
+    }
+    match last_any {
+      Some((after, taken)) if taken < t.len() => {
+        last_any = Some((after, taken + 1));
+        p = after;
+        i = taken + 1;
+      }
+      _ => return false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn is_match(pattern: &str, t: &str) -> bool {
+    Pattern::new(pattern).is_match(t)
+  }
+
+  #[test]
+  fn wildcards() {
+    assert!(is_match("a*c", "abbbc"));
+    assert!(is_match("a*c", "ac"));
+    assert!(is_match("a?c", "abc"));
+    assert!(!is_match("a?c", "ac"));
+    assert!(is_match("why~?", "why?"));
+    assert!(!is_match("why~?", "whyy"));
+    assert!(is_match("*", ""));
+    assert!(is_match("é?", "éa\u{301}"));
+  }
+
+  #[test]
+  fn many_wildcards_on_long_text() {
+    let t = "a".repeat(10_000);
+    assert!(!is_match("*a*a*a*a*a*a*a*a*b", &t));
+    assert!(is_match("*a*a*a*a*a*a*a*a*", &t));
+    assert!(is_match("a*?a", &t));
+    assert!(is_match("*ab*c", "xxabyyabzzc"));
+    assert!(!is_match("*ab*c", "xxabyyabzzcd"));
+    assert!(!is_match("a*", "ba"));
+  }
+}
@@ -0,0 +1,171 @@
+//! Graph algorithms over cell indexes, used to find the order cells are
+//! evaluated in and the cycles they form.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Strongly connected components of the graph made of `nodes` and the edges
+/// `edges` returns, ignoring edges to nodes outside of it. A component comes
+/// after every component it has edges to, so when edges point at precedents
+/// the components are in evaluation order.
+///
+/// This is Tarjan's algorithm, iterative so that long chains of formulas
+/// don't overflow the stack.
+pub fn strongly_connected<F, I>(nodes: &[usize], edges: F) -> Vec<Vec<usize>>
+where
+  F: Fn(usize) -> I,
+  I: IntoIterator<Item = usize>,
+{
+  let in_graph: HashSet<usize> = nodes.iter().copied().collect();
+  let successors = |node: usize| -> Vec<usize> {
+    let mut next: Vec<usize> = edges(node)
+      .into_iter()
+      .filter(|n| in_graph.contains(n))
+      .collect();
+    // Visit in a stable order, edges usually come out of a `HashSet`.
+    next.sort_unstable_by(|a, b| b.cmp(a));
+    next
+  };
+
+  let mut index: HashMap<usize, usize> = HashMap::new();
+  let mut low: HashMap<usize, usize> = HashMap::new();
+  let mut stack = vec![];
+  let mut on_stack = HashSet::new();
+  let mut components = vec![];
+  for &root in nodes {
+    if index.contains_key(&root) {
+      continue;
+    }
+    // Nodes being visited, with the successors they have left to visit.
+    let mut calls: Vec<(usize, Vec<usize>)> = vec![];
+    let mut next = Some(root);
+    loop {
+      if let Some(node) = next.take() {
+        index.insert(node, index.len());
+        low.insert(node, low.len());
+        stack.push(node);
+        on_stack.insert(node);
+        calls.push((node, successors(node)));
+      }
+      let (node, pending) = match calls.last_mut() {
+        Some(call) => call,
+        None => break,
+      };
+      let node = *node;
+      if let Some(succ) = pending.pop() {
+        if !index.contains_key(&succ) {
+          next = Some(succ);
+        } else if on_stack.contains(&succ) {
+          low.insert(node, low[&node].min(index[&succ]));
+        }
+        continue;
+      }
+      calls.pop();
+      if let Some((parent, _)) = calls.last() {
+        low.insert(*parent, low[parent].min(low[&node]));
+      }
+      if low[&node] == index[&node] {
+        let mut component = vec![];
+        while let Some(member) = stack.pop() {
+          on_stack.remove(&member);
+          component.push(member);
+          if member == node {
+            break;
+          }
+        }
+        components.push(component);
+      }
+    }
+  }
+  components
+}
+
+/// The shortest cycle from `start` back to itself through `members`, e.g.
+/// `[a, b, a]`. Empty if there's none.
+pub fn shortest_cycle<F, I>(start: usize, members: &HashSet<usize>, edges: F) -> Vec<usize>
+where
+  F: Fn(usize) -> I,
+  I: IntoIterator<Item = usize>,
+{
+  let mut parents: HashMap<usize, usize> = HashMap::new();
+  let mut pending = VecDeque::from(vec![start]);
+  while let Some(node) = pending.pop_front() {
+    let mut next: Vec<usize> = edges(node)
+      .into_iter()
+      .filter(|n| members.contains(n))
+      .collect();
+    next.sort_unstable();
+    for succ in next {
+      if succ == start {
+        // Walk back from `node`, `start` is the only node without a parent.
+        let mut path = vec![start, node];
+        while let Some(parent) = parents.get(path.last().unwrap()) {
+          path.push(*parent);
+        }
+        path.reverse();
+        return path;
+      }
+      if let Entry::Vacant(entry) = parents.entry(succ) {
+        entry.insert(node);
+        pending.push_back(succ);
+      }
+    }
+  }
+  vec![]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn graph(edges: &[(usize, usize)]) -> impl Fn(usize) -> Vec<usize> + '_ {
+    move |node| {
+      edges
+        .iter()
+        .filter(|(from, _)| *from == node)
+        .map(|(_, to)| *to)
+        .collect()
+    }
+  }
+
+  #[test]
+  fn components_come_after_their_precedents() {
+    // 0 -> 1 <-> 2 -> 3, and 4 on its own.
+    let edges = [(0, 1), (1, 2), (2, 1), (2, 3)];
+    let mut components = strongly_connected(&[0, 1, 2, 3, 4], graph(&edges));
+    for component in components.iter_mut() {
+      component.sort_unstable();
+    }
+    assert_eq!(components, vec![vec![3], vec![1, 2], vec![0], vec![4]]);
+  }
+
+  #[test]
+  fn edges_leaving_the_graph_are_ignored() {
+    let edges = [(0, 1), (1, 0), (1, 2)];
+    assert_eq!(
+      strongly_connected(&[1, 2], graph(&edges)),
+      vec![vec![2], vec![1]]
+    );
+  }
+
+  #[test]
+  fn long_chains_dont_overflow() {
+    let edges: Vec<(usize, usize)> = (0..100_000).map(|n| (n + 1, n)).collect();
+    let nodes: Vec<usize> = (0..=100_000).rev().collect();
+    let components = strongly_connected(&nodes, |node| {
+      edges.get(node.wrapping_sub(1)).map(|(_, to)| *to)
+    });
+    assert_eq!(components.len(), 100_001);
+    assert_eq!(components[0], vec![0]);
+  }
+
+  #[test]
+  fn shortest_cycles() {
+    let members: HashSet<usize> = (0..4).collect();
+    let edges = [(0, 1), (1, 2), (2, 0), (1, 0), (3, 3)];
+    assert_eq!(shortest_cycle(0, &members, graph(&edges)), vec![0, 1, 0]);
+    assert_eq!(shortest_cycle(2, &members, graph(&edges)), vec![2, 0, 1, 2]);
+    assert_eq!(shortest_cycle(3, &members, graph(&edges)), vec![3, 3]);
+    assert!(shortest_cycle(0, &HashSet::new(), graph(&edges)).is_empty());
+  }
+}
@@ -1,7 +1,21 @@
+pub mod audit;
+pub mod date;
 pub mod expr;
+pub mod fill;
+pub mod filter;
+pub mod format;
+pub mod functions;
+pub mod graph;
 pub mod parser;
+pub mod printer;
+pub mod random;
+pub mod scope;
+pub mod search;
+pub mod sort;
+pub mod spill;
 
-use expr::{ExprResult, ExprTree};
+use expr::{ExprResult, ExprTree, Range, ValueNode};
+use format::Format;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::mem;
@@ -17,8 +31,25 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 pub struct Cell {
     raw: String,
     out: ExprResult,
+    /// Number format code, empty for the default.
+    format: String,
+    /// `out` rendered with `format`, what the grid shows.
+    display: String,
+    /// Unresolved comment threads on the cell, lets the grid show an indicator.
+    comments: usize,
+    /// When the cell is part of a reference cycle, the cells going around it
+    /// back to this one, e.g. `[A1, B1, A1]` as `(row, col)`.
+    cycle: Vec<(usize, usize)>,
+    /// When the formula returned an array, where it goes.
+    spill: Option<spill::Spill>,
+    /// When the cell shows part of an array, the `(row, col)` of the formula
+    /// that returned it.
+    spilled_from: Option<(usize, usize)>,
     #[serde(skip)]
     expr: ExprTree,
+    /// Whether `expr` calls a volatile function, see `recalculate_volatile`.
+    #[serde(skip)]
+    volatile: bool,
     #[serde(skip)]
     outbound: HashSet<usize>,
     #[serde(skip)]
@@ -30,7 +61,14 @@ impl Default for Cell {
         Cell {
             raw: "".to_string(),
             expr: ExprTree::Empty,
+            volatile: false,
             out: ExprResult::Text("".to_string()),
+            format: "".to_string(),
+            display: "".to_string(),
+            comments: 0,
+            cycle: vec![],
+            spill: None,
+            spilled_from: None,
             outbound: HashSet::new(),
             inbound: HashSet::new(),
         }
@@ -42,9 +80,124 @@ impl Cell {
         Default::default()
     }
 
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
     pub fn out(&self) -> &ExprResult {
         &self.out
     }
+
+    pub fn comments(&self) -> usize {
+        self.comments
+    }
+
+    pub fn cycle(&self) -> &[(usize, usize)] {
+        &self.cycle
+    }
+
+    pub fn spill(&self) -> Option<spill::Spill> {
+        self.spill
+    }
+
+    pub fn spilled_from(&self) -> Option<(usize, usize)> {
+        self.spilled_from
+    }
+
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+
+    pub fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn set_out(&mut self, out: ExprResult) {
+        // Formats are validated when set, an invalid one can't get here.
+        self.display = Format::parse(&self.format)
+            .unwrap_or_default()
+            .display(&out);
+        self.out = out;
+    }
+}
+
+// `JsValue::from_serde` is deprecated in favor of `serde-wasm-bindgen`, which
+// would change how maps are handed to JS. Keep the conversion in one place.
+#[allow(deprecated)]
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    JsValue::from_serde(value).map_err(|_| JsValue::from("could not serialize"))
+}
+
+// A range from a `{top, left, bottom, right}` object.
+#[allow(deprecated)]
+fn range_from_js(value: &JsValue) -> Result<Range, JsValue> {
+    #[derive(Deserialize)]
+    struct Rect {
+        top: usize,
+        left: usize,
+        bottom: usize,
+        right: usize,
+    }
+
+    let rect: Rect = value
+        .into_serde()
+        .map_err(|_| JsValue::from("not a range"))?;
+    Ok(Range::new((rect.top, rect.left), (rect.bottom, rect.right)))
+}
+
+/// Parses what a user typed into a cell, returning its syntax tree, see
+/// `expr::ExprTree` for its shape.
+#[wasm_bindgen]
+pub fn parse_cell(raw: &str) -> Result<JsValue, JsValue> {
+    to_js(&ExprTree::new(raw))
+}
+
+/// Turns a tree returned by `parse_cell` back into text.
+#[wasm_bindgen]
+#[allow(deprecated)]
+pub fn print_cell(tree: &JsValue) -> Result<String, JsValue> {
+    let tree: ExprTree = tree
+        .into_serde()
+        .map_err(|_| JsValue::from("not a syntax tree"))?;
+    Ok(tree.to_string())
+}
+
+/// Checks a number format code like `#,##0.00`, throwing with what's wrong
+/// with it.
+#[wasm_bindgen]
+pub fn check_format(code: &str) -> Result<(), JsValue> {
+    Format::parse(code)?;
+    Ok(())
+}
+
+/// Settings for iterative calculation, which lets intentional reference
+/// cycles evaluate instead of showing `#CYCLE!`. See `set_iteration`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Iteration {
+    /// Most passes over a cycle before giving up with `#NUM!`.
+    pub max_iterations: usize,
+    /// A cycle has converged once no number in it changes by more than this
+    /// during a pass.
+    pub tolerance: f64,
+}
+
+impl Default for Iteration {
+    // Same as Excel.
+    fn default() -> Self {
+        Iteration {
+            max_iterations: 100,
+            tolerance: 0.001,
+        }
+    }
+}
+
+/// What to type in a cell, for changes made to several cells at once like
+/// `replace_all` and `fill_range`. See `set_raws`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    pub row: usize,
+    pub col: usize,
+    pub raw: String,
 }
 
 #[wasm_bindgen]
@@ -52,6 +205,18 @@ pub struct Spreadsheet {
     width: usize,
     height: usize,
     cells: Vec<Cell>,
+    /// Fixed current time for `NOW()` and `TODAY()`, see `set_now`.
+    now: Option<f64>,
+    /// Draws the numbers of `RAND()` and `RANDBETWEEN()`, see `set_seed`.
+    rng: random::Rng,
+    /// `LET` variables and `LAMBDA` parameters, while evaluating a formula.
+    scope: scope::Scope,
+    // Uppercased name -> Definition
+    names: HashMap<String, ExprTree>,
+    /// Off by default, cycles evaluate to `#CYCLE!`.
+    iteration: Option<Iteration>,
+    /// Hides rows without changing any cell, see `filtered_rows`.
+    filter: Option<filter::Filter>,
 }
 
 impl Default for Spreadsheet {
@@ -62,6 +227,12 @@ impl Default for Spreadsheet {
             width,
             height,
             cells: vec![Cell::new(); width * height],
+            now: None,
+            rng: random::Rng::default(),
+            scope: scope::Scope::default(),
+            names: HashMap::new(),
+            iteration: None,
+            filter: None,
         }
     }
 }
@@ -82,25 +253,396 @@ impl Spreadsheet {
 
     pub fn cells(&self) -> Result<JsValue, JsValue> {
         // This is expensive and should only be called to initialize the frontend.
-        let serialized_cells = JsValue::from_serde(&self.cells)
-            .map_err(|_| JsValue::from("could not serialize cells"))?;
-        Ok(serialized_cells)
+        to_js(&self.cells)
     }
 
     pub fn set(&mut self, row: usize, col: usize, raw: &str) -> Result<JsValue, JsValue> {
+        let updated = self.set_raw(row, col, raw)?;
+        // Serialize all cells that were modified for frontend to update.
+        self.serialize_cells(&updated)
+    }
+
+    /// Sets the number format of a cell, e.g. `#,##0.00` or `yyyy-mm-dd`,
+    /// returning the updated cell in the same shape as `set`.
+    pub fn set_format(&mut self, row: usize, col: usize, format: &str) -> Result<JsValue, JsValue> {
+        self.set_format_code(row, col, format)?;
+        self.serialize_cells(&[self.get_index(row, col)])
+    }
+
+    /// Sets how many unresolved comment threads the cell has, returning the
+    /// updated cell in the same shape as `set`.
+    pub fn set_comments(
+        &mut self,
+        row: usize,
+        col: usize,
+        count: usize,
+    ) -> Result<JsValue, JsValue> {
+        self.set_comment_count(row, col, count)?;
+        self.serialize_cells(&[self.get_index(row, col)])
+    }
+
+    /// Defines or redefines a name, e.g. `TaxRate` as `B1`, returning the
+    /// cells updated in the same shape as `set`.
+    pub fn set_name(&mut self, name: &str, definition: &str) -> Result<JsValue, JsValue> {
+        let updated = self.define_name(name, definition)?;
+        self.serialize_cells(&updated)
+    }
+
+    /// Forgets a name, formulas using it show `#NAME?` again. Returns the
+    /// cells updated in the same shape as `set`.
+    pub fn remove_name(&mut self, name: &str) -> Result<JsValue, JsValue> {
+        let updated = self.delete_name(name);
+        self.serialize_cells(&updated)
+    }
+
+    /// Turns iterative calculation on or off, returning the cells updated in
+    /// the same shape as `set`.
+    pub fn set_iterative(
+        &mut self,
+        enabled: bool,
+        max_iterations: usize,
+        tolerance: f64,
+    ) -> Result<JsValue, JsValue> {
+        let iteration = if enabled {
+            Some(Iteration {
+                max_iterations,
+                tolerance,
+            })
+        } else {
+            None
+        };
+        let updated = self.set_iteration(iteration)?;
+        self.serialize_cells(&updated)
+    }
+
+    /// Re-evaluates the volatile cells with the random seed and the time
+    /// (Unix milliseconds) the backend handed out, so that every participant
+    /// gets the same values. The time is read as UTC, see
+    /// `date::serial_from_unix_millis`. Returns the cells updated in the same shape as
+    /// `set`.
+    pub fn recalc(&mut self, seed: u32, now: f64) -> Result<JsValue, JsValue> {
+        self.set_seed(seed.into());
+        self.set_now(Some(date::serial_from_unix_millis(now)));
+        let updated = self.recalculate_volatile();
+        self.serialize_cells(&updated)
+    }
+
+    /// Re-evaluates every formula, returning the cells updated in the same
+    /// shape as `set`.
+    pub fn recalc_all(&mut self) -> Result<JsValue, JsValue> {
+        let updated = self.recalculate_all();
+        self.serialize_cells(&updated)
+    }
+
+    /// Sorts the rows of the range on `keys`, a list of `{col, ascending}`,
+    /// see `sort_range`. Returns `{order, edits, names, cells}` where the
+    /// backend needs the first three to sort its copy: the rows in their new
+    /// order, what was typed in the cells whose references moved (or that
+    /// moved themselves) and the new definitions of the names that moved, by
+    /// name. `cells` are the cells updated in the same shape as `set`.
+    #[allow(deprecated)]
+    pub fn sort(
+        &mut self,
+        top: usize,
+        left: usize,
+        bottom: usize,
+        right: usize,
+        keys: &JsValue,
+    ) -> Result<JsValue, JsValue> {
+        #[derive(Serialize)]
+        struct Sorted<'a> {
+            order: Vec<usize>,
+            edits: Vec<Edit>,
+            names: HashMap<String, String>,
+            cells: HashMap<usize, &'a Cell>,
+        }
+
+        let keys: Vec<sort::SortKey> = keys
+            .into_serde()
+            .map_err(|_| JsValue::from("not a list of sort keys"))?;
+        let range = Range::new((top, left), (bottom, right));
+        let order = self.sort_order(range, &keys)?;
+        let names = self.names.clone();
+        let (edits, updated) = self.move_rows(range, &order)?;
+        let names = self
+            .names
+            .iter()
+            .filter(|(name, definition)| names.get(*name) != Some(definition))
+            .map(|(name, definition)| (name.clone(), definition.to_string()))
+            .collect();
+        to_js(&Sorted {
+            order,
+            edits,
+            names,
+            cells: self.cells_by_index(&updated),
+        })
+    }
+
+    /// Hides the rows of the range whose values don't meet `conditions`, a
+    /// list of `{col, criterion}`, see `filter::Filter`.
+    #[allow(deprecated)]
+    pub fn set_filter(
+        &mut self,
+        top: usize,
+        left: usize,
+        bottom: usize,
+        right: usize,
+        conditions: &JsValue,
+    ) -> Result<(), JsValue> {
+        let conditions = conditions
+            .into_serde()
+            .map_err(|_| JsValue::from("not a list of filter conditions"))?;
+        let filter = filter::Filter {
+            range: Range::new((top, left), (bottom, right)),
+            conditions,
+        };
+        Ok(self.set_filter_view(Some(filter))?)
+    }
+
+    /// `[row, col]` of the cells matching `query`, a `search::Query`.
+    #[allow(deprecated)]
+    pub fn find(&self, query: &JsValue) -> Result<JsValue, JsValue> {
+        let query = query
+            .into_serde()
+            .map_err(|_| JsValue::from("not a search query"))?;
+        to_js(&self.find_cells(&query)?)
+    }
+
+    /// Replaces what `query` matches in every cell, see `replace_all`.
+    /// Returns `{previous, cells}`: what the cells held before, to undo it,
+    /// and the cells updated in the same shape as `set`.
+    #[allow(deprecated)]
+    pub fn replace(&mut self, query: &JsValue, replacement: &str) -> Result<JsValue, JsValue> {
+        #[derive(Serialize)]
+        struct Replaced<'a> {
+            previous: Vec<Edit>,
+            cells: HashMap<usize, &'a Cell>,
+        }
+
+        let query = query
+            .into_serde()
+            .map_err(|_| JsValue::from("not a search query"))?;
+        let (previous, updated) = self.replace_all(&query, replacement)?;
+        to_js(&Replaced {
+            previous,
+            cells: self.cells_by_index(&updated),
+        })
+    }
+
+    /// Fills `target` from `source`, both `{top, left, bottom, right}`, see
+    /// `fill_range`. Returns `{edits, cells}`: what was typed in the filled
+    /// cells, for the backend, and the cells updated in the same shape as
+    /// `set`.
+    pub fn fill(&mut self, source: &JsValue, target: &JsValue) -> Result<JsValue, JsValue> {
+        #[derive(Serialize)]
+        struct Filled<'a> {
+            edits: Vec<Edit>,
+            cells: HashMap<usize, &'a Cell>,
+        }
+
+        let (edits, updated) = self.fill_range(range_from_js(source)?, range_from_js(target)?)?;
+        to_js(&Filled {
+            edits,
+            cells: self.cells_by_index(&updated),
+        })
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+    }
+
+    /// Rows the filter hides with the current values, see `filtered_rows`.
+    pub fn hidden_rows(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.filtered_rows())
+    }
+
+    /// Cells the cell's formula reads from, see `audit::Links`.
+    pub fn precedents(&self, row: usize, col: usize) -> Result<JsValue, JsValue> {
+        to_js(&self.precedent_links(row, col)?)
+    }
+
+    /// Cells whose formulas read from the cell, see `audit::Links`.
+    pub fn dependents(&self, row: usize, col: usize) -> Result<JsValue, JsValue> {
+        to_js(&self.dependent_links(row, col)?)
+    }
+
+    /// The value of every sub-expression of the cell's formula, see
+    /// `audit::Explanation`.
+    pub fn explain(&self, row: usize, col: usize) -> Result<JsValue, JsValue> {
+        to_js(&self.explanation(row, col)?)
+    }
+
+    pub fn get_index(&self, row: usize, col: usize) -> usize {
+        row * self.width + col
+    }
+
+    fn serialize_cells(&self, indexes: &[usize]) -> Result<JsValue, JsValue> {
+        to_js(&self.cells_by_index(indexes))
+    }
+
+    fn cells_by_index(&self, indexes: &[usize]) -> HashMap<usize, &Cell> {
+        indexes
+            .iter()
+            .map(|idx| (*idx, &self.cells[*idx]))
+            .collect()
+    }
+
+    fn check_bounds(&self, row: usize, col: usize) -> Result<(), String> {
         if col >= self.width {
-            return Err(JsValue::from(format!(
-                "column out of bounds: {} >= {}",
-                col, self.width
-            )));
+            return Err(format!("column out of bounds: {} >= {}", col, self.width));
         }
         if row >= self.height {
-            return Err(JsValue::from(format!(
-                "row out of bounds: {} >= {}",
-                row, self.height
-            )));
+            return Err(format!("row out of bounds: {} >= {}", row, self.height));
         }
+        Ok(())
+    }
+}
 
+// methods not exported through web assembly
+impl Spreadsheet {
+    /// Definition of a name, ignoring case.
+    pub fn name(&self, name: &str) -> Option<&ExprTree> {
+        self.names.get(&name.to_ascii_uppercase())
+    }
+
+    /// Defines `name` as a cell, a range, a constant or a `LAMBDA` (see
+    /// `parser::name_definition`) and re-evaluates the formulas using it,
+    /// returning the indexes of the cells that changed.
+    pub fn define_name(&mut self, name: &str, definition: &str) -> Result<Vec<usize>, String> {
+        if !parser::is_valid_name(name) {
+            return Err(format!("{} is not a valid name", name));
+        }
+        let definition = parser::name_definition(definition)?;
+        match &definition {
+            ExprTree::Leaf(ValueNode::Coord(row, col)) => self.check_bounds(*row, *col)?,
+            ExprTree::Leaf(ValueNode::Range(range)) => {
+                self.check_bounds(range.end.0, range.end.1)?
+            }
+            _ => (),
+        }
+        self.names.insert(name.to_ascii_uppercase(), definition);
+        Ok(self.refresh_name(name))
+    }
+
+    /// Forgets `name`, returning the indexes of the cells that changed.
+    pub fn delete_name(&mut self, name: &str) -> Vec<usize> {
+        if self.names.remove(&name.to_ascii_uppercase()).is_none() {
+            return vec![];
+        }
+        self.refresh_name(name)
+    }
+
+    // Re-links and re-evaluates the cells using `name` after its definition
+    // changed, directly or through the lambdas using it.
+    fn refresh_name(&mut self, name: &str) -> Vec<usize> {
+        let mut changed = vec![name.to_ascii_uppercase()];
+        let mut pending = vec![name.to_ascii_uppercase()];
+        while let Some(name) = pending.pop() {
+            for (other, definition) in &self.names {
+                if !changed.contains(other) && definition.uses_name(&name) {
+                    changed.push(other.clone());
+                    pending.push(other.clone());
+                }
+            }
+        }
+        let users: Vec<usize> = (0..self.cells.len())
+            .filter(|idx| changed.iter().any(|n| self.cells[*idx].expr.uses_name(n)))
+            .collect();
+        let mut updated = HashSet::new();
+        for idx in users {
+            let raw = self.cells[idx].raw.clone();
+            if let Ok(indexes) = self.set_raw(idx / self.width, idx % self.width, &raw) {
+                updated.extend(indexes);
+            }
+        }
+        updated.into_iter().collect()
+    }
+
+    /// Current date and time as a serial number, see `date`.
+    pub fn now(&self) -> f64 {
+        self.now.unwrap_or_else(date::now)
+    }
+
+    /// Freezes the current time, or unfreezes it with `None`. Cells already
+    /// evaluated aren't updated.
+    pub fn set_now(&mut self, now: Option<f64>) {
+        self.now = now;
+    }
+
+    pub fn iteration(&self) -> Option<Iteration> {
+        self.iteration
+    }
+
+    /// Enables iterative calculation, or disables it with `None`, and
+    /// re-evaluates the cells in reference cycles, returning the indexes of
+    /// the cells that changed.
+    pub fn set_iteration(&mut self, iteration: Option<Iteration>) -> Result<Vec<usize>, String> {
+        if let Some(iteration) = iteration {
+            if iteration.max_iterations == 0 {
+                return Err("max iterations must be at least 1".to_string());
+            }
+            if !iteration.tolerance.is_finite() || iteration.tolerance < 0. {
+                return Err(format!("invalid tolerance: {}", iteration.tolerance));
+            }
+        }
+        self.iteration = iteration;
+        let cyclic: Vec<usize> = (0..self.cells.len())
+            .filter(|idx| !self.cells[*idx].cycle.is_empty())
+            .collect();
+        Ok(self.recalculate(&cyclic))
+    }
+
+    /// Seeds the numbers of `RAND()` and `RANDBETWEEN()`. Cells already
+    /// evaluated aren't updated, see `recalculate_volatile`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = random::Rng::new(seed);
+    }
+
+    /// Re-evaluates the cells calling volatile functions like `NOW()` or
+    /// `RAND()`, and everything depending on them, returning the indexes of
+    /// the cells that changed.
+    pub fn recalculate_volatile(&mut self) -> Vec<usize> {
+        let volatile: Vec<usize> = (0..self.cells.len())
+            .filter(|idx| self.cells[*idx].volatile)
+            .collect();
+        self.recalculate(&volatile)
+    }
+
+    /// Re-evaluates every formula, returning the indexes of the cells that
+    /// changed.
+    pub fn recalculate_all(&mut self) -> Vec<usize> {
+        let formulas: Vec<usize> = (0..self.cells.len())
+            .filter(|idx| self.cells[*idx].raw.starts_with('='))
+            .collect();
+        self.recalculate(&formulas)
+    }
+
+    pub fn set_format_code(&mut self, row: usize, col: usize, format: &str) -> Result<(), String> {
+        self.check_bounds(row, col)?;
+        Format::parse(format)?;
+        let idx = self.get_index(row, col);
+        let cell = &mut self.cells[idx];
+        cell.format = format.to_string();
+        cell.set_out(cell.out.clone());
+        Ok(())
+    }
+
+    pub fn set_comment_count(
+        &mut self,
+        row: usize,
+        col: usize,
+        count: usize,
+    ) -> Result<(), String> {
+        self.check_bounds(row, col)?;
+        let idx = self.get_index(row, col);
+        self.cells[idx].comments = count;
+        Ok(())
+    }
+
+    /// Sets the raw contents of a cell and re-evaluates everything that
+    /// depends on it, returning the indexes of the cells that changed.
+    pub fn set_raw(&mut self, row: usize, col: usize, raw: &str) -> Result<Vec<usize>, String> {
+        self.check_bounds(row, col)?;
         let cur_idx = self.get_index(row, col);
 
         // Replace old cell with a placeholder to deal with expired inbound references
@@ -108,86 +650,377 @@ impl Spreadsheet {
         for out_idx in &old_cell.outbound {
             self.cells[*out_idx].inbound.remove(&cur_idx);
         }
+        // The placeholder got the cell's reference to itself, if it had one.
+        let mut inbound = old_cell.inbound;
+        inbound.remove(&cur_idx);
 
         // Create new cell
         let expr = ExprTree::new(raw);
-        let out = expr.eval(self);
         let mut outbound = HashSet::new();
         expr.fill_outbound(self, &mut outbound);
-        let inbound = old_cell.inbound.clone();
-        let new_cell = Cell {
+        let volatile = expr.is_volatile(self);
+        // Dates typed into unformatted cells keep showing as dates.
+        let format = match &expr {
+            ExprTree::Leaf(ValueNode::Date(_, code)) if old_cell.format.is_empty() => {
+                code.to_string()
+            }
+            _ => old_cell.format,
+        };
+        self.cells[cur_idx] = Cell {
             raw: raw.to_string(),
+            volatile,
             expr,
-            out,
+            format,
+            comments: old_cell.comments,
+            // So that the cells the formula spilled over can be cleared.
+            spill: old_cell.spill,
             outbound,
             inbound,
+            ..Cell::new()
         };
 
-        // Add new inbound references and replace placeholder with new cell
-        for out_idx in &new_cell.outbound {
-            self.cells[*out_idx].inbound.insert(cur_idx);
+        // Add new inbound references
+        let outbound = self.cells[cur_idx].outbound.clone();
+        for out_idx in outbound {
+            self.cells[out_idx].inbound.insert(cur_idx);
         }
-        self.cells[cur_idx] = new_cell;
+        // Formulas spilling over the cell may be blocked or unblocked now.
+        let mut starts = spill::covering(self, cur_idx);
+        starts.push(cur_idx);
+        Ok(self.recalculate(&starts))
+    }
 
-        if self.has_cycle(cur_idx) {
-            self.cells[cur_idx] = old_cell;
-            return Err(JsValue::from(format!("{} introduces a cycle!", raw)));
+    // Re-evaluates `starts` and every cell depending on them, returning their
+    // indexes in the order they were evaluated. References can form cycles,
+    // the cells in one evaluate to `#CYCLE!` until it's broken, unless
+    // iterative calculation is on.
+    fn recalculate(&mut self, starts: &[usize]) -> Vec<usize> {
+        let mut affected = starts.to_vec();
+        let mut seen: HashSet<usize> = affected.iter().copied().collect();
+        let mut i = 0;
+        while i < affected.len() {
+            for in_idx in &self.cells[affected[i]].inbound {
+                if seen.insert(*in_idx) {
+                    affected.push(*in_idx);
+                }
+            }
+            i += 1;
         }
 
-        // Our references form a DAG, we can toposort it to have the correct
-        // order we should re-eval our dependencies.
-        let eval_order = self.toposort_inbound(cur_idx);
-        for in_idx in &eval_order {
-            let new_out = self.cells[*in_idx].expr.eval(self);
-            self.cells[*in_idx].out = new_out;
+        let outbound = |idx: usize| self.cells[idx].outbound.iter().copied();
+        let components = graph::strongly_connected(&affected, outbound);
+        let mut eval_order = vec![];
+        // Cells that arrays started or stopped spilling over.
+        let mut respilled = vec![];
+        for component in components {
+            let is_cycle =
+                component.len() > 1 || self.cells[component[0]].outbound.contains(&component[0]);
+            if !is_cycle {
+                let idx = component[0];
+                let out = self.evaluate(idx);
+                self.cells[idx].cycle = vec![];
+                respilled.extend(spill::place(self, idx, out));
+            } else {
+                let members: HashSet<usize> = component.iter().copied().collect();
+                for &idx in &component {
+                    let outbound = |idx: usize| self.cells[idx].outbound.iter().copied();
+                    let path = graph::shortest_cycle(idx, &members, outbound);
+                    let width = self.width;
+                    let cell = &mut self.cells[idx];
+                    cell.cycle = path.iter().map(|i| (i / width, i % width)).collect();
+                }
+                match self.iteration {
+                    Some(iteration) => self.iterate(&component, iteration),
+                    None => {
+                        for &idx in &component {
+                            self.cells[idx].set_out(ExprResult::Error("#CYCLE!".to_string()));
+                        }
+                    }
+                }
+            }
+            eval_order.extend(component);
         }
+        // Their dependents were evaluated before they were linked to the
+        // array, or with the values they had before.
+        if !respilled.is_empty() {
+            eval_order.extend(self.recalculate(&respilled));
+        }
+        eval_order
+    }
 
-        // Serialize all cells that were modified for frontend to update.
-        let mut idx_to_cell = HashMap::new();
-        for in_idx in &eval_order {
-            idx_to_cell.insert(*in_idx, &self.cells[*in_idx]);
+    // Evaluates the cell at `idx` without storing the result.
+    fn evaluate(&self, idx: usize) -> ExprResult {
+        // The formula that spilled over the cell already set its value.
+        if self.cells[idx].spilled_from.is_some() {
+            return self.cells[idx].out.clone();
+        }
+        self.rng.start_cell(idx);
+        match self.cells[idx].expr.eval(self) {
+            ExprResult::Lambda(_) => ExprResult::Error("#CALC!".to_string()),
+            out => out,
         }
-        let res =
-            JsValue::from_serde(&idx_to_cell).map_err(|_| JsValue::from("could not serialize"))?;
-        Ok(res)
     }
 
-    pub fn get_index(&self, row: usize, col: usize) -> usize {
-        row * self.width + col
+    // Evaluates the cells of a cycle pass after pass until no number changes
+    // by more than the tolerance, or gives up with `#NUM!`. Every cell starts
+    // from 0 rather than its previous value so that peers, which saw edits in
+    // different orders, agree on the result.
+    fn iterate(&mut self, component: &[usize], iteration: Iteration) {
+        let mut members = component.to_vec();
+        members.sort_unstable();
+        for &idx in &members {
+            self.cells[idx].out = ExprResult::Num(0.);
+        }
+        for _ in 0..iteration.max_iterations {
+            let mut converged = true;
+            for &idx in &members {
+                // Arrays don't spill out of cycles.
+                let out = self.evaluate(idx).top_left();
+                converged &= match (&self.cells[idx].out, &out) {
+                    (ExprResult::Num(old), ExprResult::Num(new)) => {
+                        (new - old).abs() <= iteration.tolerance
+                    }
+                    (old, new) => old == new,
+                };
+                self.cells[idx].out = out;
+            }
+            if converged {
+                for &idx in &members {
+                    let out = self.cells[idx].out.clone();
+                    self.cells[idx].set_out(out);
+                }
+                return;
+            }
+        }
+        for &idx in &members {
+            self.cells[idx].set_out(ExprResult::Error("#NUM!".to_string()));
+        }
+    }
+
+    /// Sorts the rows of `range` on `keys` and moves them into place, see
+    /// `move_rows`.
+    pub fn sort_range(
+        &mut self,
+        range: Range,
+        keys: &[sort::SortKey],
+    ) -> Result<(Vec<Edit>, Vec<usize>), String> {
+        let order = self.sort_order(range, keys)?;
+        self.move_rows(range, &order)
     }
 
-    fn has_cycle(&self, start: usize) -> bool {
-        self._has_cycle(start, HashSet::new())
+    /// The rows of `range` sorted on `keys`, which must be columns of the
+    /// range, without moving anything.
+    pub fn sort_order(&self, range: Range, keys: &[sort::SortKey]) -> Result<Vec<usize>, String> {
+        self.check_bounds(range.end.0, range.end.1)?;
+        if let Some(key) = keys
+            .iter()
+            .find(|key| key.col < range.start.1 || key.col > range.end.1)
+        {
+            return Err(format!("column {} is not part of {}", key.col, range));
+        }
+        Ok(sort::order(self, range, keys))
     }
 
-    fn _has_cycle(&self, start: usize, mut visited: HashSet<usize>) -> bool {
-        if !visited.insert(start) {
-            return true;
+    /// Rearranges the rows of `range`, `order` listing them as they should
+    /// end up. Only the columns of the range move, with their formats and
+    /// comment counts. References to the cells that moved follow them, in
+    /// formulas anywhere and in names. Returns what was typed in the cells
+    /// whose raw input changed and the indexes of the cells that changed.
+    pub fn move_rows(
+        &mut self,
+        range: Range,
+        order: &[usize],
+    ) -> Result<(Vec<Edit>, Vec<usize>), String> {
+        self.check_bounds(range.end.0, range.end.1)?;
+        let mut rows = order.to_vec();
+        rows.sort_unstable();
+        if !rows.into_iter().eq(range.start.0..=range.end.0) {
+            return Err(format!("{:?} does not order the rows of {}", order, range));
+        }
+        let moves = sort::moves(range, order);
+
+        // What each cell holds once moved, as `(raw, format, comments)`.
+        let mut contents = HashMap::new();
+        for (idx, cell) in self.cells.iter().enumerate() {
+            let (row, col) = (idx / self.width, idx % self.width);
+            let mut expr = cell.expr.clone();
+            let raw = if expr.move_references(&moves) {
+                expr.to_string()
+            } else {
+                cell.raw.clone()
+            };
+            let to = moves.get(&(row, col)).copied().unwrap_or((row, col));
+            contents.insert(to, (raw, cell.format.clone(), cell.comments));
+        }
+        let mut edits = vec![];
+        let mut updated = HashSet::new();
+        for ((row, col), (raw, format, comments)) in contents {
+            let idx = self.get_index(row, col);
+            let cell = &mut self.cells[idx];
+            if cell.raw == raw && cell.format == format && cell.comments == comments {
+                continue;
+            }
+            if cell.raw != raw {
+                edits.push(Edit {
+                    row,
+                    col,
+                    raw: raw.clone(),
+                });
+            }
+            cell.format = format;
+            cell.comments = comments;
+            updated.extend(self.set_raw(row, col, &raw)?);
         }
-        for r in self.cells[start].outbound.iter() {
-            if self._has_cycle(*r, visited.clone()) {
-                return true;
+        edits.sort_by_key(|edit| (edit.row, edit.col));
+
+        let mut moved_names = vec![];
+        for (name, definition) in self.names.iter_mut() {
+            if definition.move_references(&moves) {
+                moved_names.push(name.clone());
             }
         }
-        false
+        for name in moved_names {
+            updated.extend(self.refresh_name(&name));
+        }
+        Ok((edits, updated.into_iter().collect()))
     }
 
-    fn toposort_inbound(&self, start: usize) -> Vec<usize> {
-        let mut sorted = vec![];
-        self._toposort_inbound(start, &mut sorted);
-        sorted
+    pub fn filter_view(&self) -> Option<&filter::Filter> {
+        self.filter.as_ref()
     }
 
-    fn _toposort_inbound(&self, start: usize, result: &mut Vec<usize>) {
-        result.push(start);
-        for r in self.cells[start].inbound.iter() {
-            self._toposort_inbound(*r, result);
+    /// Sets the filter view, or removes it with `None`. Its conditions must
+    /// be on columns of its range.
+    pub fn set_filter_view(&mut self, filter: Option<filter::Filter>) -> Result<(), String> {
+        if let Some(filter) = &filter {
+            let range = filter.range;
+            self.check_bounds(range.end.0, range.end.1)?;
+            if let Some(condition) = filter
+                .conditions
+                .iter()
+                .find(|c| c.col < range.start.1 || c.col > range.end.1)
+            {
+                return Err(format!("column {} is not part of {}", condition.col, range));
+            }
+        }
+        self.filter = filter;
+        Ok(())
+    }
+
+    /// Rows the filter view hides, given the current values. Nothing is
+    /// hidden without one.
+    pub fn filtered_rows(&self) -> Vec<usize> {
+        match &self.filter {
+            Some(filter) => filter::hidden_rows(self, filter),
+            None => vec![],
+        }
+    }
+
+    /// The `(row, col)` of the cells matching `query`, row by row.
+    pub fn find_cells(&self, query: &search::Query) -> Result<Vec<(usize, usize)>, String> {
+        let matcher = search::Matcher::new(query)?;
+        Ok(self
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| {
+                matcher.is_match(if query.in_values {
+                    &cell.display
+                } else {
+                    &cell.raw
+                })
+            })
+            .map(|(idx, _)| (idx / self.width, idx % self.width))
+            .collect())
+    }
+
+    /// Replaces what `query` matches in what was typed in every cell.
+    /// Returns what the replaced cells held before, which `set_raws` puts
+    /// back, and the indexes of the cells that changed.
+    pub fn replace_all(
+        &mut self,
+        query: &search::Query,
+        replacement: &str,
+    ) -> Result<(Vec<Edit>, Vec<usize>), String> {
+        if query.in_values {
+            return Err("values can't be replaced, only what was typed in cells".to_string());
+        }
+        let matcher = search::Matcher::new(query)?;
+        let mut previous = vec![];
+        let mut edits = vec![];
+        for (idx, cell) in self.cells.iter().enumerate() {
+            let raw = match matcher.replace(&cell.raw, replacement) {
+                Some(raw) if raw != cell.raw => raw,
+                _ => continue,
+            };
+            let (row, col) = (idx / self.width, idx % self.width);
+            previous.push(Edit {
+                row,
+                col,
+                raw: cell.raw.clone(),
+            });
+            edits.push(Edit { row, col, raw });
+        }
+        let updated = self.set_raws(&edits)?;
+        Ok((previous, updated))
+    }
+
+    /// Extends the series of `source` over `target`, like dragging the fill
+    /// handle, see the `fill` module. Returns what was typed in the filled
+    /// cells and the indexes of the cells that changed.
+    pub fn fill_range(
+        &mut self,
+        source: Range,
+        target: Range,
+    ) -> Result<(Vec<Edit>, Vec<usize>), String> {
+        self.check_bounds(source.end.0, source.end.1)?;
+        self.check_bounds(target.end.0, target.end.1)?;
+        let edits = fill::fill(self, source, target)?;
+        let updated = self.set_raws(&edits)?;
+        Ok((edits, updated))
+    }
+
+    /// Sets several cells at once, returning the indexes of the cells that
+    /// changed. Nothing is set when one of them is out of bounds.
+    pub fn set_raws(&mut self, edits: &[Edit]) -> Result<Vec<usize>, String> {
+        for edit in edits {
+            self.check_bounds(edit.row, edit.col)?;
+        }
+        let mut updated = HashSet::new();
+        for edit in edits {
+            updated.extend(self.set_raw(edit.row, edit.col, &edit.raw)?);
+        }
+        Ok(updated.into_iter().collect())
+    }
+
+    pub fn precedent_links(&self, row: usize, col: usize) -> Result<audit::Links, String> {
+        self.check_bounds(row, col)?;
+        let idx = self.get_index(row, col);
+        Ok(audit::links(self, idx, audit::Direction::Outbound))
+    }
+
+    pub fn dependent_links(&self, row: usize, col: usize) -> Result<audit::Links, String> {
+        self.check_bounds(row, col)?;
+        let idx = self.get_index(row, col);
+        Ok(audit::links(self, idx, audit::Direction::Inbound))
+    }
+
+    pub fn explanation(&self, row: usize, col: usize) -> Result<audit::Explanation, String> {
+        self.check_bounds(row, col)?;
+        self.rng.start_cell(self.get_index(row, col));
+        Ok(audit::explain(self, &self.get(row, col).expr))
+    }
+
+    /// The cells the formula at `(row, col)` spilled its array over, `None`
+    /// when it didn't.
+    pub fn spill_range(&self, row: usize, col: usize) -> Option<expr::Range> {
+        self.check_bounds(row, col).ok()?;
+        match self.get(row, col).spill {
+            Some(spill) if !spill.blocked => Some(spill::area((row, col), spill)),
+            _ => None,
         }
     }
-}
 
-// methods not exported through web assembly
-impl Spreadsheet {
     pub fn get(&self, row: usize, col: usize) -> &Cell {
         let idx = self.get_index(row, col);
         &self.cells[idx]
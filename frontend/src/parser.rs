@@ -1,22 +1,40 @@
-use super::expr::{BinaryNode, BinaryOp, ExprTree, UnaryNode, UnaryOp, ValueNode};
+use super::date;
+use super::expr::{BinaryNode, BinaryOp, ExprTree, FuncNode, Range, UnaryNode, UnaryOp, ValueNode};
+use std::iter;
 /*
 Grammar
 
-Cell ::= Formula | Rational Number | Text
+Cell ::= Formula | Rational Number | DateTime | Text
 Formula ::= “=“ Expr
-Expr ::= Term ('+' Term | '-' Term)*
-Term ::= Factor ('*' Factor | '/' Factor)*
-Factor ::= ['-'] (Value | '(' Expr ')')
-Value ::= Function | Coordinate | Rational Number
-Function ::= FnId '(' Range ')' (TODO: implement)
-Range ::= Coordinate ':' Coordinate (TODO: implement)
+Expr ::= Concat (('=' | '<>' | '<=' | '>=' | '<' | '>') Concat)*
+Concat ::= Sum ('&' Sum)*
+Sum ::= Term ('+' Term | '-' Term)*
+Term ::= Power ('*' Power | '/' Power)*
+Power ::= Percent ['^' Power]
+Percent ::= Factor '%'*
+Factor ::= ('-' | '+') Factor | Value | '(' Expr ')'
+Value ::= Function | Boolean | Range | Spill | Coordinate | Name | Unsigned Number | String
+Boolean ::= 'TRUE' | 'FALSE'
+Function ::= Identifier '(' [Expr (',' Expr)*] ')'
+Name ::= Identifier, one that isn't shaped like a coordinate or a boolean
+Identifier ::= (Letter | '_') (Letter | Digit | '.' | '_')*
+Range ::= Coordinate ':' Coordinate
+Spill ::= Coordinate '#'
 Coordinate ::= Letters Natural Number
+String ::= '"' (Char | '""')* '"'
 Letters ::= Letter+
 Natural Number ::= Digit+
-Rational Number ::= [-] Digit+ ['.' Digit+] (TODO: check if adding [-] breaks Factor)
+Rational Number ::= ['-'] Unsigned Number
+Unsigned Number ::= Digit+ ['.' Digit+]
+DateTime ::= Date [' ' Time] | Time
+Date ::= Digit{4} ('-' | '/') Digit{1,2} ('-' | '/') Digit{1,2}
+Time ::= Digit+ ':' Digit{2} [':' Digit{2} ['.' Digit+]]
 Digit ::= [0-9]
 Letter ::= [a-z][A-Z]
 
+Binary operators are left associative except for '^', and whitespace is allowed
+between tokens.
+
 TODO:
 - Improve error handling while parsing. Ideally, we would get "unexpected token in line x col y, found: w expected z"
   - This includes passing ExprResult::Error instead of a standard rust error.
@@ -24,6 +42,8 @@ TODO:
 */
 pub type ParseResult<'a, Output> = Result<(Output, &'a str), &'static str>;
 
+const MAX_NAME_LEN: usize = 255;
+
 trait Parser<'a, T> {
   fn parse(&self, input: &'a str) -> ParseResult<'a, T>;
 }
@@ -39,13 +59,13 @@ where
 
 // Spreadsheet Parsers
 
-pub fn cell(input: &str) -> ParseResult<ExprTree> {
+pub fn cell(input: &str) -> ParseResult<'_, ExprTree> {
   // Cell ::= Formula | Number | Text
   let (tree, input) = if input.starts_with('=') {
     formula(input)?
   } else {
     // Order matters, we only want to treat something as text if it's not a number.
-    either(num, text).parse(input)?
+    either(num, either(date_time_leaf, text)).parse(input)?
   };
   if !input.is_empty() {
     Err("Expected input to be empty")
@@ -54,11 +74,15 @@ pub fn cell(input: &str) -> ParseResult<ExprTree> {
   }
 }
 
-fn num(input: &str) -> ParseResult<ExprTree> {
+fn num(input: &str) -> ParseResult<'_, ExprTree> {
   empty_or_err(map(rational_number, |n| ExprTree::Leaf(ValueNode::Num(n)))).parse(input)
 }
 
-fn text(input: &str) -> ParseResult<ExprTree> {
+fn date_time_leaf(input: &str) -> ParseResult<'_, ExprTree> {
+  empty_or_err(map(date_time, ExprTree::Leaf)).parse(input)
+}
+
+fn text(input: &str) -> ParseResult<'_, ExprTree> {
   let multiple_chars = zero_or_more(any_char);
   empty_or_err(map(multiple_chars, |c| {
     ExprTree::Leaf(ValueNode::Text(c.into_iter().collect()))
@@ -66,77 +90,324 @@ fn text(input: &str) -> ParseResult<ExprTree> {
   .parse(input)
 }
 
-fn formula(input: &str) -> ParseResult<ExprTree> {
+fn formula(input: &str) -> ParseResult<'_, ExprTree> {
   // Formula ::= “=“ Expr
   let (_, input) = literal("=").parse(input)?;
-  empty_or_err(expr).parse(input)
+  empty_or_err(left(expr, whitespace)).parse(input)
+}
+
+fn expr(input: &str) -> ParseResult<'_, ExprTree> {
+  // Expr ::= Concat (('=' | '<>' | '<=' | '>=' | '<' | '>') Concat)*
+  let (first_concat, input) = concat(input)?;
+  let (others, input) = zero_or_more(pair(comparison, concat)).parse(input)?;
+  Ok((reduce_trees(first_concat, others), input))
 }
 
-fn expr(input: &str) -> ParseResult<ExprTree> {
-  // Expr ::= Term ('+' Term | '-' Term)*
+fn comparison(input: &str) -> ParseResult<'_, BinaryOp> {
+  // Two character operators go first, `<` would take the start of `<=`.
+  let ne = map(token("<>"), |_| BinaryOp::Ne);
+  let le = map(token("<="), |_| BinaryOp::Le);
+  let ge = map(token(">="), |_| BinaryOp::Ge);
+  let eq = map(token("="), |_| BinaryOp::Eq);
+  let lt = map(token("<"), |_| BinaryOp::Lt);
+  let gt = map(token(">"), |_| BinaryOp::Gt);
+  either(ne, either(le, either(ge, either(eq, either(lt, gt))))).parse(input)
+}
+
+fn concat(input: &str) -> ParseResult<'_, ExprTree> {
+  // Concat ::= Sum ('&' Sum)*
+  let (first_sum, input) = sum(input)?;
+  let concat = map(token("&"), |_| BinaryOp::Concat);
+  let (others, input) = zero_or_more(pair(concat, sum)).parse(input)?;
+  Ok((reduce_trees(first_sum, others), input))
+}
+
+fn sum(input: &str) -> ParseResult<'_, ExprTree> {
+  // Sum ::= Term ('+' Term | '-' Term)*
   let (first_term, input) = term(input)?;
-  let sum = map(literal("+"), |_| BinaryOp::Sum);
-  let sub = map(literal("-"), |_| BinaryOp::Sub);
+  let sum = map(token("+"), |_| BinaryOp::Sum);
+  let sub = map(token("-"), |_| BinaryOp::Sub);
   let sum_term = pair(sum, term);
   let sub_term = pair(sub, term);
   let (others, input) = zero_or_more(either(sum_term, sub_term)).parse(input)?;
   Ok((reduce_trees(first_term, others), input))
 }
 
-fn term(input: &str) -> ParseResult<ExprTree> {
-  // Term ::= Factor ('*' Factor | '/' Factor)*
-  let (first_factor, input) = factor(input)?;
-  let mul = map(literal("*"), |_| BinaryOp::Mul);
-  let div = map(literal("/"), |_| BinaryOp::Div);
-  let mul_fact = pair(mul, factor);
-  let div_fact = pair(div, factor);
-  let (others, input) = zero_or_more(either(mul_fact, div_fact)).parse(input)?;
-  Ok((reduce_trees(first_factor, others), input))
+fn term(input: &str) -> ParseResult<'_, ExprTree> {
+  // Term ::= Power ('*' Power | '/' Power)*
+  let (first_power, input) = power(input)?;
+  let mul = map(token("*"), |_| BinaryOp::Mul);
+  let div = map(token("/"), |_| BinaryOp::Div);
+  let mul_power = pair(mul, power);
+  let div_power = pair(div, power);
+  let (others, input) = zero_or_more(either(mul_power, div_power)).parse(input)?;
+  Ok((reduce_trees(first_power, others), input))
 }
 
-fn factor(mut input: &str) -> ParseResult<ExprTree> {
-  // Factor ::= ['-'] (Number | '(' Expr ')')
-  let mut negate = false;
-  if let Ok((_, next_input)) = literal("-").parse(input) {
-    negate = true;
-    input = next_input;
+fn power(input: &str) -> ParseResult<'_, ExprTree> {
+  // Power ::= Percent ['^' Power], so that 2^3^2 is 2^(3^2).
+  let (base, input) = percent(input)?;
+  match right(token("^"), power).parse(input) {
+    Ok((exponent, input)) => {
+      let node = BinaryNode {
+        op: BinaryOp::Pow,
+        left: base,
+        right: exponent,
+      };
+      Ok((ExprTree::Binary(Box::new(node)), input))
+    }
+    Err(_) => Ok((base, input)),
   }
-  let paren_expr = right(literal("("), left(expr, literal(")")));
-  let val_or_expr = either(value, paren_expr);
-  let (child, input) = val_or_expr.parse(input)?;
+}
 
-  if negate {
+fn percent(input: &str) -> ParseResult<'_, ExprTree> {
+  // Percent ::= Factor '%'*
+  let (child, input) = factor(input)?;
+  let (percents, input) = zero_or_more(token("%")).parse(input)?;
+  let tree = percents.iter().fold(child, |child, _| {
     let node = UnaryNode {
-      op: UnaryOp::Not,
+      op: UnaryOp::Percent,
       child,
     };
-    let tree = ExprTree::Unary(Box::new(node));
-    Ok((tree, input))
-  } else {
-    Ok((child, input))
+    ExprTree::Unary(Box::new(node))
+  });
+  Ok((tree, input))
+}
+
+fn factor(input: &str) -> ParseResult<'_, ExprTree> {
+  // Factor ::= ('-' | '+') Factor | Value | '(' Expr ')'
+  // Signs bind tighter than anything else, -2^2 is (-2)^2 like in other
+  // spreadsheets.
+  let neg = map(token("-"), |_| UnaryOp::Neg);
+  let plus = map(token("+"), |_| UnaryOp::Plus);
+  if let Ok((op, input)) = either(neg, plus).parse(input) {
+    let (child, input) = factor(input)?;
+    let tree = ExprTree::Unary(Box::new(UnaryNode { op, child }));
+    return Ok((tree, input));
+  }
+  let paren_expr = right(token("("), left(expr, token(")")));
+  either(right(whitespace, value), paren_expr).parse(input)
+}
+
+fn value(input: &str) -> ParseResult<'_, ExprTree> {
+  // Value ::= Function | Boolean | Range | Spill | Coord | Name | Number | String
+  // Signs are operators in formulas, see `factor`.
+  let num_val = map(unsigned_number, ValueNode::Num);
+  let range_val = map(range, ValueNode::Range);
+  let spill_val = map(left(coordinate, literal("#")), |(row, col)| {
+    ValueNode::Spill(row, col)
+  });
+  let name_val = map(identifier, ValueNode::Name);
+  let string_val = map(string, ValueNode::Text);
+  // Functions go first, `LOG10(` would otherwise be read as a coordinate, and
+  // ranges and spills before the coordinate they start with. Names can't look
+  // like either, see `is_valid_name`.
+  let ref_val = either(range_val, either(spill_val, either(coord, name_val)));
+  let leaf = map(either(num_val, either(ref_val, string_val)), ExprTree::Leaf);
+  either(function, either(boolean, leaf)).parse(input)
+}
+
+fn boolean(input: &str) -> ParseResult<'_, ExprTree> {
+  // Boolean ::= 'TRUE' | 'FALSE', in any case. There's no boolean type, they
+  // stand for 1 and 0.
+  let (name, input) = identifier(input)?;
+  let value = match name.to_ascii_uppercase().as_str() {
+    "TRUE" => 1.,
+    "FALSE" => 0.,
+    _ => return Err("not a boolean"),
+  };
+  Ok((ExprTree::Leaf(ValueNode::Num(value)), input))
+}
+
+fn string(input: &str) -> ParseResult<'_, String> {
+  // String ::= '"' (Char | '""')* '"', a doubled quote stands for one quote.
+  let quote = || literal("\"");
+  let escaped_quote = map(pair(quote(), quote()), |_| '"');
+  let other_char = predicate(any_char, |c| *c != '"');
+  let (_, input) = quote().parse(input)?;
+  let (chars, input) = zero_or_more(either(escaped_quote, other_char)).parse(input)?;
+  let (_, input) = quote().parse(input)?;
+  Ok((chars.into_iter().collect(), input))
+}
+
+fn function(input: &str) -> ParseResult<'_, ExprTree> {
+  // Function ::= FnId '(' [Expr (',' Expr)*] ')'
+  let (name, input) = identifier(input)?;
+  let (_, input) = literal("(").parse(input)?;
+  let (first, input) = optional(expr).parse(input)?;
+  let (args, input) = match first {
+    Some(first) => {
+      let (others, input) = zero_or_more(right(token(","), expr)).parse(input)?;
+      (iter::once(first).chain(others).collect(), input)
+    }
+    None => (vec![], input),
+  };
+  let (_, input) = token(")").parse(input)?;
+  let node = FuncNode {
+    name: name.to_ascii_uppercase(),
+    args,
+  };
+  Ok((ExprTree::Func(Box::new(node)), input))
+}
+
+fn identifier(input: &str) -> ParseResult<'_, String> {
+  // Identifier ::= (Letter | '_') (Letter | Digit | '.' | '_')*
+  let (first, input) =
+    predicate(any_char, |c| c.is_ascii_alphabetic() || *c == '_').parse(input)?;
+  let (rest, input) = zero_or_more(predicate(any_char, is_ident_char)).parse(input)?;
+  Ok((iter::once(first).chain(rest).collect(), input))
+}
+
+fn is_ident_char(c: &char) -> bool {
+  c.is_ascii_alphanumeric() || *c == '.' || *c == '_'
+}
+
+/// Whether `name` can be defined as a name: an identifier that can't be
+/// mistaken for a coordinate, a boolean or a function call.
+pub fn is_valid_name(name: &str) -> bool {
+  let is_identifier = empty_or_err(identifier).parse(name).is_ok();
+  // `A0` isn't a cell either, but would be confusing as a name.
+  let is_coordinate = empty_or_err(pair(letters, digits)).parse(name).is_ok();
+  let is_boolean = name.eq_ignore_ascii_case("TRUE") || name.eq_ignore_ascii_case("FALSE");
+  is_identifier && !is_coordinate && !is_boolean && name.len() <= MAX_NAME_LEN
+}
+
+/// What a name can stand for: a cell, a range, a constant or a `LAMBDA`,
+/// with or without a leading `=`.
+pub fn name_definition(input: &str) -> Result<ExprTree, &'static str> {
+  let input = input.trim();
+  let input = input.strip_prefix('=').unwrap_or(input).trim_start();
+  if let Ok((tree @ ExprTree::Func(_), "")) = function(input) {
+    return match &tree {
+      ExprTree::Func(f) if f.name == "LAMBDA" => Ok(tree),
+      _ => Err("names can't refer to formulas other than LAMBDA"),
+    };
+  }
+  let coord_val = either(map(range, ValueNode::Range), coord);
+  let string_val = map(string, ValueNode::Text);
+  let leaf = either(
+    coord_val,
+    either(map(rational_number, ValueNode::Num), string_val),
+  );
+  // Dates first, `2024-03-15` starts like a number.
+  let definition = either(date_time_leaf, map(leaf, ExprTree::Leaf));
+  let (tree, _) = empty_or_err(definition)
+    .parse(input)
+    .map_err(|_| "names must refer to a cell, a range, a constant or a LAMBDA")?;
+  Ok(tree)
+}
+
+/// Parses text such as `2024-03-15`, `15:30` or `2024-03-15 08:00:30` into a
+/// serial number, see the `date` module.
+pub fn date_time_value(input: &str) -> Option<f64> {
+  match empty_or_err(date_time).parse(input) {
+    Ok((ValueNode::Date(serial, _), _)) => Some(serial),
+    _ => None,
+  }
+}
+
+fn date_time(input: &str) -> ParseResult<'_, ValueNode> {
+  // DateTime ::= Date [' ' Time] | Time
+  if let Ok((day, input)) = calendar_date(input) {
+    if let Ok(((time, seconds), rest)) = right(literal(" "), time).parse(input) {
+      let format = if seconds {
+        "yyyy-mm-dd hh:mm:ss"
+      } else {
+        "yyyy-mm-dd hh:mm"
+      };
+      return Ok((ValueNode::Date(day + time, format.to_string()), rest));
+    }
+    return Ok((ValueNode::Date(day, "yyyy-mm-dd".to_string()), input));
   }
+  let ((time, seconds), input) = time(input)?;
+  // Past a day it's a duration rather than a time of day.
+  let format = match (time >= 1., seconds) {
+    (true, _) => "[h]:mm:ss",
+    (false, true) => "h:mm:ss",
+    (false, false) => "h:mm",
+  };
+  Ok((ValueNode::Date(time, format.to_string()), input))
+}
+
+fn calendar_date(input: &str) -> ParseResult<'_, f64> {
+  // Date ::= Digit{4} ('-' | '/') Digit{1,2} ('-' | '/') Digit{1,2}
+  let separator = || either(literal("-"), literal("/"));
+  let (year, input) = predicate(digits, |d| d.len() == 4).parse(input)?;
+  let (month, input) = right(separator(), predicate(digits, |d| d.len() <= 2)).parse(input)?;
+  let (day, input) = right(separator(), predicate(digits, |d| d.len() <= 2)).parse(input)?;
+  let (year, month, day): (i64, u32, u32) = (
+    year.parse().unwrap(),
+    month.parse().unwrap(),
+    day.parse().unwrap(),
+  );
+  if !(1..=12).contains(&month) || day < 1 || day > date::days_in_month(year, month) {
+    return Err("invalid date");
+  }
+  Ok((date::serial_from_ymd(year, month as i64, day as i64), input))
+}
+
+fn time(input: &str) -> ParseResult<'_, (f64, bool)> {
+  // Time ::= Digit+ ':' Digit{2} [':' Digit{2} ['.' Digit+]]
+  let two_digits = || predicate(digits, |d| d.len() == 2);
+  let (hours, input) = digits(input)?;
+  let (minutes, input) = right(literal(":"), two_digits()).parse(input)?;
+  let (seconds, input) = optional(right(literal(":"), two_digits())).parse(input)?;
+  let (fraction, input) = match seconds {
+    Some(_) => optional(right(literal("."), digits)).parse(input)?,
+    None => (None, input),
+  };
+  let minutes: i64 = minutes.parse().unwrap();
+  let seconds_value: f64 = format!(
+    "{}.{}",
+    seconds.as_deref().unwrap_or("0"),
+    fraction.unwrap_or_default()
+  )
+  .parse()
+  .unwrap();
+  if minutes >= 60 || seconds_value >= 60. {
+    return Err("invalid time");
+  }
+  let hours = hours.parse().map_err(|_| "invalid time")?;
+  Ok((
+    (
+      date::serial_from_hms(hours, minutes, seconds_value),
+      seconds.is_some(),
+    ),
+    input,
+  ))
+}
+
+fn range(input: &str) -> ParseResult<'_, Range> {
+  // Range ::= Coordinate ':' Coordinate
+  let (start, input) = coordinate(input)?;
+  let (end, input) = right(literal(":"), coordinate).parse(input)?;
+  Ok((Range::new(start, end), input))
 }
 
-fn value(input: &str) -> ParseResult<ExprTree> {
-  // Value ::= Coord | Function | Number
-  let num_val = map(rational_number, ValueNode::Num);
-  let num_or_coord = either(num_val, coord);
-  let (val, input) = num_or_coord.parse(input)?;
-  Ok((ExprTree::Leaf(val), input))
+fn coord(input: &str) -> ParseResult<'_, ValueNode> {
+  let ((row, col), input) = coordinate(input)?;
+  Ok((ValueNode::Coord(row, col), input))
 }
 
-fn coord(input: &str) -> ParseResult<ValueNode> {
+fn coordinate(input: &str) -> ParseResult<'_, (usize, usize)> {
   // Coordinate ::= Letters Number
   let (ltrs, input) = letters(input)?;
   let col = letters_to_col(&ltrs);
   // TODO(adelavega): We should have a float, and int parser, and use int here.
   let (num, input) = natural_number(input)?;
+  // `Q1x` is a name, not Q1 followed by garbage.
+  if input.chars().next().is_some_and(|c| is_ident_char(&c)) {
+    return Err("not a coordinate");
+  }
+  if num < 1. {
+    return Err("rows start at 1");
+  }
   // Convert to 0-based index before returning
   let row = (num as usize) - 1;
-  Ok((ValueNode::Coord(row, col), input))
+  Ok(((row, col), input))
 }
 
-fn letters(input: &str) -> ParseResult<String> {
+fn letters(input: &str) -> ParseResult<'_, String> {
   // Letters ::= Letter+
   let (letters_vec, input) =
     one_or_more(predicate(any_char, |c| c.is_ascii_alphabetic())).parse(input)?;
@@ -157,33 +428,22 @@ fn letters_to_col(letters: &str) -> usize {
 }
 
 fn reduce_trees(first: ExprTree, others: Vec<(BinaryOp, ExprTree)>) -> ExprTree {
-  if others.is_empty() {
-    return first;
-  }
-  let mut others_iter = others.into_iter().rev();
-
-  let (op, right) = others_iter.next().unwrap();
-  let mut node = BinaryNode {
-    op,
-    left: ExprTree::Empty,
-    right,
-  };
-  for (op, left) in others_iter {
-    node.left = left;
-    node = BinaryNode {
-      op,
-      left: ExprTree::Empty,
-      right: ExprTree::Binary(Box::new(node)),
-    };
-  }
-  node.left = first;
-  ExprTree::Binary(Box::new(node))
+  // Left associative, 1-2-3 is (1-2)-3.
+  others.into_iter().fold(first, |left, (op, right)| {
+    ExprTree::Binary(Box::new(BinaryNode { op, left, right }))
+  })
 }
 
-fn rational_number(input: &str) -> ParseResult<f64> {
-  // Rational Number := [-] Digit+ [. Digit+]
+fn rational_number(input: &str) -> ParseResult<'_, f64> {
+  // Rational Number := [-] Unsigned Number
   let (negate_opt, input) = optional(literal("-")).parse(input)?;
   let neg_coefficient = if negate_opt.is_some() { -1. } else { 1. };
+  let (num, input) = unsigned_number(input)?;
+  Ok((num * neg_coefficient, input))
+}
+
+fn unsigned_number(input: &str) -> ParseResult<'_, f64> {
+  // Unsigned Number := Digit+ [. Digit+]
   let (first_num, input) = digits(input)?;
   let (dot_opt, input) = optional(literal(".")).parse(input)?;
   let (full_num, input) = if dot_opt.is_some() {
@@ -192,24 +452,32 @@ fn rational_number(input: &str) -> ParseResult<f64> {
   } else {
     (first_num, input)
   };
-  let rational_num = full_num.parse::<f64>().unwrap() * neg_coefficient;
-  Ok((rational_num, input))
+  Ok((full_num.parse::<f64>().unwrap(), input))
 }
 
-fn natural_number(input: &str) -> ParseResult<f64> {
+fn natural_number(input: &str) -> ParseResult<'_, f64> {
   // Number ::= Digit+
   let (num, input) = digits(input)?;
   Ok((num.parse().unwrap(), input))
 }
 
-fn digits(input: &str) -> ParseResult<String> {
-  let (num_vec, input) = one_or_more(predicate(any_char, |c| c.is_numeric())).parse(input)?;
+fn digits(input: &str) -> ParseResult<'_, String> {
+  let (num_vec, input) = one_or_more(predicate(any_char, |c| c.is_ascii_digit())).parse(input)?;
   Ok((num_vec.into_iter().collect::<String>(), input))
 }
 
+fn whitespace(input: &str) -> ParseResult<'_, ()> {
+  Ok(((), input.trim_start_matches(|c: char| c.is_whitespace())))
+}
+
+/// `pattern`, after any whitespace.
+fn token<'a>(pattern: &'static str) -> impl Parser<'a, ()> {
+  right(whitespace, literal(pattern))
+}
+
 // Generic Parsers
 
-fn any_char(input: &str) -> ParseResult<char> {
+fn any_char(input: &str) -> ParseResult<'_, char> {
   if let Some(c) = input.chars().next() {
     Ok((c, &input[c.len_utf8()..]))
   } else {
@@ -330,12 +598,9 @@ fn pair<'a, A, B>(
 }
 
 fn literal<'a>(pattern: &'static str) -> impl Parser<'a, ()> {
-  move |input: &'a str| {
-    if input.starts_with(pattern) {
-      Ok(((), &input[pattern.len()..]))
-    } else {
-      Err("no match")
-    }
+  move |input: &'a str| match input.strip_prefix(pattern) {
+    Some(rest) => Ok(((), rest)),
+    None => Err("no match"),
   }
 }
 
@@ -350,6 +615,113 @@ mod tests {
       assert_eq!(letters_to_col("Aa"), 26);
       assert_eq!(letters_to_col("ba"), 52);
     }
+
+    #[test]
+    fn date_time_values() {
+      assert_eq!(date_time_value("2024-03-15"), Some(45366.));
+      assert_eq!(date_time_value("2024/3/15"), Some(45366.));
+      assert_eq!(date_time_value("2024-03-15 18:00"), Some(45366.75));
+      assert_eq!(date_time_value("6:00"), Some(0.25));
+      assert_eq!(date_time_value("36:00:00"), Some(1.5));
+      assert_eq!(date_time_value("2023-02-29"), None);
+      assert_eq!(date_time_value("12:60"), None);
+      assert_eq!(date_time_value("15-03-2024"), None);
+    }
+
+    #[test]
+    fn functions_are_not_coordinates() {
+      match value("LOG10(A1,2)") {
+        Ok((ExprTree::Func(f), "")) => {
+          assert_eq!(f.name, "LOG10");
+          assert_eq!(f.args.len(), 2);
+        }
+        _ => panic!("expected a function call"),
+      }
+      match value("now()") {
+        Ok((ExprTree::Func(f), "")) => assert!(f.name == "NOW" && f.args.is_empty()),
+        _ => panic!("expected a function call"),
+      }
+      assert!(matches!(value("A1"), Ok((ExprTree::Leaf(_), ""))));
+    }
+
+    #[test]
+    fn ranges_are_normalized() {
+      let expected = Range {
+        start: (0, 0),
+        end: (2, 1),
+      };
+      assert_eq!(range("A1:B3"), Ok((expected, "")));
+      assert_eq!(range("B1:A3"), Ok((expected, "")));
+      assert!(
+        matches!(value("B3:A1"), Ok((ExprTree::Leaf(ValueNode::Range(r)), "")) if r == expected)
+      );
+      assert!(range("A1").is_err());
+      assert!(matches!(
+        value("b2#"),
+        Ok((ExprTree::Leaf(ValueNode::Spill(1, 1)), ""))
+      ));
+    }
+
+    #[test]
+    fn names() {
+      assert!(is_valid_name("TaxRate"));
+      assert!(is_valid_name("_rev.2020"));
+      assert!(is_valid_name("Q1x"));
+      assert!(!is_valid_name("Q1"));
+      assert!(!is_valid_name("A0"));
+      assert!(!is_valid_name("true"));
+      assert!(!is_valid_name("1st"));
+      assert!(!is_valid_name("Tax Rate"));
+      assert!(matches!(value("Q1x"), Ok((ExprTree::Leaf(ValueNode::Name(n)), "")) if n == "Q1x"));
+      assert!(matches!(
+        value("Q1"),
+        Ok((ExprTree::Leaf(ValueNode::Coord(0, 16)), ""))
+      ));
+      assert!(matches!(
+        value("A0"),
+        Ok((ExprTree::Leaf(ValueNode::Name(_)), ""))
+      ));
+      assert!(matches!(
+        ExprTree::new("½"),
+        ExprTree::Leaf(ValueNode::Text(_))
+      ));
+    }
+
+    #[test]
+    fn name_definitions() {
+      assert!(matches!(
+        name_definition("=B1:A3"),
+        Ok(ExprTree::Leaf(ValueNode::Range(_)))
+      ));
+      assert!(matches!(
+        name_definition(" C2 "),
+        Ok(ExprTree::Leaf(ValueNode::Coord(1, 2)))
+      ));
+      assert!(matches!(
+        name_definition("-0.2"),
+        Ok(ExprTree::Leaf(ValueNode::Num(n))) if n == -0.2
+      ));
+      assert!(matches!(
+        name_definition("2024-03-15"),
+        Ok(ExprTree::Leaf(ValueNode::Date(..)))
+      ));
+      assert!(matches!(
+        name_definition("=LAMBDA(x, x*2)"),
+        Ok(ExprTree::Func(f)) if f.args.len() == 2
+      ));
+      assert!(name_definition("=A1+1").is_err());
+      assert!(name_definition("=LEN(A1)").is_err());
+      assert!(name_definition("=LAMBDA(x, x)+1").is_err());
+      assert!(name_definition("Other").is_err());
+    }
+
+    #[test]
+    fn strings() {
+      let quoted = r#""say ""hi""" rest"#;
+      assert_eq!(string(quoted), Ok((r#"say "hi""#.to_string(), " rest")));
+      assert_eq!(string(r#""""#), Ok(("".to_string(), "")));
+      assert!(string(r#""unterminated"#).is_err());
+    }
   }
   mod combinators {
     use super::super::*;
@@ -0,0 +1,249 @@
+//! Turns parsed cells back into text. The output is canonical: no
+//! whitespace, uppercase function names and only the parentheses the
+//! precedence rules need, so `= (a1 + 2) * sum( B1 )` prints as
+//! `=(A1+2)*SUM(B1)`.
+//!
+//! Printing then parsing gives back the same tree for anything the parser
+//! produces, with two exceptions: `Empty` and `Error` trees have no text,
+//! and times are printed to the millisecond.
+
+use super::date;
+use super::expr::{BinaryOp, ExprTree, Range, UnaryOp, ValueNode};
+use std::fmt;
+
+impl fmt::Display for ExprTree {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ExprTree::Empty => Ok(()),
+      ExprTree::Error(_) => Ok(()),
+      // Literals print as they'd be typed into the cell, `=5` is just `5`.
+      ExprTree::Leaf(ValueNode::Num(n)) => write!(f, "{}", n),
+      ExprTree::Leaf(ValueNode::Date(serial, code)) => write_date(f, *serial, code),
+      ExprTree::Leaf(ValueNode::Text(t)) if is_plain_text(t) => f.write_str(t),
+      _ => {
+        f.write_str("=")?;
+        write_expr(f, self)
+      }
+    }
+  }
+}
+
+/// Prints a tree as it would appear inside a formula, without the `=` and
+/// with text quoted, e.g. `"a"&A1`.
+pub struct Formula<'a>(pub &'a ExprTree);
+
+impl fmt::Display for Formula<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_expr(f, self.0)
+  }
+}
+
+impl fmt::Display for Range {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_coord(f, self.start)?;
+    f.write_str(":")?;
+    write_coord(f, self.end)
+  }
+}
+
+// Text that would be read as something else, like `12` or `=A1`, has to be
+// written as a formula.
+fn is_plain_text(text: &str) -> bool {
+  ExprTree::new(text) == ExprTree::Leaf(ValueNode::Text(text.to_string()))
+}
+
+// How tightly a node binds, children binding looser than their parent
+// allows get parentheses.
+fn precedence(tree: &ExprTree) -> u8 {
+  match tree {
+    ExprTree::Binary(b) => match b.op {
+      BinaryOp::Eq
+      | BinaryOp::Ne
+      | BinaryOp::Lt
+      | BinaryOp::Le
+      | BinaryOp::Gt
+      | BinaryOp::Ge => 1,
+      BinaryOp::Concat => 2,
+      BinaryOp::Sum | BinaryOp::Sub => 3,
+      BinaryOp::Mul | BinaryOp::Div => 4,
+      BinaryOp::Pow => 5,
+    },
+    ExprTree::Unary(u) => match u.op {
+      UnaryOp::Percent => 6,
+      UnaryOp::Neg | UnaryOp::Plus => 7,
+    },
+    _ => 8,
+  }
+}
+
+fn write_expr(f: &mut fmt::Formatter, tree: &ExprTree) -> fmt::Result {
+  match tree {
+    ExprTree::Empty => Ok(()),
+    ExprTree::Error(e) => f.write_str(e),
+    ExprTree::Leaf(value) => write_value(f, value),
+    ExprTree::Unary(u) => match u.op {
+      UnaryOp::Percent => {
+        write_child(f, &u.child, 6)?;
+        f.write_str("%")
+      }
+      UnaryOp::Neg | UnaryOp::Plus => {
+        f.write_str(if u.op == UnaryOp::Neg { "-" } else { "+" })?;
+        write_child(f, &u.child, 7)
+      }
+    },
+    ExprTree::Binary(b) => {
+      let (op, prec) = match b.op {
+        BinaryOp::Eq => ("=", 1),
+        BinaryOp::Ne => ("<>", 1),
+        BinaryOp::Lt => ("<", 1),
+        BinaryOp::Le => ("<=", 1),
+        BinaryOp::Gt => (">", 1),
+        BinaryOp::Ge => (">=", 1),
+        BinaryOp::Concat => ("&", 2),
+        BinaryOp::Sum => ("+", 3),
+        BinaryOp::Sub => ("-", 3),
+        BinaryOp::Mul => ("*", 4),
+        BinaryOp::Div => ("/", 4),
+        BinaryOp::Pow => ("^", 5),
+      };
+      // `^` is the only right associative operator.
+      let (left, right) = if b.op == BinaryOp::Pow {
+        (prec + 1, prec)
+      } else {
+        (prec, prec + 1)
+      };
+      write_child(f, &b.left, left)?;
+      f.write_str(op)?;
+      write_child(f, &b.right, right)
+    }
+    ExprTree::Func(func) => {
+      write!(f, "{}(", func.name)?;
+      for (i, arg) in func.args.iter().enumerate() {
+        if i > 0 {
+          f.write_str(",")?;
+        }
+        write_expr(f, arg)?;
+      }
+      f.write_str(")")
+    }
+  }
+}
+
+fn write_child(f: &mut fmt::Formatter, child: &ExprTree, min_precedence: u8) -> fmt::Result {
+  if precedence(child) < min_precedence {
+    f.write_str("(")?;
+    write_expr(f, child)?;
+    f.write_str(")")
+  } else {
+    write_expr(f, child)
+  }
+}
+
+fn write_value(f: &mut fmt::Formatter, value: &ValueNode) -> fmt::Result {
+  match value {
+    ValueNode::Num(n) => write!(f, "{}", n),
+    // Formulas have no date literals, the parser only makes them for cells.
+    ValueNode::Date(serial, _) => write!(f, "{}", serial),
+    ValueNode::Text(t) => write!(f, "\"{}\"", t.replace('"', "\"\"")),
+    ValueNode::Coord(row, col) => write_coord(f, (*row, *col)),
+    ValueNode::Range(range) => write!(f, "{}", range),
+    ValueNode::Spill(row, col) => {
+      write_coord(f, (*row, *col))?;
+      f.write_str("#")
+    }
+    ValueNode::Name(name) => f.write_str(name),
+  }
+}
+
+fn write_coord(f: &mut fmt::Formatter, (row, col): (usize, usize)) -> fmt::Result {
+  write!(f, "{}{}", col_to_letters(col), row + 1)
+}
+
+// 0 is `A`, 25 `Z` and 26 `AA`.
+fn col_to_letters(col: usize) -> String {
+  let mut letters = vec![];
+  let mut n = col + 1;
+  while n > 0 {
+    letters.push((b'A' + ((n - 1) % 26) as u8) as char);
+    n = (n - 1) / 26;
+  }
+  letters.iter().rev().collect()
+}
+
+// Writes a date as typed, `code` is the format the parser picked for it.
+fn write_date(f: &mut fmt::Formatter, serial: f64, code: &str) -> fmt::Result {
+  let mut time = serial;
+  if code.starts_with("yyyy") {
+    let day = serial.floor();
+    let (year, month, day_of_month) = date::ymd_from_serial(day);
+    write!(f, "{:04}-{:02}-{:02}", year, month, day_of_month)?;
+    if code == "yyyy-mm-dd" {
+      return Ok(());
+    }
+    f.write_str(" ")?;
+    time = serial - day;
+  }
+  let millis = (time * 86_400_000.).round() as i64;
+  write!(f, "{}:{:02}", millis / 3_600_000, millis / 60_000 % 60)?;
+  if code.ends_with("ss") {
+    write!(f, ":{:02}", millis / 1000 % 60)?;
+    let fraction = format!("{:03}", millis % 1000);
+    let fraction = fraction.trim_end_matches('0');
+    if !fraction.is_empty() {
+      write!(f, ".{}", fraction)?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn normalize(raw: &str) -> String {
+    ExprTree::new(raw).to_string()
+  }
+
+  #[test]
+  fn prints_literals_as_typed() {
+    assert_eq!(normalize("-12.5"), "-12.5");
+    assert_eq!(normalize("hello"), "hello");
+    assert_eq!(normalize("=5"), "5");
+    assert_eq!(normalize("=\"12\""), "=\"12\"");
+    assert_eq!(normalize("=\"=A1\""), "=\"=A1\"");
+    assert_eq!(normalize("2024-03-05"), "2024-03-05");
+    assert_eq!(normalize("2024-3-5 8:05"), "2024-03-05 8:05");
+    assert_eq!(normalize("26:00"), "26:00:00");
+    assert_eq!(normalize("7:05:09.25"), "7:05:09.25");
+  }
+
+  #[test]
+  fn prints_canonical_formulas() {
+    assert_eq!(
+      normalize("= (a1 + 2) * sum( B1:c3 , 1)"),
+      "=(A1+2)*SUM(B1:C3,1)"
+    );
+    assert_eq!(normalize("=1-(2-3)"), "=1-(2-3)");
+    assert_eq!(normalize("=(1-2)-3"), "=1-2-3");
+    assert_eq!(normalize("=(2^3)^2"), "=(2^3)^2");
+    assert_eq!(normalize("=2^(3^2)"), "=2^3^2");
+    assert_eq!(normalize("=-(2^2)"), "=-(2^2)");
+    assert_eq!(normalize("=(-2)^2"), "=-2^2");
+    assert_eq!(normalize("=-(5%)"), "=-(5%)");
+    assert_eq!(normalize("=(1&2)+3"), "=(1&2)+3");
+    assert_eq!(
+      normalize("=\"say \"\"hi\"\"\"&TaxRate"),
+      "=\"say \"\"hi\"\"\"&TaxRate"
+    );
+    assert_eq!(normalize("=true+now()"), "=1+NOW()");
+  }
+
+  #[test]
+  fn col_to_letters_smoketest() {
+    assert_eq!(col_to_letters(0), "A");
+    assert_eq!(col_to_letters(25), "Z");
+    assert_eq!(col_to_letters(26), "AA");
+    assert_eq!(col_to_letters(701), "ZZ");
+    assert_eq!(col_to_letters(702), "AAA");
+  }
+}
@@ -0,0 +1,80 @@
+//! Random numbers for `RAND()` and `RANDBETWEEN()`. They come from a seed
+//! rather than the browser so that tests are reproducible and everyone on a
+//! sheet sees the same numbers, see `Spreadsheet::set_seed`.
+
+use std::cell::Cell;
+
+/// SplitMix64, tiny and plenty random for a spreadsheet. Functions only get
+/// `&Spreadsheet`, hence the `Cell`.
+#[derive(Default)]
+pub struct Rng {
+  seed: u64,
+  state: Cell<u64>,
+}
+
+impl Rng {
+  pub fn new(seed: u64) -> Rng {
+    Rng {
+      seed,
+      state: Cell::new(seed),
+    }
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /// Restarts the sequence for the cell at `idx`. Every cell draws from its
+  /// own sequence, so its numbers don't depend on the order cells are
+  /// evaluated in.
+  pub fn start_cell(&self, idx: usize) {
+    self.state.set(mix(self.seed ^ mix(idx as u64)));
+  }
+
+  /// A number in `[0, 1)`.
+  pub fn next_f64(&self) -> f64 {
+    let state = self.state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+    self.state.set(state);
+    // The top 53 bits, as many as an `f64` holds.
+    (mix(state) >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+
+fn mix(mut z: u64) -> u64 {
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+  z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sequences_depend_on_seed_and_cell() {
+    let rng = Rng::new(42);
+    rng.start_cell(3);
+    let first: Vec<f64> = (0..3).map(|_| rng.next_f64()).collect();
+    rng.start_cell(4);
+    let other_cell = rng.next_f64();
+    rng.start_cell(3);
+    let again: Vec<f64> = (0..3).map(|_| rng.next_f64()).collect();
+    assert_eq!(first, again);
+    assert_ne!(first[0], first[1]);
+    assert_ne!(first[0], other_cell);
+
+    let other_seed = Rng::new(43);
+    other_seed.start_cell(3);
+    assert_ne!(first[0], other_seed.next_f64());
+  }
+
+  #[test]
+  fn numbers_are_in_unit_interval() {
+    let rng = Rng::new(0);
+    rng.start_cell(0);
+    let numbers: Vec<f64> = (0..10_000).map(|_| rng.next_f64()).collect();
+    assert!(numbers.iter().all(|n| (0. ..1.).contains(n)));
+    let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+    assert!((mean - 0.5).abs() < 0.02);
+  }
+}
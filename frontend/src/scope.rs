@@ -0,0 +1,112 @@
+//! Variables of `LET` and parameters of `LAMBDA`. Scoping is lexical: a
+//! lambda's body sees what was in scope where the lambda was made, not what's
+//! in scope where it's called.
+
+use super::expr::ExprResult;
+use std::cell::{Cell, RefCell};
+
+/// Lambdas calling lambdas deeper than this evaluate to `#NUM!` rather than
+/// overflow the stack, which is only 1 MiB in WASM.
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Uppercased names and their values, innermost last.
+pub type Bindings = Vec<(String, ExprResult)>;
+
+/// What's in scope while evaluating a formula, empty outside of `LET` and
+/// `LAMBDA`. Functions only get `&Spreadsheet`, hence the `RefCell`.
+#[derive(Default)]
+pub struct Scope {
+  bindings: RefCell<Bindings>,
+  depth: Cell<usize>,
+}
+
+impl Scope {
+  /// Value of the innermost variable called `name`, ignoring case.
+  pub fn get(&self, name: &str) -> Option<ExprResult> {
+    self
+      .bindings
+      .borrow()
+      .iter()
+      .rev()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.clone())
+  }
+
+  /// Everything in scope, for a lambda to capture.
+  pub fn bindings(&self) -> Bindings {
+    self.bindings.borrow().clone()
+  }
+
+  /// Evaluates `f` with `bindings` added to what's in scope.
+  pub fn with<T>(&self, bindings: Bindings, f: impl FnOnce() -> T) -> T {
+    let len = self.bindings.borrow().len();
+    self.bindings.borrow_mut().extend(bindings);
+    let res = f();
+    self.bindings.borrow_mut().truncate(len);
+    res
+  }
+
+  /// Evaluates `f` with nothing in scope, for the definition of a workbook
+  /// name.
+  pub fn outside<T>(&self, f: impl FnOnce() -> T) -> T {
+    let outer = self.bindings.take();
+    let res = f();
+    self.bindings.replace(outer);
+    res
+  }
+
+  /// Evaluates `f` with only `bindings` in scope, for the body of a lambda.
+  /// `None` when lambdas are nested deeper than `MAX_CALL_DEPTH`.
+  pub fn call<T>(&self, bindings: Bindings, f: impl FnOnce() -> T) -> Option<T> {
+    let depth = self.depth.get();
+    if depth >= MAX_CALL_DEPTH {
+      return None;
+    }
+    let outer = self.bindings.replace(bindings);
+    self.depth.set(depth + 1);
+    let res = f();
+    self.depth.set(depth);
+    self.bindings.replace(outer);
+    Some(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn num(n: f64) -> ExprResult {
+    ExprResult::Num(n)
+  }
+
+  #[test]
+  fn inner_bindings_shadow_outer_ones() {
+    let scope = Scope::default();
+    let res = scope.with(vec![("X".to_string(), num(1.))], || {
+      let inner = scope.with(vec![("X".to_string(), num(2.))], || scope.get("x"));
+      (inner, scope.get("X"))
+    });
+    assert_eq!(res, (Some(num(2.)), Some(num(1.))));
+    assert_eq!(scope.get("X"), None);
+  }
+
+  #[test]
+  fn calls_only_see_their_bindings() {
+    let scope = Scope::default();
+    let res = scope.with(vec![("X".to_string(), num(1.))], || {
+      scope.call(vec![("Y".to_string(), num(2.))], || {
+        (scope.get("X"), scope.get("Y"))
+      })
+    });
+    assert_eq!(res, Some((None, Some(num(2.)))));
+    assert!(scope.bindings().is_empty());
+  }
+
+  #[test]
+  fn calls_nest_up_to_the_limit() {
+    fn nest(scope: &Scope) -> usize {
+      scope.call(vec![], || nest(scope) + 1).unwrap_or(0)
+    }
+    assert_eq!(nest(&Scope::default()), MAX_CALL_DEPTH);
+  }
+}
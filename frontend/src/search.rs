@@ -0,0 +1,71 @@
+//! Find and replace, see `Spreadsheet::find` and `Spreadsheet::replace_all`.
+//! Empty cells never match, not even `.*`.
+
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// What to look for. `text` is matched literally unless `regex` is set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Query {
+  pub text: String,
+  /// Search what cells show, like `10%` for `0.1`, instead of what was
+  /// typed in them. Values can be found but not replaced.
+  pub in_values: bool,
+  pub match_case: bool,
+  /// Only match cells whose whole text matches.
+  pub whole_cell: bool,
+  /// `text` is a regular expression, replacements can refer to its groups
+  /// as `$1`.
+  pub regex: bool,
+}
+
+/// A `Query` ready to run.
+pub struct Matcher {
+  regex: Regex,
+  // Whether replacements expand `$1` and the like.
+  expand: bool,
+}
+
+impl Matcher {
+  pub fn new(query: &Query) -> Result<Matcher, String> {
+    if query.text.is_empty() {
+      return Err("nothing to search for".to_string());
+    }
+    let pattern = if query.regex {
+      query.text.clone()
+    } else {
+      regex::escape(&query.text)
+    };
+    let pattern = if query.whole_cell {
+      format!("^(?:{})$", pattern)
+    } else {
+      pattern
+    };
+    let regex = RegexBuilder::new(&pattern)
+      .case_insensitive(!query.match_case)
+      .build()
+      .map_err(|e| format!("invalid regular expression: {}", e))?;
+    Ok(Matcher {
+      regex,
+      expand: query.regex,
+    })
+  }
+
+  pub fn is_match(&self, text: &str) -> bool {
+    !text.is_empty() && self.regex.is_match(text)
+  }
+
+  /// `text` with every match replaced, `None` when nothing matched.
+  pub fn replace(&self, text: &str, replacement: &str) -> Option<String> {
+    if !self.is_match(text) {
+      return None;
+    }
+    let replaced = if self.expand {
+      self.regex.replace_all(text, replacement)
+    } else {
+      self.regex.replace_all(text, NoExpand(replacement))
+    };
+    Some(replaced.into_owned())
+  }
+}
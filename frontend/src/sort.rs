@@ -0,0 +1,81 @@
+//! Sorting rearranges the rows of a range, see `Spreadsheet::sort_range`.
+//! Like other spreadsheets, numbers come before text, which ignores case,
+//! and errors come last. Empty cells go after everything else whichever way
+//! the range is sorted.
+
+use super::expr::{ExprResult, Range};
+use super::Spreadsheet;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// A column to sort on, rows tying on it are sorted on the next key, and
+/// keep their order when they tie on every key.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+  pub col: usize,
+  pub ascending: bool,
+}
+
+/// The rows of `range` in sorted order.
+pub fn order(ss: &Spreadsheet, range: Range, keys: &[SortKey]) -> Vec<usize> {
+  let mut rows: Vec<usize> = (range.start.0..=range.end.0).collect();
+  rows.sort_by(|a, b| {
+    keys
+      .iter()
+      .map(|key| {
+        let (a, b) = (ss.get(*a, key.col).out(), ss.get(*b, key.col).out());
+        let ordering = compare(a, b);
+        if key.ascending || is_empty(a) || is_empty(b) {
+          ordering
+        } else {
+          ordering.reverse()
+        }
+      })
+      .find(|ordering| *ordering != Ordering::Equal)
+      .unwrap_or(Ordering::Equal)
+  });
+  rows
+}
+
+/// Where each cell of `range` goes when its rows are put in `order`, the
+/// row that ends up first coming first. Cells that stay are left out.
+pub fn moves(range: Range, order: &[usize]) -> HashMap<(usize, usize), (usize, usize)> {
+  let mut moves = HashMap::new();
+  for (i, from) in order.iter().enumerate() {
+    let to = range.start.0 + i;
+    if *from != to {
+      for col in range.start.1..=range.end.1 {
+        moves.insert((*from, col), (to, col));
+      }
+    }
+  }
+  moves
+}
+
+// Ascending order of two values, see the module documentation.
+fn compare(a: &ExprResult, b: &ExprResult) -> Ordering {
+  match (is_empty(a), is_empty(b)) {
+    (true, true) => return Ordering::Equal,
+    (true, false) => return Ordering::Greater,
+    (false, true) => return Ordering::Less,
+    (false, false) => (),
+  }
+  match (a, b) {
+    (ExprResult::Num(n), ExprResult::Num(m)) => n.partial_cmp(m).unwrap_or(Ordering::Equal),
+    (ExprResult::Text(t), ExprResult::Text(u)) => t.to_lowercase().cmp(&u.to_lowercase()),
+    _ => kind(a).cmp(&kind(b)),
+  }
+}
+
+fn kind(value: &ExprResult) -> u8 {
+  match value {
+    ExprResult::Num(_) => 0,
+    ExprResult::Text(_) => 1,
+    _ => 2,
+  }
+}
+
+fn is_empty(value: &ExprResult) -> bool {
+  matches!(value, ExprResult::Text(t) if t.is_empty())
+}
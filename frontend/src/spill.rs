@@ -0,0 +1,121 @@
+//! Formulas returning arrays spill them over the cells below and to their
+//! right, `=SEQUENCE(3)` in `A1` fills `A1:A3`. Spilled cells keep an empty
+//! input and are linked to the formula like to a precedent, so that their
+//! own dependents are evaluated after it.
+
+use super::expr::{ExprResult, Range};
+use super::Spreadsheet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Where the array returned by a formula goes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Spill {
+  /// Number of rows and columns of the array.
+  pub size: (usize, usize),
+  /// The array would cover some input or another spill, or go past the
+  /// edges of the sheet. The formula shows `#SPILL!` instead.
+  pub blocked: bool,
+}
+
+/// Cells the array of the cell at `(row, col)` covers, itself included,
+/// whether or not it's blocked.
+pub fn area((row, col): (usize, usize), spill: Spill) -> Range {
+  let (rows, cols) = spill.size;
+  Range::new((row, col), (row + rows - 1, col + cols - 1))
+}
+
+// Stores what the cell at `idx` evaluated to, spilling arrays. Returns the
+// cells that started or stopped being spilled over, their dependents have to
+// be evaluated again.
+pub(super) fn place(ss: &mut Spreadsheet, idx: usize, out: ExprResult) -> Vec<usize> {
+  let anchor = (idx / ss.width, idx % ss.width);
+  let before = spilled_over(ss, idx);
+  let (out, spill) = match out.size() {
+    (1, 1) => (out.top_left(), None),
+    size => {
+      let spill = Spill {
+        size,
+        blocked: is_blocked(ss, anchor, size),
+      };
+      if spill.blocked {
+        (ExprResult::Error("#SPILL!".to_string()), Some(spill))
+      } else {
+        let rows = out.into_rows();
+        for (r, row) in rows.iter().enumerate() {
+          for (c, value) in row.iter().enumerate() {
+            if (r, c) == (0, 0) {
+              continue;
+            }
+            let cell_idx = ss.get_index(anchor.0 + r, anchor.1 + c);
+            ss.cells[idx].inbound.insert(cell_idx);
+            let cell = &mut ss.cells[cell_idx];
+            cell.spilled_from = Some(anchor);
+            cell.outbound.insert(idx);
+            cell.set_out(value.clone());
+          }
+        }
+        (rows[0][0].clone(), Some(spill))
+      }
+    }
+  };
+  ss.cells[idx].spill = spill;
+  ss.cells[idx].set_out(out);
+
+  let after = spilled_over(ss, idx);
+  for &cell_idx in before.difference(&after) {
+    ss.cells[idx].inbound.remove(&cell_idx);
+    let cell = &mut ss.cells[cell_idx];
+    cell.spilled_from = None;
+    cell.outbound.remove(&idx);
+    cell.set_out(ExprResult::Text("".to_string()));
+  }
+  before.symmetric_difference(&after).copied().collect()
+}
+
+/// Formulas whose array covers or would cover the cell at `idx`, other than
+/// the cell itself. They have to be evaluated again when its input changes.
+pub(super) fn covering(ss: &Spreadsheet, idx: usize) -> Vec<usize> {
+  let (row, col) = (idx / ss.width, idx % ss.width);
+  (0..ss.cells.len())
+    .filter(|anchor_idx| *anchor_idx != idx)
+    .filter(|anchor_idx| {
+      let anchor = (anchor_idx / ss.width, anchor_idx % ss.width);
+      ss.cells[*anchor_idx].spill.is_some_and(|spill| {
+        let area = area(anchor, spill);
+        (area.start.0..=area.end.0).contains(&row) && (area.start.1..=area.end.1).contains(&col)
+      })
+    })
+    .collect()
+}
+
+// Cells currently spilled over from the cell at `idx`.
+fn spilled_over(ss: &Spreadsheet, idx: usize) -> HashSet<usize> {
+  let anchor = (idx / ss.width, idx % ss.width);
+  match ss.cells[idx].spill {
+    Some(spill) if !spill.blocked => area(anchor, spill)
+      .coords(ss)
+      .filter(|(row, col)| ss.get(*row, *col).spilled_from == Some(anchor))
+      .map(|(row, col)| ss.get_index(row, col))
+      .collect(),
+    _ => HashSet::new(),
+  }
+}
+
+fn is_blocked(ss: &Spreadsheet, anchor: (usize, usize), size: (usize, usize)) -> bool {
+  let area = area(
+    anchor,
+    Spill {
+      size,
+      blocked: false,
+    },
+  );
+  if area.end.0 >= ss.height || area.end.1 >= ss.width {
+    return true;
+  }
+  area.coords(ss).any(|(row, col)| {
+    let cell = ss.get(row, col);
+    let is_other = (row, col) != anchor;
+    is_other && (!cell.raw.is_empty() || cell.spilled_from.is_some_and(|from| from != anchor))
+  })
+}
@@ -0,0 +1,1401 @@
+//! Engine tests that don't need a browser, they go through the native API
+//! instead of the `JsValue` one. See `wasm.rs` for the wasm bindings.
+
+use spreadsheet::expr::{ExprResult, Range};
+use spreadsheet::filter::{Condition, Filter};
+use spreadsheet::search::Query;
+use spreadsheet::sort::SortKey;
+use spreadsheet::{Edit, Iteration, Spreadsheet};
+
+#[test]
+fn set_raw_returns_all_updated() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "10").unwrap();
+  ss.set_raw(0, 1, "=A1*2").unwrap();
+  ss.set_raw(1, 1, "=B1*2").unwrap();
+
+  let mut updated = ss.set_raw(0, 0, "1").unwrap();
+  updated.sort();
+  assert_eq!(
+    updated,
+    vec![ss.get_index(0, 0), ss.get_index(0, 1), ss.get_index(1, 1)]
+  );
+  assert_eq!(*ss.get(1, 1).out(), ExprResult::Num(4.));
+}
+
+#[test]
+fn set_raw_rejects_out_of_bounds() {
+  let mut ss = Spreadsheet::new();
+  assert!(ss.set_raw(0, ss.width(), "1").is_err());
+  assert!(ss.set_raw(ss.height(), 0, "1").is_err());
+}
+
+#[test]
+fn set_raw_keeps_comments() {
+  let mut ss = Spreadsheet::new();
+  ss.set_comment_count(0, 0, 2).unwrap();
+  ss.set_raw(0, 0, "edited").unwrap();
+  assert_eq!(ss.get(0, 0).comments(), 2);
+  assert_eq!(ss.get(0, 1).comments(), 0);
+}
+
+#[test]
+fn formats_apply_to_dependents_and_survive_edits() {
+  let mut ss = Spreadsheet::new();
+  ss.set_format_code(0, 1, "$#,##0.00").unwrap();
+  ss.set_raw(0, 0, "1234.5").unwrap();
+  ss.set_raw(0, 1, "=A1*2").unwrap();
+  assert_eq!(ss.get(0, 1).display(), "$2,469.00");
+
+  // Re-evaluations and new contents keep the format.
+  ss.set_raw(0, 0, "0.5").unwrap();
+  assert_eq!(ss.get(0, 1).display(), "$1.00");
+  assert_eq!(ss.get(0, 0).display(), "0.5");
+  ss.set_raw(0, 1, "=A1/4").unwrap();
+  assert_eq!(ss.get(0, 1).display(), "$0.13");
+
+  assert!(ss.set_format_code(0, 0, "\"oops").is_err());
+  assert_eq!(ss.get(0, 0).format(), "");
+}
+
+#[test]
+fn dates_are_numbers_shown_as_dates() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "2024-03-15").unwrap();
+  ss.set_raw(0, 1, "=A1+17").unwrap();
+  assert_eq!(*ss.get(0, 0).out(), ExprResult::Num(45366.));
+  assert_eq!(ss.get(0, 0).display(), "2024-03-15");
+  ss.set_format_code(0, 1, "yyyy-mm-dd").unwrap();
+  assert_eq!(ss.get(0, 1).display(), "2024-04-01");
+
+  // An explicit format wins over the one picked from the input.
+  ss.set_format_code(1, 0, "0.00").unwrap();
+  ss.set_raw(1, 0, "12:00").unwrap();
+  assert_eq!(ss.get(1, 0).display(), "0.50");
+}
+
+#[test]
+fn date_functions() {
+  let mut ss = Spreadsheet::new();
+  // 2024-03-15 18:00
+  ss.set_now(Some(45366.75));
+  ss.set_raw(0, 0, "2024-01-31").unwrap();
+  let cases = [
+    ("=TODAY()", 45366.),
+    ("=NOW()", 45366.75),
+    ("=YEAR(A1)", 2024.),
+    ("=MONTH(A1)", 1.),
+    ("=DAY(A1)", 31.),
+    ("=DATE(124,2,30)", 45352.),
+    ("=EDATE(A1,1)", 45351.),
+    ("=EOMONTH(A1,-2)", 45260.),
+    ("=NETWORKDAYS(A1,TODAY())", 33.),
+    ("=NETWORKDAYS(TODAY(),A1)", -33.),
+    ("=DATEDIF(A1,TODAY(),\"D\")", 44.),
+    ("=HOUR(NOW())", 18.),
+    ("=TIME(25,0,0)", 1. / 24.),
+  ];
+  for (raw, expected) in cases.iter() {
+    ss.set_raw(1, 0, raw).unwrap();
+    assert_eq!(*ss.get(1, 0).out(), ExprResult::Num(*expected), "{}", raw);
+  }
+
+  ss.set_raw(1, 0, "=YEAR(-1)").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), ExprResult::Error("#NUM!".to_string()));
+  ss.set_raw(1, 0, "=NOPE(1)").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), ExprResult::Error("#NAME?".to_string()));
+  ss.set_raw(1, 0, "=YEAR()").unwrap();
+  assert_eq!(
+    *ss.get(1, 0).out(),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+}
+
+fn eval(ss: &mut Spreadsheet, raw: &str) -> ExprResult {
+  ss.set_raw(9, 9, raw).unwrap();
+  ss.get(9, 9).out().clone()
+}
+
+fn text(t: &str) -> ExprResult {
+  ExprResult::Text(t.to_string())
+}
+
+#[test]
+fn concatenation_coerces_numbers() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "Total: ").unwrap();
+  ss.set_raw(0, 1, "12.5").unwrap();
+  assert_eq!(eval(&mut ss, "=A1&B1*2&\"€\""), text("Total: 25€"));
+  assert_eq!(eval(&mut ss, "=1+2&3"), text("33"));
+  assert_eq!(
+    eval(&mut ss, "=\"a\"&YEAR(-1)"),
+    ExprResult::Error("#NUM!".to_string())
+  );
+}
+
+#[test]
+fn text_functions() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "  Crème   brûlée ").unwrap();
+  ss.set_raw(1, 0, "👍🏽ok").unwrap();
+  let cases = [
+    ("=LEN(B2)", ExprResult::Num(0.)),
+    ("=LEN(A2)", ExprResult::Num(3.)),
+    ("=LEFT(A2)", text("👍🏽")),
+    ("=RIGHT(A2,5)", text("👍🏽ok")),
+    ("=MID(\"spreadsheet\",7,5)", text("sheet")),
+    ("=UPPER(TRIM(A1))", text("CRÈME BRÛLÉE")),
+    ("=LOWER(\"ÀB\")", text("àb")),
+    ("=SUBSTITUTE(\"a-b-c\",\"-\",\"+\")", text("a+b+c")),
+    ("=SUBSTITUTE(\"a-b-c\",\"-\",\"+\",2)", text("a-b+c")),
+    ("=FIND(\"b\",\"abcb\",3)", ExprResult::Num(4.)),
+    (
+      "=FIND(\"B\",\"abc\")",
+      ExprResult::Error("#VALUE!".to_string()),
+    ),
+    ("=SEARCH(\"B?\",\"abcb\")", ExprResult::Num(2.)),
+    ("=SEARCH(\"b*b\",\"xxBcb\")", ExprResult::Num(3.)),
+    ("=TEXT(1234.5,\"#,##0.00\")", text("1,234.50")),
+    ("=TEXT(\"2024-03-15\",\"dd/mm/yyyy\")", text("15/03/2024")),
+    ("=VALUE(\"$1,234.5\")", ExprResult::Num(1234.5)),
+    ("=VALUE(\"15%\")", ExprResult::Num(0.15)),
+    ("=VALUE(\"abc\")", ExprResult::Error("#VALUE!".to_string())),
+    ("=SPLIT(\"a,b\",\",\")", text("a")),
+  ];
+  for (raw, expected) in cases.iter() {
+    assert_eq!(eval(&mut ss, raw), *expected, "{}", raw);
+  }
+}
+
+#[test]
+fn functions_take_ranges() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "a").unwrap();
+  ss.set_raw(0, 1, "1").unwrap();
+  ss.set_raw(1, 1, "b").unwrap();
+  ss.set_raw(4, 0, "=CONCAT(A1:B2)").unwrap();
+  ss.set_raw(4, 1, "=TEXTJOIN(\", \",1,B2:A1)").unwrap();
+  ss.set_raw(4, 2, "=TEXTJOIN(\"-\",0,A1:B2)").unwrap();
+  assert_eq!(*ss.get(4, 0).out(), text("a1b"));
+  assert_eq!(*ss.get(4, 1).out(), text("a, 1, b"));
+  assert_eq!(*ss.get(4, 2).out(), text("a-1--b"));
+
+  // Every cell in the range is a dependency.
+  ss.set_raw(1, 0, "c").unwrap();
+  assert_eq!(*ss.get(4, 0).out(), text("a1cb"));
+  assert_eq!(*ss.get(4, 1).out(), text("a, 1, c, b"));
+
+  // On its own a range is an array, it spills.
+  assert_eq!(eval(&mut ss, "=A1:B2"), text("a"));
+  assert_eq!(*ss.get(10, 10).out(), text("b"));
+}
+
+#[test]
+fn references_past_the_sheet_are_ref_errors() {
+  let mut ss = Spreadsheet::new();
+  let ref_error = ExprResult::Error("#REF!".to_string());
+  assert_eq!(eval(&mut ss, "=A101"), ref_error);
+  assert_eq!(eval(&mut ss, "=AA1"), ref_error);
+  assert_eq!(eval(&mut ss, "=SUM(A200:A300)"), ref_error);
+  assert_eq!(eval(&mut ss, "=VLOOKUP(1, A1:B200, 2)"), ref_error);
+  assert_eq!(eval(&mut ss, "=A1:A200"), ref_error);
+  assert!(ss.get(10, 9).spilled_from().is_none());
+  assert_eq!(eval(&mut ss, "=A500#"), ref_error);
+  assert_eq!(eval(&mut ss, "=ZZ1#"), ref_error);
+  let updated = ss.set_raw(26, 25, "1").unwrap();
+  assert!(!updated.contains(&ss.get_index(9, 9)));
+}
+
+fn na() -> ExprResult {
+  ExprResult::Error("#N/A".to_string())
+}
+
+fn price_list(ss: &mut Spreadsheet) {
+  let rows = [
+    ("Apple", "0.5", "100"),
+    ("Banana", "0.25", "500"),
+    ("Cherry", "4", "1000"),
+  ];
+  for (row, (name, price, min)) in rows.iter().enumerate() {
+    ss.set_raw(row, 0, name).unwrap();
+    ss.set_raw(row, 1, price).unwrap();
+    ss.set_raw(row, 2, min).unwrap();
+  }
+}
+
+#[test]
+fn lookups() {
+  let mut ss = Spreadsheet::new();
+  price_list(&mut ss);
+  let cases = [
+    ("=VLOOKUP(\"banana\",A1:C3,2,FALSE)", ExprResult::Num(0.25)),
+    ("=VLOOKUP(\"ch*\",A1:C3,2,FALSE)", ExprResult::Num(4.)),
+    ("=VLOOKUP(\"Kiwi\",A1:C3,2,FALSE)", na()),
+    (
+      "=VLOOKUP(\"Apple\",A1:C3,4,FALSE)",
+      ExprResult::Error("#REF!".to_string()),
+    ),
+    // Approximate matches take the largest key below, in sorted keys.
+    ("=VLOOKUP(\"Blueberry\",A1:C3,3)", ExprResult::Num(500.)),
+    ("=VLOOKUP(\"Aardvark\",A1:C3,3)", na()),
+    ("=HLOOKUP(0.3,B1:B3,2)", na()),
+    ("=HLOOKUP(0.25,A2:C3,2,FALSE)", ExprResult::Num(4.)),
+    ("=INDEX(A1:C3,2,3)", ExprResult::Num(500.)),
+    ("=INDEX(A3:C3,2)", ExprResult::Num(4.)),
+    ("=INDEX(A1:C3,4,1)", ExprResult::Error("#REF!".to_string())),
+    ("=MATCH(\"cherry\",A1:A3,0)", ExprResult::Num(3.)),
+    ("=MATCH(750,C1:C3)", ExprResult::Num(2.)),
+    ("=MATCH(50,C1:C3)", na()),
+    (
+      "=INDEX(B1:B3,MATCH(\"B?nana\",A1:A3,0))",
+      ExprResult::Num(0.25),
+    ),
+    ("=XLOOKUP(\"Cherry\",A1:A3,B1:B3)", ExprResult::Num(4.)),
+    ("=XLOOKUP(\"Kiwi\",A1:A3,B1:B3,\"none\")", text("none")),
+    ("=XLOOKUP(750,C1:C3,A1:A3,\"\",-1)", text("Banana")),
+    ("=XLOOKUP(750,C1:C3,A1:A3,\"\",1)", text("Cherry")),
+    (
+      "=XLOOKUP(\"*a*\",A1:A3,B1:B3,\"\",2,-1)",
+      ExprResult::Num(0.25),
+    ),
+    (
+      "=XLOOKUP(1,A1:A3,B1:B2)",
+      ExprResult::Error("#VALUE!".to_string()),
+    ),
+  ];
+  for (raw, expected) in cases.iter() {
+    assert_eq!(eval(&mut ss, raw), *expected, "{}", raw);
+  }
+}
+
+#[test]
+fn lookups_follow_table_edits() {
+  let mut ss = Spreadsheet::new();
+  price_list(&mut ss);
+  ss.set_raw(5, 0, "=VLOOKUP(\"Kiwi\",A1:C3,2,FALSE)")
+    .unwrap();
+  assert_eq!(*ss.get(5, 0).out(), na());
+  ss.set_raw(2, 0, "Kiwi").unwrap();
+  assert_eq!(*ss.get(5, 0).out(), ExprResult::Num(4.));
+  ss.set_raw(2, 1, "3.5").unwrap();
+  assert_eq!(*ss.get(5, 0).out(), ExprResult::Num(3.5));
+}
+
+#[test]
+fn precedence_matches_other_spreadsheets() {
+  // From the lowest to the highest precedence: comparisons, &, + and -,
+  // * and /, ^, % and signs. See "Operator precedence" in Excel's documentation.
+  let mut ss = Spreadsheet::new();
+  let cases = [
+    ("=1+2*3", ExprResult::Num(7.)),
+    ("=(1+2)*3", ExprResult::Num(9.)),
+    ("=1-2-3", ExprResult::Num(-4.)),
+    ("=8/4/2", ExprResult::Num(1.)),
+    ("=2*3^2", ExprResult::Num(18.)),
+    ("=-2^2", ExprResult::Num(4.)),
+    ("=0-2^2", ExprResult::Num(-4.)),
+    ("=2^-1", ExprResult::Num(0.5)),
+    ("=2*-3", ExprResult::Num(-6.)),
+    ("=--3", ExprResult::Num(3.)),
+    ("=+\"a\"", text("a")),
+    ("=50%*2", ExprResult::Num(1.)),
+    ("=-3%", ExprResult::Num(-0.03)),
+    ("=10%%", ExprResult::Num(0.001)),
+    ("=4^50%", ExprResult::Num(2.)),
+    ("=1+2&3*2", text("36")),
+    ("=1+2=3", ExprResult::Num(1.)),
+    ("=\"a\"&1=\"A1\"", ExprResult::Num(1.)),
+    // Unlike Excel, which evaluates 2^3^2 as (2^3)^2, exponents chain from
+    // the right like they do in maths.
+    ("=2^3^2", ExprResult::Num(512.)),
+    ("=0^0", ExprResult::Error("#NUM!".to_string())),
+    ("=(-8)^(1/3)", ExprResult::Error("#NUM!".to_string())),
+  ];
+  for (raw, expected) in cases.iter() {
+    assert_eq!(eval(&mut ss, raw), *expected, "{}", raw);
+  }
+}
+
+#[test]
+fn comparisons_give_ones_and_zeros() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "2").unwrap();
+  let cases = [
+    ("=A1>1", 1.),
+    ("=A1>=2", 1.),
+    ("=A1<2", 0.),
+    ("=A1<=1", 0.),
+    ("=A1=2", 1.),
+    ("=A1<>2", 0.),
+    // Text ignores case and sorts after every number.
+    ("=\"abc\"=\"ABC\"", 1.),
+    ("=\"b\">\"A\"", 1.),
+    ("=\"1\">5", 1.),
+    // Empty cells are 0 next to numbers and "" next to text.
+    ("=B1=0", 1.),
+    ("=B1=\"\"", 1.),
+    ("=1<2<3", 1.),
+    ("=3>2>1", 0.),
+  ];
+  for (raw, expected) in cases.iter() {
+    assert_eq!(eval(&mut ss, raw), ExprResult::Num(*expected), "{}", raw);
+  }
+  assert_eq!(
+    eval(&mut ss, "=A200>1"),
+    ExprResult::Error("#REF!".to_string())
+  );
+}
+
+#[test]
+fn whitespace_between_tokens() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "2").unwrap();
+  let cases = [
+    ("= 1 + 2", ExprResult::Num(3.)),
+    ("=\t( A1 * 3 ) ^ 2 / 4 ", ExprResult::Num(9.)),
+    ("= - A1", ExprResult::Num(-2.)),
+    ("=LEFT( \"abc\" , 2 )", text("ab")),
+    ("= \"a\" & \" b\"", text("a b")),
+  ];
+  for (raw, expected) in cases.iter() {
+    assert_eq!(eval(&mut ss, raw), *expected, "{}", raw);
+  }
+  // Not inside a reference or between a function and its arguments.
+  assert!(matches!(eval(&mut ss, "=A 1"), ExprResult::Error(_)));
+  assert!(matches!(
+    eval(&mut ss, "=LEFT (\"a\")"),
+    ExprResult::Error(_)
+  ));
+}
+
+#[test]
+fn names_refer_to_cells_ranges_and_constants() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "100").unwrap();
+  ss.set_raw(1, 0, "200").unwrap();
+  ss.set_raw(2, 0, "=Price*(1+TaxRate)").unwrap();
+  ss.set_raw(2, 1, "=TEXTJOIN(\"+\",1,Amounts)").unwrap();
+  ss.set_raw(2, 2, "=Price").unwrap();
+  assert_eq!(*ss.get(2, 2).out(), ExprResult::Error("#NAME?".to_string()));
+
+  ss.define_name("Price", "A1").unwrap();
+  ss.define_name("taxrate", "0.2").unwrap();
+  let mut updated = ss.define_name("Amounts", "=A1:A2").unwrap();
+  updated.sort();
+  assert_eq!(updated, vec![ss.get_index(2, 1)]);
+  assert_eq!(*ss.get(2, 0).out(), ExprResult::Num(120.));
+  assert_eq!(*ss.get(2, 1).out(), text("100+200"));
+
+  // Named cells are dependencies like any other reference.
+  ss.set_raw(0, 0, "50").unwrap();
+  assert_eq!(*ss.get(2, 0).out(), ExprResult::Num(60.));
+  assert_eq!(*ss.get(2, 1).out(), text("50+200"));
+
+  // Redefining re-links the formulas using the name.
+  ss.define_name("Price", "A2").unwrap();
+  assert_eq!(*ss.get(2, 0).out(), ExprResult::Num(240.));
+  ss.set_raw(0, 0, "1").unwrap();
+  assert_eq!(*ss.get(2, 0).out(), ExprResult::Num(240.));
+
+  ss.delete_name("PRICE");
+  assert_eq!(*ss.get(2, 2).out(), ExprResult::Error("#NAME?".to_string()));
+
+  assert!(ss.define_name("B2", "1").is_err());
+  assert!(ss.define_name("Total", "=A1+1").is_err());
+  assert!(ss.define_name("Far", "A1:A1000").is_err());
+}
+
+#[test]
+fn audits_precedents_dependents_and_sub_expressions() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "5").unwrap();
+  ss.set_raw(0, 1, "=A1*2").unwrap();
+  ss.set_raw(1, 0, "=B1+LEN(CONCAT(A1:A1))+Rate").unwrap();
+  ss.define_name("Rate", "C5").unwrap();
+  ss.set_raw(2, 2, "=A2&\"!\"").unwrap();
+
+  let precedents = ss.precedent_links(1, 0).unwrap();
+  assert_eq!(precedents.direct, vec![(0, 0), (0, 1), (4, 2)]);
+  assert_eq!(precedents.all, vec![(0, 0), (0, 1), (4, 2)]);
+  let precedents = ss.precedent_links(2, 2).unwrap();
+  assert_eq!(precedents.direct, vec![(1, 0)]);
+  assert_eq!(precedents.all, vec![(0, 0), (0, 1), (1, 0), (4, 2)]);
+  let dependents = ss.dependent_links(0, 0).unwrap();
+  assert_eq!(dependents.direct, vec![(0, 1), (1, 0)]);
+  assert_eq!(dependents.all, vec![(0, 1), (1, 0), (2, 2)]);
+  assert!(ss.dependent_links(100, 0).is_err());
+
+  ss.set_raw(0, 2, "=CONCAT(A1:B1)&A1").unwrap();
+  let explanation = ss.explanation(0, 2).unwrap();
+  assert_eq!(explanation.expr, "CONCAT(A1:B1)&A1");
+  assert_eq!(explanation.value, Some(text("5105")));
+  let concat = &explanation.children[0];
+  assert_eq!(
+    (concat.expr.as_str(), &concat.value),
+    ("CONCAT(A1:B1)", &Some(text("510")))
+  );
+  let range = &concat.children[0];
+  assert_eq!((range.expr.as_str(), &range.value), ("A1:B1", &None));
+  let a1 = &explanation.children[1];
+  assert_eq!(a1.value, Some(ExprResult::Num(5.)));
+  assert!(a1.children.is_empty());
+}
+
+fn cycle() -> ExprResult {
+  ExprResult::Error("#CYCLE!".to_string())
+}
+
+#[test]
+fn cycles_are_marked_until_broken() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "=B1").unwrap();
+  ss.set_raw(0, 2, "=A1*2").unwrap();
+  // The input is kept, every cell of the loop shows it.
+  let mut updated = ss.set_raw(0, 1, "=A1+1").unwrap();
+  updated.sort();
+  assert_eq!(updated, vec![0, 1, 2]);
+  assert_eq!(ss.get(0, 1).raw(), "=A1+1");
+  assert_eq!(*ss.get(0, 0).out(), cycle());
+  assert_eq!(*ss.get(0, 1).out(), cycle());
+  assert_eq!(ss.get(0, 0).cycle(), &[(0, 0), (0, 1), (0, 0)]);
+  assert_eq!(ss.get(0, 1).cycle(), &[(0, 1), (0, 0), (0, 1)]);
+  // Cells reading from the loop aren't part of it, they get its error.
+  assert_eq!(*ss.get(0, 2).out(), cycle());
+  assert!(ss.get(0, 2).cycle().is_empty());
+
+  ss.set_raw(0, 1, "5").unwrap();
+  assert_eq!(*ss.get(0, 0).out(), ExprResult::Num(5.));
+  assert_eq!(*ss.get(0, 2).out(), ExprResult::Num(10.));
+  assert!(ss.get(0, 0).cycle().is_empty());
+  assert!(ss.get(0, 1).cycle().is_empty());
+}
+
+#[test]
+fn self_references_and_names_can_form_cycles() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(1, 0, "=A2+1").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), cycle());
+  assert_eq!(ss.get(1, 0).cycle(), &[(1, 0), (1, 0)]);
+  ss.set_raw(1, 0, "1").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), ExprResult::Num(1.));
+  assert!(ss.get(1, 0).cycle().is_empty());
+
+  ss.set_raw(2, 0, "=Total").unwrap();
+  ss.define_name("Total", "A3").unwrap();
+  assert_eq!(*ss.get(2, 0).out(), cycle());
+  ss.define_name("Total", "A2").unwrap();
+  assert_eq!(*ss.get(2, 0).out(), ExprResult::Num(1.));
+}
+
+#[test]
+fn long_chains_recalculate() {
+  let mut ss = Spreadsheet::new();
+  for row in 1..100 {
+    ss.set_raw(row, 0, &format!("=A{}+1", row)).unwrap();
+  }
+  ss.set_raw(0, 0, "1").unwrap();
+  assert_eq!(*ss.get(99, 0).out(), ExprResult::Num(100.));
+  // Diamonds evaluate each cell once, after everything it reads from.
+  ss.set_raw(0, 1, "=A1+A50").unwrap();
+  ss.set_raw(0, 0, "2").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), ExprResult::Num(53.));
+}
+
+#[test]
+fn iterative_calculation_solves_intentional_cycles() {
+  let mut ss = Spreadsheet::new();
+  // Interest on the average balance: B1 depends on C1 which depends on B1.
+  ss.set_raw(0, 0, "1000").unwrap();
+  ss.set_raw(0, 1, "=(A1+C1)/2*0.1").unwrap();
+  ss.set_raw(0, 2, "=A1+B1").unwrap();
+  ss.set_raw(0, 3, "=C1*2").unwrap();
+  assert_eq!(*ss.get(0, 2).out(), cycle());
+  assert_eq!(ss.iteration(), None);
+
+  let updated = ss.set_iteration(Some(Iteration::default())).unwrap();
+  assert!(updated.contains(&ss.get_index(0, 3)));
+  let closed_form = 1000. + 100. / 0.95;
+  let ExprResult::Num(balance) = *ss.get(0, 2).out() else {
+    panic!("expected a number, got {:?}", ss.get(0, 2).out());
+  };
+  assert!((balance - closed_form).abs() < 0.001);
+  assert!(matches!(*ss.get(0, 3).out(), ExprResult::Num(n) if (n - 2. * balance).abs() < 1e-9));
+  // Still a cycle, only evaluated differently.
+  assert_eq!(ss.get(0, 1).cycle(), &[(0, 1), (0, 2), (0, 1)]);
+
+  // Edits re-run the iteration.
+  ss.set_raw(0, 0, "0").unwrap();
+  assert_eq!(*ss.get(0, 2).out(), ExprResult::Num(0.));
+
+  ss.set_iteration(None).unwrap();
+  assert_eq!(*ss.get(0, 2).out(), cycle());
+  assert_eq!(*ss.get(0, 3).out(), cycle());
+}
+
+#[test]
+fn iterative_calculation_reports_divergence() {
+  let mut ss = Spreadsheet::new();
+  ss.set_iteration(Some(Iteration {
+    max_iterations: 50,
+    tolerance: 0.001,
+  }))
+  .unwrap();
+  ss.set_raw(0, 0, "=A1*2+1").unwrap();
+  assert_eq!(*ss.get(0, 0).out(), ExprResult::Error("#NUM!".to_string()));
+  // Cycles that don't need many passes still converge.
+  ss.set_raw(0, 0, "=A1/2+1").unwrap();
+  assert!(matches!(*ss.get(0, 0).out(), ExprResult::Num(n) if (n - 2.).abs() < 0.001));
+  // Errors that stop changing are a stable result.
+  ss.set_raw(0, 0, "=A1+\"x\"").unwrap();
+  assert!(matches!(ss.get(0, 0).out(), ExprResult::Error(_)));
+
+  assert!(ss
+    .set_iteration(Some(Iteration {
+      max_iterations: 0,
+      tolerance: 0.001,
+    }))
+    .is_err());
+  assert!(ss
+    .set_iteration(Some(Iteration {
+      max_iterations: 10,
+      tolerance: -1.,
+    }))
+    .is_err());
+}
+
+#[test]
+fn random_numbers_come_from_the_seed() {
+  let mut ss = Spreadsheet::new();
+  ss.set_seed(7);
+  ss.set_raw(0, 0, "=RAND()").unwrap();
+  ss.set_raw(0, 1, "=RANDBETWEEN(1,6)").unwrap();
+  ss.set_raw(0, 2, "=RANDBETWEEN(3,2)").unwrap();
+  let rand = ss.get(0, 0).out().clone();
+  let die = ss.get(0, 1).out().clone();
+  assert!(matches!(rand, ExprResult::Num(n) if (0. ..1.).contains(&n)));
+  assert!(matches!(die, ExprResult::Num(n) if n.fract() == 0. && (1. ..=6.).contains(&n)));
+  assert_eq!(*ss.get(0, 2).out(), ExprResult::Error("#NUM!".to_string()));
+
+  // Same seed, same numbers, whatever order the cells were entered in.
+  let mut other = Spreadsheet::new();
+  other.set_seed(7);
+  other.set_raw(0, 1, "=RANDBETWEEN(1,6)").unwrap();
+  other.set_raw(0, 0, "=RAND()").unwrap();
+  assert_eq!(*other.get(0, 0).out(), rand);
+  assert_eq!(*other.get(0, 1).out(), die);
+
+  other.set_seed(8);
+  other.recalculate_volatile();
+  assert_ne!(*other.get(0, 0).out(), rand);
+}
+
+#[test]
+fn volatile_cells_recalculate_on_demand() {
+  let mut ss = Spreadsheet::new();
+  ss.set_now(Some(45366.5));
+  ss.set_raw(0, 0, "=NOW()").unwrap();
+  ss.set_raw(0, 1, "=A1+1").unwrap();
+  ss.set_raw(0, 2, "=LEN(\"abc\")").unwrap();
+  ss.set_raw(0, 3, "=TODAY()").unwrap();
+
+  ss.set_now(Some(45367.25));
+  assert_eq!(*ss.get(0, 1).out(), ExprResult::Num(45367.5));
+  let mut updated = ss.recalculate_volatile();
+  updated.sort();
+  assert_eq!(updated, vec![0, 1, 3]);
+  assert_eq!(*ss.get(0, 1).out(), ExprResult::Num(45368.25));
+  assert_eq!(*ss.get(0, 3).out(), ExprResult::Num(45367.));
+
+  // Once the formula no longer calls NOW() it stops being volatile.
+  ss.set_raw(0, 0, "1").unwrap();
+  assert_eq!(ss.recalculate_volatile(), vec![3]);
+
+  let mut updated = ss.recalculate_all();
+  updated.sort();
+  assert_eq!(updated, vec![1, 2, 3]);
+}
+
+fn num(n: f64) -> ExprResult {
+  ExprResult::Num(n)
+}
+
+fn column(ss: &Spreadsheet, col: usize, rows: std::ops::Range<usize>) -> Vec<ExprResult> {
+  rows.map(|row| ss.get(row, col).out().clone()).collect()
+}
+
+#[test]
+fn arrays_spill_and_resize() {
+  let mut ss = Spreadsheet::new();
+  for (row, raw) in ["1", "2", "3"].iter().enumerate() {
+    ss.set_raw(row, 0, raw).unwrap();
+  }
+  ss.set_raw(0, 3, "=C2+1").unwrap();
+  let mut updated = ss.set_raw(0, 2, "=A1:A3*2").unwrap();
+  updated.sort();
+  assert_eq!(updated, vec![2, 3, 28, 54]);
+  assert_eq!(column(&ss, 2, 0..3), vec![num(2.), num(4.), num(6.)]);
+  assert_eq!(ss.get(1, 2).spilled_from(), Some((0, 2)));
+  assert_eq!(ss.get(1, 2).raw(), "");
+  assert_eq!(*ss.get(0, 3).out(), num(5.));
+
+  // Precedents of the array update the spilled cells and their dependents.
+  ss.set_raw(1, 0, "10").unwrap();
+  assert_eq!(*ss.get(1, 2).out(), num(20.));
+  assert_eq!(*ss.get(0, 3).out(), num(21.));
+
+  // Shrinking clears the cells left behind, growing reaches their dependents.
+  ss.set_raw(1, 3, "=C4").unwrap();
+  ss.set_raw(0, 2, "=SEQUENCE(2)").unwrap();
+  assert_eq!(column(&ss, 2, 0..3), vec![num(1.), num(2.), text("")]);
+  assert_eq!(ss.get(2, 2).spilled_from(), None);
+  ss.set_raw(0, 2, "=SEQUENCE(4,1,10)").unwrap();
+  assert_eq!(*ss.get(1, 3).out(), num(13.));
+  ss.set_raw(0, 2, "5").unwrap();
+  assert_eq!(*ss.get(1, 3).out(), text(""));
+  assert!(ss.get(0, 2).spill().is_none());
+}
+
+#[test]
+fn blocked_arrays_show_spill_errors() {
+  let mut ss = Spreadsheet::new();
+  let spill = || ExprResult::Error("#SPILL!".to_string());
+  ss.set_raw(0, 0, "=SEQUENCE(3)").unwrap();
+  ss.set_raw(1, 0, "typed").unwrap();
+  assert_eq!(column(&ss, 0, 0..3), vec![spill(), text("typed"), text("")]);
+  assert!(ss.get(0, 0).spill().unwrap().blocked);
+  // Clearing what's in the way lets it spill again.
+  ss.set_raw(1, 0, "").unwrap();
+  assert_eq!(column(&ss, 0, 0..3), vec![num(1.), num(2.), num(3.)]);
+
+  // Arrays don't overlap, the first one keeps its cells.
+  ss.set_raw(2, 1, "=SEQUENCE(1,2)").unwrap();
+  ss.set_raw(2, 0, "=SEQUENCE(1,2)").unwrap();
+  assert_eq!(*ss.get(2, 0).out(), spill());
+  assert_eq!(*ss.get(2, 2).out(), num(2.));
+
+  // Nor do they go past the edges of the sheet.
+  let last = ss.height() - 1;
+  ss.set_raw(last, 0, "=SEQUENCE(2)").unwrap();
+  assert_eq!(*ss.get(last, 0).out(), spill());
+}
+
+#[test]
+fn spill_references_follow_the_array() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "=SEQUENCE(3)").unwrap();
+  ss.set_raw(0, 1, "=TEXTJOIN(\",\",1,A1#)").unwrap();
+  ss.set_raw(0, 2, "=A1#*10").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), text("1,2,3"));
+  assert_eq!(column(&ss, 2, 0..3), vec![num(10.), num(20.), num(30.)]);
+
+  ss.set_raw(0, 0, "=SEQUENCE(4)").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), text("1,2,3,4"));
+  assert_eq!(*ss.get(3, 2).out(), num(40.));
+
+  ss.set_raw(0, 0, "1").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), ExprResult::Error("#REF!".to_string()));
+  assert_eq!(*ss.get(1, 2).out(), text(""));
+}
+
+#[test]
+fn arrays_combine_value_by_value() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 5, "=SEQUENCE(2)+SEQUENCE(1,3,10,10)")
+    .unwrap();
+  assert_eq!(*ss.get(1, 7).out(), num(32.));
+  ss.set_raw(5, 5, "=SEQUENCE(2)*SEQUENCE(3)").unwrap();
+  assert_eq!(column(&ss, 5, 5..8), vec![num(1.), num(4.), na()]);
+  ss.set_raw(10, 5, "=-SEQUENCE(1,2)&\"!\"").unwrap();
+  assert_eq!(*ss.get(10, 6).out(), text("-2!"));
+}
+
+#[test]
+fn array_functions() {
+  let mut ss = Spreadsheet::new();
+  let rows = [
+    ("Cherry", "4"),
+    ("apple", "0.5"),
+    ("Banana", "0.25"),
+    ("Apple", "0.75"),
+  ];
+  for (row, (name, price)) in rows.iter().enumerate() {
+    ss.set_raw(row, 0, name).unwrap();
+    ss.set_raw(row, 1, price).unwrap();
+  }
+  let names = |ss: &Spreadsheet| column(ss, 9, 9..13);
+
+  eval(&mut ss, "=SORT(A1:B4)");
+  assert_eq!(
+    names(&ss),
+    vec![text("apple"), text("Apple"), text("Banana"), text("Cherry")]
+  );
+  eval(&mut ss, "=SORT(A1:B4,2,-1)");
+  assert_eq!(*ss.get(9, 10).out(), num(4.));
+  assert_eq!(*ss.get(12, 10).out(), num(0.25));
+  assert_eq!(
+    eval(&mut ss, "=SORT(A1:B4,3)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+
+  // Rows with a non-zero number are kept.
+  eval(&mut ss, "=FILTER(A1:A4,B1:B4-0.5)");
+  assert_eq!(
+    names(&ss),
+    vec![text("Cherry"), text("Banana"), text("Apple"), text("")]
+  );
+  eval(&mut ss, "=FILTER(A1:A4,B1:B4>=0.75)");
+  assert_eq!(
+    names(&ss),
+    vec![text("Cherry"), text("Apple"), text(""), text("")]
+  );
+  assert_eq!(
+    eval(&mut ss, "=FILTER(A1:A4,B1:B4*0)"),
+    ExprResult::Error("#CALC!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=FILTER(A1:A4,B1:B4*0,\"none\")"),
+    text("none")
+  );
+  assert_eq!(
+    eval(&mut ss, "=FILTER(A1:A4,B1:B2)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+
+  eval(&mut ss, "=UNIQUE(A1:A4)");
+  assert_eq!(
+    names(&ss),
+    vec![text("Cherry"), text("apple"), text("Banana"), text("")]
+  );
+  eval(&mut ss, "=UNIQUE(A1:A4,0,1)");
+  assert_eq!(
+    names(&ss),
+    vec![text("Cherry"), text("Banana"), text(""), text("")]
+  );
+
+  eval(&mut ss, "=SPLIT(\"a,b,c\",\",\")");
+  assert_eq!(*ss.get(9, 11).out(), text("c"));
+  eval(&mut ss, "=XLOOKUP(\"banana\",A1:A4,A1:B4)");
+  assert_eq!(*ss.get(9, 10).out(), num(0.25));
+  assert_eq!(
+    eval(&mut ss, "=SEQUENCE(0)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=SEQUENCE(100000,100000)"),
+    ExprResult::Error("#NUM!".to_string())
+  );
+}
+
+#[test]
+fn filter_keeps_rows_meeting_a_condition() {
+  let mut ss = Spreadsheet::new();
+  for row in 0..10 {
+    ss.set_raw(row, 0, &(row + 1).to_string()).unwrap();
+  }
+  ss.set_raw(0, 2, "=FILTER(A1:A10, A1:A10>5)").unwrap();
+  assert_eq!(
+    column(&ss, 2, 0..6),
+    vec![num(6.), num(7.), num(8.), num(9.), num(10.), text("")]
+  );
+  ss.set_raw(0, 3, "=FILTER(A1:A10, A1:A10<>A1:A10, \"none\")").unwrap();
+  assert_eq!(*ss.get(0, 3).out(), text("none"));
+}
+
+#[test]
+fn let_binds_local_variables() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "4").unwrap();
+  assert_eq!(eval(&mut ss, "=LET(x, A1*2, y, x+1, x*y)"), num(72.));
+  // Variables shadow names, and inner ones outer ones.
+  ss.define_name("x", "100").unwrap();
+  assert_eq!(eval(&mut ss, "=LET(x, 1, x+LET(x, 2, x))+x"), num(103.));
+  assert_eq!(
+    eval(&mut ss, "=LET(y, 1, y)+y"),
+    ExprResult::Error("#NAME?".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=LET(A2, 1, A2)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=LET(x, 1)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+
+  // Ranges bound to variables still spill.
+  ss.set_raw(1, 0, "5").unwrap();
+  eval(&mut ss, "=LET(r, A1:A2, r*10)");
+  assert_eq!(column(&ss, 9, 9..11), vec![num(40.), num(50.)]);
+}
+
+#[test]
+fn lambdas_define_functions() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "3").unwrap();
+  ss.set_raw(0, 1, "=Hypot(A1, 4)").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), ExprResult::Error("#NAME?".to_string()));
+
+  ss.define_name("Hypot", "=LAMBDA(a, b, (a^2+b^2)^0.5)")
+    .unwrap();
+  assert_eq!(*ss.get(0, 1).out(), num(5.));
+  ss.set_raw(0, 0, "0").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), num(4.));
+
+  // Lambdas can't be shown, and take as many arguments as they have
+  // parameters.
+  assert_eq!(
+    eval(&mut ss, "=Hypot"),
+    ExprResult::Error("#CALC!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=Hypot(1)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=LAMBDA(x, x, 1)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+  assert_eq!(eval(&mut ss, "=LET(f, LAMBDA(x, x*2), f(f(3)))"), num(12.));
+}
+
+#[test]
+fn lambdas_see_the_scope_they_were_made_in() {
+  let mut ss = Spreadsheet::new();
+  assert_eq!(
+    eval(&mut ss, "=LET(n, 10, add, LAMBDA(x, x+n), n, 1, add(n))"),
+    num(11.)
+  );
+  // The body doesn't see the variables of the formula calling it.
+  ss.define_name("AddN", "=LAMBDA(x, x+n)").unwrap();
+  assert_eq!(
+    eval(&mut ss, "=LET(n, 1, AddN(1))"),
+    ExprResult::Error("#NAME?".to_string())
+  );
+}
+
+#[test]
+fn lambdas_follow_the_names_and_cells_they_use() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "0.2").unwrap();
+  ss.define_name("Rate", "A1").unwrap();
+  ss.define_name("WithTax", "=LAMBDA(x, x*(1+Rate))").unwrap();
+  ss.set_raw(1, 0, "=WithTax(100)").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), num(120.));
+
+  ss.set_raw(0, 0, "0.5").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), num(150.));
+  ss.define_name("Rate", "0.25").unwrap();
+  assert_eq!(*ss.get(1, 0).out(), num(125.));
+
+  // Volatile lambdas make their callers volatile.
+  ss.define_name("Dice", "=LAMBDA(RANDBETWEEN(1, 6))")
+    .unwrap();
+  ss.set_raw(2, 0, "=Dice()").unwrap();
+  let rolls: Vec<ExprResult> = (0..20)
+    .map(|seed| {
+      ss.set_seed(seed);
+      ss.recalculate_volatile();
+      ss.get(2, 0).out().clone()
+    })
+    .collect();
+  assert!(rolls.iter().any(|roll| *roll != rolls[0]));
+}
+
+#[test]
+fn recursion_is_limited() {
+  let mut ss = Spreadsheet::new();
+  ss.define_name("Forever", "=LAMBDA(x, Forever(x+1))")
+    .unwrap();
+  ss.define_name("Ping", "=LAMBDA(x, Pong(x))").unwrap();
+  ss.define_name("Pong", "=LAMBDA(x, Ping(x))").unwrap();
+  assert_eq!(
+    eval(&mut ss, "=Forever(1)"),
+    ExprResult::Error("#NUM!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=Ping(1)"),
+    ExprResult::Error("#NUM!".to_string())
+  );
+  assert_eq!(eval(&mut ss, "=LET(f, LAMBDA(x, x+1), f(1))"), num(2.));
+}
+
+#[test]
+fn if_evaluates_one_branch() {
+  let mut ss = Spreadsheet::new();
+  assert_eq!(eval(&mut ss, "=IF(1>2, \"yes\", \"no\")"), text("no"));
+  assert_eq!(eval(&mut ss, "=IF(TRUE, 5)"), num(5.));
+  assert_eq!(eval(&mut ss, "=IF(FALSE, 1)"), num(0.));
+  // The branch not taken isn't evaluated, even if it's an error.
+  assert_eq!(eval(&mut ss, "=IF(1, 2, A200)"), num(2.));
+  assert_eq!(
+    eval(&mut ss, "=IF(A200, 1, 2)"),
+    ExprResult::Error("#REF!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=IF(\"maybe\", 1, 2)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+
+  // Which lets recursive lambdas stop.
+  ss.define_name("Fact", "=LAMBDA(n, IF(n<=1, 1, n*Fact(n-1)))")
+    .unwrap();
+  assert_eq!(eval(&mut ss, "=Fact(5)"), num(120.));
+  ss.set_raw(0, 0, "4").unwrap();
+  ss.set_raw(0, 1, "=Fact(A1)").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), num(24.));
+  ss.set_raw(0, 0, "3").unwrap();
+  assert_eq!(*ss.get(0, 1).out(), num(6.));
+}
+
+fn sales(ss: &mut Spreadsheet) {
+  let rows = [
+    ("East", "Apples", "10", "2024-01-15"),
+    ("West", "apricots", "25", "2024-02-01"),
+    ("East", "Bananas", "5", "2024-02-10"),
+    ("North", "Apples", "text", "2024-03-01"),
+    ("East", "", "40", "2024-03-20"),
+  ];
+  for (row, (region, fruit, amount, date)) in rows.iter().enumerate() {
+    ss.set_raw(row, 0, region).unwrap();
+    ss.set_raw(row, 1, fruit).unwrap();
+    ss.set_raw(row, 2, amount).unwrap();
+    ss.set_raw(row, 3, date).unwrap();
+  }
+}
+
+#[test]
+fn conditional_aggregates() {
+  let mut ss = Spreadsheet::new();
+  sales(&mut ss);
+  assert_eq!(eval(&mut ss, "=SUMIF(C1:C5, \">10\")"), num(65.));
+  assert_eq!(eval(&mut ss, "=SUMIF(A1:A5, \"east\", C1:C5)"), num(55.));
+  assert_eq!(eval(&mut ss, "=SUMIF(B1:B5, \"ap*\", C1:C5)"), num(35.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(B1:B5, \"<>apples\")"), num(3.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(B1:B5, \"\")"), num(1.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(B1:B5, \"*\")"), num(4.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(B1:B5, \"<c\")"), num(4.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(C1:C5, 10)"), num(1.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(C1:C5, \"<=25\")"), num(3.));
+  assert_eq!(eval(&mut ss, "=COUNTIF(D1:D5, \">=2024-02-10\")"), num(3.));
+  assert_eq!(
+    eval(&mut ss, "=AVERAGEIF(A1:A5, \"East\", C1:C5)"),
+    num(55. / 3.)
+  );
+  assert_eq!(
+    eval(&mut ss, "=AVERAGEIF(A1:A5, \"South\", C1:C5)"),
+    ExprResult::Error("#DIV/0!".to_string())
+  );
+
+  // Criteria can come from cells.
+  ss.set_raw(0, 5, "East").unwrap();
+  ss.set_raw(1, 5, ">=10").unwrap();
+  assert_eq!(
+    eval(&mut ss, "=SUMIFS(C1:C5, A1:A5, F1, C1:C5, F2)"),
+    num(50.)
+  );
+  assert_eq!(
+    eval(&mut ss, "=COUNTIFS(A1:A5, F1, B1:B5, \"?a*\")"),
+    num(1.)
+  );
+  assert_eq!(
+    eval(&mut ss, "=AVERAGEIFS(C1:C5, A1:A5, \"<>East\")"),
+    num(25.)
+  );
+  assert_eq!(
+    eval(&mut ss, "=SUMIFS(C1:C5, A1:A4, \"East\")"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+  assert_eq!(
+    eval(&mut ss, "=COUNTIFS(A1:A5, \"East\", C1:C5)"),
+    ExprResult::Error("#VALUE!".to_string())
+  );
+}
+
+#[test]
+fn conditional_aggregates_follow_every_range() {
+  let mut ss = Spreadsheet::new();
+  sales(&mut ss);
+  ss.set_raw(0, 5, "East").unwrap();
+  // The sum range grows to the size of the compared range, `C1:C5`.
+  ss.set_raw(9, 0, "=SUMIF(A1:A5, F1, C1)").unwrap();
+  assert_eq!(*ss.get(9, 0).out(), num(55.));
+
+  ss.set_raw(4, 2, "100").unwrap();
+  assert_eq!(*ss.get(9, 0).out(), num(115.));
+  ss.set_raw(3, 0, "East").unwrap();
+  assert_eq!(*ss.get(9, 0).out(), num(115.));
+  ss.set_raw(3, 2, "1").unwrap();
+  assert_eq!(*ss.get(9, 0).out(), num(116.));
+  ss.set_raw(0, 5, "West").unwrap();
+  assert_eq!(*ss.get(9, 0).out(), num(25.));
+  assert!(ss.precedent_links(9, 0).unwrap().all.contains(&(4, 2)));
+}
+
+fn key(col: usize, ascending: bool) -> SortKey {
+  SortKey { col, ascending }
+}
+
+#[test]
+fn sorting_uses_every_key() {
+  let mut ss = Spreadsheet::new();
+  sales(&mut ss);
+  ss.set_raw(5, 0, "east").unwrap();
+  ss.set_raw(6, 0, "=SQRT(-1)").unwrap();
+  let range = Range::new((0, 0), (6, 3));
+  assert!(ss.sort_range(range, &[key(4, true)]).is_err());
+
+  ss.sort_range(range, &[key(0, true), key(2, false)])
+    .unwrap();
+  let regions: Vec<String> = (0..7).map(|row| ss.get(row, 0).raw().to_string()).collect();
+  assert_eq!(
+    regions,
+    vec!["East", "East", "East", "east", "North", "West", "=SQRT(-1)"]
+  );
+  // Text comes after numbers, empty cells last either way.
+  assert_eq!(
+    column(&ss, 2, 0..7),
+    vec![
+      num(40.),
+      num(10.),
+      num(5.),
+      text(""),
+      text("text"),
+      num(25.),
+      text("")
+    ]
+  );
+
+  ss.sort_range(range, &[key(1, false)]).unwrap();
+  assert_eq!(
+    column(&ss, 1, 0..7),
+    vec![
+      text("Bananas"),
+      text("apricots"),
+      text("Apples"),
+      text("Apples"),
+      text(""),
+      text(""),
+      text("")
+    ]
+  );
+}
+
+#[test]
+fn sorting_moves_references_along() {
+  let mut ss = Spreadsheet::new();
+  for (row, n) in ["3", "1", "2"].iter().enumerate() {
+    ss.set_raw(row, 0, n).unwrap();
+    ss.set_raw(row, 1, &format!("=A{}*10", row + 1)).unwrap();
+  }
+  ss.set_raw(0, 2, "=B1").unwrap();
+  ss.set_raw(1, 2, "=SUM(A1:A3)").unwrap();
+  ss.set_raw(2, 2, "=SUM(A1:B1)").unwrap();
+  ss.define_name("Top", "A1").unwrap();
+  ss.set_raw(3, 2, "=Top").unwrap();
+  ss.set_format_code(0, 0, "0.0").unwrap();
+  ss.set_comment_count(0, 1, 1).unwrap();
+
+  let range = Range::new((0, 0), (2, 1));
+  assert!(ss.move_rows(range, &[0, 0, 1]).is_err());
+  assert!(ss.move_rows(range, &[1, 2, 3]).is_err());
+  let (edits, _) = ss.sort_range(range, &[key(0, true)]).unwrap();
+  // What the backend needs to sort its copy.
+  let edits: Vec<(usize, usize, &str)> = edits
+    .iter()
+    .map(|edit| (edit.row, edit.col, edit.raw.as_str()))
+    .collect();
+  assert_eq!(
+    edits,
+    vec![
+      (0, 0, "1"),
+      (0, 2, "=B3"),
+      (1, 0, "2"),
+      (2, 0, "3"),
+      (2, 2, "=SUM(A3:B3)")
+    ]
+  );
+
+  assert_eq!(column(&ss, 0, 0..3), vec![num(1.), num(2.), num(3.)]);
+  assert_eq!(ss.get(2, 0).display(), "3.0");
+  assert_eq!(ss.get(2, 1).comments(), 1);
+  assert_eq!(ss.get(0, 1).comments(), 0);
+  // Formulas that moved keep reading from their row.
+  assert_eq!(ss.get(0, 1).raw(), "=A1*10");
+  assert_eq!(column(&ss, 1, 0..3), vec![num(10.), num(20.), num(30.)]);
+  // References to moved cells follow them, ranges over the sorted rows stay.
+  assert_eq!(ss.get(0, 2).raw(), "=B3");
+  assert_eq!(ss.get(1, 2).raw(), "=SUM(A1:A3)");
+  assert_eq!(ss.get(2, 2).raw(), "=SUM(A3:B3)");
+  assert_eq!(
+    column(&ss, 2, 0..4),
+    vec![num(30.), num(6.), num(33.), num(3.)]
+  );
+
+  ss.set_raw(2, 0, "4").unwrap();
+  assert_eq!(
+    column(&ss, 2, 0..4),
+    vec![num(40.), num(7.), num(44.), num(4.)]
+  );
+}
+
+#[test]
+fn filter_views_hide_rows_without_changing_them() {
+  let mut ss = Spreadsheet::new();
+  sales(&mut ss);
+  let condition = |col: usize, criterion: &str| Condition {
+    col,
+    criterion: criterion.to_string(),
+  };
+  let filter = Filter {
+    range: Range::new((0, 0), (4, 3)),
+    conditions: vec![condition(0, "east"), condition(2, ">=10")],
+  };
+  assert!(ss
+    .set_filter_view(Some(Filter {
+      conditions: vec![condition(5, "x")],
+      ..filter.clone()
+    }))
+    .is_err());
+  assert!(ss.filtered_rows().is_empty());
+
+  ss.set_filter_view(Some(filter.clone())).unwrap();
+  assert_eq!(ss.filter_view(), Some(&filter));
+  // The first row holds the headers.
+  assert_eq!(ss.filtered_rows(), vec![1, 2, 3]);
+  ss.set_raw(4, 2, "1").unwrap();
+  assert_eq!(ss.filtered_rows(), vec![1, 2, 3, 4]);
+  assert_eq!(*ss.get(4, 2).out(), num(1.));
+
+  ss.set_filter_view(None).unwrap();
+  assert!(ss.filtered_rows().is_empty());
+}
+
+fn query(text: &str) -> Query {
+  Query {
+    text: text.to_string(),
+    ..Query::default()
+  }
+}
+
+#[test]
+fn find_searches_input_or_values() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "SKU-100").unwrap();
+  ss.set_raw(1, 0, "sku-200").unwrap();
+  ss.set_raw(2, 0, "0.5").unwrap();
+  ss.set_format_code(2, 0, "0%").unwrap();
+  ss.set_raw(0, 1, "=A1&\"x\"").unwrap();
+
+  assert_eq!(ss.find_cells(&query("sku")).unwrap(), vec![(0, 0), (1, 0)]);
+  let match_case = Query {
+    match_case: true,
+    ..query("sku")
+  };
+  assert_eq!(ss.find_cells(&match_case).unwrap(), vec![(1, 0)]);
+  let whole_cell = Query {
+    whole_cell: true,
+    ..query("sku")
+  };
+  assert!(ss.find_cells(&whole_cell).unwrap().is_empty());
+  let regex = Query {
+    regex: true,
+    ..query(r"^sku-\d00$")
+  };
+  assert_eq!(ss.find_cells(&regex).unwrap(), vec![(0, 0), (1, 0)]);
+  // Formulas are searched as typed, unless looking at what cells show.
+  assert_eq!(ss.find_cells(&query("A1")).unwrap(), vec![(0, 1)]);
+  let in_values = Query {
+    in_values: true,
+    ..query("50%")
+  };
+  assert_eq!(ss.find_cells(&in_values).unwrap(), vec![(2, 0)]);
+  let in_values = Query {
+    in_values: true,
+    ..query("sku-100x")
+  };
+  assert_eq!(ss.find_cells(&in_values).unwrap(), vec![(0, 1)]);
+
+  assert!(ss.find_cells(&query("")).is_err());
+  let invalid = Query {
+    regex: true,
+    ..query("(")
+  };
+  assert!(ss.find_cells(&invalid).is_err());
+}
+
+#[test]
+fn replace_all_can_be_undone() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "SKU-100").unwrap();
+  ss.set_raw(1, 0, "SKU-200").unwrap();
+  ss.set_raw(0, 1, "=COUNTIF(A1:A2, \"SKU-*\")").unwrap();
+
+  let (previous, _) = ss.replace_all(&query("sku-"), "PRD-").unwrap();
+  assert_eq!(
+    previous,
+    vec![
+      Edit {
+        row: 0,
+        col: 0,
+        raw: "SKU-100".to_string()
+      },
+      Edit {
+        row: 0,
+        col: 1,
+        raw: "=COUNTIF(A1:A2, \"SKU-*\")".to_string()
+      },
+      Edit {
+        row: 1,
+        col: 0,
+        raw: "SKU-200".to_string()
+      },
+    ]
+  );
+  assert_eq!(ss.get(1, 0).raw(), "PRD-200");
+  assert_eq!(ss.get(0, 1).raw(), "=COUNTIF(A1:A2, \"PRD-*\")");
+  assert_eq!(*ss.get(0, 1).out(), num(2.));
+
+  let regex = Query {
+    regex: true,
+    whole_cell: true,
+    ..query(r"PRD-(\d)00")
+  };
+  ss.replace_all(&regex, "$1").unwrap();
+  assert_eq!(*ss.get(0, 0).out(), num(1.));
+  assert_eq!(*ss.get(0, 1).out(), num(0.));
+
+  let values = Query {
+    in_values: true,
+    ..query("1")
+  };
+  assert!(ss.replace_all(&values, "x").is_err());
+
+  ss.set_raws(&previous).unwrap();
+  assert_eq!(ss.get(0, 0).raw(), "SKU-100");
+  assert_eq!(*ss.get(0, 1).out(), num(2.));
+}
+
+// Raw contents of `A1:A{rows}` after filling them from `A1:A{sources.len()}`.
+fn filled_down(sources: &[&str], rows: usize) -> Vec<String> {
+  let mut ss = Spreadsheet::new();
+  for (row, raw) in sources.iter().enumerate() {
+    ss.set_raw(row, 0, raw).unwrap();
+  }
+  let source = Range::new((0, 0), (sources.len() - 1, 0));
+  ss.fill_range(source, Range::new((0, 0), (rows - 1, 0)))
+    .unwrap();
+  (0..rows)
+    .map(|row| ss.get(row, 0).raw().to_string())
+    .collect()
+}
+
+#[test]
+fn fill_extends_series() {
+  assert_eq!(filled_down(&["1", "2"], 4), vec!["1", "2", "3", "4"]);
+  assert_eq!(filled_down(&["5", "10", "15"], 5)[3..], ["20", "25"]);
+  assert_eq!(filled_down(&["0.1", "0.2"], 4)[2..], ["0.3", "0.4"]);
+  // Uneven steps follow the trend.
+  assert_eq!(filled_down(&["1", "2", "4", "5"], 6)[4..], ["6.5", "7.9"]);
+  // A lone number is copied.
+  assert_eq!(filled_down(&["7"], 3), vec!["7", "7", "7"]);
+
+  assert_eq!(
+    filled_down(&["2024-01-30"], 3)[1..],
+    ["2024-01-31", "2024-02-01"]
+  );
+  // Shorter months end the series on their last day.
+  assert_eq!(
+    filled_down(&["2023-10-31", "2023-12-31"], 4)[2..],
+    ["2024-02-29", "2024-04-30"]
+  );
+  assert_eq!(
+    filled_down(&["2024-01-31", "2024-03-31"], 4)[2..],
+    ["2024-05-31", "2024-07-31"]
+  );
+  assert_eq!(
+    filled_down(&["2023-02-28", "2024-02-28"], 3)[2],
+    "2025-02-28"
+  );
+  assert_eq!(filled_down(&["9:30"], 2)[1], "10:30");
+
+  assert_eq!(
+    filled_down(&["Friday"], 4)[1..],
+    ["Saturday", "Sunday", "Monday"]
+  );
+  assert_eq!(filled_down(&["JAN", "MAR"], 4)[2..], ["MAY", "JUL"]);
+  assert_eq!(filled_down(&["nov"], 3)[1..], ["dec", "jan"]);
+  assert_eq!(filled_down(&["Item 1"], 3)[1..], ["Item 2", "Item 3"]);
+  assert_eq!(filled_down(&["Q08", "Q10"], 3)[2], "Q12");
+
+  // Anything else repeats.
+  assert_eq!(filled_down(&["a", "1"], 5), vec!["a", "1", "a", "1", "a"]);
+  assert_eq!(filled_down(&["Item 1", "Item 1x"], 3)[2], "Item 1");
+}
+
+#[test]
+fn fill_moves_formula_references() {
+  let mut ss = Spreadsheet::new();
+  ss.set_raw(0, 0, "1").unwrap();
+  ss.set_raw(1, 0, "2").unwrap();
+  ss.set_raw(0, 1, "=A1*10").unwrap();
+  ss.set_raw(0, 2, "=SUM(A1:B1)").unwrap();
+
+  // Down, the target containing the source.
+  let (edits, _) = ss
+    .fill_range(Range::new((0, 1), (0, 2)), Range::new((0, 1), (1, 2)))
+    .unwrap();
+  assert_eq!(
+    edits,
+    vec![
+      Edit {
+        row: 1,
+        col: 1,
+        raw: "=A2*10".to_string()
+      },
+      Edit {
+        row: 1,
+        col: 2,
+        raw: "=SUM(A2:B2)".to_string()
+      },
+    ]
+  );
+  assert_eq!(*ss.get(1, 2).out(), num(22.));
+
+  // Right, next to the source.
+  ss.fill_range(Range::new((0, 0), (1, 0)), Range::new((0, 3), (1, 3)))
+    .unwrap_err();
+  ss.fill_range(Range::new((0, 2), (1, 2)), Range::new((0, 3), (1, 4)))
+    .unwrap();
+  assert_eq!(ss.get(0, 3).raw(), "=SUM(B1:C1)");
+  assert_eq!(ss.get(1, 4).raw(), "=SUM(C2:D2)");
+
+  // Up, references can't go past the top of the sheet.
+  ss.set_raw(5, 0, "=A5").unwrap();
+  ss.fill_range(Range::new((5, 0), (5, 0)), Range::new((3, 0), (4, 0)))
+    .unwrap();
+  assert_eq!(ss.get(3, 0).raw(), "=A3");
+  assert!(ss
+    .fill_range(Range::new((3, 0), (3, 0)), Range::new((0, 0), (2, 0)))
+    .is_err());
+  assert_eq!(ss.get(2, 0).raw(), "");
+
+  // Nor past the bottom or the right.
+  let (bottom, right) = (ss.height() - 1, ss.width() - 1);
+  ss.set_raw(bottom - 1, 1, &format!("=B{}", bottom + 1))
+    .unwrap();
+  assert!(ss
+    .fill_range(
+      Range::new((bottom - 1, 1), (bottom - 1, 1)),
+      Range::new((bottom, 1), (bottom, 1))
+    )
+    .is_err());
+  assert_eq!(ss.get(bottom, 1).raw(), "");
+  ss.set_raw(6, right - 1, "=Z1").unwrap();
+  assert!(ss
+    .fill_range(
+      Range::new((6, right - 1), (6, right - 1)),
+      Range::new((6, right), (6, right))
+    )
+    .is_err());
+  assert_eq!(ss.get(6, right).raw(), "");
+}
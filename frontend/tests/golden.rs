@@ -0,0 +1,175 @@
+//! Numeric functions against Excel, mostly with the examples of its
+//! documentation. Tolerances are half a unit of the last digit Excel's
+//! documentation shows, 0 when the result is exact, except for `XIRR` which
+//! Excel only computes to within 0.000001%.
+
+// The examples of `ROUNDUP` and `ROUNDDOWN` round pi.
+#![allow(clippy::approx_constant)]
+
+use spreadsheet::expr::ExprResult;
+use spreadsheet::Spreadsheet;
+
+// Data of the examples, column by column from `A1`.
+const DATA: &[&[&str]] = &[
+  // A: STDEV and VAR.
+  &[
+    "1345", "1301", "1368", "1322", "1310", "1370", "1318", "1350", "1303", "1299",
+  ],
+  // B and C: CORREL.
+  &["3", "2", "4", "5", "6"],
+  &["9", "7", "12", "15", "17"],
+  // D: RANK.
+  &["7", "3.5", "3.5", "1", "2"],
+  // E: IRR.
+  &["-70000", "12000", "15000", "18000", "21000", "26000"],
+  // F and G: XNPV and XIRR.
+  &["-10000", "2750", "4250", "3250", "2750"],
+  &[
+    "2008-01-01",
+    "2008-03-01",
+    "2008-10-30",
+    "2009-02-15",
+    "2009-04-01",
+  ],
+  // H: QUARTILE.
+  &["1", "2", "4", "7", "8", "9", "10", "12"],
+  // I: PERCENTILE.
+  &["1", "3", "2", "4"],
+  // J: text and empty cells, skipped by aggregates.
+  &["5", "text", "", "10"],
+];
+
+/// `(formula, Excel's result, tolerance)`.
+const GOLDEN: &[(&str, f64, f64)] = &[
+  ("=SUM(3, 2)", 5., 0.),
+  ("=SUM(J1:J4, 1)", 16., 0.),
+  ("=AVERAGE(10, 7, 9, 27, 2)", 11., 0.),
+  ("=AVERAGE(J1:J4)", 7.5, 0.),
+  ("=COUNT(D1:D5, \"x\", 2)", 6., 0.),
+  ("=COUNT(J1:J4)", 2., 0.),
+  ("=MIN(10, 7, 9, 27, 2)", 2., 0.),
+  ("=MAX(10, 7, 9, 27, 2)", 27., 0.),
+  ("=MAX(J2:J3)", 0., 0.),
+  ("=MEDIAN(1, 2, 3, 4, 5)", 3., 0.),
+  ("=MEDIAN(1, 2, 3, 4, 5, 6)", 3.5, 0.),
+  ("=MODE(5.6, 4, 4, 3, 2, 4)", 4., 0.),
+  ("=MODE(1, 2, 2, 3, 3)", 2., 0.),
+  ("=STDEV(A1:A10)", 27.46391572, 5e-9),
+  ("=VAR(A1:A10)", 754.2666667, 5e-8),
+  ("=PERCENTILE(I1:I4, 0.3)", 1.9, 5e-2),
+  ("=QUARTILE(H1:H8, 1)", 3.5, 0.),
+  ("=QUARTILE(H1:H8, 4)", 12., 0.),
+  ("=CORREL(B1:B5, C1:C5)", 0.997054486, 5e-10),
+  ("=RANK(D4, D1:D5, 1)", 1., 0.),
+  ("=RANK(D2, D1:D5, 1)", 3., 0.),
+  ("=RANK(D2, D1:D5)", 2., 0.),
+  ("=ROUND(2.15, 1)", 2.2, 0.),
+  ("=ROUND(2.149, 1)", 2.1, 0.),
+  ("=ROUND(-1.475, 2)", -1.48, 0.),
+  ("=ROUND(21.5, -1)", 20., 0.),
+  ("=ROUND(626.3, -3)", 1000., 0.),
+  ("=ROUND(1.98, -1)", 0., 0.),
+  ("=ROUND(-50.55, -2)", -100., 0.),
+  ("=ROUND(2.675, 2)", 2.68, 0.),
+  ("=ROUNDUP(3.2, 0)", 4., 0.),
+  ("=ROUNDUP(76.9, 0)", 77., 0.),
+  ("=ROUNDUP(3.14159, 3)", 3.142, 0.),
+  ("=ROUNDUP(-3.14159, 1)", -3.2, 0.),
+  ("=ROUNDUP(31415.92654, -2)", 31500., 0.),
+  ("=ROUNDDOWN(3.2, 0)", 3., 0.),
+  ("=ROUNDDOWN(76.9, 0)", 76., 0.),
+  ("=ROUNDDOWN(3.14159, 3)", 3.141, 0.),
+  ("=ROUNDDOWN(-3.14159, 1)", -3.1, 0.),
+  ("=ROUNDDOWN(31415.92654, -2)", 31400., 0.),
+  ("=MOD(3, 2)", 1., 0.),
+  ("=MOD(-3, 2)", 1., 0.),
+  ("=MOD(3, -2)", -1., 0.),
+  ("=MOD(-3, -2)", -1., 0.),
+  ("=ABS(-4)", 4., 0.),
+  ("=SQRT(16)", 4., 0.),
+  ("=POWER(5, 2)", 25., 0.),
+  ("=POWER(98.6, 3.2)", 2401077.222, 5e-4),
+  ("=POWER(4, 5/4)", 5.656854249, 5e-10),
+  ("=LN(86)", 4.454347296, 5e-10),
+  ("=LN(2.7182818)", 1., 5e-8),
+  ("=LOG(10)", 1., 0.),
+  ("=LOG(8, 2)", 3., 0.),
+  ("=LOG(86, 2.7182818)", 4.4543473, 5e-8),
+  ("=PMT(0.08/12, 10, 10000)", -1037.03, 5e-3),
+  ("=PMT(0.08/12, 10, 10000, 0, 1)", -1030.16, 5e-3),
+  ("=PMT(0, 10, 1000)", -100., 0.),
+  ("=PV(0.08/12, 12*20, 500)", -59777.15, 5e-3),
+  ("=FV(0.06/12, 10, -200, -500, 1)", 2581.40, 5e-3),
+  ("=FV(0.12/12, 12, -1000)", 12682.50, 5e-3),
+  ("=NPV(0.1, -10000, 3000, 4200, 6800)", 1188.44, 5e-3),
+  ("=IRR(E1:E5)", -0.021244848, 5e-10),
+  ("=IRR(E1:E6)", 0.086630948, 5e-10),
+  ("=IRR(E1:E3, -0.1)", -0.443506941, 5e-10),
+  ("=XNPV(0.09, F1:F5, G1:G5)", 2086.65, 5e-3),
+  ("=XIRR(F1:F5, G1:G5, 0.1)", 0.373362535, 1e-8),
+  ("=RATE(4*12, -200, 8000)", 0.00770147, 5e-9),
+  ("=RATE(4*12, -200, 8000)*12", 0.09241767, 5e-9),
+];
+
+/// `(formula, Excel's error)`.
+const ERRORS: &[(&str, &str)] = &[
+  ("=SUM(1, \"x\")", "#VALUE!"),
+  ("=AVERAGE(J2:J3)", "#DIV/0!"),
+  ("=MEDIAN(J2:J3)", "#NUM!"),
+  ("=MODE(1, 2, 3)", "#N/A"),
+  ("=STDEV(1)", "#DIV/0!"),
+  ("=VAR(J2:J3)", "#DIV/0!"),
+  ("=PERCENTILE(I1:I4, 1.5)", "#NUM!"),
+  ("=QUARTILE(H1:H8, 5)", "#NUM!"),
+  ("=CORREL(B1:B5, C1:C4)", "#N/A"),
+  ("=CORREL(J1:J2, J3:J4)", "#DIV/0!"),
+  ("=RANK(100, D1:D5)", "#N/A"),
+  ("=SQRT(-1)", "#NUM!"),
+  ("=MOD(1, 0)", "#DIV/0!"),
+  ("=LN(0)", "#NUM!"),
+  ("=LOG(10, 1)", "#DIV/0!"),
+  ("=POWER(0, -1)", "#DIV/0!"),
+  ("=POWER(-8, 0.5)", "#NUM!"),
+  ("=NPV(-1, 1, 2)", "#DIV/0!"),
+  ("=IRR(B1:B5)", "#NUM!"),
+  ("=XNPV(0.09, F1:F5, G1:G4)", "#NUM!"),
+  ("=XIRR(F1:F5, D1:D5)", "#NUM!"),
+];
+
+fn sheet() -> Spreadsheet {
+  let mut ss = Spreadsheet::new();
+  for (col, values) in DATA.iter().enumerate() {
+    for (row, value) in values.iter().enumerate() {
+      ss.set_raw(row, col, value).unwrap();
+    }
+  }
+  ss
+}
+
+#[test]
+fn results_match_excel() {
+  let mut ss = sheet();
+  let mut mismatches = vec![];
+  for (formula, expected, tolerance) in GOLDEN {
+    ss.set_raw(50, 0, formula).unwrap();
+    match ss.get(50, 0).out() {
+      ExprResult::Num(n) if (n - expected).abs() <= *tolerance => (),
+      out => mismatches.push(format!("{} is {:?}, not {}", formula, out, expected)),
+    }
+  }
+  assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn errors_match_excel() {
+  let mut ss = sheet();
+  let mut mismatches = vec![];
+  for (formula, expected) in ERRORS {
+    ss.set_raw(50, 0, formula).unwrap();
+    match ss.get(50, 0).out() {
+      ExprResult::Error(e) if e == expected => (),
+      out => mismatches.push(format!("{} is {:?}, not {}", formula, out, expected)),
+    }
+  }
+  assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
@@ -0,0 +1,130 @@
+//! Printing a parsed cell and parsing it again gives back the same tree.
+
+use proptest::prelude::*;
+use spreadsheet::expr::{
+  BinaryNode, BinaryOp, ExprTree, FuncNode, Range, UnaryNode, UnaryOp, ValueNode,
+};
+use spreadsheet::parser::is_valid_name;
+
+fn leaf() -> impl Strategy<Value = ExprTree> {
+  let coord = (0..2000usize, 0..800usize);
+  prop_oneof![
+    // Formulas have no negative literals, `-1` is a negation.
+    (0. ..1e12f64).prop_map(ValueNode::Num),
+    (0..100u32).prop_map(|n| ValueNode::Num(n as f64)),
+    "[ -~]{0,8}".prop_map(ValueNode::Text),
+    coord
+      .clone()
+      .prop_map(|(row, col)| ValueNode::Coord(row, col)),
+    coord
+      .clone()
+      .prop_map(|(row, col)| ValueNode::Spill(row, col)),
+    (coord.clone(), coord).prop_map(|(a, b)| ValueNode::Range(Range::new(a, b))),
+    "[A-Za-z_][A-Za-z0-9_.]{0,8}"
+      .prop_filter("not a name", |n| is_valid_name(n))
+      .prop_map(ValueNode::Name),
+  ]
+  .prop_map(ExprTree::Leaf)
+}
+
+fn tree() -> impl Strategy<Value = ExprTree> {
+  leaf().prop_recursive(5, 64, 4, |inner| {
+    let unary_op = prop_oneof![
+      Just(UnaryOp::Neg),
+      Just(UnaryOp::Plus),
+      Just(UnaryOp::Percent)
+    ];
+    let binary_op = prop_oneof![
+      Just(BinaryOp::Sum),
+      Just(BinaryOp::Sub),
+      Just(BinaryOp::Mul),
+      Just(BinaryOp::Div),
+      Just(BinaryOp::Pow),
+      Just(BinaryOp::Concat),
+      Just(BinaryOp::Eq),
+      Just(BinaryOp::Ne),
+      Just(BinaryOp::Lt),
+      Just(BinaryOp::Le),
+      Just(BinaryOp::Gt),
+      Just(BinaryOp::Ge),
+    ];
+    prop_oneof![
+      (unary_op, inner.clone())
+        .prop_map(|(op, child)| ExprTree::Unary(Box::new(UnaryNode { op, child }))),
+      (binary_op, inner.clone(), inner.clone()).prop_map(|(op, left, right)| {
+        ExprTree::Binary(Box::new(BinaryNode { op, left, right }))
+      }),
+      ("[A-Z][A-Z0-9]{0,6}", prop::collection::vec(inner, 0..4))
+        .prop_map(|(name, args)| ExprTree::Func(Box::new(FuncNode { name, args }))),
+    ]
+  })
+}
+
+proptest! {
+  #[test]
+  fn formulas_round_trip(tree in tree()) {
+    let printed = tree.to_string();
+    prop_assert_eq!(ExprTree::new(&printed), tree, "printed as {}", printed);
+  }
+
+  #[test]
+  fn literals_round_trip(n in -1e12f64..1e12, text in "\\PC{0,12}") {
+    let tree = ExprTree::new(&n.to_string());
+    prop_assert_eq!(ExprTree::new(&tree.to_string()), tree);
+    let tree = ExprTree::new(&text);
+    // Text that fails to parse, like `=1+`, isn't kept in the tree.
+    prop_assume!(!matches!(tree, ExprTree::Error(_)));
+    prop_assert_eq!(ExprTree::new(&tree.to_string()), tree);
+  }
+
+  #[test]
+  fn dates_round_trip(
+    (year, month, day) in (1900..9999i64, 1..=12u32, 1..=28u32),
+    (hour, minute, second, millis) in (0..24u32, 0..60u32, 0..60u32, 0..1000u32),
+  ) {
+    for raw in &[
+      format!("{}-{}-{}", year, month, day),
+      format!("{}/{:02}/{:02} {}:{:02}", year, month, day, hour, minute),
+      format!("{}-{}-{} {}:{:02}:{:02}.{:03}", year, month, day, hour, minute, second, millis),
+      format!("{}:{:02}:{:02}.{:03}", hour + 24, minute, second, millis),
+    ] {
+      let tree = ExprTree::new(raw);
+      prop_assert!(matches!(tree, ExprTree::Leaf(ValueNode::Date(..))), "{} isn't a date", raw);
+      prop_assert_eq!(ExprTree::new(&tree.to_string()), tree);
+    }
+  }
+
+  #[test]
+  fn trees_serialize(tree in tree()) {
+    let json = serde_json::to_string(&tree).unwrap();
+    prop_assert_eq!(serde_json::from_str::<ExprTree>(&json).unwrap(), tree);
+  }
+}
+
+#[test]
+fn serialized_shape() {
+  let json = serde_json::to_value(ExprTree::new("=SUM(A1:B2)*-2")).unwrap();
+  assert_eq!(
+    json,
+    serde_json::json!({
+      "type": "Binary",
+      "value": {
+        "op": "Mul",
+        "left": {
+          "type": "Func",
+          "value": {
+            "name": "SUM",
+            "args": [{
+              "type": "Leaf",
+              "value": {"type": "Range", "value": {"start": [0, 0], "end": [1, 1]}},
+            }],
+          },
+        },
+        "right": {
+          "type": "Unary",
+          "value": {"op": "Neg", "child": {"type": "Leaf", "value": {"type": "Num", "value": 2.0}}},
+        },
+      },
+    })
+  );
+}
@@ -82,11 +82,14 @@ fn set_evals_fails_formula_with_text_addition() {
 }
 
 #[wasm_bindgen_test]
-fn set_detects_ref_cycle() {
+fn set_marks_ref_cycle() {
   let mut ss = Spreadsheet::new();
   ss.set(0, 0, "=A2").unwrap();
   ss.set(1, 0, "=B1").unwrap();
-  assert!(ss.set(0, 1, "=A1").is_err())
+  ss.set(0, 1, "=A1").unwrap();
+  let cycle = ExprResult::Error("#CYCLE!".to_string());
+  assert_eq!(*ss.get(0, 1).out(), cycle);
+  assert_eq!(ss.get(0, 1).cycle(), &[(0, 1), (0, 0), (1, 0), (0, 1)]);
 }
 
 #[wasm_bindgen_test]
@@ -136,3 +139,28 @@ fn set_returns_all_updated() {
     ExprResult::Num(12.)
   );
 }
+
+#[wasm_bindgen_test]
+fn set_evals_references_past_the_sheet_to_ref_errors() {
+  let mut ss = Spreadsheet::new();
+  ss.set(0, 1, "=A1:A200").unwrap();
+  ss.set(1, 1, "=SUM(A1:Z1000)").unwrap();
+
+  let ref_error = ExprResult::Error("#REF!".to_string());
+  assert_eq!(*ss.get(0, 1).out(), ref_error);
+  assert_eq!(*ss.get(1, 1).out(), ref_error);
+}
+
+#[wasm_bindgen_test]
+fn set_evals_spills_past_the_sheet_to_ref_errors() {
+  let mut ss = Spreadsheet::new();
+  ss.set(0, 1, "=A500#").unwrap();
+  ss.set(1, 1, "=ZZ1#").unwrap();
+
+  let ref_error = ExprResult::Error("#REF!".to_string());
+  assert_eq!(*ss.get(0, 1).out(), ref_error);
+  assert_eq!(*ss.get(1, 1).out(), ref_error);
+  // `ZZ1` isn't read as the cell with the same index, `Z27`.
+  let updated = ss.set_raw(26, 25, "1").unwrap();
+  assert!(!updated.contains(&ss.get_index(1, 1)));
+}
@@ -3,12 +3,20 @@ import { AppProvider } from "./AppProvider";
 import "./App.css";
 import { Sheet } from "./Sheet";
 import { Participants } from "./Participants";
+import { Names } from "./Names";
+import { Calculation } from "./Calculation";
+import { Data } from "./Data";
+import { Search } from "./Search";
 
 const App = () => {
   return (
     <AppProvider>
       <Participants />
       <Sheet />
+      <Data />
+      <Search />
+      <Names />
+      <Calculation />
     </AppProvider>
   );
 };
@@ -5,11 +5,22 @@ import React, {
   useRef,
   useEffect,
 } from "react";
-import { Spreadsheet } from "spreadsheet";
+import { Spreadsheet, check_format } from "spreadsheet";
 import { getCellIndex, getCellRowCol } from "./Utils";
+import { decode } from "./msgpack";
 
 export const AppContext = createContext();
 
+// Must match a version accepted by the backend, see `server::PROTOCOL_VERSION`.
+const PROTOCOL_VERSION = 1;
+// `msgpack` makes the backend answer with MessagePack over binary frames,
+// requests are still sent as JSON. `viewport` makes it send only the cells
+// we show, see `subscribe`.
+const CAPABILITIES = ["msgpack", "viewport"];
+// Optional display name shown to other participants, the backend picks a
+// guest name otherwise.
+const NAME = window.localStorage.getItem("name");
+
 export const AppProvider = (props) => {
   // Spreadsheet
   const ssRef = useRef(Spreadsheet.new());
@@ -33,24 +44,221 @@ export const AppProvider = (props) => {
     },
     [width]
   );
+  const localSetFormat = useCallback(
+    (index, format) => {
+      setCells((prevCells) => {
+        if (format === prevCells[index].format) {
+          return prevCells;
+        }
+        const [row, col] = getCellRowCol(index, width);
+        let updates;
+        try {
+          updates = ssRef.current.set_format(row, col, format);
+        } catch (e) {
+          // Formats stored before the server checked them may not parse.
+          console.error("failed to set format", format, e);
+          return prevCells;
+        }
+        const newCells = [...prevCells];
+        for (const [idx, cell] of Object.entries(updates)) {
+          newCells[idx] = cell;
+        }
+        return newCells;
+      });
+    },
+    [width]
+  );
+  // Names live in the engine, the list is kept for the names panel.
+  const [names, setNames] = useState([]);
+  // Name ID -> Name, to know what a renamed name was called.
+  const namesRef = useRef({});
+  const applyUpdates = useCallback((updates) => {
+    setCells((prevCells) => {
+      const newCells = [...prevCells];
+      for (const [idx, cell] of Object.entries(updates)) {
+        newCells[idx] = cell;
+      }
+      return newCells;
+    });
+  }, []);
+  const localSetName = useCallback(
+    (name) => {
+      const prev = namesRef.current[name.id];
+      try {
+        if (prev && prev.name !== name.name) {
+          applyUpdates(ssRef.current.remove_name(prev.name));
+        }
+        applyUpdates(ssRef.current.set_name(name.name, name.definition));
+      } catch (e) {
+        console.error("failed to define name", name, e);
+      }
+      namesRef.current[name.id] = name;
+      setNames(Object.values(namesRef.current));
+    },
+    [applyUpdates]
+  );
+  const localRemoveName = useCallback(
+    (nameId) => {
+      const prev = namesRef.current[nameId];
+      if (prev) {
+        applyUpdates(ssRef.current.remove_name(prev.name));
+        delete namesRef.current[nameId];
+        setNames(Object.values(namesRef.current));
+      }
+    },
+    [applyUpdates]
+  );
+  // Volatile functions evaluate with what the backend handed out, so that
+  // every participant sees the same `RAND()` and `NOW()`.
+  const localRecalc = useCallback(
+    (recalculation) => {
+      applyUpdates(
+        ssRef.current.recalc(recalculation.seed, recalculation.now)
+      );
+    },
+    [applyUpdates]
+  );
+  // The shared filter view, `null` when there's none. Rows are hidden by
+  // evaluating it against the cells, see `filter.rs`.
+  const [filter, setFilterState] = useState(null);
+  const [hiddenRows, setHiddenRows] = useState(new Set());
+  const localSetFilter = useCallback((newFilter) => {
+    try {
+      if (newFilter) {
+        const { top, left, bottom, right } = newFilter.range;
+        ssRef.current.set_filter(top, left, bottom, right, newFilter.conditions);
+      } else {
+        ssRef.current.clear_filter();
+      }
+      setFilterState(newFilter);
+    } catch (e) {
+      console.error("failed to set filter", newFilter, e);
+    }
+  }, []);
+  useEffect(() => {
+    setHiddenRows(new Set(filter ? ssRef.current.hidden_rows() : []));
+  }, [cells, filter]);
+  // Iterative calculation, a setting of the sheet shared with the backend.
+  // `null` when off so cycles show `#CYCLE!`. See
+  // `Spreadsheet::set_iteration`.
+  const [iteration, setIterationState] = useState(null);
+  const localSetIteration = useCallback(
+    (newIteration) => {
+      try {
+        applyUpdates(
+          newIteration
+            ? ssRef.current.set_iterative(
+                true,
+                newIteration.max_iterations,
+                newIteration.tolerance
+              )
+            : ssRef.current.set_iterative(false, 0, 0)
+        );
+        setIterationState(newIteration);
+      } catch (e) {
+        console.error("failed to set iterative calculation", newIteration, e);
+      }
+    },
+    [applyUpdates]
+  );
+
   // Web socket
   const [userId, setUserId] = useState(0);
   const [participants, setParticipants] = useState([]);
+  // User ID -> Selection of the other participants
+  const [selections, setSelections] = useState({});
+  const [comments, setComments] = useState([]);
+  // The last viewport subscribed to, so that we don't resend it.
+  const viewportRef = useRef(null);
   const onWsEvent = useCallback(
     (response) => {
       switch (response.type) {
+        case "Welcome":
+          break;
+        case "Rejected":
+          console.error("backend rejected connection:", response.reason);
+          break;
         case "Connected":
           setUserId(response.user_id);
+          viewportRef.current = null;
+          localRecalc(response.recalculation);
+          localSetIteration(response.iteration);
           // TODO: Ideally we would wait until we got the cells to create the SS WASM object.
           response.cells.map((c) => {
             console.log("setting");
+            localSetFormat(getCellIndex(c.row, c.col, width), c.format);
             localSetCell(getCellIndex(c.row, c.col, width), c.raw);
           });
           break;
+        case "CellsLoaded":
+          for (const cell of response.cells) {
+            const index = getCellIndex(cell.row, cell.col, width);
+            localSetFormat(index, cell.format);
+            localSetCell(index, cell.raw);
+          }
+          break;
         case "Participants":
-          setParticipants(response.ids);
+          setParticipants(response.participants);
+          break;
+        case "SelectionChanged":
+          setSelections((prev) => ({
+            ...prev,
+            [response.user_id]: response.selection,
+          }));
+          break;
+        case "SelectionCleared":
+          setSelections((prev) => {
+            const next = { ...prev };
+            delete next[response.user_id];
+            return next;
+          });
+          break;
+        case "Comments":
+          setComments(response.comments);
+          break;
+        case "CommentAdded":
+          setComments((prev) => [...prev, response.comment]);
+          break;
+        case "CommentUpdated":
+          setComments((prev) =>
+            prev.map((c) => (c.id === response.comment.id ? response.comment : c))
+          );
+          break;
+        case "Names":
+          response.names.forEach(localSetName);
+          break;
+        case "NameUpdated":
+          localSetName(response.name);
+          break;
+        case "NameDeleted":
+          localRemoveName(response.name_id);
+          break;
+        case "CellsUpdated":
+          for (const cell of response.cells) {
+            const index = getCellIndex(cell.row, cell.col, width);
+            localSetFormat(index, cell.format);
+            localSetCell(index, cell.raw);
+          }
+          break;
+        case "Replaced":
+          // The local replacement already returned what to undo, see
+          // `replaceAll`.
+          break;
+        case "Filter":
+        case "FilterChanged":
+          localSetFilter(response.filter);
+          break;
+        case "Recalculated":
+          localRecalc(response.recalculation);
+          break;
+        case "IterationChanged":
+          localSetIteration(response.iteration);
           break;
         case "CellUpdated":
+          localSetFormat(
+            getCellIndex(response.cell.row, response.cell.col, width),
+            response.cell.format
+          );
           localSetCell(
             getCellIndex(response.cell.row, response.cell.col, width),
             response.cell.raw
@@ -61,7 +269,16 @@ export const AppProvider = (props) => {
           break;
       }
     },
-    [width, localSetCell]
+    [
+      width,
+      localSetCell,
+      localSetFormat,
+      localSetName,
+      localRemoveName,
+      localRecalc,
+      localSetIteration,
+      localSetFilter,
+    ]
   );
   const [ws, isOnline] = useWs(onWsEvent);
 
@@ -86,6 +303,256 @@ export const AppProvider = (props) => {
     [isOnline, userId, ws, width, localSetCell]
   );
 
+  const setFormat = useCallback(
+    (index, format) => {
+      try {
+        check_format(format);
+      } catch (e) {
+        console.error("invalid format", format, e);
+        return;
+      }
+      if (isOnline && userId) {
+        const [row, col] = getCellRowCol(index, width);
+        ws.current.send(
+          JSON.stringify({
+            type: "SetFormat",
+            sheet_id: 1,
+            row: row,
+            col: col,
+            format: format,
+          })
+        );
+      }
+      localSetFormat(index, format);
+    },
+    [isOnline, userId, ws, width, localSetFormat]
+  );
+
+  // Keep the comment indicators of the grid in sync with the threads.
+  const commentCounts = useRef({});
+  useEffect(() => {
+    const counts = {};
+    for (const c of comments) {
+      if (c.parent_id === null && !c.resolved) {
+        const index = getCellIndex(c.row, c.col, width);
+        counts[index] = (counts[index] || 0) + 1;
+      }
+    }
+    const indexes = new Set([
+      ...Object.keys(commentCounts.current),
+      ...Object.keys(counts),
+    ]);
+    commentCounts.current = counts;
+    setCells((prevCells) => {
+      const newCells = [...prevCells];
+      for (const index of indexes) {
+        const [row, col] = getCellRowCol(Number(index), width);
+        const updates = ssRef.current.set_comments(row, col, counts[index] || 0);
+        for (const [idx, cell] of Object.entries(updates)) {
+          newCells[idx] = cell;
+        }
+      }
+      return newCells;
+    });
+  }, [comments, width]);
+
+  const send = useCallback(
+    (request) => {
+      if (isOnline && userId) {
+        ws.current.send(JSON.stringify(request));
+      }
+    },
+    [isOnline, userId, ws]
+  );
+
+  // Asks the backend for the cells of `viewport`, `{top, left, bottom,
+  // right}`, and to only keep us up to date on those and what they read from.
+  const subscribe = useCallback(
+    (viewport) => {
+      const prev = viewportRef.current;
+      const same =
+        prev &&
+        ["top", "left", "bottom", "right"].every((k) => prev[k] === viewport[k]);
+      if (same || !isOnline || !userId) {
+        return;
+      }
+      viewportRef.current = viewport;
+      ws.current.send(JSON.stringify({ type: "Subscribe", viewport: viewport }));
+    },
+    [isOnline, userId, ws]
+  );
+
+  const addComment = useCallback(
+    (index, body, parentId) => {
+      const [row, col] = getCellRowCol(index, width);
+      send({
+        type: "AddComment",
+        row: row,
+        col: col,
+        body: body,
+        parent_id: parentId,
+      });
+    },
+    [send, width]
+  );
+
+  const editComment = useCallback(
+    (commentId, body) => {
+      send({ type: "EditComment", comment_id: commentId, body: body });
+    },
+    [send]
+  );
+
+  const resolveComment = useCallback(
+    (commentId, resolved) => {
+      send({ type: "ResolveComment", comment_id: commentId, resolved: resolved });
+    },
+    [send]
+  );
+
+  const defineName = useCallback(
+    (name, definition) => {
+      send({ type: "DefineName", name: name, definition: definition });
+    },
+    [send]
+  );
+
+  const renameName = useCallback(
+    (nameId, name) => {
+      send({ type: "RenameName", name_id: nameId, name: name });
+    },
+    [send]
+  );
+
+  const deleteName = useCallback(
+    (nameId) => {
+      send({ type: "DeleteName", name_id: nameId });
+    },
+    [send]
+  );
+
+  // Sorts locally, then hands the backend the new order of the rows and the
+  // cells and names whose references the engine rewrote, so that its copy
+  // ends up the same.
+  const sortRange = useCallback(
+    (range, keys) => {
+      const { top, left, bottom, right } = range;
+      try {
+        const sorted = ssRef.current.sort(top, left, bottom, right, keys);
+        applyUpdates(sorted.cells);
+        send({
+          type: "SortRange",
+          range: range,
+          order: sorted.order,
+          cells: sorted.edits,
+          names: sorted.names,
+        });
+      } catch (e) {
+        console.error("failed to sort", range, e);
+      }
+    },
+    [applyUpdates, send]
+  );
+
+  // Extends the series of `source` over `target`, then sends every filled
+  // cell to the backend in one batch.
+  const fillRange = useCallback(
+    (source, target) => {
+      try {
+        const filled = ssRef.current.fill(source, target);
+        applyUpdates(filled.cells);
+        send({ type: "UpdateCells", cells: filled.edits });
+      } catch (e) {
+        console.error("failed to fill", source, target, e);
+      }
+    },
+    [applyUpdates, send]
+  );
+
+  const setFilter = useCallback(
+    (newFilter) => {
+      send({ type: "SetFilter", filter: newFilter });
+      localSetFilter(newFilter);
+    },
+    [send, localSetFilter]
+  );
+
+  // Cells matching `query`, see `search::Query`, as `[row, col]`.
+  const findCells = useCallback((query) => {
+    try {
+      return ssRef.current.find(query);
+    } catch (e) {
+      console.error("failed to search", query, e);
+      return [];
+    }
+  }, []);
+
+  // What the last replacement changed, to undo it in one go.
+  const [lastReplace, setLastReplace] = useState(null);
+  const replaceAll = useCallback(
+    (query, replacement) => {
+      try {
+        const replaced = ssRef.current.replace(query, replacement);
+        applyUpdates(replaced.cells);
+        setLastReplace(replaced.previous.length ? replaced.previous : null);
+        send({ type: "ReplaceAll", query: query, replacement: replacement });
+      } catch (e) {
+        console.error("failed to replace", query, e);
+      }
+    },
+    [applyUpdates, send]
+  );
+
+  const undoReplace = useCallback(() => {
+    if (lastReplace) {
+      for (const edit of lastReplace) {
+        localSetCell(getCellIndex(edit.row, edit.col, width), edit.raw);
+      }
+      send({ type: "UpdateCells", cells: lastReplace });
+      setLastReplace(null);
+    }
+  }, [lastReplace, localSetCell, send, width]);
+
+  const recalculate = useCallback(() => {
+    send({ type: "Recalculate" });
+  }, [send]);
+
+  const setSelection = useCallback(
+    (index) => {
+      if (isOnline && userId) {
+        const [row, col] = getCellRowCol(index, width);
+        ws.current.send(
+          JSON.stringify({
+            type: "SelectionChanged",
+            selection: { row: row, col: col, range: null },
+          })
+        );
+      }
+    },
+    [isOnline, userId, ws, width]
+  );
+
+  const setIteration = useCallback(
+    (newIteration) => {
+      send({ type: "SetIteration", iteration: newIteration });
+      localSetIteration(newIteration);
+    },
+    [send, localSetIteration]
+  );
+
+  // Where the cell's value comes from and what it feeds, see `audit.rs`.
+  const audit = useCallback(
+    (index) => {
+      const [row, col] = getCellRowCol(index, width);
+      return {
+        precedents: ssRef.current.precedents(row, col),
+        dependents: ssRef.current.dependents(row, col),
+        explanation: ssRef.current.explain(row, col),
+      };
+    },
+    [width]
+  );
+
   const value = {
     cells,
     width,
@@ -93,7 +560,32 @@ export const AppProvider = (props) => {
     isOnline,
     userId,
     participants,
+    selections,
+    comments,
     setCell,
+    setFormat,
+    setSelection,
+    subscribe,
+    addComment,
+    editComment,
+    resolveComment,
+    names,
+    defineName,
+    renameName,
+    deleteName,
+    audit,
+    iteration,
+    setIteration,
+    recalculate,
+    sortRange,
+    fillRange,
+    filter,
+    setFilter,
+    hiddenRows,
+    findCells,
+    replaceAll,
+    canUndoReplace: lastReplace !== null,
+    undoReplace,
   };
   return (
     <AppContext.Provider value={value}>{props.children}</AppContext.Provider>
@@ -106,13 +598,24 @@ const useWs = (onEvent) => {
 
   useEffect(() => {
     ws.current = new WebSocket("ws://localhost:8888/ws/");
+    ws.current.binaryType = "arraybuffer";
 
     ws.current.onopen = () => {
+      ws.current.send(
+        JSON.stringify({
+          type: "Hello",
+          protocol_version: PROTOCOL_VERSION,
+          capabilities: CAPABILITIES,
+          name: NAME,
+        })
+      );
       setIsOnline(true);
     };
 
     ws.current.onmessage = (e) => {
-      const event = JSON.parse(e.data);
+      // Responses are JSON until the handshake picks MessagePack.
+      const event =
+        typeof e.data === "string" ? JSON.parse(e.data) : decode(e.data);
       console.log("event", event);
       onEvent(event);
     };
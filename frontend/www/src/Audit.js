@@ -0,0 +1,77 @@
+import React, { useContext, useState } from "react";
+import { AppContext } from "./AppProvider";
+import { getCellName } from "./Utils";
+
+// Precedents, dependents and the value of every part of the focused cell's
+// formula.
+export const Audit = ({ index }) => {
+  const { cells, audit } = useContext(AppContext);
+  const [isOpen, setIsOpen] = useState(false);
+
+  if (!isOpen) {
+    return <button onClick={() => setIsOpen(true)}>Audit</button>;
+  }
+  // Recomputed on every render, the context changes along with `cells`.
+  const { precedents, dependents, explanation } = audit(index);
+  return (
+    <div className="audit-container">
+      <button onClick={() => setIsOpen(false)}>Close</button>
+      <CellList label="Precedents" links={precedents} />
+      <CellList label="Dependents" links={dependents} />
+      {cells[index].raw.startsWith("=") && (
+        <ul className="audit-explanation">
+          <Step step={explanation} />
+        </ul>
+      )}
+    </div>
+  );
+};
+
+const CellList = ({ label, links }) => {
+  const direct = new Set(links.direct.map(([row, col]) => getCellName(row, col)));
+  return (
+    <div>
+      <span className="audit-label">{label}:</span>{" "}
+      {links.all.length === 0 && "none"}
+      {links.all.map(([row, col]) => {
+        const name = getCellName(row, col);
+        // Cells only reached through other cells are dimmed.
+        const className = direct.has(name) ? "audit-cell" : "audit-cell indirect";
+        return (
+          <span key={name} className={className}>
+            {name}
+          </span>
+        );
+      })}
+    </div>
+  );
+};
+
+const describe = (value) => {
+  if (value === null) {
+    return "range";
+  }
+  if (value.type === "Array") {
+    return `array of ${value.value.length}×${value.value[0].length}`;
+  }
+  if (value.type === "Lambda") {
+    return `LAMBDA of ${value.value.params.length} parameters`;
+  }
+  return value.value;
+};
+
+const Step = ({ step }) => {
+  const value = describe(step.value);
+  return (
+    <li>
+      <code>{step.expr}</code> = {String(value)}
+      {step.children.length > 0 && (
+        <ul>
+          {step.children.map((child, i) => (
+            <Step key={i} step={child} />
+          ))}
+        </ul>
+      )}
+    </li>
+  );
+};
@@ -0,0 +1,67 @@
+import React, { useContext, useEffect, useState } from "react";
+import { AppContext } from "./AppProvider";
+
+// Same defaults as `Iteration::default`.
+const DEFAULT_ITERATION = { max_iterations: 100, tolerance: 0.001 };
+
+// Sheet calculation settings, shared with everyone on it: whether intentional reference cycles are
+// solved by iterating instead of showing `#CYCLE!`, and a button to refresh
+// volatile functions like `NOW()` and `RAND()` for everyone.
+export const Calculation = () => {
+  const { iteration, setIteration, recalculate } = useContext(AppContext);
+  const [settings, setSettings] = useState(DEFAULT_ITERATION);
+
+  // Show what someone else on the sheet picked.
+  useEffect(() => {
+    if (iteration) {
+      setSettings(iteration);
+    }
+  }, [iteration]);
+
+  const onToggle = (event) => {
+    setIteration(event.target.checked ? settings : null);
+  };
+
+  const onChange = (key) => (event) => {
+    const newSettings = { ...settings, [key]: Number(event.target.value) };
+    setSettings(newSettings);
+    if (iteration) {
+      setIteration(newSettings);
+    }
+  };
+
+  return (
+    <div className="calculation-container">
+      <button className="calculation-recalculate" onClick={recalculate}>
+        Recalculate
+      </button>
+      <label>
+        <input
+          type="checkbox"
+          checked={iteration !== null}
+          onChange={onToggle}
+        />
+        Iterative calculation
+      </label>
+      <label className="calculation-setting">
+        Max iterations
+        <input
+          type="number"
+          min="1"
+          value={settings.max_iterations}
+          onChange={onChange("max_iterations")}
+        />
+      </label>
+      <label className="calculation-setting">
+        Tolerance
+        <input
+          type="number"
+          min="0"
+          step="any"
+          value={settings.tolerance}
+          onChange={onChange("tolerance")}
+        />
+      </label>
+    </div>
+  );
+};
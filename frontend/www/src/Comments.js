@@ -0,0 +1,94 @@
+import React, { useContext, useState } from "react";
+import { AppContext } from "./AppProvider";
+import { getCellIndex, getCellRowCol } from "./Utils";
+
+// Comment threads on the focused cell, with a box to start a new one.
+export const CommentThreads = ({ index }) => {
+  const { comments, width, addComment } = useContext(AppContext);
+  const [row, col] = getCellRowCol(index, width);
+  const onCell = comments.filter((c) => c.row === row && c.col === col);
+  const threads = onCell.filter((c) => c.parent_id === null);
+
+  return (
+    <div className="comment-container">
+      {threads.map((thread) => (
+        <Thread
+          key={thread.id}
+          thread={thread}
+          replies={onCell.filter((c) => c.parent_id === thread.id)}
+        />
+      ))}
+      <CommentInput
+        placeholder="Add a comment"
+        onSubmit={(body) => addComment(index, body, null)}
+      />
+    </div>
+  );
+};
+
+const Thread = ({ thread, replies }) => {
+  const { resolveComment, addComment, width } = useContext(AppContext);
+  const className = thread.resolved ? "comment-thread resolved" : "comment-thread";
+  const index = getCellIndex(thread.row, thread.col, width);
+
+  return (
+    <div className={className}>
+      {[thread, ...replies].map((c) => (
+        <Comment key={c.id} comment={c} />
+      ))}
+      <button onClick={() => resolveComment(thread.id, !thread.resolved)}>
+        {thread.resolved ? "Reopen" : "Resolve"}
+      </button>
+      {!thread.resolved && (
+        <CommentInput
+          placeholder="Reply"
+          onSubmit={(body) => addComment(index, body, thread.id)}
+        />
+      )}
+    </div>
+  );
+};
+
+const Comment = ({ comment }) => {
+  const { editComment } = useContext(AppContext);
+  const [isEditing, setIsEditing] = useState(false);
+
+  if (isEditing) {
+    return (
+      <CommentInput
+        initialValue={comment.body}
+        onSubmit={(body) => {
+          editComment(comment.id, body);
+          setIsEditing(false);
+        }}
+      />
+    );
+  }
+  return (
+    <div className="comment">
+      <span className="comment-author">{comment.author}</span> {comment.body}
+      <button onClick={() => setIsEditing(true)}>Edit</button>
+    </div>
+  );
+};
+
+const CommentInput = ({ placeholder, initialValue = "", onSubmit }) => {
+  const [value, setValue] = useState(initialValue);
+
+  const onKeyDown = (event) => {
+    if (event.key === "Enter" && value.trim() !== "") {
+      onSubmit(value);
+      setValue("");
+    }
+  };
+
+  return (
+    <input
+      className="comment-input"
+      value={value}
+      placeholder={placeholder}
+      onChange={(e) => setValue(e.target.value)}
+      onKeyDown={onKeyDown}
+    />
+  );
+};
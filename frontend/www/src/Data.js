@@ -0,0 +1,107 @@
+import React, { useContext, useState } from "react";
+import { AppContext } from "./AppProvider";
+import { parseRange, getCellName } from "./Utils";
+
+// Sorting a range on a column, the filter view shared by everyone that hides
+// the rows whose values don't meet a criterion like `>10`, and autofill.
+export const Data = () => {
+  const { filter, sortRange, fillRange, setFilter } = useContext(AppContext);
+  const [rangeText, setRangeText] = useState("");
+  const [fillText, setFillText] = useState("");
+  const [column, setColumn] = useState("A");
+  const [criterion, setCriterion] = useState("");
+
+  // The range and the absolute index of the column, `null` when either is
+  // invalid or the column is outside the range.
+  const parsed = () => {
+    const range = parseRange(rangeText);
+    const col = parseRange(`${column}1`);
+    if (!range || !col || col.left < range.left || col.left > range.right) {
+      return null;
+    }
+    return [range, col.left];
+  };
+
+  const onSort = (ascending) => () => {
+    const target = parsed();
+    if (target) {
+      const [range, col] = target;
+      sortRange(range, [{ col: col, ascending: ascending }]);
+    }
+  };
+
+  const onFilter = (event) => {
+    event.preventDefault();
+    const target = parsed();
+    if (target && criterion.trim() !== "") {
+      const [range, col] = target;
+      setFilter({
+        range: range,
+        conditions: [{ col: col, criterion: criterion.trim() }],
+      });
+    }
+  };
+
+  // Extends the series of the range over the fill range, e.g. `A1:A2` over
+  // `A1:A500`.
+  const onFill = (event) => {
+    event.preventDefault();
+    const source = parseRange(rangeText);
+    const target = parseRange(fillText);
+    if (source && target) {
+      fillRange(source, target);
+    }
+  };
+
+  return (
+    <div className="data-container">
+      <form onSubmit={onFilter}>
+        <input
+          className="data-input"
+          value={rangeText}
+          placeholder="A1:C10"
+          onChange={(e) => setRangeText(e.target.value)}
+        />
+        <input
+          className="data-input data-column"
+          value={column}
+          placeholder="Column"
+          onChange={(e) => setColumn(e.target.value)}
+        />
+        <button type="button" onClick={onSort(true)}>
+          Sort A→Z
+        </button>
+        <button type="button" onClick={onSort(false)}>
+          Sort Z→A
+        </button>
+        <input
+          className="data-input"
+          value={criterion}
+          placeholder=">10, East or ab*"
+          onChange={(e) => setCriterion(e.target.value)}
+        />
+        <button type="submit">Filter</button>
+      </form>
+      <form onSubmit={onFill}>
+        <input
+          className="data-input"
+          value={fillText}
+          placeholder="Fill over A1:A500"
+          onChange={(e) => setFillText(e.target.value)}
+        />
+        <button type="submit">Fill</button>
+      </form>
+      {filter && (
+        <div className="data-filter">
+          Filtering{" "}
+          {getCellName(filter.range.top, filter.range.left)}:
+          {getCellName(filter.range.bottom, filter.range.right)} on{" "}
+          {filter.conditions
+            .map((c) => `${getCellName(0, c.col).slice(0, -1)} ${c.criterion}`)
+            .join(", ")}
+          <button onClick={() => setFilter(null)}>Clear</button>
+        </div>
+      )}
+    </div>
+  );
+};
@@ -0,0 +1,75 @@
+import React, { useContext, useState } from "react";
+import { AppContext } from "./AppProvider";
+
+// Names defined in the sheet, e.g. `TaxRate` for `B1`, usable in formulas.
+export const Names = () => {
+  const { names, defineName } = useContext(AppContext);
+  const [name, setName] = useState("");
+  const [definition, setDefinition] = useState("");
+
+  const onSubmit = (event) => {
+    event.preventDefault();
+    if (name.trim() !== "" && definition.trim() !== "") {
+      defineName(name.trim(), definition.trim());
+      setName("");
+      setDefinition("");
+    }
+  };
+
+  return (
+    <div className="names-container">
+      {names.map((n) => (
+        <NameRow key={n.id} name={n} />
+      ))}
+      <form onSubmit={onSubmit}>
+        <input
+          className="name-input"
+          value={name}
+          placeholder="Name"
+          onChange={(e) => setName(e.target.value)}
+        />
+        <input
+          className="name-input"
+          value={definition}
+          placeholder="B1, A1:A10, 0.2 or LAMBDA(x, x*2)"
+          onChange={(e) => setDefinition(e.target.value)}
+        />
+        <button type="submit">Define</button>
+      </form>
+    </div>
+  );
+};
+
+const NameRow = ({ name }) => {
+  const { renameName, deleteName } = useContext(AppContext);
+  const [newName, setNewName] = useState(null);
+
+  const onKeyDown = (event) => {
+    if (event.key === "Enter" && newName.trim() !== "") {
+      renameName(name.id, newName.trim());
+      setNewName(null);
+    } else if (event.key === "Escape") {
+      setNewName(null);
+    }
+  };
+
+  return (
+    <div className="name-row">
+      {newName === null ? (
+        <span className="name" onDoubleClick={() => setNewName(name.name)}>
+          {name.name}
+        </span>
+      ) : (
+        <input
+          className="name-input"
+          value={newName}
+          autoFocus
+          onChange={(e) => setNewName(e.target.value)}
+          onKeyDown={onKeyDown}
+        />
+      )}{" "}
+      {name.definition}
+      <button onClick={() => deleteName(name.id)}>Delete</button>
+    </div>
+  );
+};
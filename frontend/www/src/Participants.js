@@ -10,8 +10,12 @@ export const Participants = () => {
       />
       {participants.map((p) => {
         return (
-          <span key={p} className="participant-tag">
-            {p}
+          <span
+            key={p.id}
+            className="participant-tag"
+            style={{ borderColor: p.color }}
+          >
+            {p.name}
           </span>
         );
       })}
@@ -0,0 +1,89 @@
+import React, { useContext, useState } from "react";
+import { AppContext } from "./AppProvider";
+import { getCellName } from "./Utils";
+
+const OPTIONS = [
+  ["match_case", "Match case"],
+  ["whole_cell", "Whole cell"],
+  ["regex", "Regular expression"],
+  ["in_values", "Search values"],
+];
+
+// Find and replace across the sheet. Values can be searched, but only what
+// was typed in cells can be replaced.
+export const Search = () => {
+  const { findCells, replaceAll, canUndoReplace, undoReplace } = useContext(
+    AppContext
+  );
+  const [text, setText] = useState("");
+  const [replacement, setReplacement] = useState("");
+  const [options, setOptions] = useState({});
+  const [matches, setMatches] = useState(null);
+
+  const query = () => ({ text: text, ...options });
+
+  const onFind = (event) => {
+    event.preventDefault();
+    if (text !== "") {
+      setMatches(findCells(query()));
+    }
+  };
+
+  const onReplace = () => {
+    if (text !== "") {
+      replaceAll(query(), replacement);
+      setMatches(null);
+    }
+  };
+
+  const onToggle = (key) => (event) => {
+    setOptions({ ...options, [key]: event.target.checked });
+  };
+
+  return (
+    <div className="search-container">
+      <form onSubmit={onFind}>
+        <input
+          className="search-input"
+          value={text}
+          placeholder="Find"
+          onChange={(e) => setText(e.target.value)}
+        />
+        <input
+          className="search-input"
+          value={replacement}
+          placeholder="Replace with"
+          onChange={(e) => setReplacement(e.target.value)}
+        />
+        <button type="submit">Find</button>
+        <button type="button" disabled={options.in_values} onClick={onReplace}>
+          Replace all
+        </button>
+        <button
+          type="button"
+          disabled={!canUndoReplace}
+          onClick={undoReplace}
+        >
+          Undo replace
+        </button>
+      </form>
+      {OPTIONS.map(([key, label]) => (
+        <label key={key} className="search-option">
+          <input
+            type="checkbox"
+            checked={!!options[key]}
+            onChange={onToggle(key)}
+          />
+          {label}
+        </label>
+      ))}
+      {matches && (
+        <div className="search-matches">
+          {matches.length === 0
+            ? "No matches"
+            : matches.map(([row, col]) => getCellName(row, col)).join(", ")}
+        </div>
+      )}
+    </div>
+  );
+};
@@ -1,14 +1,23 @@
-import React, { memo, useContext, useState } from "react";
+import React, { memo, useContext, useEffect, useRef, useState } from "react";
 import { AppContext } from "./AppProvider";
-import { getCellIndex, getCellRowCol } from "./Utils";
+import { CommentThreads } from "./Comments";
+import { Audit } from "./Audit";
+import { colToLetters, getCellIndex, getCellName, getCellRowCol } from "./Utils";
 
 export const Sheet = () => {
-  const { cells, width, height, setCell } = useContext(AppContext);
+  const { cells, width, height, setCell, setSelection } = useContext(
+    AppContext
+  );
   const [focusedCellIndex, setFocusedCellIndex] = useState(0);
   const [focusedCellValue, setFocusedCellValue] = useState(
     cells[focusedCellIndex].raw
   );
 
+  // Let the other participants know where we are.
+  useEffect(() => {
+    setSelection(focusedCellIndex);
+  }, [focusedCellIndex, setSelection]);
+
   const onFocusedCellValueChange = (value) => {
     setFocusedCellValue(value);
   };
@@ -31,6 +40,9 @@ export const Sheet = () => {
         focusedCellIndex={focusedCellIndex}
         onFocusedCellUpdate={onFocusedCellUpdate}
       />
+      <FormatPicker index={focusedCellIndex} />
+      <CommentThreads index={focusedCellIndex} />
+      <Audit index={focusedCellIndex} />
       <Table
         width={width}
         height={height}
@@ -74,6 +86,46 @@ const FormulaBar = ({
   );
 };
 
+// Common format codes, any other code can be typed in.
+const FORMAT_PRESETS = [
+  ["", "General"],
+  ["0.00", "Number"],
+  ["#,##0.00", "Thousands"],
+  ["$#,##0.00;($#,##0.00)", "Currency"],
+  ["0%", "Percent"],
+  ["0.00E+00", "Scientific"],
+  ["yyyy-mm-dd", "Date"],
+  ["hh:mm:ss", "Time"],
+];
+
+const FormatPicker = ({ index }) => {
+  const { cells, setFormat } = useContext(AppContext);
+  const format = cells[index].format;
+  const isPreset = FORMAT_PRESETS.some(([code]) => code === format);
+
+  const onChange = (event) => {
+    let code = event.target.value;
+    if (code === "custom") {
+      code = window.prompt("Format code", format);
+      if (code === null) {
+        return;
+      }
+    }
+    setFormat(index, code);
+  };
+
+  return (
+    <select value={isPreset ? format : "custom"} onChange={onChange}>
+      {FORMAT_PRESETS.map(([code, label]) => (
+        <option key={label} value={code}>
+          {label}
+        </option>
+      ))}
+      <option value="custom">{isPreset ? "Custom..." : format}</option>
+    </select>
+  );
+};
+
 const Table = ({
   width,
   height,
@@ -82,9 +134,12 @@ const Table = ({
   onFocusedCellValueChange,
   onFocusedCellUpdate,
 }) => {
+  const tableRef = useRef(null);
+  useViewport(tableRef, width, height);
+
   return (
     <div className="table-container">
-      <table id="table" cellSpacing="0">
+      <table id="table" cellSpacing="0" ref={tableRef}>
         <TableHeader width={width} />
         <TableBody
           width={width}
@@ -99,6 +154,56 @@ const Table = ({
   );
 };
 
+// Rows loaded above and below the ones on screen, so that scrolling a bit
+// doesn't show empty cells.
+const VIEWPORT_MARGIN = 20;
+
+// Subscribes to the rows scrolled into view, the backend then only sends the
+// cells we show and what they read from. See `viewport.rs`.
+const useViewport = (tableRef, width, height) => {
+  const { subscribe, hiddenRows } = useContext(AppContext);
+
+  useEffect(() => {
+    let frame = null;
+    const update = () => {
+      frame = null;
+      const rows = range(height).filter((row) => !hiddenRows.has(row));
+      if (!tableRef.current || rows.length === 0) {
+        return;
+      }
+      const rect = tableRef.current.getBoundingClientRect();
+      // The header is as tall as the rows.
+      const rowHeight = rect.height / (rows.length + 1);
+      const first = Math.floor(-rect.top / rowHeight) - 1 - VIEWPORT_MARGIN;
+      const last =
+        Math.ceil((window.innerHeight - rect.top) / rowHeight) + VIEWPORT_MARGIN;
+      const clamp = (i) => Math.min(Math.max(i, 0), rows.length - 1);
+      subscribe({
+        top: rows[clamp(first)],
+        left: 0,
+        bottom: rows[clamp(last)],
+        right: width - 1,
+      });
+    };
+    const onScroll = () => {
+      if (frame === null) {
+        frame = window.requestAnimationFrame(update);
+      }
+    };
+
+    update();
+    window.addEventListener("scroll", onScroll);
+    window.addEventListener("resize", onScroll);
+    return () => {
+      window.removeEventListener("scroll", onScroll);
+      window.removeEventListener("resize", onScroll);
+      if (frame !== null) {
+        window.cancelAnimationFrame(frame);
+      }
+    };
+  }, [tableRef, width, height, hiddenRows, subscribe]);
+};
+
 const TableHeader = ({ width }) => {
   return (
     <thead>
@@ -114,28 +219,6 @@ const TableHeader = ({ width }) => {
   );
 };
 
-const colToLetters = (col) => {
-  const base = 26;
-  let remainders = [];
-
-  remainders.push(col % base);
-  let quotient = Math.floor(col / base);
-
-  while (quotient !== 0) {
-    remainders.push(quotient % base);
-    quotient = Math.floor(quotient / base);
-  }
-
-  const asciiOffset = "A".charCodeAt(0);
-  const asciiCode = remainders
-    .map((n) => {
-      return asciiOffset + n;
-    })
-    .reverse();
-
-  return String.fromCharCode(asciiCode);
-};
-
 const TableBody = ({
   width,
   height,
@@ -144,31 +227,49 @@ const TableBody = ({
   onFocusedCellValueChange,
   onFocusedCellUpdate,
 }) => {
-  const { cells } = useContext(AppContext);
-
-  let idx = 0;
-  const rows = range(height).map((row) => {
-    return (
-      <tr key={row}>
-        <td className="cell-header">{row + 1}</td>
-        {range(width).map(() => {
-          const isFocused = focusedCellIndex === idx;
-          const cell = cells[idx];
-          const tableCell = isFocused ? (
-            <FocusedTableCell
-              key={idx}
-              value={focusedCellValue}
-              onChange={onFocusedCellValueChange}
-            />
-          ) : (
-            <UnfocusedTableCell key={idx} index={idx} cell={cell} />
-          );
-          idx++;
-          return tableCell;
-        })}
-      </tr>
-    );
-  });
+  const { cells, participants, selections, hiddenRows } = useContext(
+    AppContext
+  );
+
+  // Cell index -> Color of the participant that has it selected
+  const remoteColors = {};
+  for (const p of participants) {
+    const selection = selections[p.id];
+    if (selection) {
+      remoteColors[getCellIndex(selection.row, selection.col, width)] = p.color;
+    }
+  }
+
+  // Rows hidden by the filter view are left out, keeping their numbers.
+  const rows = range(height)
+    .filter((row) => !hiddenRows.has(row))
+    .map((row) => {
+      return (
+        <tr key={row}>
+          <td className="cell-header">{row + 1}</td>
+          {range(width).map((col) => {
+            const idx = getCellIndex(row, col, width);
+            const isFocused = focusedCellIndex === idx;
+            const cell = cells[idx];
+            const tableCell = isFocused ? (
+              <FocusedTableCell
+                key={idx}
+                value={focusedCellValue}
+                onChange={onFocusedCellValueChange}
+              />
+            ) : (
+              <UnfocusedTableCell
+                key={idx}
+                index={idx}
+                cell={cell}
+                remoteColor={remoteColors[idx]}
+              />
+            );
+            return tableCell;
+          })}
+        </tr>
+      );
+    });
 
   const onClick = (event) => {
     // See comment on UnfocusedTableCell's onClick to understand why this works
@@ -212,7 +313,7 @@ const FocusedTableCell = ({ value, onChange }) => {
   );
 };
 
-const _UnfocusedTableCell = ({ index, cell }) => {
+const _UnfocusedTableCell = ({ index, cell, remoteColor }) => {
   const onClick = (event) => {
     // HACK: we can't pass the onFocusedCellUpdate fn to our cells or
     // we will trigger a re-render of all cells whenever a single cell
@@ -239,11 +340,28 @@ const _UnfocusedTableCell = ({ index, cell }) => {
       break;
   }
 
+  if (cell.comments > 0) {
+    className += " cell-commented";
+  }
+  // Values an array formula spilled over, see `spill.rs`.
+  if (cell.spilled_from) {
+    className += " cell-spilled";
+  }
+
+  // Other participants' cursors are drawn as an outline in their color.
+  const style = remoteColor ? { outline: `2px solid ${remoteColor}` } : {};
+  // Cells in a reference cycle say which cells form it, e.g. A1 → B1 → A1.
+  const title =
+    cell.cycle.length > 0
+      ? cell.cycle.map(([row, col]) => getCellName(row, col)).join(" → ")
+      : undefined;
+
   return (
-    <td className="cell">
+    <td className="cell" style={style}>
       <input
         className={className}
-        value={cell.out.value}
+        value={cell.display}
+        title={title}
         onClick={onClick}
         readOnly
       />
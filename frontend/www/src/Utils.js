@@ -7,3 +7,51 @@ export const getCellRowCol = (index, width) => {
   const row = Math.floor(index / width);
   return [row, col];
 };
+
+export const colToLetters = (col) => {
+  const base = 26;
+  let remainders = [];
+
+  remainders.push(col % base);
+  let quotient = Math.floor(col / base);
+
+  while (quotient !== 0) {
+    remainders.push(quotient % base);
+    quotient = Math.floor(quotient / base);
+  }
+
+  const asciiOffset = "A".charCodeAt(0);
+  const asciiCode = remainders
+    .map((n) => {
+      return asciiOffset + n;
+    })
+    .reverse();
+
+  return String.fromCharCode(asciiCode);
+};
+
+// `A1` style name of a cell.
+export const getCellName = (row, col) => {
+  return `${colToLetters(col)}${row + 1}`;
+};
+
+// Parses an `A1` or `A1:C10` style range into `{top, left, bottom, right}`,
+// `null` when it isn't one.
+export const parseRange = (text) => {
+  const match = /^([A-Z]+)(\d+)(?::([A-Z]+)(\d+))?$/.exec(
+    text.trim().toUpperCase()
+  );
+  if (!match) {
+    return null;
+  }
+  const col = (letters) =>
+    [...letters].reduce((n, c) => n * 26 + c.charCodeAt(0) - 64, 0) - 1;
+  const [top, left] = [Number(match[2]) - 1, col(match[1])];
+  const [bottom, right] = match[3]
+    ? [Number(match[4]) - 1, col(match[3])]
+    : [top, left];
+  if (top < 0 || bottom < top || right < left) {
+    return null;
+  }
+  return { top, left, bottom, right };
+};
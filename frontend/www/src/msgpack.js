@@ -0,0 +1,140 @@
+// Just enough of a MessagePack decoder to read what the backend sends over
+// binary frames, see `codec.rs`. Extension types aren't used there.
+export const decode = (buffer) => {
+  const view = new DataView(buffer);
+  const bytes = new Uint8Array(buffer);
+  const utf8 = new TextDecoder();
+  let offset = 0;
+
+  const uint = (size) => {
+    let n;
+    if (size === 1) {
+      n = view.getUint8(offset);
+    } else if (size === 2) {
+      n = view.getUint16(offset);
+    } else if (size === 4) {
+      n = view.getUint32(offset);
+    } else {
+      n = Number(view.getBigUint64(offset));
+    }
+    offset += size;
+    return n;
+  };
+
+  const int = (size) => {
+    let n;
+    if (size === 1) {
+      n = view.getInt8(offset);
+    } else if (size === 2) {
+      n = view.getInt16(offset);
+    } else if (size === 4) {
+      n = view.getInt32(offset);
+    } else {
+      n = Number(view.getBigInt64(offset));
+    }
+    offset += size;
+    return n;
+  };
+
+  const float = (size) => {
+    const n = size === 4 ? view.getFloat32(offset) : view.getFloat64(offset);
+    offset += size;
+    return n;
+  };
+
+  const str = (len) => {
+    const s = utf8.decode(bytes.subarray(offset, offset + len));
+    offset += len;
+    return s;
+  };
+
+  const bin = (len) => {
+    const b = bytes.slice(offset, offset + len);
+    offset += len;
+    return b;
+  };
+
+  const array = (len) => {
+    const a = [];
+    for (let i = 0; i < len; i++) {
+      a.push(value());
+    }
+    return a;
+  };
+
+  const map = (len) => {
+    const m = {};
+    for (let i = 0; i < len; i++) {
+      const key = value();
+      m[key] = value();
+    }
+    return m;
+  };
+
+  const value = () => {
+    const tag = uint(1);
+    if (tag <= 0x7f) {
+      return tag;
+    } else if (tag <= 0x8f) {
+      return map(tag & 0x0f);
+    } else if (tag <= 0x9f) {
+      return array(tag & 0x0f);
+    } else if (tag <= 0xbf) {
+      return str(tag & 0x1f);
+    } else if (tag >= 0xe0) {
+      return tag - 0x100;
+    }
+    switch (tag) {
+      case 0xc0:
+        return null;
+      case 0xc2:
+        return false;
+      case 0xc3:
+        return true;
+      case 0xc4:
+        return bin(uint(1));
+      case 0xc5:
+        return bin(uint(2));
+      case 0xc6:
+        return bin(uint(4));
+      case 0xca:
+        return float(4);
+      case 0xcb:
+        return float(8);
+      case 0xcc:
+        return uint(1);
+      case 0xcd:
+        return uint(2);
+      case 0xce:
+        return uint(4);
+      case 0xcf:
+        return uint(8);
+      case 0xd0:
+        return int(1);
+      case 0xd1:
+        return int(2);
+      case 0xd2:
+        return int(4);
+      case 0xd3:
+        return int(8);
+      case 0xd9:
+        return str(uint(1));
+      case 0xda:
+        return str(uint(2));
+      case 0xdb:
+        return str(uint(4));
+      case 0xdc:
+        return array(uint(2));
+      case 0xdd:
+        return array(uint(4));
+      case 0xde:
+        return map(uint(2));
+      case 0xdf:
+        return map(uint(4));
+      default:
+        throw new Error(`unsupported MessagePack type 0x${tag.toString(16)}`);
+    }
+  };
+
+  return value();
+};